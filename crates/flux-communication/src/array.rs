@@ -1,9 +1,16 @@
-use std::{alloc::Layout, borrow::Borrow, ops::Deref, path::Path};
+use std::{
+    alloc::Layout,
+    borrow::Borrow,
+    ops::Deref,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use shared_memory::{ShmemConf, ShmemError};
+use type_hash::TypeHash;
 
 use crate::{
-    Seqlock,
+    Seqlock, TypeIdentity,
     error::{EmptyError, QueueError, ReadError},
 };
 
@@ -13,6 +20,12 @@ pub struct ArrayHeader {
     pub elsize: usize,
     pub bufsize: usize,
     pub is_initialized: u8,
+    /// Whether writes are logged for [`InnerSeqlockArray::changed_since`],
+    /// decided when the array is created.
+    pub change_log: u8,
+    /// Check [`TypeIdentity::is_current`] before trusting the other fields,
+    /// arrays created by older flux versions have no identity.
+    pub identity: TypeIdentity,
 }
 
 impl ArrayHeader {
@@ -36,29 +49,101 @@ impl ArrayHeader {
     }
 }
 
+/// Number of writes an array remembers for
+/// [`InnerSeqlockArray::changed_since`].
+pub const CHANGE_LOG_LEN: usize = 1024;
+const CHANGE_LOG_MASK: u64 = CHANGE_LOG_LEN as u64 - 1;
+
+/// Ring of the slots written last, stored after the slots of arrays created
+/// with one.
+///
+/// Every write takes the next generation and stores the slot index tagged
+/// with that generation in its entry, after the slot itself was written. A
+/// reader finds an entry either carrying its generation, from a previous lap
+/// (not logged yet), or from a later lap (overwritten).
+///
+/// The ring is followed by the generation each slot was last logged with,
+/// stored before its entry, so readers skip all but the latest write of a
+/// slot without keeping track of the slots they yielded.
+#[repr(C, align(64))]
+struct ChangeLog {
+    generation: AtomicU64,
+    _pad: [u64; 7],
+    entries: [AtomicU64; CHANGE_LOG_LEN],
+}
+
+enum LogEntry {
+    Changed(usize),
+    Pending,
+    Overwritten,
+}
+
+impl ChangeLog {
+    /// Tags are offset by one so that zeroed entries read as pending.
+    #[inline]
+    const fn tag(generation: u64) -> u32 {
+        generation.wrapping_add(1) as u32
+    }
+
+    /// Logs a write of `pos`. Concurrent writers of the same slot pass
+    /// `multi_producer`, so its last generation never goes backwards.
+    #[inline]
+    fn log(&self, pos: usize, last_logged: &AtomicU64, multi_producer: bool) {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        if multi_producer {
+            last_logged.fetch_max(generation, Ordering::Relaxed);
+        } else {
+            last_logged.store(generation, Ordering::Relaxed);
+        }
+        self.entries[(generation & CHANGE_LOG_MASK) as usize]
+            .store((u64::from(Self::tag(generation)) << 32) | pos as u64, Ordering::Release);
+    }
+
+    fn entry(&self, generation: u64) -> LogEntry {
+        let entry = self.entries[(generation & CHANGE_LOG_MASK) as usize].load(Ordering::Acquire);
+        let behind = Self::tag(generation).wrapping_sub((entry >> 32) as u32).cast_signed();
+        match behind {
+            0 => LogEntry::Changed((entry & u64::from(u32::MAX)) as usize),
+            1.. => LogEntry::Pending,
+            _ => LogEntry::Overwritten,
+        }
+    }
+}
+
 #[repr(C, align(64))]
 pub struct InnerSeqlockArray<T> {
     header: ArrayHeader,
     buffer: [Seqlock<T>],
 }
 impl<T: Copy> InnerSeqlockArray<T> {
-    fn new(len: usize) -> *const Self {
+    fn new(len: usize, change_log: bool) -> *const Self {
         // because we don't need len to be power of 2
-        let size = Self::size_of(len);
+        let size = Self::size_of(len, change_log);
 
         unsafe {
             let ptr = std::alloc::alloc_zeroed(
                 Layout::array::<u8>(size).unwrap().align_to(64).unwrap().pad_to_align(),
             );
-            Self::from_uninitialized_ptr(ptr, len)
+            Self::from_uninitialized_ptr(ptr, len, TypeIdentity::unhashed::<T>(), change_log)
         }
     }
 
-    const fn size_of(len: usize) -> usize {
-        std::mem::size_of::<ArrayHeader>() + len * std::mem::size_of::<Seqlock<T>>()
+    const fn size_of(len: usize, change_log: bool) -> usize {
+        let size = std::mem::size_of::<ArrayHeader>() + len * std::mem::size_of::<Seqlock<T>>();
+        if change_log {
+            size + std::mem::size_of::<ChangeLog>() + len * std::mem::size_of::<AtomicU64>()
+        } else {
+            size
+        }
     }
 
-    fn from_uninitialized_ptr(ptr: *mut u8, len: usize) -> *const Self {
+    fn from_uninitialized_ptr(
+        ptr: *mut u8,
+        len: usize,
+        identity: TypeIdentity,
+        change_log: bool,
+    ) -> *const Self {
+        assert!(u32::try_from(len).is_ok(), "array of {len} slots is too large");
         unsafe {
             // why len? because the size in the fat pointer ONLY cares about the unsized
             // part of the struct i.e. the length of the buffer
@@ -66,6 +151,8 @@ impl<T: Copy> InnerSeqlockArray<T> {
             let elsize = std::mem::size_of::<Seqlock<T>>();
             (*q).header.bufsize = len;
             (*q).header.elsize = elsize;
+            (*q).header.identity = identity;
+            (*q).header.change_log = u8::from(change_log);
             (*q).header.is_initialized = 1;
             q
         }
@@ -82,14 +169,45 @@ impl<T: Copy> InnerSeqlockArray<T> {
 
     //TODO @lopo: ErrorHandling
     #[inline]
-    fn load(&self, pos: usize) -> &Seqlock<T> {
+    pub(crate) fn load(&self, pos: usize) -> &Seqlock<T> {
         unsafe { self.buffer.get_unchecked(pos) }
     }
 
+    /// Whether the array logs its writes, see [`Self::changed_since`].
+    #[inline]
+    pub fn has_change_log(&self) -> bool {
+        self.header.change_log != 0
+    }
+
+    /// Only valid if [`Self::has_change_log`].
+    #[inline]
+    fn change_log(&self) -> &ChangeLog {
+        // Seqlocks are 64 byte aligned, so the end of the buffer is too.
+        #[allow(clippy::cast_ptr_alignment)]
+        unsafe {
+            &*self.buffer.as_ptr().add(self.buffer.len()).cast::<ChangeLog>()
+        }
+    }
+
+    /// Generation slot `pos` was last logged with, only valid if
+    /// [`Self::has_change_log`].
+    #[inline]
+    fn last_logged(&self, pos: usize) -> &AtomicU64 {
+        unsafe { &*std::ptr::from_ref(self.change_log()).add(1).cast::<AtomicU64>().add(pos) }
+    }
+
+    #[inline]
+    fn log_change(&self, pos: usize, multi_producer: bool) {
+        if self.has_change_log() {
+            self.change_log().log(pos, self.last_logged(pos), multi_producer);
+        }
+    }
+
     /// Use this is you are sure you are the only writer.
     pub fn write(&self, pos: usize, item: &T) {
         let lock = self.load(pos);
         lock.write(item);
+        self.log_change(pos, false);
     }
 
     pub fn reset_lock(&self, pos: usize) {
@@ -101,6 +219,68 @@ impl<T: Copy> InnerSeqlockArray<T> {
     pub fn write_multi_producer(&self, pos: usize, item: &T) {
         let lock = self.load(pos);
         lock.write_multi_producer(item);
+        self.log_change(pos, true);
+    }
+
+    /// Write only if the slot is still at `version`, returns whether it was.
+    pub fn write_at_version(&self, pos: usize, item: &T, version: u64) -> bool {
+        let lock = self.load(pos);
+        let written = lock.write_at_version(item, version);
+        if written {
+            self.log_change(pos, true);
+        }
+        written
+    }
+
+    /// Number of writes to the array so far, to pass to
+    /// [`Self::changed_since`] later. Always 0 without a change log.
+    pub fn generation(&self) -> u64 {
+        if !self.has_change_log() {
+            return 0;
+        }
+        self.change_log().generation.load(Ordering::Acquire)
+    }
+
+    /// The slots written since `generation`, each once with its current
+    /// value and version, most recently written first.
+    ///
+    /// Only the last [`CHANGE_LOG_LEN`] writes are remembered. A reader that
+    /// is further behind, or any reader of an array without a change log,
+    /// gets every written slot instead, see [`Changes::is_full_scan`].
+    /// Clearing the array is not a change.
+    pub fn changed_since(&self, generation: u64) -> Changes<'_, T> {
+        if !self.has_change_log() {
+            return Changes {
+                array: self,
+                since: generation,
+                next: generation,
+                end: generation,
+                generation,
+                full_scan: Some(0),
+            };
+        }
+        let log = self.change_log();
+        let current = log.generation.load(Ordering::Acquire);
+        let mut end = generation;
+        let mut full_scan = generation > current || current - generation > CHANGE_LOG_LEN as u64;
+        while !full_scan && end < current {
+            match log.entry(end) {
+                LogEntry::Changed(_) => end += 1,
+                LogEntry::Pending => break,
+                LogEntry::Overwritten => full_scan = true,
+            }
+        }
+        if full_scan {
+            return Changes {
+                array: self,
+                since: generation,
+                next: generation,
+                end: generation,
+                generation: current,
+                full_scan: Some(0),
+            };
+        }
+        Changes { array: self, since: generation, next: end, end, generation: end, full_scan: None }
     }
 
     pub fn read(&self, pos: usize, result: &mut T) -> Result<(), EmptyError> {
@@ -141,30 +321,53 @@ impl<T: Copy> InnerSeqlockArray<T> {
         self.load(pos).version()
     }
 
+    /// Type mismatches are returned to the caller, any other issue with a
+    /// preexisting segment removes and recreates it. An existing array
+    /// keeps logging changes if it did; one that doesn't is in use without a
+    /// log, so `change_log` fails with [`QueueError::NoChangeLog`] rather
+    /// than recreating it.
     fn create_or_open_shared<P: AsRef<Path>>(
         shmem_flink: P,
         len: usize,
+        identity: TypeIdentity,
+        change_log: bool,
     ) -> Result<*const Self, QueueError> {
         use shared_memory::{ShmemConf, ShmemError};
         if let Some(p) = shmem_flink.as_ref().parent() {
             let _ = std::fs::create_dir_all(p);
         }
-        match ShmemConf::new().size(Self::size_of(len)).flink(&shmem_flink).create() {
+        let size = Self::size_of(len, change_log);
+        if let Some(mapped) = crate::memfd::map(shmem_flink.as_ref(), size) {
+            let (ptr, is_new, _) = mapped?;
+            if is_new {
+                return Ok(Self::from_uninitialized_ptr(ptr, len, identity, change_log));
+            }
+            let header = Self::verify_header(ArrayHeader::from_ptr(ptr), &identity, change_log)?;
+            return if header.bufsize < len {
+                Err(QueueError::TooSmall)
+            } else {
+                Ok(Self::from_initialized_ptr(header))
+            };
+        }
+        match ShmemConf::new().size(size).flink(&shmem_flink).create() {
             Ok(shmem) => {
                 let ptr = shmem.as_ptr();
                 std::mem::forget(shmem);
-                Ok(Self::from_uninitialized_ptr(ptr, len))
+                Ok(Self::from_uninitialized_ptr(ptr, len, identity, change_log))
             }
             Err(ShmemError::LinkExists) => {
-                let v = match Self::open_shared(shmem_flink.as_ref()) {
+                let v = match Self::open_shared(shmem_flink.as_ref(), &identity, change_log) {
                     Ok(v) => v,
+                    Err(e @ (QueueError::TypeMismatch { .. } | QueueError::NoChangeLog)) => {
+                        return Err(e);
+                    }
                     Err(e) if shmem_flink.as_ref().exists() => {
                         tracing::warn!(
                             "There was an error opening {:?}, removing and recreating: {e}",
                             shmem_flink.as_ref()
                         );
                         let _ = std::fs::remove_file(shmem_flink.as_ref());
-                        return Self::create_or_open_shared(shmem_flink, len);
+                        return Self::create_or_open_shared(shmem_flink, len, identity, change_log);
                     }
                     Err(e) => return Err(e),
                 };
@@ -174,16 +377,33 @@ impl<T: Copy> InnerSeqlockArray<T> {
         }
     }
 
-    fn open_shared<S: AsRef<Path>>(shmem_file: S) -> Result<*const Self, QueueError> {
+    fn open_shared<S: AsRef<Path>>(
+        shmem_file: S,
+        identity: &TypeIdentity,
+        change_log: bool,
+    ) -> Result<*const Self, QueueError> {
         let path = std::path::Path::new(shmem_file.as_ref());
         if !path.exists() {
             return Err(QueueError::NonExistingFile);
         }
         let header = ArrayHeader::open_shared(shmem_file.as_ref())?;
+        let header = Self::verify_header(header, identity, change_log)?;
+        Ok(Self::from_initialized_ptr(header))
+    }
+
+    fn verify_header(
+        header: &'static mut ArrayHeader,
+        identity: &TypeIdentity,
+        change_log: bool,
+    ) -> Result<&'static mut ArrayHeader, QueueError> {
         if !header.is_initialized() {
             return Err(QueueError::UnInitialized);
         }
-        Ok(Self::from_initialized_ptr(header))
+        header.identity.verify(identity)?;
+        if change_log && header.change_log == 0 {
+            return Err(QueueError::NoChangeLog);
+        }
+        Ok(header)
     }
 
     fn clear(&self) {
@@ -204,20 +424,61 @@ pub struct SeqlockArray<T> {
     inner: *const InnerSeqlockArray<T>,
 }
 
+impl<T: Copy + TypeHash> SeqlockArray<T> {
+    /// Fails with [`QueueError::TypeMismatch`] if the existing array was
+    /// created for a different element type.
+    pub fn create_or_open_shared<P: AsRef<Path>>(
+        shmem_file: P,
+        len: usize,
+    ) -> Result<Self, QueueError> {
+        Self::create_or_open_shared_with_identity(shmem_file, len, TypeIdentity::of::<T>())
+    }
+
+    /// Like [`Self::create_or_open_shared`] for an array that logs its
+    /// writes, see [`InnerSeqlockArray::changed_since`]. An existing array
+    /// without a change log fails with [`QueueError::NoChangeLog`].
+    pub fn create_or_open_shared_with_change_log<P: AsRef<Path>>(
+        shmem_file: P,
+        len: usize,
+    ) -> Result<Self, QueueError> {
+        InnerSeqlockArray::create_or_open_shared(shmem_file, len, TypeIdentity::of::<T>(), true)
+            .map(|inner| Self { inner })
+    }
+
+    pub fn open_shared<P: AsRef<Path>>(shmem_file: P) -> Result<Self, QueueError> {
+        Self::open_shared_with_identity(shmem_file, &TypeIdentity::of::<T>())
+    }
+}
+
 impl<T: Copy> SeqlockArray<T> {
     pub fn new(len: usize) -> Self {
-        Self { inner: InnerSeqlockArray::new(len) }
+        Self { inner: InnerSeqlockArray::new(len, false) }
     }
 
-    pub fn create_or_open_shared<P: AsRef<Path>>(
+    /// Heap-backed array that logs its writes, see
+    /// [`InnerSeqlockArray::changed_since`]. Every write then also bumps a
+    /// shared generation counter.
+    pub fn with_change_log(len: usize) -> Self {
+        Self { inner: InnerSeqlockArray::new(len, true) }
+    }
+
+    /// Like [`Self::create_or_open_shared`] but records and verifies
+    /// `identity`, e.g. [`TypeIdentity::unhashed`] for element types that
+    /// cannot implement `TypeHash`.
+    pub fn create_or_open_shared_with_identity<P: AsRef<Path>>(
         shmem_file: P,
         len: usize,
+        identity: TypeIdentity,
     ) -> Result<Self, QueueError> {
-        InnerSeqlockArray::create_or_open_shared(shmem_file, len).map(|inner| Self { inner })
+        InnerSeqlockArray::create_or_open_shared(shmem_file, len, identity, false)
+            .map(|inner| Self { inner })
     }
 
-    pub fn open_shared<P: AsRef<Path>>(shmem_file: P) -> Result<Self, QueueError> {
-        InnerSeqlockArray::open_shared(shmem_file).map(|inner| Self { inner })
+    pub fn open_shared_with_identity<P: AsRef<Path>>(
+        shmem_file: P,
+        identity: &TypeIdentity,
+    ) -> Result<Self, QueueError> {
+        InnerSeqlockArray::open_shared(shmem_file, identity, false).map(|inner| Self { inner })
     }
 
     pub fn vec_iter(&self) -> VectorIterator<'_, T> {
@@ -250,6 +511,84 @@ impl<T> AsRef<InnerSeqlockArray<T>> for SeqlockArray<T> {
     }
 }
 
+/// Iterator over the slots written since a generation, see
+/// [`InnerSeqlockArray::changed_since`]. Yields `(index, value, version)`.
+pub struct Changes<'a, T> {
+    array: &'a InnerSeqlockArray<T>,
+    since: u64,
+    /// One past the next logged write to yield, counting down to `since`.
+    next: u64,
+    /// One past the first logged write yielded.
+    end: u64,
+    generation: u64,
+    /// Next slot of the full scan, once the log turned out to be overwritten.
+    full_scan: Option<usize>,
+}
+
+impl<T> Changes<'_, T> {
+    /// Generation to pass to the next `changed_since` call.
+    ///
+    /// Writes still being logged when the iterator was created are left for
+    /// that call.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether the reader fell too far behind and every written slot is
+    /// yielded.
+    pub fn is_full_scan(&self) -> bool {
+        self.full_scan.is_some()
+    }
+}
+
+impl<T: Copy> Changes<'_, T> {
+    /// Whether slot `pos` was yielded from the log before falling back to a
+    /// full scan.
+    fn was_yielded(&self, pos: usize) -> bool {
+        self.next < self.end &&
+            (self.next..self.end).contains(&self.array.last_logged(pos).load(Ordering::Relaxed))
+    }
+}
+
+impl<T: Copy> Iterator for Changes<'_, T> {
+    type Item = (usize, T, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.full_scan.is_none() && self.next > self.since {
+            self.next -= 1;
+            let pos = match self.array.change_log().entry(self.next) {
+                LogEntry::Changed(pos) => pos,
+                // Lapped while iterating, slots logged since `generation`
+                // are still among those written, at worst yielded again.
+                LogEntry::Overwritten | LogEntry::Pending => {
+                    self.next += 1;
+                    self.full_scan = Some(0);
+                    self.generation = self.generation.max(self.array.generation());
+                    break;
+                }
+            };
+            // Only the latest write of a slot is yielded. If that one isn't
+            // logged yet, the slot is left for the next call.
+            if pos < self.array.len() &&
+                self.array.last_logged(pos).load(Ordering::Relaxed) == self.next &&
+                let Ok((value, version)) = self.array.read_copy(pos)
+            {
+                return Some((pos, value, version));
+            }
+        }
+        while let Some(i) = self.full_scan.filter(|i| *i < self.array.len()) {
+            self.full_scan = Some(i + 1);
+            if self.was_yielded(i) {
+                continue;
+            }
+            if let Ok((value, version)) = self.array.read_copy(i) {
+                return Some((i, value, version));
+            }
+        }
+        None
+    }
+}
+
 pub struct VectorIterator<'a, T> {
     vector: &'a SeqlockArray<T>,
     next_id: usize,
@@ -268,3 +607,83 @@ impl<T: Copy + Default> Iterator for VectorIterator<'_, T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_since_yields_each_written_slot_once() {
+        let array = SeqlockArray::<u64>::with_change_log(64);
+        assert_eq!(array.generation(), 0);
+        assert_eq!(array.changed_since(0).count(), 0);
+
+        array.write(3, &30);
+        array.write(7, &70);
+        array.write_multi_producer(3, &31);
+        assert!(!array.write_at_version(9, &90, 1));
+        let mut changes = array.changed_since(0);
+        let seen: Vec<_> = changes.by_ref().map(|(i, v, _)| (i, v)).collect();
+        assert_eq!(seen, [(3, 31), (7, 70)]);
+        assert!(!changes.is_full_scan());
+        assert_eq!(changes.generation(), 3);
+        assert_eq!(array.changed_since(3).count(), 0);
+
+        array.write(5, &50);
+        let since: Vec<_> = array.changed_since(3).map(|(i, ..)| i).collect();
+        assert_eq!(since, [5]);
+    }
+
+    #[test]
+    fn changed_since_falls_back_to_a_full_scan() {
+        let array = SeqlockArray::<u64>::with_change_log(8);
+        array.write(1, &1);
+        let generation = array.generation();
+        for i in 0..=CHANGE_LOG_LEN as u64 {
+            array.write(2 + (i as usize % 3), &i);
+        }
+        let mut changes = array.changed_since(generation);
+        let seen: Vec<_> = changes.by_ref().map(|(i, ..)| i).collect();
+        assert!(changes.is_full_scan());
+        assert_eq!(seen, [1, 2, 3, 4]);
+        assert_eq!(changes.generation(), array.generation());
+    }
+
+    #[test]
+    fn change_log_is_opt_in() {
+        let array = SeqlockArray::<u64>::new(8);
+        assert!(!array.has_change_log());
+        array.write(2, &20);
+        array.write(5, &50);
+        assert_eq!(array.generation(), 0);
+        let mut changes = array.changed_since(0);
+        assert_eq!(changes.by_ref().map(|(i, ..)| i).collect::<Vec<_>>(), [2, 5]);
+        assert!(changes.is_full_scan());
+
+        let path = Path::new("/dev/shm/flux_test_array_change_log");
+        let _ = std::fs::remove_file(path);
+        let plain = SeqlockArray::<u64>::create_or_open_shared(path, 8).unwrap();
+        plain.write(1, &1);
+        // An array in use without a change log is left alone.
+        assert!(matches!(
+            SeqlockArray::<u64>::create_or_open_shared_with_change_log(path, 8),
+            Err(QueueError::NoChangeLog)
+        ));
+        let reopened = SeqlockArray::<u64>::create_or_open_shared(path, 8).unwrap();
+        assert!(!reopened.has_change_log());
+        assert_eq!(reopened.read_copy(1).unwrap().0, 1);
+        let _ = std::fs::remove_file(path);
+
+        let logged_path = Path::new("/dev/shm/flux_test_array_change_log_logged");
+        let _ = std::fs::remove_file(logged_path);
+        let logged =
+            SeqlockArray::<u64>::create_or_open_shared_with_change_log(logged_path, 8).unwrap();
+        assert!(logged.has_change_log());
+        logged.write(3, &3);
+        assert_eq!(logged.changed_since(0).map(|(i, ..)| i).collect::<Vec<_>>(), [3]);
+        // Opened without asking, it keeps logging.
+        let reopened = SeqlockArray::<u64>::create_or_open_shared(logged_path, 8).unwrap();
+        assert!(reopened.has_change_log());
+        let _ = std::fs::remove_file(logged_path);
+    }
+}
@@ -0,0 +1,398 @@
+//! Latency budgets in shared memory.
+//!
+//! A [`LatencyBudget`] is checked by a [`crate::Timer`] against every
+//! `ingestion_t`→consume latency it records. It counts the latencies checked
+//! and the ones over budget, keeps the worst offenders, and splits the counts
+//! into [`BUDGET_WINDOWS`] windows of [`BUDGET_WINDOW`] so readers get a
+//! rolling compliance over the last minute without the writers ever resetting
+//! anything on their behalf. Every instance of the timing tile, in any
+//! process, checks against the same budget, so all counts are multi-writer.
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU8, AtomicU64, Ordering},
+};
+
+use flux_timing::{Duration, Instant, Nanos, TSC_MASK};
+use shared_memory::ShmemConf;
+
+use crate::{
+    MappingOptions, Seqlock, TypeIdentity, error::QueueError, queue::shmem_map_create_or_open,
+};
+
+/// Length of one compliance window.
+pub const BUDGET_WINDOW: Nanos = Nanos::from_secs(1);
+/// Windows the rolling compliance spans.
+pub const BUDGET_WINDOWS: usize = 60;
+/// Offenders kept, worst first.
+pub const WORST_OFFENDERS: usize = 8;
+/// Epoch of a window while a writer reuses it.
+const ROTATING: u64 = u64::MAX;
+/// Times a writer checks on a window another writer is rotating before
+/// leaving the latency out of it.
+const ROTATE_SPINS: usize = 1024;
+
+/// A latency over budget.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Offender {
+    pub latency: Nanos,
+    /// When it was consumed, on the wall clock of the shared TSC anchor.
+    pub at: Nanos,
+}
+
+#[repr(C, align(64))]
+struct WindowCounts {
+    /// Masked TSC divided by the window length in ticks.
+    epoch: AtomicU64,
+    checked: AtomicU64,
+    violations: AtomicU64,
+}
+
+#[derive(Debug)]
+#[repr(C, align(64))]
+struct BudgetHeader {
+    is_initialized: AtomicU8,
+    identity: TypeIdentity,
+}
+
+/// Shared-memory segment of one [`LatencyBudget`].
+#[repr(C, align(64))]
+pub struct InnerLatencyBudget {
+    header: BudgetHeader,
+    budget: AtomicU64,
+    budget_ticks: AtomicU64,
+    window_ticks: AtomicU64,
+    /// Epoch of the latest window a latency was checked in, so writers only
+    /// divide when they cross into the next one.
+    epoch: AtomicU64,
+    checked: AtomicU64,
+    violations: AtomicU64,
+    /// Latency in ticks an offender has to exceed once the list is full.
+    worst_floor: AtomicU64,
+    worst: Seqlock<[Offender; WORST_OFFENDERS]>,
+    windows: [WindowCounts; BUDGET_WINDOWS],
+}
+
+impl InnerLatencyBudget {
+    fn init(&self, budget: Nanos) {
+        self.set_budget(budget);
+        self.window_ticks.store(Duration::from(BUDGET_WINDOW).0.max(1), Ordering::Relaxed);
+        self.worst.write(&[Offender::default(); WORST_OFFENDERS]);
+    }
+
+    fn set_budget(&self, budget: Nanos) {
+        self.budget.store(budget.0, Ordering::Relaxed);
+        self.budget_ticks.store(Duration::from(budget).0, Ordering::Relaxed);
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.header.is_initialized.load(Ordering::Acquire) != 0
+    }
+
+    fn verify(&self) -> Result<(), QueueError> {
+        if !self.is_initialized() {
+            return Err(QueueError::UnInitialized);
+        }
+        self.header.identity.verify(&TypeIdentity::unhashed::<Self>())
+    }
+
+    #[inline]
+    fn epoch(&self, at: Instant) -> u64 {
+        let ticks = at.0 & TSC_MASK;
+        let window_ticks = self.window_ticks.load(Ordering::Relaxed);
+        let cached = self.epoch.load(Ordering::Relaxed);
+        let start = cached * window_ticks;
+        if ticks >= start && ticks - start < window_ticks {
+            return cached;
+        }
+        self.next_epoch(ticks, window_ticks)
+    }
+
+    #[cold]
+    fn next_epoch(&self, ticks: u64, window_ticks: u64) -> u64 {
+        let epoch = ticks / window_ticks;
+        self.epoch.fetch_max(epoch, Ordering::Relaxed);
+        epoch
+    }
+
+    pub fn budget(&self) -> Nanos {
+        Nanos(self.budget.load(Ordering::Relaxed))
+    }
+
+    /// Checks one `latency`, consumed `at`.
+    #[inline]
+    pub fn check(&self, latency: Duration, at: Instant) {
+        let over = latency.0 > self.budget_ticks.load(Ordering::Relaxed);
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        if over {
+            self.violations.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let epoch = self.epoch(at);
+        let window = &self.windows[epoch as usize % BUDGET_WINDOWS];
+        let mut seen = window.epoch.load(Ordering::Acquire);
+        if seen < epoch || seen == ROTATING {
+            seen = Self::rotate(window, seen, epoch);
+        }
+        // A stale `at` from a lagging writer only counts in the totals.
+        if seen == epoch {
+            window.checked.fetch_add(1, Ordering::Relaxed);
+            if over {
+                window.violations.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        if over && latency.0 > self.worst_floor.load(Ordering::Relaxed) {
+            self.offend(Offender { latency: latency.into(), at: at.to_wall() });
+        }
+    }
+
+    /// Reuses `window` for `epoch` unless another writer got there first,
+    /// returning the epoch it ends up with. The winner parks the window at
+    /// [`ROTATING`] while it zeroes the counts, so neither readers nor the
+    /// other writers see the old counts under the new epoch.
+    #[cold]
+    fn rotate(window: &WindowCounts, mut seen: u64, epoch: u64) -> u64 {
+        for _ in 0..ROTATE_SPINS {
+            if seen == ROTATING {
+                std::hint::spin_loop();
+                seen = window.epoch.load(Ordering::Acquire);
+                continue;
+            }
+            if seen >= epoch {
+                return seen;
+            }
+            match window.epoch.compare_exchange(
+                seen,
+                ROTATING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    window.checked.store(0, Ordering::Relaxed);
+                    window.violations.store(0, Ordering::Relaxed);
+                    window.epoch.store(epoch, Ordering::Release);
+                    return epoch;
+                }
+                Err(current) => seen = current,
+            }
+        }
+        seen
+    }
+
+    #[cold]
+    fn offend(&self, offender: Offender) {
+        loop {
+            let Ok((mut worst, version)) = self.worst.read_copy() else {
+                return;
+            };
+            let Some(at) = worst.iter().position(|o| o.latency < offender.latency) else {
+                return;
+            };
+            worst.copy_within(at..WORST_OFFENDERS - 1, at + 1);
+            worst[at] = offender;
+            if self.worst.write_at_version(&worst, version) {
+                let floor = worst[WORST_OFFENDERS - 1].latency;
+                if floor.0 != 0 {
+                    self.worst_floor.store(Duration::from(floor).0, Ordering::Relaxed);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Copy of the counts as of now.
+    pub fn snapshot(&self) -> BudgetSnapshot {
+        let now = (Instant::now().0 & TSC_MASK) / self.window_ticks.load(Ordering::Relaxed);
+        let (mut recent_checked, mut recent_violations) = (0, 0);
+        for window in &self.windows {
+            let epoch = window.epoch.load(Ordering::Acquire);
+            if epoch <= now && now - epoch < BUDGET_WINDOWS as u64 {
+                recent_checked += window.checked.load(Ordering::Relaxed);
+                recent_violations += window.violations.load(Ordering::Relaxed);
+            }
+        }
+        let mut worst = [Offender::default(); WORST_OFFENDERS];
+        let _ = self.worst.read(&mut worst);
+        BudgetSnapshot {
+            budget: self.budget(),
+            checked: self.checked.load(Ordering::Relaxed),
+            violations: self.violations.load(Ordering::Relaxed),
+            recent_checked,
+            recent_violations,
+            worst: worst.into_iter().take_while(|o| o.latency.0 != 0).collect(),
+        }
+    }
+}
+
+/// A copy of the counts of a [`LatencyBudget`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BudgetSnapshot {
+    pub budget: Nanos,
+    pub checked: u64,
+    pub violations: u64,
+    /// Latencies checked over the last [`BUDGET_WINDOWS`] windows.
+    pub recent_checked: u64,
+    pub recent_violations: u64,
+    /// Worst first.
+    pub worst: Vec<Offender>,
+}
+
+impl BudgetSnapshot {
+    /// Percentage of the recent latencies within budget, `None` if there were
+    /// none.
+    pub fn compliance(&self) -> Option<f64> {
+        (self.recent_checked != 0).then(|| {
+            100.0 * (self.recent_checked - self.recent_violations) as f64 /
+                self.recent_checked as f64
+        })
+    }
+}
+
+/// Handle to a latency budget, see [`InnerLatencyBudget`].
+#[derive(Clone, Copy, Debug)]
+pub struct LatencyBudget {
+    inner: *const InnerLatencyBudget,
+}
+
+unsafe impl Send for LatencyBudget {}
+unsafe impl Sync for LatencyBudget {}
+
+impl LatencyBudget {
+    /// Creates or opens the budget at `shmem_flink`. Opening an existing
+    /// segment switches it to `budget` and keeps its counts.
+    ///
+    /// Type mismatches are returned to the caller, any other issue with a
+    /// preexisting segment removes and recreates it.
+    pub fn create_or_open_shared<P: AsRef<Path>>(
+        shmem_flink: P,
+        budget: Nanos,
+    ) -> Result<Self, QueueError> {
+        let flink = shmem_flink.as_ref();
+        let size = size_of::<InnerLatencyBudget>();
+        let (ptr, is_new, mapped_size) =
+            shmem_map_create_or_open(flink, size, MappingOptions::default());
+        #[allow(clippy::cast_ptr_alignment)]
+        let inner = ptr.cast::<InnerLatencyBudget>().cast_const();
+        let segment = unsafe { &*inner };
+
+        if is_new {
+            segment.init(budget);
+            unsafe {
+                std::ptr::addr_of!((*inner).header.identity)
+                    .cast_mut()
+                    .write(TypeIdentity::unhashed::<InnerLatencyBudget>());
+            }
+            segment.header.is_initialized.store(1, Ordering::Release);
+            return Ok(Self { inner });
+        }
+
+        match (mapped_size >= size).then(|| segment.verify()) {
+            Some(Ok(())) => {
+                segment.set_budget(budget);
+                Ok(Self { inner })
+            }
+            Some(Err(e @ QueueError::TypeMismatch { .. })) => Err(e),
+            _ => {
+                tracing::warn!("invalid latency budget at {flink:?}, removing and recreating");
+                crate::memfd::remove_segment(flink);
+                Self::create_or_open_shared(flink, budget)
+            }
+        }
+    }
+
+    /// Opens an existing budget for reading.
+    pub fn open_shared<P: AsRef<Path>>(shmem_flink: P) -> Result<Self, QueueError> {
+        let flink = shmem_flink.as_ref();
+        if !flink.exists() {
+            return Err(QueueError::NonExistingFile);
+        }
+        let shmem = ShmemConf::new().flink(flink).open()?;
+        if shmem.len() < size_of::<InnerLatencyBudget>() {
+            return Err(QueueError::TooSmall);
+        }
+        #[allow(clippy::cast_ptr_alignment)]
+        let inner = shmem.as_ptr().cast::<InnerLatencyBudget>().cast_const();
+        std::mem::forget(shmem);
+        unsafe { (*inner).verify()? };
+        Ok(Self { inner })
+    }
+}
+
+impl std::ops::Deref for LatencyBudget {
+    type Target = InnerLatencyBudget;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_violations_and_keeps_the_worst() {
+        let flink = Path::new("/dev/shm/flux_test_latency_budget");
+        let _ = std::fs::remove_file(flink);
+        let budget = LatencyBudget::create_or_open_shared(flink, Nanos::from_micros(5)).unwrap();
+        assert_eq!(budget.snapshot().compliance(), None);
+
+        let now = Instant::now();
+        for micros in 1..=20 {
+            budget.check(Duration::from(Nanos::from_micros(micros)), now);
+        }
+
+        let reader = LatencyBudget::open_shared(flink).unwrap().snapshot();
+        assert_eq!(reader.budget, Nanos::from_micros(5));
+        assert_eq!((reader.checked, reader.violations), (20, 15));
+        assert_eq!((reader.recent_checked, reader.recent_violations), (20, 15));
+        assert_eq!(reader.compliance(), Some(25.0));
+        let worst: Vec<_> = reader.worst.iter().map(|o| o.latency).collect();
+        assert_eq!(worst.len(), WORST_OFFENDERS);
+        assert!(worst.is_sorted_by(|a, b| a >= b));
+        assert!(worst[0] >= Nanos::from_micros(19), "worst {}", worst[0]);
+        assert!(worst[WORST_OFFENDERS - 1] >= Nanos::from_micros(12));
+        let _ = std::fs::remove_file(flink);
+    }
+
+    #[test]
+    fn concurrent_writers_keep_every_count() {
+        let flink = Path::new("/dev/shm/flux_test_latency_budget_writers");
+        let _ = std::fs::remove_file(flink);
+        let budget = LatencyBudget::create_or_open_shared(flink, Nanos::from_micros(5)).unwrap();
+        let now = Instant::now();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(move || {
+                    for micros in 0..10_000 {
+                        budget.check(Duration::from(Nanos::from_micros(micros % 10)), now);
+                    }
+                });
+            }
+        });
+
+        let snapshot = budget.snapshot();
+        assert_eq!((snapshot.checked, snapshot.violations), (40_000, 16_000));
+        assert_eq!((snapshot.recent_checked, snapshot.recent_violations), (40_000, 16_000));
+        let _ = std::fs::remove_file(flink);
+    }
+
+    #[test]
+    fn compliance_only_spans_recent_windows() {
+        let flink = Path::new("/dev/shm/flux_test_latency_budget_windows");
+        let _ = std::fs::remove_file(flink);
+        let budget = LatencyBudget::create_or_open_shared(flink, Nanos::from_micros(5)).unwrap();
+        let window = Duration::from(BUDGET_WINDOW);
+        let now = Instant::now();
+        let long_ago = Instant(now.0.saturating_sub(window.0 * (BUDGET_WINDOWS as u64 + 2)));
+        budget.check(Duration::from(Nanos::from_micros(50)), long_ago);
+        budget.check(Duration::from(Nanos::from_micros(1)), now);
+
+        let snapshot = budget.snapshot();
+        assert_eq!((snapshot.checked, snapshot.violations), (2, 1));
+        assert_eq!(snapshot.compliance(), Some(100.0));
+        assert_eq!(snapshot.worst.len(), 1);
+        let _ = std::fs::remove_file(flink);
+    }
+}
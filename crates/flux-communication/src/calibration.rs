@@ -0,0 +1,217 @@
+use std::{path::Path, sync::OnceLock};
+
+use flux_timing::{Nanos, OurClockForNanos, TSC_MASK, TscCalibration, read_tsc_and_node};
+use shared_memory::ShmemError;
+
+use crate::{Seqlock, ShmemData};
+
+/// Drift past which a re-anchor is logged as a warning rather than at debug.
+const DRIFT_WARN_NS: u64 = 100_000;
+
+/// Per-app segment holding the [`TscCalibration`] that the app's processes
+/// share.
+#[repr(C)]
+pub struct TscCalibrationSegment {
+    calibration: Seqlock<TscCalibration>,
+}
+
+/// The app's [`TscCalibration`] in shared memory.
+///
+/// The first process of the app publishes its own, and every process,
+/// including later ones, adopts it, so the same `Instant` converts to the
+/// same nanos and wall clock time everywhere. The anchor to the wall clock
+/// moves in [`maintain`](Self::maintain), the rate stays.
+///
+/// Sockets share the anchor's timeline until their offsets are measured
+/// with [`publish_socket_offsets`](Self::publish_socket_offsets), e.g. by
+/// `flux-ctl calibrate-sockets`; from then on `Instant`s from different
+/// sockets are corrected by the same offsets everywhere.
+pub struct SharedTscCalibration {
+    segment: ShmemData<TscCalibrationSegment>,
+}
+
+static ATTACHED: OnceLock<SharedTscCalibration> = OnceLock::new();
+
+/// Opens the calibration of `app_name` and adopts it, see
+/// [`SharedTscCalibration`]. Later calls return the first app attached.
+pub fn attach_tsc_calibration<D: AsRef<Path>, A: AsRef<Path>>(
+    base_dir: D,
+    app_name: A,
+) -> Result<&'static SharedTscCalibration, ShmemError> {
+    if let Some(attached) = ATTACHED.get() {
+        return Ok(attached);
+    }
+    let shared = SharedTscCalibration::open_or_publish_with_base_dir(base_dir, app_name)?;
+    let attached = ATTACHED.get_or_init(|| shared);
+    attached.adopt();
+    Ok(attached)
+}
+
+/// The calibration [`attach_tsc_calibration`] attached, if any.
+#[inline]
+pub fn attached_tsc_calibration() -> Option<&'static SharedTscCalibration> {
+    ATTACHED.get()
+}
+
+impl SharedTscCalibration {
+    /// Opens the calibration of `app_name`, publishing ours if there is none
+    /// or the one there predates a reboot.
+    pub fn open_or_publish_with_base_dir<D: AsRef<Path>, A: AsRef<Path>>(
+        base_dir: D,
+        app_name: A,
+    ) -> Result<Self, ShmemError> {
+        let segment = ShmemData::open_or_init_with_base_dir(base_dir, app_name, || {
+            TscCalibrationSegment { calibration: Seqlock::new(TscCalibration::measure()) }
+        })?;
+        let shared = Self { segment };
+        let (tsc, _) = read_tsc_and_node();
+        if let Ok((found, version)) = shared.segment.calibration.read_copy() &&
+            found.is_from_before(tsc & TSC_MASK) &&
+            shared.segment.calibration.write_at_version(&TscCalibration::measure(), version)
+        {
+            tracing::info!("replaced the TSC calibration of a previous boot");
+        }
+        Ok(shared)
+    }
+
+    #[inline]
+    pub fn read(&self) -> TscCalibration {
+        let mut calibration = TscCalibration::default();
+        let _ = self.segment.calibration.read(&mut calibration);
+        calibration
+    }
+
+    /// Makes the shared rate ours, see [`TscCalibration::adopt`].
+    pub fn adopt(&self) -> TscCalibration {
+        let shared = self.read();
+        let ours = TscCalibration::measure();
+        shared.adopt();
+        tracing::debug!(
+            error_ppm = ours.rate_error_ppm(&shared),
+            "adopted the shared TSC calibration"
+        );
+        shared
+    }
+
+    /// Measures the TSC offset of every socket, see
+    /// [`TscCalibration::measure_sockets`], and publishes them with the
+    /// shared rate. Processes adopt them on their next
+    /// [`maintain`](Self::maintain).
+    ///
+    /// Pins a thread onto every CPU the caller may run on in turn, so run it
+    /// with an affinity that leaves out isolated and busy-spinning cores.
+    pub fn publish_socket_offsets(&self) -> Option<TscCalibration> {
+        let measured = TscCalibration::measure_sockets();
+        loop {
+            let (shared, version) = self.segment.calibration.read_copy().ok()?;
+            let calibration = TscCalibration { nanos_per_tick: shared.nanos_per_tick, ..measured };
+            if self.segment.calibration.write_at_version(&calibration, version) {
+                return Some(calibration);
+            }
+        }
+    }
+
+    /// Re-anchors the calibration to the wall clock if the anchor is older
+    /// than `every`, returning the drift it found. Any process may call it;
+    /// of the ones that race, one writes and the rest return `None`. Either
+    /// way the calibration in the segment is adopted, so processes follow
+    /// the re-anchors of others.
+    pub fn maintain(&self, every: Nanos) -> Option<i64> {
+        let (mut calibration, version) = self.segment.calibration.read_copy().ok()?;
+        if OurClockForNanos::System.now().saturating_sub(calibration.anchor_wall) < every {
+            calibration.adopt();
+            return None;
+        }
+        let drift = calibration.reanchor();
+        if !self.segment.calibration.write_at_version(&calibration, version) {
+            self.read().adopt();
+            return None;
+        }
+        calibration.adopt();
+        if drift.unsigned_abs() > DRIFT_WARN_NS {
+            tracing::warn!(drift_ns = drift, "TSC drifted from the wall clock, re-anchored");
+        } else {
+            tracing::debug!(drift_ns = drift, "re-anchored the TSC calibration");
+        }
+        Some(drift)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flux_timing::Instant;
+
+    use super::*;
+
+    #[test]
+    fn later_processes_find_the_first_calibration() {
+        let tmp = tempfile::tempdir().unwrap();
+        let first =
+            SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "calib").unwrap();
+        let published = first.read();
+        assert_ne!(published.nanos_per_tick, 0);
+
+        let second =
+            SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "calib").unwrap();
+        assert_eq!(second.read(), published);
+    }
+
+    #[test]
+    fn socket_offsets_are_measured_on_request() {
+        let tmp = tempfile::tempdir().unwrap();
+        let shared =
+            SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "calib").unwrap();
+        let published = shared.read();
+        assert_eq!(published.socket_errors, [0; flux_timing::MAX_SOCKETS]);
+
+        let measured = shared.publish_socket_offsets().unwrap();
+        assert_eq!(shared.read(), measured);
+        assert_eq!(measured.nanos_per_tick, published.nanos_per_tick);
+        #[cfg(target_os = "linux")]
+        assert_ne!(measured.socket_errors, [0; flux_timing::MAX_SOCKETS]);
+    }
+
+    #[test]
+    fn maintain_reanchors_once_per_interval() {
+        let tmp = tempfile::tempdir().unwrap();
+        let shared =
+            SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "calib").unwrap();
+        let before = shared.read();
+        assert_eq!(shared.maintain(Nanos::from_secs(60)), None);
+
+        let drift = shared.maintain(Nanos(0)).expect("re-anchors");
+        let after = shared.read();
+        assert!(after.anchor_tsc > before.anchor_tsc);
+        assert_eq!(after.drift_ns, drift);
+        assert_eq!(after.nanos_per_tick, before.nanos_per_tick);
+    }
+
+    #[test]
+    fn maintain_adopts_the_anchor_of_other_processes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let shared =
+            SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "calib").unwrap();
+        // Another process re-anchored onto a wall clock a second ahead.
+        let mut moved = shared.read();
+        moved.anchor_wall.0 += 1_000_000_000;
+        shared.segment.calibration.write(&moved);
+
+        assert_eq!(shared.maintain(Nanos::from_secs(60)), None);
+        let at = Instant(moved.anchor_tsc + (1 << 20));
+        assert!(at.to_wall().0.abs_diff(moved.to_wall(at).0) <= 1);
+    }
+
+    #[test]
+    fn calibration_from_a_previous_boot_is_replaced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let shared =
+            SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "calib").unwrap();
+        let mut stale = shared.read();
+        stale.anchor_tsc = TSC_MASK;
+        shared.segment.calibration.write(&stale);
+
+        let reopened =
+            SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "calib").unwrap();
+        assert!(reopened.read().anchor_tsc < TSC_MASK);
+    }
+}
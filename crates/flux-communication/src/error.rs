@@ -1,12 +1,32 @@
 use shared_memory::ShmemError;
 use thiserror::Error;
 
+use crate::TypeIdentity;
+
 #[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum EmptyError {
     #[error("Lock empty")]
     Empty,
 }
 
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FullError {
+    #[error("No free slot")]
+    Full,
+}
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TooLargeError {
+    #[error("Record payload of {0} bytes exceeds the maximum of {1}")]
+    TooLarge(usize, usize),
+}
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimeoutError {
+    #[error("Timed out waiting for a message")]
+    TimedOut,
+}
+
 #[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ReadError {
@@ -27,12 +47,24 @@ pub enum QueueError {
         "Element size changed from {0} to {1}. Need to reinit the queue after detaching processes"
     )]
     ElementSizeChanged(usize, usize),
+    #[error(
+        "Header version changed from {0} to {1}. Need to reinit the queue after detaching processes"
+    )]
+    HeaderVersionChanged(u32, u32),
+    #[error("Type mismatch: shared memory holds {stored}, expected {expected}")]
+    TypeMismatch { stored: TypeIdentity, expected: TypeIdentity },
     #[error("Element at {0} poisoned. Need to reinit the queue after detaching processes")]
     ElementPoisoned(usize),
     #[error("Shared memory file does not exist")]
     NonExistingFile,
     #[error("Preexisting shared memory too small")]
     TooSmall,
+    #[error("Preexisting array doesn't log its changes")]
+    NoChangeLog,
+    #[error(transparent)]
+    TooLarge(#[from] TooLargeError),
     #[error("Shmem error")]
     ShmemError(#[from] ShmemError),
+    #[error("Segment broker error: {0}")]
+    SegmentBroker(#[from] std::io::Error),
 }
@@ -0,0 +1,542 @@
+//! Fixed-bucket latency histograms in shared memory.
+//!
+//! Values are nanoseconds, bucketed log-linearly like HDR histograms: exact
+//! below `2^SUB_BUCKET_BITS`, above that every power of two is split into
+//! `2^SUB_BUCKET_BITS` equally wide sub-buckets, so a value is never off by
+//! more than ~3%.
+//!
+//! Each [`IntervalHistogram`] keeps a small ring of intervals. Writers record
+//! into the active one; once its time is up, the first record or read after
+//! that moves on to the next, clearing the oldest. Finished intervals are
+//! published one interval later, so a record racing the swap still lands
+//! before readers copy them, and stay in the ring for a few more, so readers
+//! copy them at their own pace without ever slowing the writer down.
+use std::{
+    ops::Range,
+    path::Path,
+    sync::atomic::{AtomicU8, AtomicU64, Ordering, fence},
+};
+
+use flux_timing::{Duration, Instant, Nanos, TSC_MASK};
+use shared_memory::ShmemConf;
+
+use crate::{MappingOptions, TypeIdentity, error::QueueError, queue::shmem_map_create_or_open};
+
+/// Linear sub-buckets per power of two, as a power of two.
+pub const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// Values of `2^(MAX_MAGNITUDE + 1)` ns (~73 min) and up share the last
+/// bucket.
+const MAX_MAGNITUDE: u32 = 41;
+pub const N_BUCKETS: usize = SUB_BUCKETS * (MAX_MAGNITUDE - SUB_BUCKET_BITS + 2) as usize;
+const MAX_READ_ATTEMPTS: usize = 1 << 16;
+/// Intervals in the ring of an [`IntervalHistogram`]: the active one, the one
+/// waiting to be published and the readable ones.
+const SLOTS: u64 = 8;
+
+/// Index of the bucket counting `value`.
+#[inline]
+pub const fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let magnitude = 63 - value.leading_zeros();
+    if magnitude > MAX_MAGNITUDE {
+        return N_BUCKETS - 1;
+    }
+    let shift = magnitude - SUB_BUCKET_BITS;
+    SUB_BUCKETS * (shift as usize + 1) + ((value >> shift) as usize - SUB_BUCKETS)
+}
+
+/// Lowest and highest value counted in bucket `index`.
+pub const fn bucket_range(index: usize) -> (u64, u64) {
+    if index < SUB_BUCKETS {
+        return (index as u64, index as u64);
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let low = ((SUB_BUCKETS + index % SUB_BUCKETS) as u64) << shift;
+    (low, low + (1 << shift) - 1)
+}
+
+#[repr(C, align(64))]
+struct HistogramCounts {
+    start: AtomicU64,
+    end: AtomicU64,
+    count: AtomicU64,
+    sum: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+    buckets: [AtomicU64; N_BUCKETS],
+}
+
+impl HistogramCounts {
+    #[inline]
+    fn record(&self, value: u64) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        // Loads first, the read-modify-writes are rarely needed.
+        if value < self.min.load(Ordering::Relaxed) {
+            self.min.fetch_min(value, Ordering::Relaxed);
+        }
+        if value > self.max.load(Ordering::Relaxed) {
+            self.max.fetch_max(value, Ordering::Relaxed);
+        }
+        self.buckets[bucket_index(value)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn clear(&self, start: u64) {
+        self.start.store(start, Ordering::Relaxed);
+        self.end.store(0, Ordering::Relaxed);
+        self.count.store(0, Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        self.min.store(u64::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn copy_into(&self, snapshot: &mut HistogramSnapshot) {
+        snapshot.start = Nanos(self.start.load(Ordering::Relaxed));
+        snapshot.end = Nanos(self.end.load(Ordering::Relaxed));
+        snapshot.count = self.count.load(Ordering::Relaxed);
+        snapshot.sum = self.sum.load(Ordering::Relaxed);
+        snapshot.min = self.min.load(Ordering::Relaxed);
+        snapshot.max = self.max.load(Ordering::Relaxed);
+        for (to, from) in snapshot.buckets.iter_mut().zip(&self.buckets) {
+            *to = from.load(Ordering::Relaxed);
+        }
+    }
+}
+
+/// A histogram that is swapped out every `interval`.
+///
+/// Interval `n` is recorded into slot `n % SLOTS`. It is published once
+/// interval `n + 2` started, and readable until the swap that reuses its
+/// slot, i.e. the last `SLOTS - 2` finished intervals are kept. A record
+/// stalled for more than an interval between picking its slot and writing
+/// it may still be counted in an interval that was already read.
+#[repr(C, align(64))]
+pub struct IntervalHistogram {
+    /// Odd while a slot is being recycled.
+    seq: AtomicU64,
+    /// Number of the interval being recorded.
+    active: AtomicU64,
+    /// Interval length in nanos.
+    interval: AtomicU64,
+    /// Masked TSC at which the active interval is due to be swapped out.
+    swap_at: AtomicU64,
+    slots: [HistogramCounts; SLOTS as usize],
+}
+
+impl IntervalHistogram {
+    fn init(&self, interval: Nanos) {
+        self.interval.store(interval.0, Ordering::Relaxed);
+        for slot in &self.slots {
+            slot.clear(0);
+        }
+        self.slots[0].start.store(Instant::now().to_wall().0, Ordering::Relaxed);
+        self.swap_at.store(Self::tsc() + Duration::from(interval).0, Ordering::Release);
+    }
+
+    #[inline]
+    fn tsc() -> u64 {
+        Instant::now().0 & TSC_MASK
+    }
+
+    pub fn interval(&self) -> Nanos {
+        Nanos(self.interval.load(Ordering::Relaxed))
+    }
+
+    /// Records `value`, swapping intervals first if the active one is over.
+    #[inline]
+    pub fn record(&self, value: Nanos) {
+        self.swap_if_due();
+        let active = self.active.load(Ordering::Acquire);
+        self.slots[(active % SLOTS) as usize].record(value.0);
+    }
+
+    /// Moves on to the next interval if the active one is over. Readers call
+    /// it too, so intervals of a timer that stopped recording are still
+    /// published.
+    #[inline]
+    pub fn swap_if_due(&self) {
+        let now = Self::tsc();
+        let swap_at = self.swap_at.load(Ordering::Relaxed);
+        if now >= swap_at {
+            self.swap(swap_at, now);
+        }
+    }
+
+    #[cold]
+    fn swap(&self, swap_at: u64, now: u64) {
+        let next = now + Duration::from(self.interval()).0;
+        // Writers on other threads and readers race for the swap, one wins.
+        if self
+            .swap_at
+            .compare_exchange(swap_at, next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let active = self.active.load(Ordering::Relaxed);
+        let end = Instant::now().to_wall().0;
+
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.slots[((active + 1) % SLOTS) as usize].clear(end);
+        self.slots[(active % SLOTS) as usize].end.store(end, Ordering::Relaxed);
+        self.active.store(active + 1, Ordering::Release);
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+
+    /// Numbers of the finished intervals that can be read.
+    pub fn readable_intervals(&self) -> Range<u64> {
+        let active = self.active.load(Ordering::Acquire);
+        (active + 2).saturating_sub(SLOTS)..active.saturating_sub(1)
+    }
+
+    /// Copies interval `n` into `snapshot`, reusing its buckets. Returns
+    /// `false` if it isn't readable, see [`Self::readable_intervals`].
+    ///
+    /// Gives up if the slots stay mid-swap, e.g. because the writer died
+    /// swapping them.
+    pub fn read_interval(&self, n: u64, snapshot: &mut HistogramSnapshot) -> bool {
+        for _ in 0..MAX_READ_ATTEMPTS {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            if !self.readable_intervals().contains(&n) {
+                return false;
+            }
+            self.slots[(n % SLOTS) as usize].copy_into(snapshot);
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Copy of the last published interval, `None` before the first one.
+    pub fn last_interval(&self) -> Option<HistogramSnapshot> {
+        let mut snapshot = HistogramSnapshot::default();
+        self.read_last_interval(&mut snapshot).then_some(snapshot)
+    }
+
+    /// Like [`Self::last_interval`] but reuses `snapshot`'s buckets. Returns
+    /// whether there was a published interval.
+    pub fn read_last_interval(&self, snapshot: &mut HistogramSnapshot) -> bool {
+        self.swap_if_due();
+        let readable = self.readable_intervals();
+        !readable.is_empty() && self.read_interval(readable.end - 1, snapshot)
+    }
+}
+
+/// Reads every interval of an [`IntervalHistogram`] once, in order.
+#[derive(Clone, Debug, Default)]
+pub struct IntervalCursor {
+    next: u64,
+    /// Intervals that were recycled before they were read.
+    pub missed: u64,
+    snapshot: HistogramSnapshot,
+}
+
+impl IntervalCursor {
+    /// Reads the next interval not seen yet, returning `None` if there is
+    /// none. Intervals that already left the ring are counted in
+    /// [`Self::missed`].
+    pub fn next(&mut self, histogram: &IntervalHistogram) -> Option<&HistogramSnapshot> {
+        histogram.swap_if_due();
+        loop {
+            let readable = histogram.readable_intervals();
+            if self.next < readable.start {
+                self.missed += readable.start - self.next;
+                self.next = readable.start;
+            }
+            if self.next >= readable.end {
+                return None;
+            }
+            if histogram.read_interval(self.next, &mut self.snapshot) {
+                self.next += 1;
+                return Some(&self.snapshot);
+            }
+            // Either recycled while reading it, or the slots are stuck
+            // mid-swap.
+            if histogram.readable_intervals().contains(&self.next) {
+                return None;
+            }
+        }
+    }
+
+    /// The interval read last.
+    pub fn last(&self) -> &HistogramSnapshot {
+        &self.snapshot
+    }
+}
+
+/// A copy of one finished interval.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    pub start: Nanos,
+    pub end: Nanos,
+    pub count: u64,
+    /// Sum of all values in nanos.
+    pub sum: u64,
+    pub min: u64,
+    pub max: u64,
+    buckets: Box<[u64]>,
+}
+
+impl Default for HistogramSnapshot {
+    fn default() -> Self {
+        Self {
+            start: Nanos(0),
+            end: Nanos(0),
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+            buckets: vec![0; N_BUCKETS].into_boxed_slice(),
+        }
+    }
+}
+
+impl HistogramSnapshot {
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn mean(&self) -> Nanos {
+        Nanos(self.sum.checked_div(self.count).unwrap_or_default())
+    }
+
+    /// Smallest value that `quantile` (0.0..=1.0) of all values are at or
+    /// below, to bucket precision. Zero if the interval is empty.
+    pub fn value_at_quantile(&self, quantile: f64) -> Nanos {
+        if self.count == 0 {
+            return Nanos(0);
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let (_, high) = bucket_range(i);
+                return Nanos(high.clamp(self.min, self.max));
+            }
+        }
+        Nanos(self.max)
+    }
+
+    /// Messages per second over the interval.
+    pub fn rate(&self) -> f64 {
+        let secs = self.end.saturating_sub(self.start).as_secs();
+        if secs > 0.0 { self.count as f64 / secs } else { 0.0 }
+    }
+
+    /// Non-empty buckets as `(lowest value, highest value, count)`.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.buckets.iter().enumerate().filter(|(_, n)| **n != 0).map(|(i, n)| {
+            let (low, high) = bucket_range(i);
+            (low, high, *n)
+        })
+    }
+}
+
+#[derive(Debug)]
+#[repr(C, align(64))]
+pub struct HistogramHeader {
+    pub is_initialized: AtomicU8,
+    pub identity: TypeIdentity,
+}
+
+/// Shared-memory segment of one [`crate::Timer`]: a histogram each for its
+/// processing times and its latencies.
+#[repr(C, align(64))]
+pub struct InnerTimerHistograms {
+    header: HistogramHeader,
+    pub processing: IntervalHistogram,
+    pub latency: IntervalHistogram,
+}
+
+impl InnerTimerHistograms {
+    fn is_initialized(&self) -> bool {
+        self.header.is_initialized.load(Ordering::Acquire) != 0
+    }
+
+    fn verify(&self) -> Result<(), QueueError> {
+        if !self.is_initialized() {
+            return Err(QueueError::UnInitialized);
+        }
+        self.header.identity.verify(&TypeIdentity::unhashed::<Self>())
+    }
+}
+
+/// Handle to the histograms of a timer, see [`InnerTimerHistograms`].
+#[derive(Clone, Copy, Debug)]
+pub struct TimerHistograms {
+    inner: *const InnerTimerHistograms,
+}
+
+unsafe impl Send for TimerHistograms {}
+unsafe impl Sync for TimerHistograms {}
+
+impl TimerHistograms {
+    /// Creates or opens the histograms at `shmem_flink`, swapped every
+    /// `interval`. Opening an existing segment switches it to `interval`.
+    ///
+    /// Type mismatches are returned to the caller, any other issue with a
+    /// preexisting segment removes and recreates it.
+    pub fn create_or_open_shared<P: AsRef<Path>>(
+        shmem_flink: P,
+        interval: Nanos,
+    ) -> Result<Self, QueueError> {
+        assert!(interval.0 > 0, "histogram interval must be > 0");
+        let flink = shmem_flink.as_ref();
+        let size = size_of::<InnerTimerHistograms>();
+        let (ptr, is_new, mapped_size) =
+            shmem_map_create_or_open(flink, size, MappingOptions::default());
+        #[allow(clippy::cast_ptr_alignment)]
+        let inner = ptr.cast::<InnerTimerHistograms>().cast_const();
+        let histograms = unsafe { &*inner };
+
+        if is_new {
+            histograms.processing.init(interval);
+            histograms.latency.init(interval);
+            unsafe {
+                std::ptr::addr_of!((*inner).header.identity)
+                    .cast_mut()
+                    .write(TypeIdentity::unhashed::<InnerTimerHistograms>());
+            }
+            histograms.header.is_initialized.store(1, Ordering::Release);
+            return Ok(Self { inner });
+        }
+
+        match (mapped_size >= size).then(|| histograms.verify()) {
+            Some(Ok(())) => {
+                histograms.processing.interval.store(interval.0, Ordering::Relaxed);
+                histograms.latency.interval.store(interval.0, Ordering::Relaxed);
+                Ok(Self { inner })
+            }
+            Some(Err(e @ QueueError::TypeMismatch { .. })) => Err(e),
+            _ => {
+                tracing::warn!("invalid histograms at {flink:?}, removing and recreating");
+                crate::memfd::remove_segment(flink);
+                Self::create_or_open_shared(flink, interval)
+            }
+        }
+    }
+
+    /// Opens existing histograms for reading.
+    pub fn open_shared<P: AsRef<Path>>(shmem_flink: P) -> Result<Self, QueueError> {
+        let flink = shmem_flink.as_ref();
+        if !flink.exists() {
+            return Err(QueueError::NonExistingFile);
+        }
+        let shmem = ShmemConf::new().flink(flink).open()?;
+        if shmem.len() < size_of::<InnerTimerHistograms>() {
+            return Err(QueueError::TooSmall);
+        }
+        #[allow(clippy::cast_ptr_alignment)]
+        let inner = shmem.as_ptr().cast::<InnerTimerHistograms>().cast_const();
+        std::mem::forget(shmem);
+        unsafe { (*inner).verify()? };
+        Ok(Self { inner })
+    }
+}
+
+impl std::ops::Deref for TimerHistograms {
+    type Target = InnerTimerHistograms;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_values_within_precision() {
+        let mut last = 0;
+        for value in (0..100_000).chain([1 << 20, (1 << 30) + 12_345, 1 << 41]) {
+            let index = bucket_index(value);
+            assert!(index >= last, "bucket index went backwards at {value}");
+            last = index;
+            let (low, high) = bucket_range(index);
+            assert!((low..=high).contains(&value), "{value} not in {low}..={high}");
+            assert!((high - low) * SUB_BUCKETS as u64 <= value.max(1), "{value} too coarse");
+        }
+        assert_eq!(bucket_index(u64::MAX), N_BUCKETS - 1);
+        assert_eq!(bucket_range(N_BUCKETS - 1).1, (1 << (MAX_MAGNITUDE + 1)) - 1);
+    }
+
+    #[test]
+    fn swaps_out_exact_intervals() {
+        let flink = Path::new("/dev/shm/flux_test_histograms");
+        let _ = std::fs::remove_file(flink);
+        let histograms =
+            TimerHistograms::create_or_open_shared(flink, Nanos::from_millis(20)).unwrap();
+        assert!(histograms.latency.last_interval().is_none());
+
+        for value in 1..=1000 {
+            histograms.latency.record(Nanos(value * 1000));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(25));
+        histograms.latency.record(Nanos(5));
+
+        // Published once the next interval is over too, even without records.
+        let reader = TimerHistograms::open_shared(flink).unwrap();
+        assert!(reader.latency.last_interval().is_none());
+        std::thread::sleep(std::time::Duration::from_millis(25));
+        let interval = reader.latency.last_interval().unwrap();
+        assert_eq!(interval.count, 1000);
+        assert_eq!(interval.min, 1000);
+        assert_eq!(interval.max, 1_000_000);
+        assert_eq!(interval.mean(), Nanos(500_500));
+        let p99 = interval.value_at_quantile(0.99).0;
+        assert!((990_000..=990_000 + 990_000 / 32).contains(&p99), "p99 {p99}");
+        assert_eq!(interval.value_at_quantile(1.0), Nanos(1_000_000));
+        assert!(interval.end > interval.start);
+        assert!(reader.processing.last_interval().is_none());
+        let _ = std::fs::remove_file(flink);
+    }
+
+    #[test]
+    fn cursor_reads_every_retained_interval() {
+        let flink = Path::new("/dev/shm/flux_test_histograms_cursor");
+        let _ = std::fs::remove_file(flink);
+        let histograms =
+            TimerHistograms::create_or_open_shared(flink, Nanos::from_millis(10)).unwrap();
+        let mut cursor = IntervalCursor::default();
+        assert!(cursor.next(&histograms.latency).is_none());
+
+        // One record per interval, read in one go after a few of them.
+        for value in 1..=4 {
+            histograms.latency.record(Nanos(value));
+            std::thread::sleep(std::time::Duration::from_millis(12));
+        }
+        histograms.latency.swap_if_due();
+        std::thread::sleep(std::time::Duration::from_millis(12));
+        let mut maxes = Vec::new();
+        while let Some(interval) = cursor.next(&histograms.latency) {
+            maxes.push(interval.max);
+        }
+        assert_eq!(maxes, [1, 2, 3, 4]);
+        assert_eq!(cursor.missed, 0);
+
+        // Readers that fall behind the ring skip what was recycled.
+        for _ in 0..SLOTS {
+            histograms.latency.record(Nanos(1));
+            std::thread::sleep(std::time::Duration::from_millis(12));
+        }
+        while cursor.next(&histograms.latency).is_some() {}
+        assert!(cursor.missed > 0);
+        let _ = std::fs::remove_file(flink);
+    }
+}
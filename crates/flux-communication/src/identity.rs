@@ -0,0 +1,80 @@
+use flux_utils::{SHORT_TYPENAME_CAP, ShortTypename, short_typename};
+use type_hash::TypeHash;
+
+use crate::error::QueueError;
+
+/// Layout version of the shared-memory headers (`QueueHeader`, `ArrayHeader`).
+/// Bump whenever a field is added, removed or moved.
+pub const HEADER_VERSION: u32 = 4;
+
+/// Identity of the element type stored in a shared-memory segment.
+///
+/// Written once at creation and verified by every process that opens the
+/// segment, so two binaries that disagree on the layout of `T` cannot silently
+/// reinterpret each other's bytes even when `size_of::<T>()` matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct TypeIdentity {
+    /// `TypeHash::TYPE_HASH` of the element, or 0 if the creator did not
+    /// provide one.
+    pub type_hash: u64,
+    pub align: u32,
+    pub header_version: u32,
+    pub type_name: ShortTypename,
+}
+
+impl TypeIdentity {
+    /// Identity of `T` including its `TypeHash::TYPE_HASH`. This is what every
+    /// shared-memory creator and opener records and verifies.
+    pub fn of<T: TypeHash>() -> Self {
+        Self { type_hash: T::TYPE_HASH, ..Self::unhashed::<T>() }
+    }
+
+    /// Identity of `T` without a type hash, for element types that cannot
+    /// implement `TypeHash`. Only the name and alignment are verified against
+    /// it, so layout changes that keep both are not detected.
+    pub fn unhashed<T>() -> Self {
+        Self {
+            type_hash: 0,
+            align: align_of::<T>() as u32,
+            header_version: HEADER_VERSION,
+            type_name: short_typename::<T>(),
+        }
+    }
+
+    /// Whether the stored bytes look like a header of the current layout.
+    ///
+    /// Segments created by older flux versions have unrelated data at this
+    /// offset, so everything else must be ignored when this returns `false`.
+    pub fn is_current(&self) -> bool {
+        self.header_version == HEADER_VERSION && self.type_name.len() <= SHORT_TYPENAME_CAP
+    }
+
+    /// Verify that `self`, as read from shared memory, matches the identity
+    /// the caller expects. Type hashes are only compared when both sides
+    /// recorded one.
+    pub fn verify(&self, expected: &Self) -> Result<(), QueueError> {
+        if !self.is_current() {
+            return Err(QueueError::HeaderVersionChanged(self.header_version, HEADER_VERSION));
+        }
+        let hash_differs =
+            self.type_hash != 0 && expected.type_hash != 0 && self.type_hash != expected.type_hash;
+        if hash_differs || self.align != expected.align || self.type_name != expected.type_name {
+            return Err(QueueError::TypeMismatch { stored: *self, expected: *expected });
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for TypeIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.is_current() {
+            return write!(f, "<unknown, header v{}>", self.header_version);
+        }
+        write!(f, "{} (align {}", self.type_name, self.align)?;
+        if self.type_hash != 0 {
+            write!(f, ", hash {:#018x}", self.type_hash)?;
+        }
+        write!(f, ", header v{})", self.header_version)
+    }
+}
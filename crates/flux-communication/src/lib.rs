@@ -1,28 +1,44 @@
 pub mod array;
+pub mod budget;
+pub mod calibration;
 pub mod cleanup;
 mod error;
+pub mod histogram;
+mod identity;
+pub mod map;
+mod mapping;
+pub mod memfd;
 #[cfg(feature = "park")]
 pub mod park;
 pub mod queue;
 mod seqlock;
 mod shmem_data;
 pub mod timer;
+mod wait;
 
 use std::path::Path;
 
 pub use array::SeqlockArray;
+pub use budget::{BudgetSnapshot, LatencyBudget};
+pub use calibration::{SharedTscCalibration, attach_tsc_calibration, attached_tsc_calibration};
 pub use cleanup::{cleanup_flink, cleanup_shmem, is_pid_alive};
-pub use error::{EmptyError, QueueError, ReadError};
+pub use error::{EmptyError, FullError, QueueError, ReadError, TimeoutError, TooLargeError};
 use flux_utils::{
     DCache, DCachePtr,
     directories::{
-        local_share_dir, shmem_dir_arrays_with_base, shmem_dir_queues, shmem_dir_queues_with_base,
+        local_share_dir, shmem_dir_arrays_with_base, shmem_dir_conflated_with_base,
+        shmem_dir_maps_with_base, shmem_dir_queues, shmem_dir_queues_with_base,
+        shmem_dir_varlen_with_base,
     },
     short_typename,
 };
+pub use identity::{HEADER_VERSION, TypeIdentity};
+pub use map::SeqlockMap;
+pub use mapping::{HUGE_PAGE_SIZE, MappingOptions};
 pub use seqlock::Seqlock;
 pub use shmem_data::ShmemData;
 pub use timer::{Timer, TimingMessage};
+use type_hash::TypeHash;
 
 /// Classification of shared-memory segment types.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -33,6 +49,7 @@ pub enum ShmemKind {
     Queue = 1,
     Data = 2,
     SeqlockArray = 3,
+    SeqlockMap = 4,
 }
 
 impl ShmemKind {
@@ -43,6 +60,7 @@ impl ShmemKind {
             Self::Queue => "queue",
             Self::Data => "data",
             Self::SeqlockArray => "seqlockarray",
+            Self::SeqlockMap => "seqlockmap",
         }
     }
 }
@@ -54,6 +72,7 @@ impl std::fmt::Display for ShmemKind {
             Self::Queue => write!(f, "Queue"),
             Self::Data => write!(f, "Data"),
             Self::SeqlockArray => write!(f, "SeqlockArray"),
+            Self::SeqlockMap => write!(f, "SeqlockMap"),
         }
     }
 }
@@ -70,27 +89,39 @@ pub fn shmem_dir_queues_string_with_base<D: AsRef<Path>, S: AsRef<Path>>(
     shmem_dir_queues_with_base(base_dir, app_name).to_string_lossy().to_string()
 }
 
-pub fn shmem_queue<S: AsRef<Path>, T: Copy>(
+pub fn shmem_queue<S: AsRef<Path>, T: Copy + TypeHash>(
     app_name: S,
     len: usize,
     typ: queue::QueueType,
-) -> queue::Queue<T> {
+) -> Result<queue::Queue<T>, error::QueueError> {
     shmem_queue_with_base_dir(local_share_dir(), app_name, len, typ)
 }
 
-pub fn shmem_queue_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy>(
+pub fn shmem_queue_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy + TypeHash>(
     base_dir: D,
     app_name: S,
     len: usize,
     typ: queue::QueueType,
-) -> queue::Queue<T> {
+) -> Result<queue::Queue<T>, error::QueueError> {
+    shmem_queue_with_base_dir_and_options(base_dir, app_name, len, typ, MappingOptions::default())
+}
+
+/// Like [`shmem_queue_with_base_dir`] but backs the segment according to
+/// `options` (huge pages, prefaulting, `mlock`).
+pub fn shmem_queue_with_base_dir_and_options<D: AsRef<Path>, S: AsRef<Path>, T: Copy + TypeHash>(
+    base_dir: D,
+    app_name: S,
+    len: usize,
+    typ: queue::QueueType,
+    options: MappingOptions,
+) -> Result<queue::Queue<T>, error::QueueError> {
     let queue_name = short_typename::<T>();
     let flink_path = shmem_dir_queues_with_base(&base_dir, &app_name).join(queue_name.as_str());
-    let q = queue::Queue::create_or_open_shared(&flink_path, len, typ);
+    let q = queue::Queue::create_or_open_shared_with_options(&flink_path, len, typ, options)?;
     // Spine queues feed tiles that may be parked; set unconditionally since
     // another process may have created the queue without the flag.
     q.set_signal_on_produce(true);
-    q
+    Ok(q)
 }
 
 /// Allocates a spine queue and its dcache contiguously in a single shmem
@@ -100,17 +131,71 @@ pub fn shmem_queue_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy>(
 ///
 /// `mtu` is the maximum payload size in bytes; dcache capacity is derived as
 /// `DCache::required_capacity(queue_len, mtu).next_power_of_two()`.
+///
+/// Fails with [`QueueError::TypeMismatch`] if the existing queue was created
+/// for a different element type.
 pub fn shmem_queue_dcache_with_base_dir<D, S, T>(
     base_dir: D,
     app_name: S,
     queue_len: usize,
     mtu: usize,
     typ: queue::QueueType,
-) -> (queue::Queue<T>, DCachePtr)
+) -> Result<(queue::Queue<T>, DCachePtr), error::QueueError>
+where
+    D: AsRef<Path>,
+    S: AsRef<Path>,
+    T: Copy + TypeHash,
+{
+    shmem_queue_dcache_with_base_dir_and_options(
+        base_dir,
+        app_name,
+        queue_len,
+        mtu,
+        typ,
+        MappingOptions::default(),
+    )
+}
+
+/// Like [`shmem_queue_dcache_with_base_dir`] but backs the region according
+/// to `options` (huge pages, prefaulting, `mlock`).
+pub fn shmem_queue_dcache_with_base_dir_and_options<D, S, T>(
+    base_dir: D,
+    app_name: S,
+    queue_len: usize,
+    mtu: usize,
+    typ: queue::QueueType,
+    options: MappingOptions,
+) -> Result<(queue::Queue<T>, DCachePtr), error::QueueError>
 where
     D: AsRef<Path>,
     S: AsRef<Path>,
-    T: Copy,
+    T: Copy + TypeHash,
+{
+    shmem_queue_dcache_with_base_dir_and_overflow(
+        base_dir, app_name, queue_len, mtu, 0, typ, options,
+    )
+}
+
+/// Like [`shmem_queue_dcache_with_base_dir_and_options`] with an overflow
+/// region for the payloads larger than `mtu`.
+///
+/// The region holds `overflow.next_power_of_two()` bytes after the dcache
+/// (see [`DCachePtr::write_payload`]), there is none if `overflow` is 0.
+///
+/// Layout: `[queue header | queue seqlocks | dcache | overflow]`
+pub fn shmem_queue_dcache_with_base_dir_and_overflow<D, S, T>(
+    base_dir: D,
+    app_name: S,
+    queue_len: usize,
+    mtu: usize,
+    overflow: usize,
+    typ: queue::QueueType,
+    options: MappingOptions,
+) -> Result<(queue::Queue<T>, DCachePtr), error::QueueError>
+where
+    D: AsRef<Path>,
+    S: AsRef<Path>,
+    T: Copy + TypeHash,
 {
     assert!(mtu > 0, "mtu must be > 0");
 
@@ -121,18 +206,22 @@ where
     let queue_bytes = queue::Queue::<T>::byte_size(real_len);
     let queue_bytes_aligned = (queue_bytes + 63) & !63;
     let dcache_cap = DCache::required_capacity(real_len, mtu).next_power_of_two();
+    let overflow_cap = if overflow == 0 { 0 } else { overflow.next_power_of_two().max(64) };
     // 64: DCache fixed prefix (reserved + cacheline pad) preceding the data slice.
-    let total = queue_bytes_aligned + 64 + dcache_cap;
+    let overflow_start = queue_bytes_aligned + 64 + dcache_cap;
+    let total = if overflow_cap == 0 { overflow_start } else { overflow_start + 64 + overflow_cap };
 
-    let (ptr, is_new, mapped_size) = queue::shmem_map_create_or_open(&flink_path, total);
+    let (ptr, is_new, mapped_size) = queue::shmem_map_create_or_open(&flink_path, total, options);
 
     if !is_new && mapped_size < total {
         tracing::error!(
             "shmem at {flink_path:?} is too small ({mapped_size} < {total}); \
-             mtu or queue_len changed — removing and recreating.",
+             mtu, overflow or queue_len changed — removing and recreating.",
+        );
+        memfd::remove_segment(&flink_path);
+        return shmem_queue_dcache_with_base_dir_and_overflow(
+            base_dir, app_name, queue_len, mtu, overflow, typ, options,
         );
-        let _ = std::fs::remove_file(&flink_path);
-        return shmem_queue_dcache_with_base_dir(base_dir, app_name, queue_len, mtu, typ);
     }
 
     let q = if is_new {
@@ -146,13 +235,18 @@ where
                      queue_len changed — removing and recreating.",
                     q.n_slots()
                 );
-                let _ = std::fs::remove_file(&flink_path);
-                return shmem_queue_dcache_with_base_dir(base_dir, app_name, queue_len, mtu, typ);
+                memfd::remove_segment(&flink_path);
+                return shmem_queue_dcache_with_base_dir_and_overflow(
+                    base_dir, app_name, queue_len, mtu, overflow, typ, options,
+                );
             }
+            Err(e @ error::QueueError::TypeMismatch { .. }) => return Err(e),
             Err(e) => {
                 tracing::error!("invalid queue at {:?}: {e}. Removing and recreating.", flink_path);
-                let _ = std::fs::remove_file(&flink_path);
-                return shmem_queue_dcache_with_base_dir(base_dir, app_name, queue_len, mtu, typ);
+                memfd::remove_segment(&flink_path);
+                return shmem_queue_dcache_with_base_dir_and_overflow(
+                    base_dir, app_name, queue_len, mtu, overflow, typ, options,
+                );
             }
         }
     };
@@ -160,19 +254,23 @@ where
     q.set_signal_on_produce(true);
 
     let dcache_ptr = unsafe { ptr.add(queue_bytes_aligned) };
-    let dc = unsafe { DCachePtr::from_raw(DCache::from_ptr(dcache_ptr, dcache_cap)) };
+    let mut dc = unsafe { DCachePtr::from_raw(DCache::from_ptr(dcache_ptr, dcache_cap)) };
+    if overflow_cap != 0 {
+        let overflow_ptr = unsafe { ptr.add(overflow_start) };
+        dc = unsafe { dc.with_overflow(DCache::from_ptr(overflow_ptr, overflow_cap), mtu) };
+    }
 
-    (q, dc)
+    Ok((q, dc))
 }
 
-pub fn shmem_array<S: AsRef<Path>, T: Copy>(
+pub fn shmem_array<S: AsRef<Path>, T: Copy + TypeHash>(
     app_name: S,
     len: usize,
 ) -> Result<SeqlockArray<T>, error::QueueError> {
     shmem_array_with_base_dir(local_share_dir(), app_name, len)
 }
 
-pub fn shmem_array_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy>(
+pub fn shmem_array_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy + TypeHash>(
     base_dir: D,
     app_name: S,
     len: usize,
@@ -182,3 +280,94 @@ pub fn shmem_array_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy>(
     let arr = SeqlockArray::create_or_open_shared(&flink_path, len)?;
     Ok(arr)
 }
+
+pub fn shmem_map<S: AsRef<Path>, K, V>(
+    app_name: S,
+    capacity: usize,
+) -> Result<SeqlockMap<K, V>, error::QueueError>
+where
+    K: Copy + Eq + std::hash::Hash + TypeHash,
+    V: Copy + TypeHash,
+{
+    shmem_map_with_base_dir(local_share_dir(), app_name, capacity)
+}
+
+/// Creates or opens the map of `K` to `V` under `shmem/maps/`, named after
+/// its slot type (`MapSlot<K, V>`).
+pub fn shmem_map_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, K, V>(
+    base_dir: D,
+    app_name: S,
+    capacity: usize,
+) -> Result<SeqlockMap<K, V>, error::QueueError>
+where
+    K: Copy + Eq + std::hash::Hash + TypeHash,
+    V: Copy + TypeHash,
+{
+    let type_name = short_typename::<map::MapSlot<K, V>>();
+    let flink_path = shmem_dir_maps_with_base(&base_dir, &app_name).join(type_name.as_str());
+    SeqlockMap::create_or_open_shared(&flink_path, capacity)
+}
+
+pub fn shmem_varlen_queue<S: AsRef<Path>, T: Copy + TypeHash>(
+    app_name: S,
+    capacity: usize,
+) -> Result<queue::VarlenQueue<T>, error::QueueError> {
+    shmem_varlen_queue_with_base_dir(local_share_dir(), app_name, capacity)
+}
+
+/// Creates or opens the variable-length queue of `T` under `shmem/varlen/`,
+/// with a ring of `capacity.next_power_of_two()` bytes.
+pub fn shmem_varlen_queue_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy + TypeHash>(
+    base_dir: D,
+    app_name: S,
+    capacity: usize,
+) -> Result<queue::VarlenQueue<T>, error::QueueError> {
+    shmem_varlen_queue_with_base_dir_and_options(
+        base_dir,
+        app_name,
+        capacity,
+        MappingOptions::default(),
+    )
+}
+
+/// Like [`shmem_varlen_queue_with_base_dir`] but backs the ring according to
+/// `options` (huge pages, prefaulting, `mlock`).
+pub fn shmem_varlen_queue_with_base_dir_and_options<
+    D: AsRef<Path>,
+    S: AsRef<Path>,
+    T: Copy + TypeHash,
+>(
+    base_dir: D,
+    app_name: S,
+    capacity: usize,
+    options: MappingOptions,
+) -> Result<queue::VarlenQueue<T>, error::QueueError> {
+    let queue_name = short_typename::<T>();
+    let flink_path = shmem_dir_varlen_with_base(&base_dir, &app_name).join(queue_name.as_str());
+    let q = queue::VarlenQueue::create_or_open_shared_with_options(&flink_path, capacity, options)?;
+    q.set_signal_on_produce(true);
+    Ok(q)
+}
+
+pub fn shmem_conflating_queue<S: AsRef<Path>, T: Copy + TypeHash>(
+    app_name: S,
+    keys: usize,
+    key: fn(&T) -> u64,
+) -> Result<queue::ConflatingQueue<T>, error::QueueError> {
+    shmem_conflating_queue_with_base_dir(local_share_dir(), app_name, keys, key)
+}
+
+/// Creates or opens the conflating queue of `T` under `shmem/conflated/`,
+/// with room for `keys.next_power_of_two()` keys.
+pub fn shmem_conflating_queue_with_base_dir<D: AsRef<Path>, S: AsRef<Path>, T: Copy + TypeHash>(
+    base_dir: D,
+    app_name: S,
+    keys: usize,
+    key: fn(&T) -> u64,
+) -> Result<queue::ConflatingQueue<T>, error::QueueError> {
+    let queue_name = short_typename::<T>();
+    let flink_path = shmem_dir_conflated_with_base(&base_dir, &app_name).join(queue_name.as_str());
+    let mut q = queue::ConflatingQueue::create_or_open_shared(&flink_path, keys, key)?;
+    q.set_signal_on_produce(true);
+    Ok(q)
+}
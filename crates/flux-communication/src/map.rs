@@ -0,0 +1,382 @@
+use std::{
+    hash::{Hash, Hasher},
+    hint::spin_loop,
+    path::Path,
+};
+
+use rustc_hash::FxHasher;
+use type_hash::TypeHash;
+use type_hash_derive::TypeHash;
+
+use crate::{
+    SeqlockArray,
+    array::Changes,
+    error::{FullError, QueueError},
+};
+
+/// Slot of a [`SeqlockMap`].
+///
+/// A slot is bound to the first key inserted into it for the lifetime of the
+/// segment. Removing the key leaves a tombstone (`live == false`) that only a
+/// later insert of the same key revives, so a key's slot index never changes
+/// and readers can cache it.
+#[derive(Clone, Copy, Debug, TypeHash)]
+#[repr(C)]
+pub struct MapSlot<K, V> {
+    pub key: K,
+    pub value: V,
+    pub live: bool,
+}
+
+/// Fixed-capacity open-addressing hash map of seqlocked slots, usable in
+/// shared memory.
+///
+/// Reads are lock-free seqlock reads. Slots are claimed with a CAS on their
+/// seqlock version, so concurrent inserts of new keys are safe from any
+/// number of writers; updating an existing key uses [`Self::insert`] with a
+/// single writer per key or [`Self::insert_multi_producer`] otherwise.
+///
+/// Keys are hashed with `FxHasher`, which is unseeded, so every process
+/// probes the same slots for the same key.
+#[derive(Clone, Copy, Debug)]
+pub struct SeqlockMap<K, V> {
+    slots: SeqlockArray<MapSlot<K, V>>,
+    mask: usize,
+}
+
+impl<K: Copy + Eq + Hash, V: Copy> SeqlockMap<K, V> {
+    /// Heap-backed map with room for `capacity.next_power_of_two()` keys.
+    pub fn new(capacity: usize) -> Self {
+        Self::from_slots(SeqlockArray::new(capacity.next_power_of_two()))
+    }
+
+    /// Like [`Self::new`] for a map that logs its writes, see
+    /// [`Self::changed_since`].
+    pub fn with_change_log(capacity: usize) -> Self {
+        Self::from_slots(SeqlockArray::with_change_log(capacity.next_power_of_two()))
+    }
+
+    /// Fails with [`QueueError::TypeMismatch`] if the existing map was created
+    /// for different key or value types.
+    pub fn create_or_open_shared<P: AsRef<Path>>(
+        shmem_file: P,
+        capacity: usize,
+    ) -> Result<Self, QueueError>
+    where
+        K: TypeHash,
+        V: TypeHash,
+    {
+        let slots = SeqlockArray::create_or_open_shared(shmem_file, capacity.next_power_of_two())?;
+        Self::checked(slots)
+    }
+
+    /// Like [`Self::create_or_open_shared`] for a map that logs its writes,
+    /// see [`Self::changed_since`]. An existing map without a change log
+    /// fails with [`QueueError::NoChangeLog`].
+    pub fn create_or_open_shared_with_change_log<P: AsRef<Path>>(
+        shmem_file: P,
+        capacity: usize,
+    ) -> Result<Self, QueueError>
+    where
+        K: TypeHash,
+        V: TypeHash,
+    {
+        let slots = SeqlockArray::create_or_open_shared_with_change_log(
+            shmem_file,
+            capacity.next_power_of_two(),
+        )?;
+        Self::checked(slots)
+    }
+
+    pub fn open_shared<P: AsRef<Path>>(shmem_file: P) -> Result<Self, QueueError>
+    where
+        K: TypeHash,
+        V: TypeHash,
+    {
+        Self::checked(SeqlockArray::open_shared(shmem_file)?)
+    }
+
+    fn checked(slots: SeqlockArray<MapSlot<K, V>>) -> Result<Self, QueueError> {
+        if !slots.len().is_power_of_two() {
+            return Err(QueueError::LengthNotPowerOfTwo);
+        }
+        Ok(Self::from_slots(slots))
+    }
+
+    fn from_slots(slots: SeqlockArray<MapSlot<K, V>>) -> Self {
+        let mask = slots.len() - 1;
+        Self { slots, mask }
+    }
+
+    #[inline]
+    fn probe(&self, key: &K) -> impl Iterator<Item = usize> + use<K, V> {
+        let mut hasher = FxHasher::default();
+        key.hash(&mut hasher);
+        let start = hasher.finish() as usize;
+        let mask = self.mask;
+        (0..=mask).map(move |i| start.wrapping_add(i) & mask)
+    }
+
+    /// Slot index bound to `key`, live or tombstoned.
+    #[inline]
+    pub fn index_of(&self, key: &K) -> Option<usize> {
+        for pos in self.probe(key) {
+            loop {
+                match self.slots.read_copy(pos) {
+                    Ok((slot, _)) if slot.key == *key => return Some(pos),
+                    Ok(_) => break,
+                    // A never-written slot ends the probe sequence, one being
+                    // claimed right now has its key once the claim lands.
+                    Err(_) if self.slots.version(pos) == 0 => return None,
+                    Err(_) => spin_loop(),
+                }
+            }
+        }
+        None
+    }
+
+    #[inline]
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_with_version(key).map(|(v, _)| v)
+    }
+
+    /// Value of `key` together with its slot's seqlock version, which changes
+    /// on every write to the key.
+    #[inline]
+    pub fn get_with_version(&self, key: &K) -> Option<(V, u64)> {
+        let (slot, version) = self.slots.read_copy(self.index_of(key)?).ok()?;
+        slot.live.then_some((slot.value, version))
+    }
+
+    #[inline]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_with_version(key).is_some()
+    }
+
+    /// Insert or update `key`, returning its slot index. Use this if you are
+    /// the only writer of `key`.
+    pub fn insert(&self, key: K, value: V) -> Result<usize, FullError> {
+        self.insert_with(key, value, false)
+    }
+
+    /// Insert or update `key` when other writers may update it concurrently.
+    pub fn insert_multi_producer(&self, key: K, value: V) -> Result<usize, FullError> {
+        self.insert_with(key, value, true)
+    }
+
+    fn insert_with(&self, key: K, value: V, multi_producer: bool) -> Result<usize, FullError> {
+        let slot = MapSlot { key, value, live: true };
+        for pos in self.probe(&key) {
+            loop {
+                match self.slots.read_copy(pos) {
+                    Ok((existing, _)) if existing.key == key => {
+                        if multi_producer {
+                            self.slots.write_multi_producer(pos, &slot);
+                        } else {
+                            self.slots.write(pos, &slot);
+                        }
+                        return Ok(pos);
+                    }
+                    Ok(_) => break,
+                    // Unclaimed: try to take it, otherwise another writer is
+                    // claiming it right now and we recheck its key.
+                    Err(_) if self.slots.write_at_version(pos, &slot, 0) => return Ok(pos),
+                    Err(_) => spin_loop(),
+                }
+            }
+        }
+        Err(FullError::Full)
+    }
+
+    /// Tombstone `key`, returning its last value if it was live.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let pos = self.index_of(key)?;
+        loop {
+            let (slot, version) = self.slots.read_copy(pos).ok()?;
+            if !slot.live {
+                return None;
+            }
+            let tombstone = MapSlot { live: false, ..slot };
+            if self.slots.write_at_version(pos, &tombstone, version) {
+                return Some(slot.value);
+            }
+        }
+    }
+
+    /// Number of slots, i.e. the maximum number of distinct keys.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Number of live keys. Scans every slot.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Iterate live entries as `(key, value, version)`. Comparing versions
+    /// with a previous pass tells which keys changed since.
+    pub fn iter(&self) -> MapIterator<'_, K, V> {
+        MapIterator { map: self, next_id: 0 }
+    }
+
+    /// Number of writes to the map so far, see [`Self::changed_since`].
+    /// Always 0 for maps without a change log.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.slots.generation()
+    }
+
+    /// Slots written since `generation`, tombstones included. Maps created
+    /// without a change log yield every slot. See
+    /// [`InnerSeqlockArray::changed_since`](crate::array::InnerSeqlockArray::changed_since).
+    #[inline]
+    pub fn changed_since(&self, generation: u64) -> Changes<'_, MapSlot<K, V>> {
+        self.slots.changed_since(generation)
+    }
+
+    /// Drop every key, tombstones included. Only safe while no other process
+    /// is using the map.
+    pub fn clear(&self) {
+        self.slots.clear();
+    }
+}
+
+impl<'a, K: Copy + Eq + Hash, V: Copy> IntoIterator for &'a SeqlockMap<K, V> {
+    type IntoIter = MapIterator<'a, K, V>;
+    type Item = (K, V, u64);
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct MapIterator<'a, K, V> {
+    map: &'a SeqlockMap<K, V>,
+    next_id: usize,
+}
+
+impl<K: Copy + Eq + Hash, V: Copy> Iterator for MapIterator<'_, K, V> {
+    type Item = (K, V, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_id <= self.map.mask {
+            let pos = self.next_id;
+            self.next_id += 1;
+            if let Ok((slot, version)) = self.map.slots.read_copy(pos) &&
+                slot.live
+            {
+                return Some((slot.key, slot.value, version));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_get_remove() {
+        let map = SeqlockMap::<u64, u32>::new(6);
+        assert_eq!(map.capacity(), 8);
+        assert!(map.is_empty());
+
+        let idx = map.insert(7, 70).unwrap();
+        assert_eq!(map.get(&7), Some(70));
+        assert_eq!(map.get(&8), None);
+
+        let (_, v1) = map.get_with_version(&7).unwrap();
+        assert_eq!(map.insert(7, 71), Ok(idx));
+        let (value, v2) = map.get_with_version(&7).unwrap();
+        assert_eq!(value, 71);
+        assert!(v2 > v1);
+
+        assert_eq!(map.remove(&7), Some(71));
+        assert_eq!(map.remove(&7), None);
+        assert_eq!(map.get(&7), None);
+        assert_eq!(map.index_of(&7), Some(idx));
+
+        // The tombstone is revived in place.
+        assert_eq!(map.insert(7, 72), Ok(idx));
+        assert_eq!(map.get(&7), Some(72));
+    }
+
+    #[test]
+    fn full_and_iter() {
+        let map = SeqlockMap::<u32, u32>::new(4);
+        for k in 0..4 {
+            map.insert(k, k * 10).unwrap();
+        }
+        assert_eq!(map.insert(4, 40), Err(FullError::Full));
+        map.remove(&2);
+        // Tombstones keep their key, so the map stays full for new keys.
+        assert_eq!(map.insert(4, 40), Err(FullError::Full));
+
+        let mut entries: Vec<_> = map.iter().map(|(k, v, _)| (k, v)).collect();
+        entries.sort_unstable();
+        assert_eq!(entries, [(0, 0), (1, 10), (3, 30)]);
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn concurrent_inserts_claim_one_slot_per_key() {
+        let map = SeqlockMap::<u32, u32>::new(64);
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                s.spawn(move || {
+                    for k in 0..32 {
+                        map.insert_multi_producer(k, t).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(map.len(), 32);
+        for k in 0..32 {
+            assert!(map.get(&k).is_some_and(|v| v < 4));
+        }
+    }
+
+    #[test]
+    fn lookup_waits_for_a_slot_being_claimed() {
+        use std::sync::atomic::Ordering;
+
+        let map = SeqlockMap::<u64, u32>::new(8);
+        let pos = map.probe(&7).next().unwrap();
+        let lock = map.slots.load(pos);
+        // Claimed but not yet written, as between the CAS and the store of
+        // `write_at_version`.
+        lock.set_version_unsafe(1);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                unsafe { *lock.data.get() = MapSlot { key: 7, value: 1, live: true } };
+                lock.version.store(2, Ordering::Release);
+            });
+            assert_eq!(map.index_of(&7), Some(pos));
+        });
+        assert_eq!(map.get(&7), Some(1));
+        assert_eq!(map.get(&8), None);
+    }
+
+    #[test]
+    fn shared() {
+        let path = std::path::Path::new("/dev/shm/seqlock_map_test");
+        let _ = std::fs::remove_file(path);
+        let map = SeqlockMap::<u64, u64>::create_or_open_shared(path, 16).unwrap();
+        map.insert(1, 100).unwrap();
+
+        let other = SeqlockMap::<u64, u64>::open_shared(path).unwrap();
+        assert_eq!(other.get(&1), Some(100));
+        assert!(matches!(
+            SeqlockMap::<u64, i64>::open_shared(path),
+            Err(QueueError::TypeMismatch { .. })
+        ));
+        let _ = std::fs::remove_file(path);
+    }
+}
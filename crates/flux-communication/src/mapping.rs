@@ -0,0 +1,251 @@
+use std::path::Path;
+
+/// Size of a PMD-level transparent huge page on `x86_64` and `aarch64` (4K
+/// base).
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+const PAGE_SIZE: usize = 4096;
+const SHMEM_THP_CONTROL: &str = "/sys/kernel/mm/transparent_hugepage/shmem_enabled";
+// From <linux/mempolicy.h>, not exported by libc.
+const MPOL_BIND: libc::c_int = 2;
+const MPOL_MF_STRICT: libc::c_uint = 1 << 0;
+const MPOL_MF_MOVE: libc::c_uint = 1 << 1;
+const MPOL_MF_MOVE_ALL: libc::c_uint = 1 << 2;
+const MAX_NUMA_NODES: usize = 1024;
+
+/// Page-level options applied to a shared-memory segment once it is mapped.
+///
+/// Segments are POSIX shm objects so that every process (and `flux-ctl`) can
+/// open them by name; the kernel backs those with huge pages through shmem
+/// THP, which must be enabled via `shmem_enabled` (`advise` or `always`).
+/// Huge pages are verified in `/proc/self/smaps` after the advice, so a
+/// request the kernel ignored is logged as an error. The segment keeps working
+/// with normal 4K pages whenever an option can't be honoured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default)]
+pub struct MappingOptions {
+    /// Back the segment with 2M huge pages. The segment size is rounded up to
+    /// a multiple of [`HUGE_PAGE_SIZE`] on creation.
+    pub huge_pages: bool,
+    /// Fault in every page right after mapping instead of on first touch.
+    pub prefault: bool,
+    /// `mlock` the mapping so its pages are never swapped or reclaimed.
+    pub mlock: bool,
+    /// Bind the segment's pages to this NUMA node. The policy lives on the
+    /// shm object, so it holds for every process mapping it. Pages already
+    /// faulted in by other processes can only be migrated with
+    /// `CAP_SYS_NICE`; without it binding such a segment fails and its
+    /// pages stay where they are.
+    pub numa_node: Option<u16>,
+}
+
+impl MappingOptions {
+    pub(crate) const fn segment_size(self, size: usize) -> usize {
+        if self.huge_pages { size.next_multiple_of(HUGE_PAGE_SIZE) } else { size }
+    }
+
+    /// Applies the options to a freshly mapped segment. Must run before the
+    /// creator touches the memory for huge pages to be used from the start.
+    pub(crate) fn apply(self, ptr: *mut u8, len: usize, path: &Path) {
+        if let Some(node) = self.numa_node &&
+            let Err(e) = bind_to_node(ptr, len, node)
+        {
+            tracing::warn!(
+                "couldn't bind {} to NUMA node {node}: {e}; using the default placement",
+                path.display()
+            );
+        }
+        let advised = self.huge_pages && {
+            let advised = advise_huge_pages(ptr, len);
+            if let Err(reason) = &advised {
+                tracing::error!(
+                    "huge pages requested but unavailable for {}: {reason}; using 4K pages",
+                    path.display()
+                );
+            }
+            advised.is_ok()
+        };
+        if self.prefault {
+            prefault(ptr, len);
+        }
+        if advised && let Err(reason) = verify_huge_pages(ptr, len) {
+            tracing::error!(
+                "huge pages requested but not applied to {}: {reason}; using 4K pages",
+                path.display()
+            );
+        }
+        if self.mlock &&
+            let Err(e) = mlock(ptr, len)
+        {
+            tracing::warn!(
+                "couldn't mlock {} ({len} bytes): {e}; check RLIMIT_MEMLOCK",
+                path.display()
+            );
+        }
+    }
+}
+
+fn advise_huge_pages(ptr: *mut u8, len: usize) -> Result<(), String> {
+    let control = std::fs::read_to_string(SHMEM_THP_CONTROL)
+        .map_err(|e| format!("can't read {SHMEM_THP_CONTROL}: {e}"))?;
+    let mode = selected_thp_mode(&control).unwrap_or("never");
+    if matches!(mode, "never" | "deny") {
+        return Err(format!("shmem THP is set to `{mode}` in {SHMEM_THP_CONTROL}"));
+    }
+    if unsafe { libc::madvise(ptr.cast(), len, libc::MADV_HUGEPAGE) } != 0 {
+        return Err(format!(
+            "madvise(MADV_HUGEPAGE): {} (shmem THP is `{mode}`)",
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the kernel backed the mapping at `ptr` with huge pages,
+/// faulting in its first huge page unless the segment was prefaulted.
+fn verify_huge_pages(ptr: *mut u8, len: usize) -> Result<(), String> {
+    let mode = std::fs::read_to_string(SHMEM_THP_CONTROL)
+        .ok()
+        .and_then(|control| selected_thp_mode(&control).map(str::to_owned))
+        .unwrap_or_else(|| "unknown".to_owned());
+    let start = ptr as usize;
+    let first_huge = start.next_multiple_of(HUGE_PAGE_SIZE);
+    if first_huge + HUGE_PAGE_SIZE > start + len {
+        return Err(format!("no {HUGE_PAGE_SIZE} byte aligned page in the mapping"));
+    }
+    // A read fault allocates the shmem page without modifying it.
+    unsafe { (first_huge as *const u8).read_volatile() };
+    let smaps = std::fs::read_to_string("/proc/self/smaps")
+        .map_err(|e| format!("can't read /proc/self/smaps: {e}"))?;
+    match pmd_mapped_kb(&smaps, start) {
+        Some(0) => Err(format!("no huge pages mapped (shmem THP is `{mode}`)")),
+        Some(_) => Ok(()),
+        None => Err(format!("mapping at {start:#x} not found in /proc/self/smaps")),
+    }
+}
+
+/// Kilobytes mapped with PMD-level pages in the `smaps` entry containing
+/// `addr`.
+fn pmd_mapped_kb(smaps: &str, addr: usize) -> Option<u64> {
+    let mut lines = smaps.lines();
+    lines.find(|line| {
+        let range = line.split_whitespace().next().unwrap_or_default();
+        range.split_once('-').is_some_and(|(lo, hi)| {
+            let parse = |s| usize::from_str_radix(s, 16).ok();
+            matches!((parse(lo), parse(hi)), (Some(lo), Some(hi)) if (lo..hi).contains(&addr))
+        })
+    })?;
+    let mut kb = 0;
+    for line in lines.take_while(|line| !line.starts_with("VmFlags")) {
+        let Some((key, value)) = line.split_once(':') else { break };
+        if matches!(key, "ShmemPmdMapped" | "FilePmdMapped" | "AnonHugePages") {
+            kb += value.trim().trim_end_matches("kB").trim().parse::<u64>().ok()?;
+        }
+    }
+    Some(kb)
+}
+
+/// The active mode is the bracketed one, e.g. `always within_size [advise]`.
+fn selected_thp_mode(control: &str) -> Option<&str> {
+    control.split_whitespace().find_map(|m| m.strip_prefix('[')?.strip_suffix(']'))
+}
+
+fn bind_to_node(ptr: *mut u8, len: usize, node: u16) -> std::io::Result<()> {
+    let node = usize::from(node);
+    if node >= MAX_NUMA_NODES {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+    }
+    let mut mask = [0 as libc::c_ulong; MAX_NUMA_NODES / libc::c_ulong::BITS as usize];
+    let bits = libc::c_ulong::BITS as usize;
+    mask[node / bits] |= 1 << (node % bits);
+    let mbind = |flags: libc::c_uint| {
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                ptr,
+                len,
+                MPOL_BIND,
+                mask.as_ptr(),
+                MAX_NUMA_NODES + 1,
+                flags,
+            )
+        };
+        if res == 0 { Ok(()) } else { Err(std::io::Error::last_os_error()) }
+    };
+    // Moving pages mapped by other processes needs CAP_SYS_NICE. Without it,
+    // MPOL_MF_MOVE skips them and MPOL_MF_STRICT turns that into EIO.
+    match mbind(MPOL_MF_MOVE_ALL | MPOL_MF_STRICT) {
+        Err(e) if e.raw_os_error() == Some(libc::EPERM) => mbind(MPOL_MF_MOVE | MPOL_MF_STRICT)
+            .map_err(|e| {
+                if e.raw_os_error() == Some(libc::EIO) {
+                    std::io::Error::other(
+                        "pages faulted in by other processes can't be migrated without \
+                         CAP_SYS_NICE",
+                    )
+                } else {
+                    e
+                }
+            }),
+        res => res,
+    }
+}
+
+fn mlock(ptr: *mut u8, len: usize) -> std::io::Result<()> {
+    if unsafe { libc::mlock(ptr.cast(), len) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn prefault(ptr: *mut u8, len: usize) {
+    // Populating for write allocates the backing pages without modifying
+    // them, so it's safe on segments other processes are already using.
+    if unsafe { libc::madvise(ptr.cast(), len, libc::MADV_POPULATE_WRITE) } == 0 {
+        return;
+    }
+    // Pre-5.14 kernels: read faults on shmem allocate the page as well.
+    for offset in (0..len).step_by(PAGE_SIZE) {
+        unsafe { ptr.add(offset).read_volatile() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_selected_thp_mode() {
+        assert_eq!(
+            selected_thp_mode("always within_size [advise] never deny force\n"),
+            Some("advise")
+        );
+        assert_eq!(selected_thp_mode("[never] always"), Some("never"));
+        assert_eq!(selected_thp_mode(""), None);
+    }
+
+    #[test]
+    fn parses_pmd_mapped_kb() {
+        let smaps = "\
+7f0000000000-7f0000400000 rw-s 00000000 00:01 12 /dev/shm/flux
+Size:               4096 kB
+AnonHugePages:         0 kB
+ShmemPmdMapped:     2048 kB
+FilePmdMapped:         0 kB
+VmFlags: rd wr sh mr mw me ms sd hg
+7f0000400000-7f0000401000 rw-p 00000000 00:00 0
+ShmemPmdMapped:        0 kB
+VmFlags: rd wr mr mw me ac
+";
+        assert_eq!(pmd_mapped_kb(smaps, 0x7f00_0020_0000), Some(2048));
+        assert_eq!(pmd_mapped_kb(smaps, 0x7f00_0040_0000), Some(0));
+        assert_eq!(pmd_mapped_kb(smaps, 0x7f00_0050_0000), None);
+    }
+
+    #[test]
+    fn huge_pages_round_segment_size() {
+        let huge = MappingOptions { huge_pages: true, ..Default::default() };
+        assert_eq!(huge.segment_size(1), HUGE_PAGE_SIZE);
+        assert_eq!(huge.segment_size(HUGE_PAGE_SIZE + 1), 2 * HUGE_PAGE_SIZE);
+        assert_eq!(MappingOptions::default().segment_size(12345), 12345);
+    }
+}
@@ -0,0 +1,553 @@
+//! memfd-backed segments for processes that can't see the shmem directory.
+//!
+//! Segments are normally POSIX shm objects found through the flinks under
+//! `<base>/<app>/shmem`, which a process in a restricted mount namespace
+//! can't reach. Instead, one process hosts the segments as anonymous
+//! `memfd_create` files and hands their file descriptors out by name over a
+//! Unix socket with `SCM_RIGHTS`:
+//!
+//! - The host calls [`serve_segments`] before creating its spine. Every segment
+//!   it creates from then on is a memfd, served on the socket at
+//!   [`segment_socket_with_base`], where `flux-ctl` finds them as well.
+//! - Peers call [`use_segment_broker`] with that socket before creating the
+//!   spine. Children spawned by the host can instead inherit a connection, see
+//!   [`child_connection`] and [`use_inherited_segment_broker`].
+//!
+//! Segments are named after their flink path without the base dir and the
+//! `shmem` component, e.g. `myapp/queues/Order`, so both sides agree on the
+//! names whatever their base dir.
+//!
+//! The backend is process-wide and can't be changed once set. Memfd
+//! segments live as long as the host process.
+
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::{
+            fs::PermissionsExt,
+            net::{UnixListener, UnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use flux_utils::directories::shmem_dir_with_base;
+
+/// File name of the broker socket inside an app's `shmem` directory.
+pub const SEGMENT_SOCKET: &str = "segments.sock";
+/// Environment variable holding the fd of an inherited broker connection.
+pub const SEGMENT_BROKER_FD_ENV: &str = "FLUX_SEGMENT_BROKER_FD";
+
+// memfd names are limited to 249 bytes, including the `flux:` prefix.
+const MAX_MEMFD_NAME: usize = 240;
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+enum Backend {
+    Host(Arc<SegmentBroker>),
+    Peer(Mutex<SegmentClient>),
+}
+
+/// Path of the broker socket for `app_name` under `base_dir`.
+pub fn segment_socket_with_base<D: AsRef<Path>, S: AsRef<Path>>(
+    base_dir: D,
+    app_name: S,
+) -> PathBuf {
+    shmem_dir_with_base(base_dir, app_name).join(SEGMENT_SOCKET)
+}
+
+/// Name the segment behind `flink` is served under.
+pub fn segment_name(flink: &Path) -> String {
+    let parts: Vec<_> = flink.iter().map(|p| p.to_string_lossy()).collect();
+    match parts.iter().rposition(|p| p == "shmem") {
+        Some(i) if i > 0 => std::iter::once(&parts[i - 1])
+            .chain(&parts[i + 1..])
+            .map(AsRef::as_ref)
+            .collect::<Vec<&str>>()
+            .join("/"),
+        _ => flink.to_string_lossy().into_owned(),
+    }
+}
+
+/// Makes this process the host of its segments: every segment created from
+/// now on is a memfd, served to peers on `socket`.
+///
+/// The socket is only accessible to the owner, and connections from
+/// processes running under another effective uid are refused.
+pub fn serve_segments<P: AsRef<Path>>(socket: P) -> io::Result<()> {
+    let socket = socket.as_ref();
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // A socket left behind by a previous host.
+    let _ = std::fs::remove_file(socket);
+    let listener = UnixListener::bind(socket)?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+
+    let broker = Arc::new(SegmentBroker::default());
+    set_backend(Backend::Host(broker.clone()))?;
+    let euid = unsafe { libc::geteuid() };
+    std::thread::Builder::new().name("flux-segments".into()).spawn(move || {
+        for stream in listener.incoming() {
+            match stream.and_then(|s| peer_uid(&s).map(|uid| (s, uid))) {
+                Ok((stream, uid)) if uid == euid => broker.clone().serve_in_background(stream),
+                Ok((_, uid)) => {
+                    tracing::warn!("segment broker refused a connection from uid {uid}");
+                }
+                Err(e) => tracing::warn!("segment broker failed to accept a connection: {e}"),
+            }
+        }
+    })?;
+    Ok(())
+}
+
+/// Effective uid of the process on the other end of `stream`, as of when it
+/// connected.
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut cred).cast(),
+            &raw mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// Makes this process get its segments from the broker listening on
+/// `socket`.
+pub fn use_segment_broker<P: AsRef<Path>>(socket: P) -> io::Result<()> {
+    set_backend(Backend::Peer(Mutex::new(SegmentClient::connect(socket)?)))
+}
+
+/// Like [`use_segment_broker`] over the connection inherited through
+/// [`SEGMENT_BROKER_FD_ENV`]. Returns `false` if there is none.
+pub fn use_inherited_segment_broker() -> io::Result<bool> {
+    let Ok(fd) = std::env::var(SEGMENT_BROKER_FD_ENV) else {
+        return Ok(false);
+    };
+    let fd: RawFd = fd.parse().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{SEGMENT_BROKER_FD_ENV}={fd}"))
+    })?;
+    // Safety: the parent handed this fd over for exactly this purpose.
+    let client = SegmentClient::from_fd(unsafe { OwnedFd::from_raw_fd(fd) });
+    set_backend(Backend::Peer(Mutex::new(client)))?;
+    Ok(true)
+}
+
+/// A new broker connection for a child process, on the host. The fd is
+/// inherited across `exec`; pass its number to the child in
+/// [`SEGMENT_BROKER_FD_ENV`] and close it in the parent once spawned.
+pub fn child_connection() -> io::Result<OwnedFd> {
+    let Some(Backend::Host(broker)) = BACKEND.get() else {
+        return Err(io::Error::other("this process doesn't serve segments"));
+    };
+    let (ours, theirs) = UnixStream::pair()?;
+    broker.clone().serve_in_background(ours);
+    let fd = OwnedFd::from(theirs);
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+fn set_backend(backend: Backend) -> io::Result<()> {
+    BACKEND
+        .set(backend)
+        .map_err(|_| io::Error::new(io::ErrorKind::AlreadyExists, "segment backend already set"))
+}
+
+/// Maps the segment behind `flink` from the broker, creating it with `size`
+/// bytes if it doesn't exist. `None` if segments are plain shm objects.
+/// Returns `(ptr, is_new, mapped_size)`.
+pub(crate) fn map(flink: &Path, size: usize) -> Option<io::Result<(*mut u8, bool, usize)>> {
+    let backend = BACKEND.get()?;
+    let name = segment_name(flink);
+    Some(match backend {
+        Backend::Host(broker) => {
+            broker.open(&name, size).map(|(_, ptr, len, is_new)| (ptr, is_new, len))
+        }
+        Backend::Peer(client) => {
+            client.lock().unwrap().open(&name, size).and_then(|(fd, is_new)| {
+                let (ptr, len) = map_fd(&fd)?;
+                Ok((ptr, is_new, len))
+            })
+        }
+    })
+}
+
+/// Removes the segment behind `flink`, so the next open creates it anew.
+/// Processes that have it mapped keep using the old memory.
+pub fn remove_segment(flink: &Path) {
+    let _ = std::fs::remove_file(flink);
+    match BACKEND.get() {
+        Some(Backend::Host(broker)) => broker.remove(&segment_name(flink)),
+        Some(Backend::Peer(client)) => {
+            let removed = client.lock().unwrap().remove(&segment_name(flink));
+            if let Err(e) = removed {
+                tracing::warn!("couldn't remove segment {}: {e}", flink.display());
+            }
+        }
+        None => {}
+    }
+}
+
+struct Segment {
+    fd: OwnedFd,
+    // The host's own mapping, never unmapped: segments may be handed out
+    // as `&'static`.
+    ptr: *mut u8,
+    len: usize,
+}
+
+unsafe impl Send for Segment {}
+
+/// The segments of a host process, served by name.
+#[derive(Default)]
+pub struct SegmentBroker {
+    segments: Mutex<HashMap<String, Segment>>,
+}
+
+impl SegmentBroker {
+    /// Returns the segment `name`, creating it if it doesn't exist or is
+    /// smaller than `size`. A `size` of 0 only opens existing segments.
+    fn open(&self, name: &str, size: usize) -> io::Result<(OwnedFd, *mut u8, usize, bool)> {
+        let mut segments = self.segments.lock().unwrap();
+        if let Some(s) = segments.get(name) &&
+            s.len >= size
+        {
+            return Ok((s.fd.try_clone()?, s.ptr, s.len, false));
+        }
+        if size == 0 {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no segment {name}")));
+        }
+        let fd = create_memfd(name, size)?;
+        let (ptr, len) = map_fd(&fd)?;
+        let handed_out = fd.try_clone()?;
+        segments.insert(name.to_owned(), Segment { fd, ptr, len });
+        drop(segments);
+        Ok((handed_out, ptr, len, true))
+    }
+
+    fn remove(&self, name: &str) {
+        self.segments.lock().unwrap().remove(name);
+    }
+
+    fn list(&self) -> Vec<(String, usize)> {
+        let mut out: Vec<_> =
+            self.segments.lock().unwrap().iter().map(|(n, s)| (n.clone(), s.len)).collect();
+        out.sort();
+        out
+    }
+
+    fn serve_in_background(self: Arc<Self>, stream: UnixStream) {
+        let spawned =
+            std::thread::Builder::new().name("flux-segments-conn".into()).spawn(move || {
+                if let Err(e) = self.serve(&stream) {
+                    tracing::debug!("segment broker connection closed: {e}");
+                }
+            });
+        if let Err(e) = spawned {
+            tracing::warn!("couldn't spawn segment broker connection thread: {e}");
+        }
+    }
+
+    /// Answers requests until the peer hangs up. One tab-separated request
+    /// per line: `open <size> <name>`, `remove <name>` or `list`.
+    fn serve(&self, stream: &UnixStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let mut args = line.trim_end_matches('\n').split('\t');
+            match (args.next(), args.next(), args.next()) {
+                (Some("open"), Some(size), Some(name)) => {
+                    let opened = size
+                        .parse()
+                        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+                        .and_then(|size| self.open(name, size));
+                    match opened {
+                        Ok((fd, _, len, is_new)) => send_with_fd(
+                            stream,
+                            format!("ok\t{len}\t{}\n", u8::from(is_new)).as_bytes(),
+                            Some(fd.as_raw_fd()),
+                        )?,
+                        Err(e) => send_with_fd(stream, format!("err\t{e}\n").as_bytes(), None)?,
+                    }
+                }
+                (Some("remove"), Some(name), None) => {
+                    self.remove(name);
+                    send_with_fd(stream, b"ok\n", None)?;
+                }
+                (Some("list"), None, None) => {
+                    let mut reply = String::from("ok");
+                    for (name, len) in self.list() {
+                        let _ = write!(reply, "\t{name}\t{len}");
+                    }
+                    reply.push('\n');
+                    send_with_fd(stream, reply.as_bytes(), None)?;
+                }
+                _ => send_with_fd(stream, b"err\tbad request\n", None)?,
+            }
+        }
+    }
+}
+
+/// A connection to a [`SegmentBroker`].
+#[derive(Debug)]
+pub struct SegmentClient {
+    stream: UnixStream,
+}
+
+impl SegmentClient {
+    pub fn connect<P: AsRef<Path>>(socket: P) -> io::Result<Self> {
+        Ok(Self { stream: UnixStream::connect(socket)? })
+    }
+
+    pub fn from_fd(fd: OwnedFd) -> Self {
+        Self { stream: UnixStream::from(fd) }
+    }
+
+    /// The fd of segment `name`, created with `size` bytes if it doesn't
+    /// exist or is smaller. A `size` of 0 only opens existing segments.
+    /// The flag is set if this call created it.
+    pub fn open(&mut self, name: &str, size: usize) -> io::Result<(OwnedFd, bool)> {
+        let (reply, fd) = self.request(&format!("open\t{size}\t{name}\n"))?;
+        let is_new = reply.get(1).is_some_and(|f| *f == "1");
+        let fd = fd.ok_or_else(|| io::Error::other("broker sent no fd"))?;
+        Ok((fd, is_new))
+    }
+
+    pub fn remove(&mut self, name: &str) -> io::Result<()> {
+        self.request(&format!("remove\t{name}\n")).map(|_| ())
+    }
+
+    /// Names and sizes of all served segments.
+    pub fn list(&mut self) -> io::Result<Vec<(String, usize)>> {
+        let (reply, _) = self.request("list\n")?;
+        Ok(reply.chunks_exact(2).filter_map(|c| Some((c[0].clone(), c[1].parse().ok()?))).collect())
+    }
+
+    /// Sends `request` and returns the fields of the reply after its `ok`.
+    fn request(&mut self, request: &str) -> io::Result<(Vec<String>, Option<OwnedFd>)> {
+        self.stream.write_all(request.as_bytes())?;
+        let (reply, fd) = recv_line_with_fd(&self.stream)?;
+        let mut fields = reply.split('\t');
+        match fields.next() {
+            Some("ok") => Ok((fields.map(str::to_owned).collect(), fd)),
+            Some("err") => Err(io::Error::other(fields.collect::<Vec<_>>().join("\t"))),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, reply)),
+        }
+    }
+}
+
+fn create_memfd(name: &str, size: usize) -> io::Result<OwnedFd> {
+    let mut name = format!("flux:{name}");
+    name.truncate(name.floor_char_boundary(MAX_MEMFD_NAME));
+    let name = CString::new(name).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let size =
+        libc::off_t::try_from(size).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    if unsafe { libc::ftruncate(fd.as_raw_fd(), size) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// A shared mapping of a whole segment fd, unmapped on drop.
+#[derive(Debug)]
+pub struct FdMapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl FdMapping {
+    pub fn new(fd: &OwnedFd) -> io::Result<Self> {
+        let (ptr, len) = map_fd(fd)?;
+        Ok(Self { ptr, len })
+    }
+
+    pub const fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for FdMapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.cast(), self.len) };
+    }
+}
+
+/// Maps all of `fd` shared, returning the pointer and its length. The
+/// mapping is never unmapped.
+fn map_fd(fd: &OwnedFd) -> io::Result<(*mut u8, usize)> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd.as_raw_fd(), &raw mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let len =
+        usize::try_from(stat.st_size).map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?;
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            fd.as_raw_fd(),
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((ptr.cast(), len))
+}
+
+// Room for the control message carrying one fd, aligned for `cmsghdr`.
+type CmsgBuf = [u64; 4];
+
+fn send_with_fd(stream: &UnixStream, data: &[u8], fd: Option<RawFd>) -> io::Result<()> {
+    let mut iov = libc::iovec { iov_base: data.as_ptr().cast_mut().cast(), iov_len: data.len() };
+    let mut cmsg_buf: CmsgBuf = [0; 4];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &raw mut iov;
+    msg.msg_iovlen = 1;
+    if let Some(fd) = fd {
+        msg.msg_control = cmsg_buf.as_mut_ptr().cast();
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(size_of::<RawFd>() as u32) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&raw const msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+            libc::CMSG_DATA(cmsg).cast::<RawFd>().write_unaligned(fd);
+        }
+    }
+    let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &raw const msg, libc::MSG_NOSIGNAL) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The fd went with the first byte, the rest is plain data.
+    (&*stream).write_all(&data[sent as usize..])
+}
+
+/// Reads one reply line, collecting the fd sent along with it.
+fn recv_line_with_fd(stream: &UnixStream) -> io::Result<(String, Option<OwnedFd>)> {
+    let mut line = Vec::new();
+    let mut fd = None;
+    while !line.ends_with(b"\n") {
+        let mut buf = [0u8; 512];
+        let mut iov = libc::iovec { iov_base: buf.as_mut_ptr().cast(), iov_len: buf.len() };
+        let mut cmsg_buf: CmsgBuf = [0; 4];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &raw mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsg_buf.as_mut_ptr().cast();
+        msg.msg_controllen = size_of::<CmsgBuf>() as _;
+
+        let n = unsafe { libc::recvmsg(stream.as_raw_fd(), &raw mut msg, libc::MSG_CMSG_CLOEXEC) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&raw const msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let received = libc::CMSG_DATA(cmsg).cast::<RawFd>().read_unaligned();
+                    fd = Some(OwnedFd::from_raw_fd(received));
+                }
+                cmsg = libc::CMSG_NXTHDR(&raw const msg, cmsg);
+            }
+        }
+        line.extend_from_slice(&buf[..n as usize]);
+    }
+    line.pop();
+    let line =
+        String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((line, fd))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_segments_after_their_flink() {
+        assert_eq!(
+            segment_name(Path::new("/home/me/.local/share/app/shmem/queues/Order")),
+            "app/queues/Order"
+        );
+        assert_eq!(
+            segment_name(Path::new("/tmp/x/nested/app/shmem/data/TileInfo")),
+            "app/data/TileInfo"
+        );
+        assert_eq!(segment_name(Path::new("/tmp/elsewhere")), "/tmp/elsewhere");
+    }
+
+    #[test]
+    fn reads_peer_uid() {
+        let (ours, _theirs) = UnixStream::pair().unwrap();
+        assert_eq!(peer_uid(&ours).unwrap(), unsafe { libc::geteuid() });
+    }
+
+    #[test]
+    fn hands_out_segments_over_a_socket() {
+        let broker = Arc::new(SegmentBroker::default());
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        broker.clone().serve_in_background(ours);
+        let mut client = SegmentClient::from_fd(OwnedFd::from(theirs));
+
+        let (fd, is_new) = client.open("app/queues/Order", 4096).unwrap();
+        assert!(is_new);
+        let (ptr, len) = map_fd(&fd).unwrap();
+        assert_eq!(len, 4096);
+        unsafe { ptr.write(42) };
+
+        // The same memory, seen by the host and by a second open.
+        let (_, host_ptr, _, is_new) = broker.open("app/queues/Order", 4096).unwrap();
+        assert!(!is_new);
+        assert_eq!(unsafe { host_ptr.read() }, 42);
+        let (fd, is_new) = client.open("app/queues/Order", 0).unwrap();
+        assert!(!is_new);
+        assert_eq!(unsafe { map_fd(&fd).unwrap().0.read() }, 42);
+
+        assert_eq!(client.list().unwrap(), [("app/queues/Order".to_owned(), 4096)]);
+        assert!(client.open("app/queues/Missing", 0).is_err());
+        client.remove("app/queues/Order").unwrap();
+        assert!(client.list().unwrap().is_empty());
+    }
+}
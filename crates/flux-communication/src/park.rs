@@ -4,10 +4,14 @@ use std::{
     cell::UnsafeCell,
     hint::spin_loop,
     mem::MaybeUninit,
-    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+    path::Path,
+    sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering},
 };
 
 use mio::Waker;
+use shared_memory::ShmemError;
+
+use crate::{ShmemData, wait::futex_wake};
 
 pub const MAX_SIGNAL_WAKERS: usize = 8;
 
@@ -45,8 +49,18 @@ impl WakerSlot {
     }
 }
 
+/// Per-app wake counter living in shared memory, so that produces in one
+/// process wake tiles parked in another.
+#[derive(Debug, Default)]
+#[repr(C, align(64))]
+pub struct SharedSignal {
+    counter: AtomicU32,
+}
+
 pub struct Signal {
     counter: AtomicU32,
+    /// Counter in a [`SharedSignal`] segment once attached, null before.
+    shared: AtomicPtr<AtomicU32>,
     reserved_wakers: AtomicUsize,
     published_wakers: AtomicUsize,
     wakers: [WakerSlot; MAX_SIGNAL_WAKERS],
@@ -68,6 +82,7 @@ impl Signal {
     pub const fn new() -> Self {
         Self {
             counter: AtomicU32::new(0),
+            shared: AtomicPtr::new(std::ptr::null_mut()),
             reserved_wakers: AtomicUsize::new(0),
             published_wakers: AtomicUsize::new(0),
             wakers: [const { WakerSlot::uninit() }; MAX_SIGNAL_WAKERS],
@@ -78,32 +93,61 @@ impl Signal {
         }
     }
 
+    /// The counter threads park on: the shared one once attached, the
+    /// process-local one otherwise.
+    #[inline]
+    fn word(&self) -> &AtomicU32 {
+        let shared = self.shared.load(Ordering::Acquire);
+        if shared.is_null() { &self.counter } else { unsafe { &*shared } }
+    }
+
+    /// Move the counter into the [`SharedSignal`] segment of `app_name`, so
+    /// that every process attached to the same app wakes each other's parked
+    /// tiles. Registered mio wakers stay process-local.
+    ///
+    /// Threads parked on the previous counter are woken so they re-park on
+    /// the shared one. Attaching again replaces the previous segment.
+    pub fn attach_shared<D: AsRef<Path>, A: AsRef<Path>>(
+        &self,
+        base_dir: D,
+        app_name: A,
+    ) -> Result<(), ShmemError> {
+        let segment =
+            ShmemData::<SharedSignal>::open_or_init_with_base_dir(base_dir, app_name, || {
+                SharedSignal::default()
+            })?;
+        // The mapping is never unmapped, so the counter outlives `segment`.
+        let word = (&raw const segment.counter).cast_mut();
+        let prev = self.shared.swap(word, Ordering::AcqRel);
+        if prev != word {
+            let prev = if prev.is_null() { &self.counter } else { unsafe { &*prev } };
+            prev.fetch_add(1, Ordering::Release);
+            futex_wake(prev);
+            #[cfg(not(target_os = "linux"))]
+            self.cond.notify_all();
+        }
+        Ok(())
+    }
+
+    /// Whether the counter lives in shared memory.
+    #[inline]
+    pub fn is_shared(&self) -> bool {
+        !self.shared.load(Ordering::Relaxed).is_null()
+    }
+
     /// Read the current state of the atomic counter.
     #[inline]
     pub fn read_counter(&self) -> u32 {
-        self.counter.load(Ordering::Acquire)
+        self.word().load(Ordering::Acquire)
     }
 
     /// Signal the sticky event. Increment the counter, wake all parked threads
     /// via `FUTEX_WAKE` (on Linux) or Condvar (on non-Linux), and wake all
     /// registered mio Wakers.
     pub fn signal(&self) {
-        self.counter.fetch_add(1, Ordering::Release);
-
-        #[cfg(target_os = "linux")]
-        {
-            unsafe {
-                libc::syscall(
-                    libc::SYS_futex,
-                    self.counter.as_ptr(),
-                    libc::FUTEX_WAKE,
-                    libc::c_int::MAX,
-                    std::ptr::null::<libc::timespec>(),
-                    std::ptr::null::<libc::c_int>(),
-                    0 as libc::c_int,
-                );
-            }
-        }
+        let word = self.word();
+        word.fetch_add(1, Ordering::Release);
+        futex_wake(word);
 
         #[cfg(not(target_os = "linux"))]
         {
@@ -128,29 +172,35 @@ impl Signal {
     /// `expected`.
     pub fn park(&self, expected: u32) {
         #[cfg(target_os = "linux")]
-        {
-            unsafe {
-                libc::syscall(
-                    libc::SYS_futex,
-                    self.counter.as_ptr(),
-                    libc::FUTEX_WAIT,
-                    expected as libc::c_int,
-                    std::ptr::null::<libc::timespec>(),
-                    std::ptr::null::<libc::c_int>(),
-                    0 as libc::c_int,
-                );
-            }
-        }
+        crate::wait::futex_wait(self.word(), expected, None);
 
         #[cfg(not(target_os = "linux"))]
         {
             let mut guard = self.mutex.lock().unwrap();
-            while self.counter.load(Ordering::Acquire) == expected {
+            while self.word().load(Ordering::Acquire) == expected {
                 guard = self.cond.wait(guard).unwrap();
             }
         }
     }
 
+    /// Like [`Self::park`], but returns after `timeout` at the latest so the
+    /// caller can do periodic upkeep, e.g. renew consumer group leases.
+    pub fn park_timeout(&self, expected: u32, timeout: std::time::Duration) {
+        #[cfg(target_os = "linux")]
+        crate::wait::futex_wait(self.word(), expected, Some(timeout));
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let guard = self.mutex.lock().unwrap();
+            let _ = self
+                .cond
+                .wait_timeout_while(guard, timeout, |_| {
+                    self.word().load(Ordering::Acquire) == expected
+                })
+                .unwrap();
+        }
+    }
+
     /// Register a `mio::Waker` instance. It will be woken whenever `signal` is
     /// called.
     ///
@@ -0,0 +1,244 @@
+//! Last-value-per-key queues.
+//!
+//! The latest message of every key lives in a slot of a [`SeqlockMap`], and
+//! every write of a slot is logged in the change log of the map (see
+//! [`SeqlockMap::changed_since`]), which serves as the notification ring.
+//! Readers never get lapped: a reader that fell behind still gets each key it
+//! missed once, with its latest value.
+
+use std::path::Path;
+
+use type_hash::TypeHash;
+
+use crate::{
+    SeqlockMap,
+    error::{FullError, QueueError},
+};
+
+/// Conflating queue of `T`, keyed by a function of the message.
+///
+/// Any number of producers, in any process, may update the same keys. The
+/// key function is not part of the shared memory, every process opening the
+/// queue brings its own.
+#[derive(Clone, Copy, Debug)]
+pub struct ConflatingQueue<T> {
+    slots: SeqlockMap<u64, T>,
+    key: fn(&T) -> u64,
+    signal_on_produce: bool,
+}
+
+impl<T: Copy> ConflatingQueue<T> {
+    /// Heap-backed queue with room for `keys.next_power_of_two()` keys.
+    pub fn new(keys: usize, key: fn(&T) -> u64) -> Self {
+        Self { slots: SeqlockMap::with_change_log(keys), key, signal_on_produce: false }
+    }
+
+    /// Fails with [`QueueError::TypeMismatch`] if the existing queue was
+    /// created for a different element type.
+    pub fn create_or_open_shared<P: AsRef<Path>>(
+        shmem_file: P,
+        keys: usize,
+        key: fn(&T) -> u64,
+    ) -> Result<Self, QueueError>
+    where
+        T: TypeHash,
+    {
+        let slots = SeqlockMap::create_or_open_shared_with_change_log(shmem_file, keys)?;
+        Ok(Self { slots, key, signal_on_produce: false })
+    }
+
+    /// Overwrites the slot of the key of `msg` and notifies consumers,
+    /// returning the slot index. Fails once every slot is bound to another
+    /// key.
+    #[inline]
+    pub fn produce(&self, msg: &T) -> Result<usize, FullError> {
+        let pos = self.slots.insert_multi_producer((self.key)(msg), *msg)?;
+        #[cfg(feature = "park")]
+        if self.signal_on_produce {
+            crate::park::SIGNAL.signal();
+        }
+        Ok(pos)
+    }
+
+    /// Latest message of `key`, if it was ever produced.
+    #[inline]
+    pub fn latest(&self, key: u64) -> Option<T> {
+        self.slots.get(&key)
+    }
+
+    /// Key of `msg` according to the queue's key function.
+    #[inline]
+    pub fn key_of(&self, msg: &T) -> u64 {
+        (self.key)(msg)
+    }
+
+    /// Maximum number of distinct keys.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    /// Number of updates produced so far.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.slots.generation()
+    }
+
+    /// Wake parked tile threads on every produce (`park` feature).
+    pub fn set_signal_on_produce(&mut self, enabled: bool) {
+        self.signal_on_produce = enabled;
+    }
+}
+
+/// Reader of a [`ConflatingQueue`], receiving each key updated since its
+/// last read once, with the latest value.
+#[derive(Clone, Copy, Debug)]
+pub struct ConflatingConsumer<T> {
+    queue: ConflatingQueue<T>,
+    generation: u64,
+}
+
+impl<T: Copy> ConflatingConsumer<T> {
+    /// Starts with the updates produced after its creation.
+    pub fn new(queue: ConflatingQueue<T>) -> Self {
+        Self { queue, generation: queue.generation() }
+    }
+
+    /// Makes the next read return every key produced so far, e.g. to build
+    /// the full state on startup.
+    #[inline]
+    pub fn replay_latest(&mut self) {
+        self.generation = 0;
+    }
+
+    /// Whether keys were updated since the last read.
+    #[inline]
+    pub fn has_updates(&self) -> bool {
+        self.queue.generation() != self.generation
+    }
+
+    /// Runs `f` on the latest value of every key updated since the last
+    /// read, most recently updated first, and returns how many there were.
+    ///
+    /// A consumer that is more than
+    /// [`CHANGE_LOG_LEN`](crate::array::CHANGE_LOG_LEN) updates behind gets
+    /// every key instead.
+    #[inline]
+    pub fn consume<F: FnMut(&T)>(&mut self, mut f: F) -> usize {
+        if !self.has_updates() {
+            return 0;
+        }
+        let mut changes = self.queue.slots.changed_since(self.generation);
+        let mut n = 0;
+        for (_, slot, _) in changes.by_ref() {
+            if slot.live {
+                f(&slot.value);
+                n += 1;
+            }
+        }
+        self.generation = changes.generation();
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use type_hash_derive::TypeHash;
+
+    use super::*;
+    use crate::array::CHANGE_LOG_LEN;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, TypeHash)]
+    struct Quote {
+        symbol: u32,
+        price: u64,
+    }
+
+    fn symbol(q: &Quote) -> u64 {
+        u64::from(q.symbol)
+    }
+
+    fn drain(c: &mut ConflatingConsumer<Quote>) -> Vec<Quote> {
+        let mut seen = Vec::new();
+        c.consume(|q| seen.push(*q));
+        seen.sort_unstable_by_key(|q| q.symbol);
+        seen
+    }
+
+    #[test]
+    fn delivers_each_dirty_key_once() {
+        let q = ConflatingQueue::new(16, symbol);
+        let mut c = ConflatingConsumer::new(q);
+        assert_eq!(c.consume(|_| ()), 0);
+
+        for price in 0..10 {
+            for symbol in 0..3 {
+                q.produce(&Quote { symbol, price }).unwrap();
+            }
+        }
+        assert!(c.has_updates());
+        let expected: Vec<_> = (0..3).map(|symbol| Quote { symbol, price: 9 }).collect();
+        assert_eq!(drain(&mut c), expected);
+        assert!(!c.has_updates());
+
+        q.produce(&Quote { symbol: 1, price: 10 }).unwrap();
+        assert_eq!(drain(&mut c), [Quote { symbol: 1, price: 10 }]);
+        assert_eq!(q.latest(1), Some(Quote { symbol: 1, price: 10 }));
+        assert_eq!(q.latest(7), None);
+    }
+
+    #[test]
+    fn lapped_consumer_gets_every_key() {
+        let q = ConflatingQueue::new(4, symbol);
+        let mut c = ConflatingConsumer::new(q);
+        for price in 0..=CHANGE_LOG_LEN as u64 {
+            q.produce(&Quote { symbol: (price % 4) as u32, price }).unwrap();
+        }
+        let seen = drain(&mut c);
+        assert_eq!(seen.len(), 4);
+        for quote in seen {
+            assert_eq!(q.latest(u64::from(quote.symbol)), Some(quote));
+        }
+        assert_eq!(q.produce(&Quote { symbol: 4, price: 0 }), Err(FullError::Full));
+    }
+
+    #[test]
+    fn concurrent_producers_update_the_same_keys() {
+        let q = ConflatingQueue::new(8, symbol);
+        let mut c = ConflatingConsumer::new(q);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for price in 0..10_000 {
+                        q.produce(&Quote { symbol: (price % 4) as u32, price }).unwrap();
+                    }
+                });
+            }
+        });
+        let seen = drain(&mut c);
+        assert_eq!(seen.iter().map(|q| q.symbol).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        for quote in seen {
+            assert_eq!(u64::from(quote.symbol), quote.price % 4);
+        }
+    }
+
+    #[test]
+    fn replay_latest_and_shared() {
+        let path = Path::new("/dev/shm/flux_test_conflating_queue");
+        let _ = std::fs::remove_file(path);
+        let q = ConflatingQueue::create_or_open_shared(path, 8, symbol).unwrap();
+        q.produce(&Quote { symbol: 5, price: 1 }).unwrap();
+        q.produce(&Quote { symbol: 5, price: 2 }).unwrap();
+
+        let other = ConflatingQueue::create_or_open_shared(path, 8, symbol).unwrap();
+        let mut c = ConflatingConsumer::new(other);
+        assert_eq!(c.consume(|_| ()), 0);
+        c.replay_latest();
+        assert_eq!(drain(&mut c), [Quote { symbol: 5, price: 2 }]);
+        assert!(matches!(
+            ConflatingQueue::<u64>::create_or_open_shared(path, 8, |v| *v),
+            Err(QueueError::TypeMismatch { .. })
+        ));
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -7,7 +7,7 @@ use std::{
     path::Path,
     sync::{
         Mutex, OnceLock,
-        atomic::{AtomicU8, AtomicUsize, Ordering},
+        atomic::{AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering, fence},
     },
     time::{Duration, Instant},
 };
@@ -53,12 +53,15 @@ fn is_pid_alive(pid: u32) -> bool {
     Path::new(&format!("/proc/{pid}")).exists()
 }
 
+use flux_timing::Nanos;
 use flux_utils::{ArrayStr, safe_panic};
 use shared_memory::{ShmemConf, ShmemError};
+use type_hash::TypeHash;
 
 use crate::{
-    Seqlock,
-    error::{EmptyError, QueueError, ReadError},
+    MappingOptions, Seqlock, TypeIdentity,
+    error::{EmptyError, QueueError, ReadError, TimeoutError},
+    wait::{Backoff, futex_wait, futex_wake},
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -70,7 +73,33 @@ pub enum QueueType {
 }
 
 pub const MAX_GROUPS: usize = 256;
+/// Longest single sleep of a blocking consumer before it rechecks its slot.
+const MAX_SLEEP: Duration = Duration::from_millis(1);
+// Bits of `QueueHeader::waiters`, above them the wake sequence.
+/// A consumer is asleep on the futex.
+const WAITING: u32 = 1;
+/// A consumer ever blocked on the queue, set for good.
+const BLOCKING: u32 = 2;
+const WAKE: u32 = 4;
 pub const GROUP_LABEL_LEN: usize = 64;
+/// Lease of a consumer group that hasn't been given one with
+/// [`Queue::set_group_lease`].
+pub const DEFAULT_GROUP_LEASE: Duration = Duration::from_secs(30);
+/// Idle consumers refresh their group's `last_active` at most this often.
+const LEASE_RENEW_INTERVAL: Nanos = Nanos::from_millis(100);
+
+/// Whether a consumer's lease renewal is due, moving `renew_at` on if so.
+/// Only reads the TSC until the deadline passes, so it is cheap enough for
+/// empty polls.
+#[inline]
+fn lease_renewal_due(renew_at: &mut flux_timing::Instant) -> bool {
+    let now = flux_timing::Instant::now();
+    if now.saturating_sub(*renew_at).0 == 0 {
+        return false;
+    }
+    *renew_at = now + LEASE_RENEW_INTERVAL;
+    true
+}
 
 #[derive(Debug)]
 #[repr(C, align(64))]
@@ -78,22 +107,133 @@ pub struct AlignedCursor {
     pub cursor: AtomicUsize,
 }
 
+/// How the members of a consumer group read the queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GroupKind {
+    /// Registered through [`QueueHeader::find_or_insert_group`] directly.
+    Unknown = 0,
+    /// A single consumer reading every message.
+    Broadcast = 1,
+    /// Consumers sharing one cursor, each message read by one of them.
+    Collaborative = 2,
+    /// Position committed by a broadcast consumer to resume from after a
+    /// restart. Never expires and doesn't hold producers back.
+    Checkpoint = 3,
+}
+
+impl GroupKind {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            1 => Self::Broadcast,
+            2 => Self::Collaborative,
+            3 => Self::Checkpoint,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for GroupKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown => write!(f, "unknown"),
+            Self::Broadcast => write!(f, "broadcast"),
+            Self::Collaborative => write!(f, "collaborative"),
+            Self::Checkpoint => write!(f, "checkpoint"),
+        }
+    }
+}
+
+/// Ownership and liveness of one consumer group slot.
+///
+/// A slot is reclaimed once its owner process is gone or nobody renewed its
+/// lease in time. `generation` is bumped whenever the slot is handed to a new
+/// group so members of the previous one notice and rejoin.
+#[derive(Debug)]
+#[repr(C)]
+pub struct GroupMeta {
+    owner_pid: AtomicU32,   // 4
+    members: AtomicU32,     // 8
+    generation: AtomicU32,  // 12
+    kind: AtomicU8,         // 13
+    _pad: [u8; 3],          // 16
+    created: AtomicU64,     // 24 — Nanos
+    last_active: AtomicU64, // 32 — Nanos
+}
+
+/// Snapshot of a consumer group, see [`QueueHeader::groups`].
+#[derive(Clone, Debug)]
+pub struct GroupInfo<'a> {
+    pub slot: usize,
+    pub label: &'a str,
+    pub cursor: usize,
+    pub kind: GroupKind,
+    /// 0 for groups registered before group metadata existed.
+    pub owner_pid: u32,
+    pub members: u32,
+    pub created: Nanos,
+    pub last_active: Nanos,
+    /// Owner gone or lease run out: the slot is free to be reclaimed.
+    pub expired: bool,
+}
+
+/// A consumer's claim on a group slot, released with
+/// [`QueueHeader::leave_group`].
+#[derive(Clone, Copy, Debug)]
+pub struct GroupMembership {
+    pub cursor: *const AtomicUsize,
+    pub slot: u16,
+    pub generation: u32,
+}
+
+/// Shared between processes, so the layout is fixed: the comments give the
+/// offset each field starts at and the asserts below hold them to it.
 #[derive(Debug)]
 #[repr(C, align(64))]
 pub struct QueueHeader {
-    pub queue_type: QueueType,   // 1
-    is_initialized: u8,          // 2
-    group_lock: AtomicU8,        // 3  — spinlock protecting group label search/insert
-    signal_on_produce: AtomicU8, // 4 — produces wake parked tile threads (`park` feature)
-    _pad1: [u8; 4],              // 8
-    pub elsize: usize,           // 16
-    pub mask: usize,             // 24
-    pub count: AtomicUsize,      /* 32 */
-
-    group_labels: [ArrayStr<GROUP_LABEL_LEN>; MAX_GROUPS],
-    group_cursors: [AlignedCursor; MAX_GROUPS],
+    pub queue_type: QueueType, // 0
+    is_initialized: u8,        // 1
+    /// Spinlock protecting group label search/insert.
+    group_lock: AtomicU8, // 2
+    /// Produces wake parked tile threads (`park` feature).
+    signal_on_produce: AtomicU8, // 3
+    /// Futex word of blocking consumers: wake seq | BLOCKING | WAITING.
+    waiters: AtomicU32, // 4
+    pub elsize: usize,         // 8
+    pub mask: usize,           // 16
+    pub count: AtomicUsize,    // 24
+    identity: TypeIdentity,    // 32
+    /// 0 → [`DEFAULT_GROUP_LEASE`], `u64::MAX` → never expire.
+    group_lease_ns: AtomicU64, // 88
+
+    group_labels: [ArrayStr<GROUP_LABEL_LEN>; MAX_GROUPS], // 96
+    group_cursors: [AlignedCursor; MAX_GROUPS],            // 18560
+    group_meta: [GroupMeta; MAX_GROUPS],                   // 34944
+
+    producers: [ProducerLease; MAX_PRODUCERS], // 43136
+    repair_log: RepairLog,                     // 43264
 }
 
+const _: () = {
+    use std::mem::offset_of;
+    assert!(offset_of!(QueueHeader, queue_type) == 0);
+    assert!(offset_of!(QueueHeader, is_initialized) == 1);
+    assert!(offset_of!(QueueHeader, group_lock) == 2);
+    assert!(offset_of!(QueueHeader, signal_on_produce) == 3);
+    assert!(offset_of!(QueueHeader, waiters) == 4);
+    assert!(offset_of!(QueueHeader, elsize) == 8);
+    assert!(offset_of!(QueueHeader, mask) == 16);
+    assert!(offset_of!(QueueHeader, count) == 24);
+    assert!(offset_of!(QueueHeader, identity) == 32);
+    assert!(offset_of!(QueueHeader, group_lease_ns) == 88);
+    assert!(offset_of!(QueueHeader, group_labels) == 96);
+    assert!(offset_of!(QueueHeader, group_cursors) == 18560);
+    assert!(offset_of!(QueueHeader, group_meta) == 34944);
+    assert!(offset_of!(QueueHeader, producers) == 43136);
+    assert!(offset_of!(QueueHeader, repair_log) == 43264);
+    assert!(size_of::<QueueHeader>() == 43328);
+};
+
 #[allow(dead_code)]
 impl QueueHeader {
     /// in bytes
@@ -124,6 +264,13 @@ impl QueueHeader {
         self.elsize
     }
 
+    /// Identity of the element type recorded at creation. Check
+    /// [`TypeIdentity::is_current`] before trusting the other fields, queues
+    /// created by older flux versions have no identity.
+    pub fn identity(&self) -> &TypeIdentity {
+        &self.identity
+    }
+
     pub fn open_shared<S: AsRef<Path>>(path: S) -> Result<&'static mut Self, QueueError> {
         let path = path.as_ref();
         let shmem = ShmemConf::new().flink(path).open()?;
@@ -159,71 +306,251 @@ impl QueueHeader {
     }
 
     pub fn find_or_insert_group(&mut self, key: &str) -> *const AtomicUsize {
+        self.join_group(key, GroupKind::Unknown).cursor
+    }
+
+    /// Joins the group `key`, claiming a free or expired slot if it doesn't
+    /// exist yet. Every join must be paired with a [`Self::leave_group`]; the
+    /// slot is freed when its last member leaves.
+    pub fn join_group(&mut self, key: &str, kind: GroupKind) -> GroupMembership {
+        self.claim_group(key, kind, true)
+    }
+
+    fn claim_group(&mut self, key: &str, kind: GroupKind, join: bool) -> GroupMembership {
         let key = ArrayStr::<GROUP_LABEL_LEN>::from_str_truncate(key);
+        let now = Nanos::now();
 
         self.acquire_group_lock();
 
         // 1. Exact match — reuse the existing slot
-        for i in 0..MAX_GROUPS {
-            if self.group_labels[i] == key {
-                self.release_group_lock();
-                return &raw const self.group_cursors[i].cursor;
-            }
+        let slot = (0..MAX_GROUPS).find(|&i| self.group_labels[i] == key).or_else(|| {
+            // 2. Empty slot, or 3. one whose owner is gone or lease expired
+            let free = (0..MAX_GROUPS).find(|&i| {
+                self.group_labels[i] == ArrayStr::<GROUP_LABEL_LEN>::new() ||
+                    self.is_group_expired(i, now)
+            })?;
+            self.group_labels[free] = key;
+            self.group_cursors[free].cursor.store(0, Ordering::Relaxed);
+            let meta = &self.group_meta[free];
+            // Consumer keys carry the pid of the process they belong to.
+            let owner = pid_from_label(key.as_str()).unwrap_or_else(current_pid);
+            meta.owner_pid.store(owner, Ordering::Relaxed);
+            meta.members.store(0, Ordering::Relaxed);
+            meta.kind.store(kind as u8, Ordering::Relaxed);
+            meta.created.store(now.0, Ordering::Relaxed);
+            meta.generation.fetch_add(1, Ordering::Relaxed);
+            Some(free)
+        });
+        let Some(slot) = slot else {
+            self.release_group_lock();
+            panic!("no group slots available (max {MAX_GROUPS} groups)");
+        };
+
+        let meta = &self.group_meta[slot];
+        if join {
+            meta.members.fetch_add(1, Ordering::Relaxed);
         }
-        for i in 0..MAX_GROUPS {
-            // 2. Empty slot
-            if self.group_labels[i] == ArrayStr::<GROUP_LABEL_LEN>::new() {
-                self.group_labels[i] = key;
-                self.group_cursors[i].cursor.store(0, Ordering::Relaxed);
-                self.release_group_lock();
-                return &raw const self.group_cursors[i].cursor;
-            }
-            // 3. Slot owned by a dead process — reclaim it
-            if let Some(pid) = pid_from_label(self.group_labels[i].as_str()) &&
-                !is_pid_alive(pid)
-            {
-                self.group_labels[i] = key;
-                self.group_cursors[i].cursor.store(0, Ordering::Relaxed);
-                self.release_group_lock();
-                return &raw const self.group_cursors[i].cursor;
-            }
+        meta.last_active.store(now.0, Ordering::Relaxed);
+        let generation = meta.generation.load(Ordering::Relaxed);
+        self.release_group_lock();
+
+        GroupMembership {
+            cursor: &raw const self.group_cursors[slot].cursor,
+            slot: slot as u16,
+            generation,
         }
+    }
 
+    /// Drops one member of the group. The slot is freed with its last member,
+    /// unless it was reclaimed by another group in the meantime.
+    pub fn leave_group(&mut self, membership: GroupMembership) {
+        let slot = usize::from(membership.slot);
+        self.acquire_group_lock();
+        let meta = &self.group_meta[slot];
+        if meta.generation.load(Ordering::Relaxed) == membership.generation &&
+            meta.members.load(Ordering::Relaxed) <= 1
+        {
+            self.free_group_slot(slot);
+        } else if meta.generation.load(Ordering::Relaxed) == membership.generation {
+            meta.members.fetch_sub(1, Ordering::Relaxed);
+        }
         self.release_group_lock();
-        panic!("no group slots available (max {MAX_GROUPS} groups)");
     }
 
-    /// Returns all non-empty consumer group slots as `(label, cursor_value)`
-    /// pairs.
+    /// Refreshes the lease of the group behind `membership`. Returns `false`
+    /// if the slot has since been handed to another group, in which case the
+    /// caller must rejoin.
+    #[inline]
+    pub fn renew_group(&self, membership: GroupMembership, now: Nanos) -> bool {
+        if !self.is_group_current(membership) {
+            return false;
+        }
+        let meta = &self.group_meta[usize::from(membership.slot)];
+        if now.saturating_sub(Nanos(meta.last_active.load(Ordering::Relaxed))) >=
+            LEASE_RENEW_INTERVAL
+        {
+            meta.last_active.store(now.0, Ordering::Relaxed);
+        }
+        true
+    }
+
+    /// Whether the slot behind `membership` still belongs to its group.
+    #[inline]
+    pub fn is_group_current(&self, membership: GroupMembership) -> bool {
+        self.group_meta[usize::from(membership.slot)].generation.load(Ordering::Relaxed) ==
+            membership.generation
+    }
+
+    /// Lease after which a group nobody renewed is reclaimed, `None` if
+    /// groups never expire.
+    pub fn group_lease(&self) -> Option<Duration> {
+        match self.group_lease_ns.load(Ordering::Relaxed) {
+            0 => Some(DEFAULT_GROUP_LEASE),
+            u64::MAX => None,
+            ns => Some(Duration::from_nanos(ns)),
+        }
+    }
+
+    pub fn set_group_lease(&self, lease: Option<Duration>) {
+        let ns = lease.map_or(u64::MAX, |l| (l.as_nanos() as u64).clamp(1, u64::MAX - 1));
+        self.group_lease_ns.store(ns, Ordering::Relaxed);
+    }
+
+    /// Moves the cursor of the group labelled `label`. Returns `false` if no
+    /// such group exists.
+    pub fn reset_group(&self, label: &str, cursor: usize) -> bool {
+        let Some(slot) = self.group_slot(label) else {
+            return false;
+        };
+        self.group_cursors[slot].cursor.store(cursor, Ordering::Relaxed);
+        true
+    }
+
+    /// Frees the slot of the group labelled `label`; live members rejoin
+    /// under a fresh slot the next time they renew their lease. Returns
+    /// `false` if no such group exists.
+    pub fn remove_group(&mut self, label: &str) -> bool {
+        self.acquire_group_lock();
+        let slot = self.group_slot(label);
+        if let Some(slot) = slot {
+            self.free_group_slot(slot);
+        }
+        self.release_group_lock();
+        slot.is_some()
+    }
+
+    /// Looks up the checkpoint `key`, returning it with the committed count.
+    pub fn checkpoint(&self, key: &str) -> Option<(GroupMembership, usize)> {
+        let slot = self.group_slot(key)?;
+        let meta = &self.group_meta[slot];
+        if GroupKind::from_u8(meta.kind.load(Ordering::Relaxed)) != GroupKind::Checkpoint {
+            return None;
+        }
+        let cursor = &self.group_cursors[slot].cursor;
+        let membership = GroupMembership {
+            cursor,
+            slot: slot as u16,
+            generation: meta.generation.load(Ordering::Relaxed),
+        };
+        Some((membership, cursor.load(Ordering::Acquire)))
+    }
+
+    /// Stores `count` as the checkpoint behind `membership`. Returns `false`
+    /// if the checkpoint was removed in the meantime, in which case the
+    /// caller must claim it again.
+    pub fn commit_checkpoint(&self, membership: GroupMembership, count: usize) -> bool {
+        let slot = usize::from(membership.slot);
+        let meta = &self.group_meta[slot];
+        if meta.generation.load(Ordering::Relaxed) != membership.generation {
+            return false;
+        }
+        self.group_cursors[slot].cursor.store(count, Ordering::Release);
+        meta.last_active.store(Nanos::now().0, Ordering::Relaxed);
+        true
+    }
+
+    fn group_slot(&self, label: &str) -> Option<usize> {
+        let key = ArrayStr::<GROUP_LABEL_LEN>::from_str_truncate(label);
+        (0..MAX_GROUPS).find(|&i| !key.is_empty() && self.group_labels[i] == key)
+    }
+
+    // Caller holds the group lock.
+    fn free_group_slot(&mut self, slot: usize) {
+        self.group_labels[slot] = ArrayStr::new();
+        let meta = &self.group_meta[slot];
+        meta.members.store(0, Ordering::Relaxed);
+        meta.owner_pid.store(0, Ordering::Relaxed);
+        meta.kind.store(GroupKind::Unknown as u8, Ordering::Relaxed);
+        meta.created.store(0, Ordering::Relaxed);
+        meta.last_active.store(0, Ordering::Relaxed);
+        meta.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether the group in `slot` may be reclaimed: its owner process is
+    /// gone, or its lease ran out. Groups from before group metadata existed
+    /// fall back to the pid in their `binary[PID]` label.
+    fn is_group_expired(&self, slot: usize, now: Nanos) -> bool {
+        let meta = &self.group_meta[slot];
+        // Checkpoints are there to outlive their process.
+        if GroupKind::from_u8(meta.kind.load(Ordering::Relaxed)) == GroupKind::Checkpoint {
+            return false;
+        }
+        let owner_alive = match meta.owner_pid.load(Ordering::Relaxed) {
+            0 => pid_from_label(self.group_labels[slot].as_str()).is_none_or(is_pid_alive),
+            pid => is_pid_alive(pid),
+        };
+        if !owner_alive {
+            return true;
+        }
+        let last_active = meta.last_active.load(Ordering::Relaxed);
+        self.group_lease().is_some_and(|lease| {
+            last_active != 0 && now.saturating_sub(Nanos(last_active)).0 > lease.as_nanos() as u64
+        })
+    }
+
+    /// All consumer groups with their metadata, including expired ones that
+    /// haven't been reclaimed yet.
     ///
     /// Queues created by older flux versions may not have the `group_labels`
     /// region initialised — the raw `ArrayStr::len` field will contain
     /// garbage.  We detect this (`len > GROUP_LABEL_LEN`) and bail early
     /// with an empty vec instead of letting `from_raw_parts` abort.
-    pub fn active_groups(&self) -> Vec<(&str, usize)> {
+    pub fn groups(&self) -> Vec<GroupInfo<'_>> {
+        let now = Nanos::now();
         let mut out = Vec::new();
         for i in 0..MAX_GROUPS {
             let label = &self.group_labels[i];
-
-            // Guard: if the stored length exceeds the fixed-size buffer the
-            // memory is uninitialised / from an incompatible header layout.
             if label.len() > GROUP_LABEL_LEN {
                 return out;
             }
-
-            if !label.is_empty() &&
-                pid_from_label(self.group_labels[i].as_str()).is_none_or(is_pid_alive)
-            {
-                let label = label.as_str();
-                let cursor = self.group_cursors[i].cursor.load(Ordering::Relaxed);
-                out.push((label, cursor));
+            if label.is_empty() {
+                continue;
             }
+            let meta = &self.group_meta[i];
+            out.push(GroupInfo {
+                slot: i,
+                label: label.as_str(),
+                cursor: self.group_cursors[i].cursor.load(Ordering::Relaxed),
+                kind: GroupKind::from_u8(meta.kind.load(Ordering::Relaxed)),
+                owner_pid: meta.owner_pid.load(Ordering::Relaxed),
+                members: meta.members.load(Ordering::Relaxed),
+                created: Nanos(meta.created.load(Ordering::Relaxed)),
+                last_active: Nanos(meta.last_active.load(Ordering::Relaxed)),
+                expired: self.is_group_expired(i, now),
+            });
         }
         out
     }
 
+    /// Returns all live consumer group slots as `(label, cursor_value)`
+    /// pairs.
+    pub fn active_groups(&self) -> Vec<(&str, usize)> {
+        self.groups().into_iter().filter(|g| !g.expired).map(|g| (g.label, g.cursor)).collect()
+    }
+
     pub fn max_writable_msgs_without_speeding_past(&self) -> usize {
         let mut min_cursor = self.count.load(Ordering::Relaxed);
+        let now = Nanos::now();
         for i in 0..MAX_GROUPS {
             let label = &self.group_labels[i];
 
@@ -233,8 +560,8 @@ impl QueueHeader {
                 return self.len();
             }
 
-            if !label.is_empty() &&
-                pid_from_label(self.group_labels[i].as_str()).is_none_or(is_pid_alive)
+            let kind = GroupKind::from_u8(self.group_meta[i].kind.load(Ordering::Relaxed));
+            if !label.is_empty() && kind != GroupKind::Checkpoint && !self.is_group_expired(i, now)
             {
                 min_cursor = min_cursor
                     .min(self.group_cursors[i].cursor.load(Ordering::Relaxed).saturating_sub(1));
@@ -244,23 +571,36 @@ impl QueueHeader {
     }
 }
 
-pub(crate) fn shmem_map_create_or_open(flink_path: &Path, size: usize) -> (*mut u8, bool, usize) {
+pub(crate) fn shmem_map_create_or_open(
+    flink_path: &Path,
+    size: usize,
+    options: MappingOptions,
+) -> (*mut u8, bool, usize) {
     let _ = std::fs::create_dir_all(flink_path.parent().unwrap());
+    let size = options.segment_size(size);
+    if let Some(mapped) = crate::memfd::map(flink_path, size) {
+        let (ptr, is_new, mapped_size) = mapped
+            .unwrap_or_else(|e| panic!("memfd segment for {} failed: {e}", flink_path.display()));
+        options.apply(ptr, mapped_size, flink_path);
+        return (ptr, is_new, mapped_size);
+    }
     match ShmemConf::new().size(size).flink(flink_path).create() {
         Ok(shmem) => {
             let ptr = shmem.as_ptr();
             std::mem::forget(shmem);
+            options.apply(ptr, size, flink_path);
             (ptr, true, size)
         }
         Err(ShmemError::LinkExists) => ShmemConf::new().flink(flink_path).open().map_or_else(
             |_| {
                 let _ = std::fs::remove_file(flink_path);
-                shmem_map_create_or_open(flink_path, size)
+                shmem_map_create_or_open(flink_path, size, options)
             },
             |shmem| {
                 let mapped_size = shmem.len();
                 let ptr = shmem.as_ptr();
                 std::mem::forget(shmem);
+                options.apply(ptr, mapped_size, flink_path);
                 (ptr, false, mapped_size)
             },
         ),
@@ -290,7 +630,7 @@ impl<T: Copy> InnerQueue<T> {
             );
             // Why real len you may ask. The size of the fat pointer ONLY includes the
             // length of the unsized part of the struct i.e. the buffer.
-            Self::from_uninitialized_ptr(ptr, real_len, queue_type)
+            Self::from_uninitialized_ptr(ptr, real_len, queue_type, TypeIdentity::unhashed::<T>())
         }
     }
 
@@ -298,7 +638,12 @@ impl<T: Copy> InnerQueue<T> {
         size_of::<QueueHeader>() + len * size_of::<Seqlock<T>>()
     }
 
-    fn from_uninitialized_ptr(ptr: *mut u8, len: usize, queue_type: QueueType) -> *const Self {
+    fn from_uninitialized_ptr(
+        ptr: *mut u8,
+        len: usize,
+        queue_type: QueueType,
+        identity: TypeIdentity,
+    ) -> *const Self {
         unsafe {
             let q = std::ptr::slice_from_raw_parts_mut(ptr, len) as *mut Self;
             let elsize = size_of::<Seqlock<T>>();
@@ -307,6 +652,7 @@ impl<T: Copy> InnerQueue<T> {
             (*q).header.queue_type = queue_type;
             (*q).header.mask = mask;
             (*q).header.elsize = elsize;
+            (*q).header.identity = identity;
             (*q).header.is_initialized = true as u8;
             (*q).header.count = AtomicUsize::new(0);
             q
@@ -377,11 +723,69 @@ impl<T: Copy> InnerQueue<T> {
     #[inline]
     fn produce(&self, item: &T) -> usize {
         let next_count = self.next_count();
-        let lock = self.load(next_count & self.header.mask);
+        let pos = next_count & self.header.mask;
+        let lock = self.load(pos);
         lock.write(item);
+        self.wake_waiters();
+        if pos == 0 {
+            self.header.touch_producer();
+        }
         next_count
     }
 
+    /// Wake consumers sleeping in `consume_until`. Only a load of a header
+    /// line the producer already owns until a consumer ever blocked on the
+    /// queue.
+    ///
+    /// From then on the fence orders the slot write before the load of
+    /// `waiters`; it pairs with the one in [`Self::sleep_until_produced`], so
+    /// either the producer sees the waiting bit or the consumer sees the new
+    /// slot version.
+    #[inline]
+    fn wake_waiters(&self) {
+        if self.header.waiters.load(Ordering::Relaxed) & BLOCKING == 0 {
+            return;
+        }
+        self.wake_blocking();
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn wake_blocking(&self) {
+        fence(Ordering::SeqCst);
+        let word = self.header.waiters.load(Ordering::Relaxed);
+        // Clears the waiting bit and bumps the wake sequence in one step.
+        if word & WAITING == WAITING &&
+            self.header
+                .waiters
+                .compare_exchange(
+                    word,
+                    word.wrapping_add(WAKE - WAITING),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            futex_wake(&self.header.waiters);
+        }
+    }
+
+    /// Sleep until a producer wakes us or `timeout` passes, unless the slot
+    /// at `pos` already holds `version`.
+    ///
+    /// A producer that read `waiters` before the first consumer set
+    /// `BLOCKING` skipped the fence and may not wake us; sleeps are capped
+    /// at `MAX_SLEEP` so its slot is re-checked soon after.
+    fn sleep_until_produced(&self, pos: usize, version: u64, timeout: Duration) {
+        let word =
+            self.header.waiters.fetch_or(BLOCKING | WAITING, Ordering::SeqCst) | BLOCKING | WAITING;
+        fence(Ordering::SeqCst);
+        if self.load(pos).version() >= version {
+            return;
+        }
+        futex_wait(&self.header.waiters, word, Some(timeout.min(MAX_SLEEP)));
+    }
+
     #[inline]
     fn consume(&self, el: &mut T, ri: usize, ri_ver: u64) -> Result<(), ReadError> {
         self.load(ri).read_with_version(el, ri_ver)
@@ -427,6 +831,7 @@ impl<T: Copy> InnerQueue<T> {
                 let lock = self.load(p);
                 if lock.version() & 1 == 1 {
                     lock.write_unpoison(item);
+                    self.wake_waiters();
                     p
                 } else {
                     self.produce(item)
@@ -454,28 +859,7 @@ impl<T: Copy> InnerQueue<T> {
         self.header.signal_on_produce.store(enabled as u8, Ordering::Relaxed);
     }
 
-    fn is_poisoned(&self) -> Option<usize> {
-        // We assume that nothing would take longer than 10 micros to be written.
-        // If it does that means that nothing will actually be written and the queue is
-        // poisoned.
-        const MAX_CHECK_TIME: Duration = Duration::from_micros(10);
-        for i in 0..=self.header.mask {
-            let lck = self.load(i);
-
-            if lck.version() & 1 == 0 {
-                continue;
-            }
-            let curt = Instant::now();
-            while lck.version() & 1 == 1 {
-                if curt.elapsed() > MAX_CHECK_TIME {
-                    return Some(i);
-                }
-            }
-        }
-        None
-    }
-
-    fn validate(&self, len: usize) -> Result<(), QueueError> {
+    fn validate(&self, len: usize, identity: &TypeIdentity) -> Result<(), QueueError> {
         let elsize = std::mem::size_of::<Seqlock<T>>();
         if self.header.len() < len {
             return Err(QueueError::TooSmall);
@@ -486,43 +870,54 @@ impl<T: Copy> InnerQueue<T> {
         if self.header.elsize != elsize {
             return Err(QueueError::ElementSizeChanged(self.header.elsize, elsize));
         }
-        if let Some(poisoned_element) = self.is_poisoned() {
-            return Err(QueueError::ElementPoisoned(poisoned_element));
-        }
-        Ok(())
+        self.header.identity.verify(identity)
     }
 
+    /// Type mismatches are returned to the caller: the segment belongs to a
+    /// different type, so recreating it would pull it out from under the
+    /// processes that are using it. Any other issue (old header layout,
+    /// changed element size) removes and recreates the queue. Poisoned slots
+    /// are left to the repair of the next producer attaching, see
+    /// [`QueueHeader::repair_poisoned`].
     fn create_or_open_shared<P: AsRef<Path>>(
         shmem_file: P,
         mut len: usize,
         typ: QueueType,
-    ) -> *const Self {
+        identity: TypeIdentity,
+        options: MappingOptions,
+    ) -> Result<*const Self, QueueError> {
         len = len.next_power_of_two();
-        let (ptr, is_new, _) = shmem_map_create_or_open(shmem_file.as_ref(), Self::size_of(len));
+        let (ptr, is_new, _) =
+            shmem_map_create_or_open(shmem_file.as_ref(), Self::size_of(len), options);
         if is_new {
-            return Self::from_uninitialized_ptr(ptr, len, typ);
+            return Ok(Self::from_uninitialized_ptr(ptr, len, typ, identity));
+        }
+        match Self::open_initialized(ptr, len, &identity) {
+            Ok(v) => Ok(v),
+            Err(e @ QueueError::TypeMismatch { .. }) => Err(e),
+            Err(e) => {
+                tracing::error!(
+                    "issue with preexisting shmem at {:?}: {e}. Removing and recreating. Should probably upgrade and reattach any other processes.",
+                    shmem_file.as_ref()
+                );
+                crate::memfd::remove_segment(shmem_file.as_ref());
+                Self::create_or_open_shared(shmem_file, len, typ, identity, options)
+            }
         }
-        let Ok(v) = Self::open_initialized(ptr, len).inspect_err(|e| {
-            tracing::error!(
-                "issue with preexisting shmem at {:?}: {e}. Removing and recreating. Should probably upgrade and reattach any other processes.",
-                shmem_file.as_ref()
-            );
-        }) else {
-            let _ = std::fs::remove_file(shmem_file.as_ref());
-            return Self::create_or_open_shared(shmem_file, len, typ);
-        };
-        v
     }
 
-    fn open_shared<S: AsRef<Path>>(shmem_file: S) -> Result<*const Self, QueueError> {
+    fn open_shared<S: AsRef<Path>>(
+        shmem_file: S,
+        identity: &TypeIdentity,
+    ) -> Result<*const Self, QueueError> {
         let path = shmem_file.as_ref();
         if !path.exists() {
             return Err(QueueError::NonExistingFile);
         }
         let shmem = ShmemConf::new().flink(path).open()?;
         let ptr = shmem.as_ptr();
-        // len=0: skip floor check, still validates elsize + poisoned slots.
-        let opened = Self::open_initialized(ptr, 0);
+        // len=0: skip floor check, still validates elsize + identity.
+        let opened = Self::open_initialized(ptr, 0, identity);
         if opened.is_ok() {
             std::mem::forget(shmem);
         }
@@ -530,7 +925,11 @@ impl<T: Copy> InnerQueue<T> {
     }
 
     // Wait for the queue at `ptr` to finish initialising, then validate it.
-    fn open_initialized(ptr: *mut u8, len: usize) -> Result<*const Self, QueueError> {
+    fn open_initialized(
+        ptr: *mut u8,
+        len: usize,
+        identity: &TypeIdentity,
+    ) -> Result<*const Self, QueueError> {
         #[allow(clippy::cast_ptr_alignment)]
         let header = ptr.cast::<QueueHeader>();
         let mut tries = 0;
@@ -542,7 +941,7 @@ impl<T: Copy> InnerQueue<T> {
             }
         }
         let v = Self::from_initialized_ptr(header)?;
-        unsafe { (&*v).validate(len) }?;
+        unsafe { (&*v).validate(len, identity) }?;
         Ok(v)
     }
     pub fn max_writable_msgs_without_speeding_past(&self) -> usize {
@@ -565,55 +964,137 @@ pub struct Queue<T> {
     inner: *const InnerQueue<T>,
 }
 
-impl<T: Copy> Queue<T> {
-    pub fn new(len: usize, queue_type: QueueType) -> Self {
-        Self { inner: InnerQueue::new(len, queue_type) }
-    }
-
+impl<T: Copy + TypeHash> Queue<T> {
+    /// Creates the queue at `shmem_file`, or opens it if it already exists.
+    ///
+    /// Fails with [`QueueError::TypeMismatch`] if the existing queue was
+    /// created for a different element type.
     pub fn create_or_open_shared<P: AsRef<Path>>(
         shmem_file: P,
         len: usize,
         queue_type: QueueType,
-    ) -> Self {
+    ) -> Result<Self, QueueError> {
+        Self::create_or_open_shared_with_identity(
+            shmem_file,
+            len,
+            queue_type,
+            TypeIdentity::of::<T>(),
+        )
+    }
+
+    /// Like [`Self::create_or_open_shared`] but backs the segment according
+    /// to `options` (huge pages, prefaulting, `mlock`).
+    pub fn create_or_open_shared_with_options<P: AsRef<Path>>(
+        shmem_file: P,
+        len: usize,
+        queue_type: QueueType,
+        options: MappingOptions,
+    ) -> Result<Self, QueueError> {
         let shmem_file = shmem_file.as_ref();
-        Self { inner: InnerQueue::create_or_open_shared(shmem_file, len, queue_type) }
+        let inner = InnerQueue::create_or_open_shared(
+            shmem_file,
+            len,
+            queue_type,
+            TypeIdentity::of::<T>(),
+            options,
+        )?;
+        Ok(Self { inner })
     }
 
     pub fn open_shared<P: AsRef<Path>>(shmem_file: P) -> Self {
-        let shmem_file = shmem_file.as_ref();
-        Self {
-            inner: InnerQueue::open_shared(shmem_file)
-                .expect("Couldn't open shared queue, was it initialized?"),
-        }
+        Self::try_open_shared(shmem_file).expect("Couldn't open shared queue, was it initialized?")
     }
 
     /// Open an existing shared-memory queue, returning an error instead of
-    /// panicking if the queue is invalid (e.g. poisoned, wrong element size).
+    /// panicking if the queue is invalid (e.g. wrong element size).
     pub fn try_open_shared<P: AsRef<Path>>(shmem_file: P) -> Result<Self, QueueError> {
-        let shmem_file = shmem_file.as_ref();
-        Ok(Self { inner: InnerQueue::open_shared(shmem_file)? })
-    }
-
-    /// Size in bytes of the queue region for `len` slots
-    pub(crate) fn byte_size(len: usize) -> usize {
-        InnerQueue::<T>::size_of(len)
+        Self::try_open_shared_with_identity(shmem_file, &TypeIdentity::of::<T>())
     }
 
     /// Initialise a new queue at a pre-mapped pointer. The caller owns the
     /// mapping.
     pub(crate) fn from_raw_init(ptr: *mut u8, len: usize, typ: QueueType) -> Self {
-        Self { inner: InnerQueue::from_uninitialized_ptr(ptr, len, typ) }
+        Self { inner: InnerQueue::from_uninitialized_ptr(ptr, len, typ, TypeIdentity::of::<T>()) }
     }
 
     /// Open an existing queue at a pre-mapped pointer. Waits for init and
-    /// validates element size + length.
+    /// validates element size, identity + length.
     pub(crate) fn from_raw_open(ptr: *mut u8, len: usize) -> Result<Self, QueueError> {
-        let inner = InnerQueue::<T>::open_initialized(ptr, len)?;
+        let inner = InnerQueue::<T>::open_initialized(ptr, len, &TypeIdentity::of::<T>())?;
+        Ok(Self { inner })
+    }
+}
+
+impl<T: Copy> Queue<T> {
+    pub fn new(len: usize, queue_type: QueueType) -> Self {
+        Self { inner: InnerQueue::new(len, queue_type) }
+    }
+
+    /// Like [`Self::create_or_open_shared`] but records and verifies
+    /// `identity`. Element types that cannot implement `TypeHash` opt out
+    /// of layout hashing by passing [`TypeIdentity::unhashed`].
+    pub fn create_or_open_shared_with_identity<P: AsRef<Path>>(
+        shmem_file: P,
+        len: usize,
+        queue_type: QueueType,
+        identity: TypeIdentity,
+    ) -> Result<Self, QueueError> {
+        let shmem_file = shmem_file.as_ref();
+        let inner = InnerQueue::create_or_open_shared(
+            shmem_file,
+            len,
+            queue_type,
+            identity,
+            MappingOptions::default(),
+        )?;
         Ok(Self { inner })
     }
 
-    fn group_cursor(&self, key: &str) -> *const AtomicUsize {
-        unsafe { &mut *self.inner.cast_mut() }.header.find_or_insert_group(key)
+    /// Like [`Self::try_open_shared`] but verifies against `identity`, see
+    /// [`Self::create_or_open_shared_with_identity`].
+    pub fn try_open_shared_with_identity<P: AsRef<Path>>(
+        shmem_file: P,
+        identity: &TypeIdentity,
+    ) -> Result<Self, QueueError> {
+        Ok(Self { inner: InnerQueue::open_shared(shmem_file, identity)? })
+    }
+
+    /// Size in bytes of the queue region for `len` slots
+    pub(crate) fn byte_size(len: usize) -> usize {
+        InnerQueue::<T>::size_of(len)
+    }
+
+    pub(crate) fn join_group(&self, key: &str, kind: GroupKind) -> GroupMembership {
+        unsafe { &mut *self.inner.cast_mut() }.header.join_group(key, kind)
+    }
+
+    pub(crate) fn leave_group(&self, membership: GroupMembership) {
+        unsafe { &mut *self.inner.cast_mut() }.header.leave_group(membership);
+    }
+
+    /// Claims the checkpoint `key` without joining it: checkpoints have no
+    /// members and stay until removed.
+    pub(crate) fn claim_checkpoint(&self, key: &str) -> GroupMembership {
+        unsafe { &mut *self.inner.cast_mut() }.header.claim_group(key, GroupKind::Checkpoint, false)
+    }
+
+    /// See [`QueueHeader::set_group_lease`].
+    pub fn set_group_lease(&self, lease: Option<Duration>) {
+        self.header.set_group_lease(lease);
+    }
+
+    /// See [`QueueHeader::reset_group`].
+    pub fn reset_group(&self, label: &str, cursor: usize) -> bool {
+        self.header.reset_group(label, cursor)
+    }
+
+    /// See [`QueueHeader::remove_group`].
+    pub fn remove_group(&self, label: &str) -> bool {
+        unsafe { &mut *self.inner.cast_mut() }.header.remove_group(label)
+    }
+
+    pub fn groups(&self) -> Vec<GroupInfo<'_>> {
+        self.header.groups()
     }
 
     /// Advance the collaborative-group cursor for `label` to the producer's
@@ -632,7 +1113,12 @@ impl<T: Copy> Queue<T> {
     /// attaching the group's tiles.
     pub fn fast_forward_collaborative_group(&self, label: &str) {
         let key = format!("{}[{}].{}.collab", binary_name(), current_pid(), label);
-        let cursor = self.group_cursor(&key);
+        // Claimed on behalf of the group's future members, who join it when
+        // they first consume.
+        let cursor = unsafe { &mut *self.inner.cast_mut() }
+            .header
+            .claim_group(&key, GroupKind::Collaborative, false)
+            .cursor;
         let head = self.count();
         unsafe { (*cursor).fetch_max(head, Ordering::Relaxed) };
     }
@@ -673,7 +1159,11 @@ pub struct Producer<T> {
     pub queue: Queue<T>,
 }
 impl<T: Copy> From<Queue<T>> for Producer<T> {
+    /// The first producer a process attaches repairs slots a dead producer
+    /// left mid-write, see [`QueueHeader::repair_poisoned`].
     fn from(queue: Queue<T>) -> Self {
+        queue.header.touch_producer();
+        queue.header.repair_on_restart();
         Self { produced_first: 0, queue }
     }
 }
@@ -721,14 +1211,21 @@ impl<T> AsMut<Self> for Producer<T> {
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct ConsumerBare<T> {
-    pos: usize,                 // 8
-    mask: usize,                // 16
-    expected_version: u64,      // 24
-    is_running: u8,             // 25
-    _pad: [u8; 7],              // 32
-    cursor: *const AtomicUsize, // 40
-    label: &'static str,        // 56 (ptr + len)
-    queue: Queue<T>,            // 64 fat ptr: (usize, pointer)
+    pos: usize,                     // 8
+    mask: usize,                    // 16
+    expected_version: u64,          // 24
+    is_running: u8,                 // 25
+    _pad: u8,                       // 26
+    group_slot: u16,                // 28
+    group_generation: u32,          // 32 — 0 while not in a group
+    checkpoint_slot: u16,           // 34
+    checkpoint_id: u16,             // 36 — broadcast id the checkpoint key was made with
+    checkpoint_generation: u32,     // 40 — 0 while nothing was committed
+    renew_at: flux_timing::Instant, // 48 — TSC deadline of the next lease renewal
+    lost_since_checkpoint: u64,     // 56 — overwritten before resuming, see resume_from_checkpoint
+    cursor: *const AtomicUsize,     // 64
+    label: &'static str,            // 80 (ptr + len)
+    queue: Queue<T>,                // 88 fat ptr: (usize, pointer)
 }
 
 unsafe impl<T> Send for ConsumerBare<T> {}
@@ -740,7 +1237,14 @@ impl<T: Copy> ConsumerBare<T> {
             mask: queue.header.mask,
             expected_version: 0,
             is_running: 1,
-            _pad: [0; 7],
+            _pad: 0,
+            group_slot: 0,
+            group_generation: 0,
+            checkpoint_slot: 0,
+            checkpoint_id: 0,
+            checkpoint_generation: 0,
+            renew_at: flux_timing::Instant::ZERO,
+            lost_since_checkpoint: 0,
             cursor: std::ptr::null(),
             label,
             queue,
@@ -772,12 +1276,70 @@ impl<T: Copy> ConsumerBare<T> {
     }
 
     pub fn set_collaborative_cursor(&mut self, cursor: *const AtomicUsize) {
+        self.leave_group();
         self.cursor = cursor;
     }
 
     #[inline]
     pub fn recover_after_error(&mut self) {
-        self.set_broadcast_pos(self.queue.count());
+        self.renew_group();
+        if self.cursor.is_null() {
+            self.init_broadcast();
+        } else {
+            self.set_broadcast_pos(self.queue.count());
+        }
+    }
+
+    fn join_group(&mut self, key: &str, kind: GroupKind) {
+        let membership = self.queue.join_group(key, kind);
+        self.cursor = membership.cursor;
+        self.group_slot = membership.slot;
+        self.group_generation = membership.generation;
+    }
+
+    const fn membership(&self) -> GroupMembership {
+        GroupMembership {
+            cursor: self.cursor,
+            slot: self.group_slot,
+            generation: self.group_generation,
+        }
+    }
+
+    /// Keeps the group's lease alive. If the slot was reclaimed in the
+    /// meantime the cursor is dropped so the next consume rejoins.
+    ///
+    /// Called on empty polls and once per lap of the ring; owners of
+    /// consumers that may go unpolled for longer than the lease, e.g. parked
+    /// tiles, call it periodically. The slot is checked every time, the
+    /// clock is only read once the TSC says a renewal is due.
+    #[inline]
+    pub fn renew_group(&mut self) {
+        if self.group_generation == 0 {
+            return;
+        }
+        let header = &self.queue.header;
+        let current = if lease_renewal_due(&mut self.renew_at) {
+            header.renew_group(self.membership(), Nanos::now())
+        } else {
+            header.is_group_current(self.membership())
+        };
+        if !current {
+            self.cursor = std::ptr::null();
+            self.group_generation = 0;
+        }
+    }
+
+    /// Leaves the consumer group, freeing its slot if this was the last
+    /// member. The next consume joins it again.
+    ///
+    /// Consumers are `Copy` so this doesn't happen on drop; owners call it
+    /// when they are done with the queue.
+    pub fn leave_group(&mut self) {
+        if self.group_generation != 0 {
+            self.queue.leave_group(self.membership());
+            self.cursor = std::ptr::null();
+            self.group_generation = 0;
+        }
     }
 
     #[inline]
@@ -801,6 +1363,11 @@ impl<T: Copy> ConsumerBare<T> {
     #[inline]
     fn acquire_next_slot(&mut self) {
         self.acquire_specific_slot(1);
+        // A consumer that never catches up doesn't hit the empty paths,
+        // renew once per lap of the ring instead.
+        if self.pos == 0 {
+            self.renew_group();
+        }
     }
 
     #[inline]
@@ -825,18 +1392,99 @@ impl<T: Copy> ConsumerBare<T> {
     #[inline(never)]
     fn init_broadcast(&mut self) {
         let id = broadcast_id_for(self.label, std::any::type_name::<T>());
+        let first_init = self.pos == usize::MAX;
+        if first_init {
+            self.checkpoint_id = id as u16;
+        }
+        let id = if id == 0 { "" } else { &format!(".{id}") };
+
+        self.join_group(
+            &format!("{}[{}].{}{}.broadcast", binary_name(), current_pid(), self.label, id),
+            GroupKind::Broadcast,
+        );
+
+        // Never restore from the group cursor, it belongs to this process;
+        // only a committed checkpoint carries over a restart.
+        let head = self.queue.count();
+        let start = if first_init { self.resume_from_checkpoint(head) } else { head };
+        self.set_broadcast_pos(start);
+    }
+
+    /// Key of this consumer's checkpoint. Unlike group keys it has no pid, so
+    /// the next process running the same binary finds it.
+    fn checkpoint_key(&self) -> String {
+        let id = self.checkpoint_id;
         let id = if id == 0 { "" } else { &format!(".{id}") };
+        format!("{}.{}{}.checkpoint", binary_name(), self.label, id)
+    }
 
-        self.cursor = self.queue.group_cursor(&format!(
-            "{}[{}].{}{}.broadcast",
-            binary_name(),
-            current_pid(),
-            self.label,
-            id
-        ));
+    /// Where a freshly attached consumer starts: at its committed checkpoint
+    /// if those messages are still in the ring, otherwise at the oldest one
+    /// that is, recording how many were lost in
+    /// [`lost_since_checkpoint`](Self::lost_since_checkpoint).
+    fn resume_from_checkpoint(&mut self, head: usize) -> usize {
+        let Some((membership, committed)) = self.queue.header.checkpoint(&self.checkpoint_key())
+        else {
+            return head;
+        };
+        self.checkpoint_slot = membership.slot;
+        self.checkpoint_generation = membership.generation;
 
-        // Always set current producer position without restoring value from cursor
-        self.set_broadcast_pos(self.queue.count());
+        let oldest = head.saturating_sub(self.queue.len());
+        if committed > head {
+            // The queue was recreated since.
+            return head;
+        }
+        if committed < oldest {
+            self.lost_since_checkpoint = (oldest - committed) as u64;
+            tracing::warn!(
+                "Consumer<{}> {} lost {} messages since its checkpoint, they were overwritten",
+                std::any::type_name::<T>(),
+                self.label,
+                self.lost_since_checkpoint
+            );
+            return oldest;
+        }
+        committed
+    }
+
+    /// Messages that were overwritten between the committed checkpoint and
+    /// this consumer attaching, 0 if it resumed without a gap or there was no
+    /// checkpoint. Only known once the consumer has attached, i.e. after the
+    /// first consume.
+    #[inline]
+    pub fn lost_since_checkpoint(&self) -> u64 {
+        self.lost_since_checkpoint
+    }
+
+    /// Commits everything consumed so far: a consumer with the same label
+    /// attaching after a restart resumes right after it.
+    ///
+    /// Opt-in; the first commit claims a group slot for the checkpoint, which
+    /// stays until removed with [`QueueHeader::remove_group`].
+    pub fn commit(&mut self) {
+        if self.pos == usize::MAX {
+            return;
+        }
+        let count = self.queue.count_at(self.pos, self.expected_version);
+        let membership = GroupMembership {
+            cursor: std::ptr::null(),
+            slot: self.checkpoint_slot,
+            generation: self.checkpoint_generation,
+        };
+        if self.checkpoint_generation != 0 && self.queue.header.commit_checkpoint(membership, count)
+        {
+            return;
+        }
+        self.claim_checkpoint(count);
+    }
+
+    #[cold]
+    fn claim_checkpoint(&mut self, count: usize) {
+        let membership = self.queue.claim_checkpoint(&self.checkpoint_key());
+        self.checkpoint_slot = membership.slot;
+        self.checkpoint_generation = membership.generation;
+        self.queue.header.commit_checkpoint(membership, count);
     }
 
     #[inline]
@@ -848,22 +1496,17 @@ impl<T: Copy> ConsumerBare<T> {
 
     #[inline(never)]
     fn init_collaborative(&mut self) {
-        self.cursor = self.queue.group_cursor(&format!(
-            "{}[{}].{}.collab",
-            binary_name(),
-            current_pid(),
-            self.label,
-        ));
+        self.join_group(
+            &format!("{}[{}].{}.collab", binary_name(), current_pid(), self.label),
+            GroupKind::Collaborative,
+        );
         self.acquire_next_slot();
     }
 
     /// Nonblocking consume returning either Ok(()) or a `ReadError`
     #[inline]
     pub fn try_consume(&mut self, el: &mut T) -> Result<(), ReadError> {
-        self.try_init_broadcast();
-        self.queue.consume(el, self.pos, self.expected_version)?;
-        self.acquire_next_slot();
-        Ok(())
+        self.try_consume_with_epoch(el).map(|_| ())
     }
 
     /// Like `try_consume` but also returns `(slot_pos, slot_version)` for the
@@ -871,11 +1514,35 @@ impl<T: Copy> ConsumerBare<T> {
     #[inline]
     pub fn try_consume_with_epoch(&mut self, el: &mut T) -> Result<(usize, u64), ReadError> {
         self.try_init_broadcast();
-        let slot_pos = self.pos;
-        let slot_ver = self.expected_version;
-        self.queue.consume(el, slot_pos, slot_ver)?;
-        self.acquire_next_slot();
-        Ok((slot_pos, slot_ver))
+        loop {
+            let slot_pos = self.pos;
+            let slot_ver = self.expected_version;
+            if let Err(e) = self.queue.consume(el, slot_pos, slot_ver) {
+                if e == ReadError::Empty {
+                    self.renew_group();
+                }
+                return Err(e);
+            }
+            self.acquire_next_slot();
+            if !self.skip_tombstone(slot_pos, slot_ver) {
+                return Ok((slot_pos, slot_ver));
+            }
+        }
+    }
+
+    /// Whether the message just read is a tombstone left by slot repair, see
+    /// [`QueueHeader::repair_poisoned`]. Those are reported and skipped.
+    #[inline]
+    fn skip_tombstone(&self, slot_pos: usize, slot_ver: u64) -> bool {
+        if self.queue.header.is_tombstone(slot_pos, slot_ver) {
+            self.queue.header.report_tombstone_skipped(
+                slot_pos,
+                slot_ver,
+                std::any::type_name::<T>(),
+            );
+            return true;
+        }
+        false
     }
 
     #[inline]
@@ -904,6 +1571,44 @@ impl<T: Copy> ConsumerBare<T> {
         }
     }
 
+    /// Blocking consume that gives up once `deadline` (on the global
+    /// [`Nanos`] clock) has passed.
+    ///
+    /// Spins briefly, then yields, then sleeps on a futex in the queue header
+    /// until a producer wakes it, so it is meant for consumers off the hot
+    /// path that shouldn't burn a core while idle.
+    pub fn consume_until(&mut self, el: &mut T, deadline: Nanos) -> Result<(), TimeoutError> {
+        let mut backoff = Backoff::default();
+        loop {
+            match self.try_consume(el) {
+                Ok(()) => return Ok(()),
+                Err(ReadError::Empty) => self.wait_for_message(&mut backoff, deadline)?,
+                Err(ReadError::SpedPast) => self.recover_after_error(),
+            }
+        }
+    }
+
+    /// [`Self::consume_until`] with a deadline `timeout` from now.
+    pub fn consume_timeout(&mut self, el: &mut T, timeout: Duration) -> Result<(), TimeoutError> {
+        self.consume_until(el, Nanos::now() + Nanos::from(timeout.as_nanos()))
+    }
+
+    /// One backoff step after finding the queue empty.
+    fn wait_for_message(&self, backoff: &mut Backoff, deadline: Nanos) -> Result<(), TimeoutError> {
+        let now = Nanos::now();
+        if now >= deadline {
+            return Err(TimeoutError::TimedOut);
+        }
+        if backoff.snooze() {
+            self.queue.sleep_until_produced(
+                self.pos,
+                self.expected_version,
+                (deadline - now).into(),
+            );
+        }
+        Ok(())
+    }
+
     pub fn set_collaborative_group(&mut self, group_label: &'static str) {
         self.label = group_label;
     }
@@ -915,6 +1620,13 @@ impl<T: Copy> ConsumerBare<T> {
             (*consumer_ptr).expected_version = 0;
             (*consumer_ptr).mask = queue.header.mask;
             (*consumer_ptr).cursor = std::ptr::null();
+            (*consumer_ptr).group_slot = 0;
+            (*consumer_ptr).group_generation = 0;
+            (*consumer_ptr).checkpoint_slot = 0;
+            (*consumer_ptr).checkpoint_id = 0;
+            (*consumer_ptr).checkpoint_generation = 0;
+            (*consumer_ptr).renew_at = flux_timing::Instant::ZERO;
+            (*consumer_ptr).lost_since_checkpoint = 0;
             (*consumer_ptr).label = label;
             (*consumer_ptr).queue = queue;
         }
@@ -933,8 +1645,12 @@ impl<T: Copy> ConsumerBare<T> {
             return Err(EmptyError::Empty);
         }
 
-        self.queue.consume_always(message, self.get_pos(last_count))?;
+        let pos = self.get_pos(last_count);
+        self.queue.consume_always(message, pos)?;
         self.set_broadcast_pos(last_count + 1);
+        if self.queue.header.is_tombstone(pos, self.queue.version_at(last_count)) {
+            return Err(EmptyError::Empty);
+        }
         Ok(())
     }
 }
@@ -997,6 +1713,27 @@ impl<T: 'static + Copy> Consumer<T> {
         }
     }
 
+    /// Like [`Self::consume`] but blocks until a message was handled by `f`
+    /// or `deadline` passed. See [`ConsumerBare::consume_until`].
+    pub fn consume_until<F>(&mut self, deadline: Nanos, mut f: F) -> Result<(), TimeoutError>
+    where
+        F: FnMut(&mut T),
+    {
+        let mut backoff = Backoff::default();
+        while !self.consume(&mut f) {
+            self.bare.wait_for_message(&mut backoff, deadline)?;
+        }
+        Ok(())
+    }
+
+    /// [`Self::consume_until`] with a deadline `timeout` from now.
+    pub fn consume_timeout<F>(&mut self, timeout: Duration, f: F) -> Result<(), TimeoutError>
+    where
+        F: FnMut(&mut T),
+    {
+        self.consume_until(Nanos::now() + Nanos::from(timeout.as_nanos()), f)
+    }
+
     #[inline]
     pub fn consume_last<F>(&mut self, mut f: F) -> bool
     where
@@ -1030,18 +1767,20 @@ impl<T: 'static + Copy> Consumer<T> {
     {
         self.bare.try_init_collaborative();
 
-        match self
-            .bare
-            .queue
-            .load(self.bare.pos)
-            .read_with_version(&mut self.message, self.bare.expected_version)
-        {
+        let (slot_pos, slot_ver) = (self.bare.pos, self.bare.expected_version);
+        match self.bare.queue.load(slot_pos).read_with_version(&mut self.message, slot_ver) {
             Ok(()) => {
-                f(&mut self.message);
+                let tombstone = self.bare.skip_tombstone(slot_pos, slot_ver);
+                if !tombstone {
+                    f(&mut self.message);
+                }
                 self.bare.acquire_next_slot();
-                true
+                !tombstone
+            }
+            Err(ReadError::Empty) => {
+                self.bare.renew_group();
+                false
             }
-            Err(ReadError::Empty) => false,
             Err(ReadError::SpedPast) => {
                 if self.should_log {
                     safe_panic!(
@@ -1065,11 +1804,15 @@ impl<T: 'static + Copy> Consumer<T> {
     #[inline]
     pub fn try_consume_with_epoch_collaborative(&mut self) -> Result<(&T, usize, u64), ReadError> {
         self.bare.try_init_collaborative();
-        let slot_pos = self.bare.pos;
-        let slot_ver = self.bare.expected_version;
-        self.bare.queue.consume(&mut self.message, slot_pos, slot_ver)?;
-        self.bare.acquire_next_slot();
-        Ok((&self.message, slot_pos, slot_ver))
+        loop {
+            let slot_pos = self.bare.pos;
+            let slot_ver = self.bare.expected_version;
+            self.bare.queue.consume(&mut self.message, slot_pos, slot_ver)?;
+            self.bare.acquire_next_slot();
+            if !self.bare.skip_tombstone(slot_pos, slot_ver) {
+                return Ok((&self.message, slot_pos, slot_ver));
+            }
+        }
     }
 
     #[inline]
@@ -1084,7 +1827,34 @@ impl<T: 'static + Copy> Consumer<T> {
 
     #[inline]
     pub fn recover_collaborative_after_error(&mut self) {
-        self.bare.acquire_earliest_available_slot();
+        self.bare.renew_group();
+        if self.bare.cursor.is_null() {
+            self.bare.init_collaborative();
+        } else {
+            self.bare.acquire_earliest_available_slot();
+        }
+    }
+
+    /// See [`ConsumerBare::renew_group`].
+    #[inline]
+    pub fn renew_group(&mut self) {
+        self.bare.renew_group();
+    }
+
+    /// See [`ConsumerBare::leave_group`].
+    pub fn leave_group(&mut self) {
+        self.bare.leave_group();
+    }
+
+    /// See [`ConsumerBare::commit`].
+    pub fn commit(&mut self) {
+        self.bare.commit();
+    }
+
+    /// See [`ConsumerBare::lost_since_checkpoint`].
+    #[inline]
+    pub fn lost_since_checkpoint(&self) -> u64 {
+        self.bare.lost_since_checkpoint()
     }
 
     #[inline]
@@ -1106,6 +1876,14 @@ impl<T: 'static + Copy> Consumer<T> {
     }
 }
 
+mod conflate;
+mod repair;
+mod varlen;
+pub use conflate::{ConflatingConsumer, ConflatingQueue};
+pub use repair::{MAX_PRODUCERS, RESTART_REPAIR_GRACE, RepairReport};
+use repair::{ProducerLease, RepairLog};
+pub use varlen::{VarlenConsumer, VarlenHeader, VarlenQueue};
+
 #[cfg(test)]
 mod tests_basic;
 
@@ -0,0 +1,257 @@
+//! Repair of seqlock slots left at an odd version by a producer that died
+//! mid-write.
+//!
+//! Such a slot stalls every consumer waiting on it and, on the next lap,
+//! the producer itself (an SPMC write keeps the version odd, an MPMC write
+//! spins on it). Repair bumps the slot to the even version the interrupted
+//! write would have published and records its message count as a tombstone,
+//! which consumers skip and report instead of handing out the half-written
+//! message.
+
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use flux_timing::Nanos;
+
+use super::{QueueHeader, current_pid, is_pid_alive};
+
+/// Producer processes tracked per queue, see [`QueueHeader::producers`].
+pub const MAX_PRODUCERS: usize = 8;
+/// Tombstones consumers check against; older ones are forgotten, by then
+/// every consumer has long been sped past them.
+const MAX_TOMBSTONES: usize = 4;
+/// How long a slot must stay at the same odd version before a restarting
+/// producer repairs it.
+pub const RESTART_REPAIR_GRACE: Duration = Duration::from_millis(1);
+
+/// A process producing to a queue. Renewed once per lap of the ring.
+#[derive(Debug)]
+#[repr(C)]
+pub(super) struct ProducerLease {
+    pub(super) pid: AtomicU32,
+    _pad: u32,
+    pub(super) last_active: AtomicU64, // Nanos
+}
+
+#[derive(Debug)]
+#[repr(C, align(64))]
+pub(super) struct RepairLog {
+    /// Highest slot version that was ever tombstoned; consumers only look
+    /// the tombstones up for versions up to it.
+    latest_version: AtomicU64,
+    repaired: AtomicU64,
+    skipped: AtomicU64,
+    /// Last process that checked the queue for poisoned slots on startup.
+    pub(super) checked_by: AtomicU32,
+    _pad: u32,
+    /// Message count + 1 of the latest tombstones, 0 if unused.
+    counts: [AtomicU64; MAX_TOMBSTONES],
+}
+
+/// Outcome of [`QueueHeader::repair_poisoned`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Message counts of the slots that were tombstoned.
+    pub repaired: Vec<usize>,
+    /// Poisoned slots left alone because a producer in another process is
+    /// still alive and may own them.
+    pub writer_alive: usize,
+}
+
+impl QueueHeader {
+    /// Registers the calling process as a producer, or renews its lease.
+    #[cold]
+    pub(super) fn touch_producer(&self) {
+        let pid = current_pid();
+        let now = Nanos::now();
+        if let Some(lease) = self.producers.iter().find(|l| l.pid.load(Ordering::Relaxed) == pid) {
+            lease.last_active.store(now.0, Ordering::Relaxed);
+            return;
+        }
+        for lease in &self.producers {
+            let old = lease.pid.load(Ordering::Relaxed);
+            if (old == 0 || !self.producer_alive(lease, now)) &&
+                lease.pid.compare_exchange(old, pid, Ordering::AcqRel, Ordering::Relaxed).is_ok()
+            {
+                lease.last_active.store(now.0, Ordering::Relaxed);
+                return;
+            }
+        }
+        // All entries held by live producers: this one just goes untracked,
+        // which only makes repair less cautious about it.
+    }
+
+    fn producer_alive(&self, lease: &ProducerLease, now: Nanos) -> bool {
+        let pid = lease.pid.load(Ordering::Relaxed);
+        if pid == 0 || !is_pid_alive(pid) {
+            return false;
+        }
+        let last_active = Nanos(lease.last_active.load(Ordering::Relaxed));
+        self.group_lease()
+            .is_none_or(|lease| now.saturating_sub(last_active).0 <= lease.as_nanos() as u64)
+    }
+
+    /// Pids of the producers with a live lease, the calling process included.
+    pub fn producers(&self) -> Vec<u32> {
+        let now = Nanos::now();
+        self.producers
+            .iter()
+            .filter(|l| self.producer_alive(l, now))
+            .map(|l| l.pid.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// `(repaired, skipped)`: slots tombstoned so far and how often consumers
+    /// skipped one.
+    pub fn tombstone_stats(&self) -> (u64, u64) {
+        (
+            self.repair_log.repaired.load(Ordering::Relaxed),
+            self.repair_log.skipped.load(Ordering::Relaxed),
+        )
+    }
+
+    /// # Safety
+    /// `self` must be followed by its slot buffer, i.e. sit at the start of
+    /// a mapped queue.
+    unsafe fn slot_version(&self, pos: usize) -> &AtomicU64 {
+        // Every `Seqlock<T>` starts with its version.
+        unsafe {
+            let buffer = std::ptr::from_ref(self).add(1).cast::<u8>();
+            #[allow(clippy::cast_ptr_alignment)]
+            &*buffer.add(pos * self.elsize).cast::<AtomicU64>()
+        }
+    }
+
+    fn message_count(&self, pos: usize, version: u64) -> usize {
+        ((version as usize - 2) / 2) * self.len() + pos
+    }
+
+    /// Finds slots stuck at an odd version and tombstones them.
+    ///
+    /// A slot counts as stuck if its version doesn't move for `grace`. Unless
+    /// `force` is set nothing is repaired while a producer in another process
+    /// holds a live lease, as the write could still be its own.
+    ///
+    /// # Safety
+    /// `self` must be followed by its slot buffer, i.e. sit at the start of
+    /// a mapped queue.
+    pub unsafe fn repair_poisoned(&self, grace: Duration, force: bool) -> RepairReport {
+        unsafe { self.repair_slots(0..self.len(), grace, force) }
+    }
+
+    /// # Safety
+    /// See [`Self::repair_poisoned`].
+    unsafe fn repair_slots(
+        &self,
+        positions: impl Iterator<Item = usize>,
+        grace: Duration,
+        force: bool,
+    ) -> RepairReport {
+        let mut report = RepairReport::default();
+        let odd: Vec<(usize, u64)> = positions
+            .filter_map(|pos| {
+                let v = unsafe { self.slot_version(pos) }.load(Ordering::Acquire);
+                (v & 1 == 1).then_some((pos, v))
+            })
+            .collect();
+        if odd.is_empty() {
+            return report;
+        }
+
+        let now = Nanos::now();
+        let pid = current_pid();
+        if !force &&
+            self.producers
+                .iter()
+                .any(|l| l.pid.load(Ordering::Relaxed) != pid && self.producer_alive(l, now))
+        {
+            report.writer_alive = odd.len();
+            return report;
+        }
+
+        std::thread::sleep(grace);
+        for (pos, v) in odd {
+            let count = self.message_count(pos, v + 1);
+            // Published before the version so a consumer can't read the slot
+            // without seeing its tombstone.
+            let entry = self.record_tombstone(count, v + 1);
+            if unsafe { self.slot_version(pos) }
+                .compare_exchange(v, v + 1, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                report.repaired.push(count);
+            } else {
+                // The write completed after all.
+                entry.store(0, Ordering::Relaxed);
+            }
+        }
+        self.repair_log.repaired.fetch_add(report.repaired.len() as u64, Ordering::Relaxed);
+        report
+    }
+
+    /// Runs a repair of the latest slots the first time the calling process
+    /// attaches a producer. Scanning the whole ring would fault in every page
+    /// of it on each attach.
+    pub(super) fn repair_on_restart(&self) {
+        let pid = current_pid();
+        if self.repair_log.checked_by.swap(pid, Ordering::Relaxed) == pid {
+            return;
+        }
+        // A write is only ever in flight on the slots right behind `count`,
+        // which producers bump before writing: at most one per producer.
+        let count = self.count.load(Ordering::Acquire);
+        let behind = count.min(MAX_PRODUCERS).min(self.len());
+        let positions = (count - behind..count).map(|c| c & self.mask);
+        let report = unsafe { self.repair_slots(positions, RESTART_REPAIR_GRACE, false) };
+        if !report.repaired.is_empty() {
+            tracing::warn!(
+                "repaired {} queue slots left mid-write by a dead producer: {:?}",
+                report.repaired.len(),
+                report.repaired
+            );
+        }
+        if report.writer_alive != 0 {
+            tracing::warn!(
+                "{} queue slots look poisoned but another producer is alive, leaving them",
+                report.writer_alive
+            );
+        }
+    }
+
+    fn record_tombstone(&self, count: usize, version: u64) -> &AtomicU64 {
+        let log = &self.repair_log;
+        let i = log
+            .counts
+            .iter()
+            .position(|c| c.load(Ordering::Relaxed) == 0)
+            .unwrap_or_else(|| log.repaired.load(Ordering::Relaxed) as usize % MAX_TOMBSTONES);
+        log.counts[i].store(count as u64 + 1, Ordering::Release);
+        log.latest_version.fetch_max(version, Ordering::AcqRel);
+        &log.counts[i]
+    }
+
+    /// Whether the message at `pos` with `version` is a tombstone left by
+    /// [`Self::repair_poisoned`].
+    #[inline]
+    pub(super) fn is_tombstone(&self, pos: usize, version: u64) -> bool {
+        version <= self.repair_log.latest_version.load(Ordering::Acquire) &&
+            self.tombstone_lookup(pos, version)
+    }
+
+    #[cold]
+    fn tombstone_lookup(&self, pos: usize, version: u64) -> bool {
+        let key = self.message_count(pos, version) as u64 + 1;
+        self.repair_log.counts.iter().any(|c| c.load(Ordering::Acquire) == key)
+    }
+
+    #[cold]
+    pub(super) fn report_tombstone_skipped(&self, pos: usize, version: u64, type_name: &str) {
+        self.repair_log.skipped.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "Consumer<{type_name}> skipped message {}: its producer died while writing it",
+            self.message_count(pos, version)
+        );
+    }
+}
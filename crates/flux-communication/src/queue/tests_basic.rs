@@ -1,14 +1,16 @@
-use std::sync::atomic::Ordering;
+use std::{sync::atomic::Ordering, time::Duration};
+
+use flux_timing::Nanos;
 
 use crate::{
-    ReadError,
-    queue::{ConsumerBare, Producer, Queue, QueueHeader, QueueType},
+    QueueError, ReadError, TimeoutError, TypeIdentity,
+    queue::{BLOCKING, ConsumerBare, GroupKind, Producer, Queue, QueueHeader, QueueType},
 };
 
 #[test]
 fn headersize() {
-    assert_eq!(34880, std::mem::size_of::<QueueHeader>());
-    assert_eq!(72, std::mem::size_of::<ConsumerBare<[u8; 60]>>());
+    assert_eq!(43328, std::mem::size_of::<QueueHeader>());
+    assert_eq!(96, std::mem::size_of::<ConsumerBare<[u8; 60]>>());
 }
 
 #[test]
@@ -111,12 +113,47 @@ fn multithread_8_8() {
     multithread(8, 8, 100_000);
 }
 
+#[test]
+fn consume_timeout() {
+    use std::time::{Duration, Instant};
+
+    let q = Queue::new(16, QueueType::SPMC);
+    let mut p = Producer::from(q);
+    let mut c = ConsumerBare::new_broadcast_test(q);
+    let mut m = 0;
+
+    // Producers skip the fence for sleepers until a consumer blocks.
+    assert_eq!(q.header.waiters.load(Ordering::Relaxed) & BLOCKING, 0);
+    let start = Instant::now();
+    assert_eq!(c.consume_timeout(&mut m, Duration::from_millis(20)), Err(TimeoutError::TimedOut));
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert_eq!(q.header.waiters.load(Ordering::Relaxed) & BLOCKING, BLOCKING);
+
+    p.produce(&7);
+    assert_eq!(c.consume_timeout(&mut m, Duration::ZERO), Ok(()));
+    assert_eq!(m, 7);
+
+    // A consumer asleep on the futex is woken by the produce, well before its
+    // deadline.
+    let handle = std::thread::spawn(move || {
+        let mut m = 0;
+        let start = Instant::now();
+        c.consume_timeout(&mut m, Duration::from_secs(10)).unwrap();
+        (m, start.elapsed())
+    });
+    std::thread::sleep(Duration::from_millis(50));
+    p.produce(&8);
+    let (m, waited) = handle.join().unwrap();
+    assert_eq!(m, 8);
+    assert!(waited < Duration::from_secs(5));
+}
+
 #[test]
 fn basic_shared() {
     for typ in [QueueType::SPMC, QueueType::MPMC] {
         let path = std::path::Path::new("/dev/shm/blabla_test");
         let _ = std::fs::remove_file(path);
-        let q = Queue::create_or_open_shared(path, 16, typ);
+        let q = Queue::create_or_open_shared(path, 16, typ).unwrap();
         let mut p = Producer::from(q);
         let mut c = ConsumerBare::new_broadcast_test(q);
 
@@ -253,3 +290,294 @@ fn max_writable_msgs_matches_safe_writes_before_overwrite() {
         assert!(matches!(c.try_consume(&mut m), Err(ReadError::Empty)));
     }
 }
+
+#[test]
+fn shared_type_mismatch() {
+    let path = std::path::Path::new("/dev/shm/flux_test_type_mismatch");
+    let _ = std::fs::remove_file(path);
+
+    let q = Queue::<u64>::create_or_open_shared(path, 16, QueueType::SPMC).unwrap();
+    assert_eq!(q.header.identity().type_name.as_str(), "u64");
+    assert_eq!(q.header.identity().type_hash, <u64 as type_hash::TypeHash>::TYPE_HASH);
+    assert!(Queue::<u64>::create_or_open_shared(path, 16, QueueType::SPMC).is_ok());
+
+    // Same size and alignment, different type.
+    let err = Queue::<i64>::create_or_open_shared(path, 16, QueueType::SPMC).unwrap_err();
+    assert!(matches!(err, QueueError::TypeMismatch { .. }), "{err}");
+    assert!(matches!(Queue::<i64>::try_open_shared(path), Err(QueueError::TypeMismatch { .. })));
+
+    // Same name, size and alignment, different layout hash.
+    let stored = TypeIdentity { type_hash: 1, ..TypeIdentity::of::<u64>() };
+    let _ = std::fs::remove_file(path);
+    Queue::<u64>::create_or_open_shared_with_identity(path, 16, QueueType::SPMC, stored).unwrap();
+    let err = Queue::<u64>::create_or_open_shared(path, 16, QueueType::SPMC).unwrap_err();
+    assert!(matches!(err, QueueError::TypeMismatch { .. }), "{err}");
+    assert!(matches!(Queue::<u64>::try_open_shared(path), Err(QueueError::TypeMismatch { .. })));
+
+    // Hashes are only compared when both sides recorded one.
+    let unhashed = TypeIdentity::unhashed::<u64>();
+    assert!(
+        Queue::<u64>::create_or_open_shared_with_identity(path, 16, QueueType::SPMC, unhashed)
+            .is_ok()
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn group_metadata_and_leave() {
+    let q = Queue::<u64>::new(16, QueueType::SPMC);
+    let mut a = ConsumerBare::new_collaborative_test(q, "workers");
+    let mut b = ConsumerBare::new_collaborative_test(q, "workers");
+
+    let groups = q.groups();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].kind, GroupKind::Collaborative);
+    assert_eq!(groups[0].owner_pid, std::process::id());
+    assert_eq!(groups[0].members, 2);
+    assert!(!groups[0].expired);
+    assert!(groups[0].created.0 > 0 && groups[0].last_active >= groups[0].created);
+
+    a.leave_group();
+    assert_eq!(q.groups()[0].members, 1);
+    // Leaving twice is a no-op.
+    a.leave_group();
+    assert_eq!(q.groups()[0].members, 1);
+    b.leave_group();
+    assert!(q.groups().is_empty());
+
+    let mut c = ConsumerBare::new_broadcast_test(q);
+    assert_eq!(q.groups()[0].kind, GroupKind::Broadcast);
+    c.leave_group();
+    assert!(q.groups().is_empty());
+}
+
+#[test]
+fn expired_lease_slot_reclaimed() {
+    let q = Queue::<u64>::new(16, QueueType::SPMC);
+    q.set_group_lease(Some(Duration::from_millis(1)));
+    let header: &mut QueueHeader = &mut unsafe { &mut *q.inner.cast_mut() }.header;
+
+    let stale = header.join_group("idle.stream.broadcast", GroupKind::Broadcast);
+    std::thread::sleep(Duration::from_millis(5));
+    assert!(header.groups()[0].expired);
+    assert!(header.active_groups().is_empty());
+
+    let fresh = header.join_group("app.stream.broadcast", GroupKind::Broadcast);
+    assert_eq!(stale.slot, fresh.slot);
+    assert!(fresh.generation > stale.generation);
+    // The old member notices on renewal and its leave doesn't free the slot.
+    assert!(!header.renew_group(stale, Nanos::now()));
+    header.leave_group(stale);
+    assert_eq!(header.groups()[0].label, "app.stream.broadcast");
+
+    q.set_group_lease(None);
+    std::thread::sleep(Duration::from_millis(5));
+    assert!(!header.groups()[0].expired);
+}
+
+#[test]
+fn renew_group_keeps_unpolled_consumer() {
+    let q = Queue::<u64>::new(16, QueueType::SPMC);
+    q.set_group_lease(Some(Duration::from_millis(300)));
+    let mut c = ConsumerBare::new_broadcast_test(q);
+    for _ in 0..5 {
+        std::thread::sleep(Duration::from_millis(150));
+        c.renew_group();
+    }
+    assert!(!q.groups()[0].expired);
+
+    // Renewals until the next one is due only read the TSC.
+    let last_active = q.groups()[0].last_active;
+    c.renew_group();
+    assert_eq!(q.groups()[0].last_active, last_active);
+}
+
+#[test]
+fn reset_and_remove_group() {
+    let q = Queue::<u64>::new(16, QueueType::SPMC);
+    let mut p = Producer::from(q);
+    let mut c = ConsumerBare::new_collaborative_test(q, "workers");
+    let label = q.groups()[0].label.to_owned();
+
+    for i in 0..4 {
+        p.produce(&i);
+    }
+    assert!(q.reset_group(&label, 3));
+    assert!(!q.reset_group("nope", 0));
+
+    let mut m = 0;
+    assert!(q.remove_group(&label));
+    assert!(!q.remove_group(&label));
+    assert!(q.groups().is_empty());
+
+    // The consumer rejoins once it finds the queue empty.
+    c.try_consume(&mut m).unwrap();
+    while c.try_consume(&mut m).is_ok() {}
+    c.try_init_collaborative();
+    assert_eq!(q.groups().len(), 1);
+    assert_eq!(q.groups()[0].members, 1);
+}
+
+#[test]
+fn commit_checkpoint() {
+    let q = Queue::<u64>::new(16, QueueType::SPMC);
+    let mut p = Producer::from(q);
+    let mut c = ConsumerBare::new_broadcast_test(q);
+    let mut m = 0;
+
+    // Nothing consumed from a checkpoint that doesn't exist yet.
+    assert!(q.header.checkpoint(&c.checkpoint_key()).is_none());
+    for i in 0..3 {
+        p.produce(&i);
+    }
+    c.try_consume(&mut m).unwrap();
+    c.try_consume(&mut m).unwrap();
+    c.commit();
+    let (_, committed) = q.header.checkpoint(&c.checkpoint_key()).unwrap();
+    assert_eq!(committed, 2);
+    let checkpoint = q.groups().into_iter().find(|g| g.kind == GroupKind::Checkpoint).unwrap();
+    assert!(!checkpoint.expired);
+
+    // Checkpoints don't hold the producer back, only live consumers do.
+    while c.try_consume(&mut m).is_ok() {}
+    assert_eq!(q.max_writable_msgs_without_speeding_past(), 16);
+
+    // Removed checkpoints are claimed again on the next commit.
+    assert!(q.remove_group(checkpoint.label));
+    c.commit();
+    assert_eq!(q.header.checkpoint(&c.checkpoint_key()).unwrap().1, 3);
+    assert_eq!(c.lost_since_checkpoint(), 0);
+
+    // A restart within the ring resumes right after the checkpoint.
+    let mut restarted = c;
+    assert_eq!(restarted.resume_from_checkpoint(q.count()), 3);
+    assert_eq!(restarted.lost_since_checkpoint(), 0);
+
+    // Once the checkpoint was lapped the gap is reported.
+    for i in 0..20 {
+        p.produce(&i);
+    }
+    let mut restarted = c;
+    assert_eq!(restarted.resume_from_checkpoint(q.count()), 23 - 16);
+    assert_eq!(restarted.lost_since_checkpoint(), 4);
+}
+
+/// Leaves the next slot the way a producer killed mid-write does: count
+/// already bumped, version odd.
+fn poison_next_slot(q: Queue<u64>) -> usize {
+    let c = q.count();
+    q.header.count.store(c + 1, Ordering::Relaxed);
+    q.load(c & q.header.mask).set_version_unsafe(q.version_at(c) - 1);
+    c
+}
+
+#[test]
+fn repair_tombstones_slot_of_dead_producer() {
+    for typ in [QueueType::SPMC, QueueType::MPMC] {
+        let q = Queue::new(8, typ);
+        let mut p = Producer::from(q);
+        let mut c = ConsumerBare::new_broadcast_test(q);
+        let mut m = 0;
+
+        for i in 0..3 {
+            p.produce(&i);
+            c.try_consume(&mut m).unwrap();
+        }
+        let poisoned = poison_next_slot(q);
+        assert_eq!(c.try_consume(&mut m), Err(ReadError::Empty));
+
+        let report = unsafe { q.header.repair_poisoned(Duration::ZERO, false) };
+        assert_eq!(report.repaired, vec![poisoned]);
+        assert_eq!(report.writer_alive, 0);
+        // Nothing left to repair.
+        assert!(unsafe { q.header.repair_poisoned(Duration::ZERO, false) }.repaired.is_empty());
+
+        // The tombstone is skipped and the producer carries on, also on the
+        // next lap over the repaired slot.
+        p.produce(&42);
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m, 42);
+        for i in 0..8 {
+            p.produce(&(100 + i));
+            c.try_consume(&mut m).unwrap();
+            assert_eq!(m, 100 + i);
+        }
+        assert_eq!(q.header.tombstone_stats(), (1, 1));
+    }
+}
+
+#[test]
+fn restarted_producer_repairs_shared_queue() {
+    let path = std::path::Path::new("/dev/shm/flux_test_restart_repair");
+    let _ = std::fs::remove_file(path);
+    let q = Queue::<u64>::create_or_open_shared(path, 8, QueueType::SPMC).unwrap();
+    let mut p = Producer::from(q);
+    let mut c = ConsumerBare::new_broadcast_test(q);
+    for i in 0..3 {
+        p.produce(&i);
+    }
+    let poisoned = poison_next_slot(q);
+
+    // The producer's process restarts: the queue is reopened, not recreated.
+    q.header.repair_log.checked_by.store(0, Ordering::Relaxed);
+    let reopened = Queue::<u64>::create_or_open_shared(path, 8, QueueType::SPMC).unwrap();
+    let mut p = Producer::from(reopened);
+    assert_eq!(reopened.header.tombstone_stats(), (1, 0));
+    assert_eq!(q.load(poisoned & q.header.mask).version(), q.version_at(poisoned));
+
+    p.produce(&42);
+    let mut m = 0;
+    for expected in [0, 1, 2, 42] {
+        c.try_consume(&mut m).unwrap();
+        assert_eq!(m, expected);
+    }
+    assert_eq!(reopened.header.tombstone_stats(), (1, 1));
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn repair_waits_for_live_producers() {
+    let q = Queue::new(8, QueueType::SPMC);
+    let mut p = Producer::from(q);
+    p.produce(&1);
+    poison_next_slot(q);
+
+    // A producer in another live process may still finish the write.
+    q.header.producers[1].pid.store(1, Ordering::Relaxed);
+    q.header.producers[1].last_active.store(Nanos::now().0, Ordering::Relaxed);
+    assert_eq!(q.header.producers().len(), 2);
+    let report = unsafe { q.header.repair_poisoned(Duration::ZERO, false) };
+    assert!(report.repaired.is_empty());
+    assert_eq!(report.writer_alive, 1);
+
+    // Unless its lease ran out.
+    q.set_group_lease(Some(Duration::from_millis(1)));
+    std::thread::sleep(Duration::from_millis(5));
+    q.header.touch_producer();
+    assert_eq!(q.header.producers(), vec![std::process::id()]);
+    let report = unsafe { q.header.repair_poisoned(Duration::ZERO, false) };
+    assert_eq!(report.repaired.len(), 1);
+}
+
+#[test]
+fn repair_leaves_completed_writes() {
+    let q = Queue::new(8, QueueType::SPMC);
+    let mut p = Producer::from(q);
+    let mut c = ConsumerBare::new_broadcast_test(q);
+    let mut m = 0;
+    p.produce(&1);
+    c.try_consume(&mut m).unwrap();
+
+    // A write that finishes during the grace period isn't touched.
+    let slot = poison_next_slot(q) & q.header.mask;
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(Duration::from_millis(5));
+            q.load(slot).version.fetch_add(1, Ordering::Release);
+        });
+        let report = unsafe { q.header.repair_poisoned(Duration::from_millis(50), false) };
+        assert!(report.repaired.is_empty());
+    });
+    assert_eq!(q.header.tombstone_stats(), (0, 0));
+    c.try_consume(&mut m).unwrap();
+}
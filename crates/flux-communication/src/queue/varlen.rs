@@ -0,0 +1,697 @@
+//! Variable-length records in a single shared-memory byte ring.
+//!
+//! Every record is `[RecordHeader | T | payload]`, padded to 8 bytes. The
+//! producer publishes the end of the record it is about to write in `intent`
+//! before touching the ring and the committed end in the queue `count` after,
+//! so `intent` plays the role of a seqlock version for the whole ring: a
+//! record at byte position `pos` is intact as long as
+//! `intent <= pos + capacity` after it was read.
+
+use std::{
+    alloc::Layout,
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering, compiler_fence},
+};
+
+use flux_timing::Nanos;
+use flux_utils::safe_panic;
+use shared_memory::ShmemConf;
+use type_hash::TypeHash;
+
+use super::{
+    GroupInfo, GroupKind, GroupMembership, QueueHeader, QueueType, binary_name, current_pid,
+    lease_renewal_due, shmem_map_create_or_open,
+};
+use crate::{
+    MappingOptions, TypeIdentity,
+    error::{QueueError, ReadError, TooLargeError},
+};
+
+const RECORD_ALIGN: usize = 8;
+/// Fills the tail of the ring when a record doesn't fit before the wrap.
+const PADDING: u32 = 1;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct RecordHeader {
+    /// Payload bytes, excluding the header and the fixed `T`.
+    len: u32,
+    flags: u32,
+}
+
+const HEADER_SIZE: usize = size_of::<RecordHeader>();
+
+/// `count` of the inner header is the committed head in bytes, `mask` is
+/// `capacity - 1`.
+#[derive(Debug)]
+#[repr(C, align(64))]
+pub struct VarlenHeader {
+    pub queue: QueueHeader,
+    /// End of the record being written, always `>= count`.
+    intent: AtomicUsize,
+    /// Start of the oldest record not yet overwritten.
+    tail: AtomicUsize,
+}
+
+/// Broadcast queue of variable-length records without a separate dcache.
+///
+/// Each record carries a fixed `T` and a byte payload that readers get as a
+/// zero-copy `&[u8]`. Single producer: [`Self::produce`] must only be called
+/// from one thread at a time.
+#[derive(Clone, Copy, Debug)]
+pub struct VarlenQueue<T> {
+    header: *const VarlenHeader,
+    ring: *mut u8,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T> Send for VarlenQueue<T> {}
+unsafe impl<T> Sync for VarlenQueue<T> {}
+
+impl<T: Copy> VarlenQueue<T> {
+    const FIXED_SIZE: usize = size_of::<T>().next_multiple_of(RECORD_ALIGN);
+
+    /// Heap-backed queue with a ring of `capacity.next_power_of_two()` bytes.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.next_power_of_two();
+        let layout = Layout::from_size_align(Self::byte_size(capacity), 64).unwrap();
+        let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+        Self::from_uninitialized_ptr(ptr, capacity, TypeIdentity::unhashed::<T>())
+    }
+
+    /// Fails with [`QueueError::TypeMismatch`] if the existing queue was
+    /// created for a different element type. A ring of a different capacity
+    /// or an otherwise invalid header is removed and recreated.
+    pub fn create_or_open_shared<P: AsRef<Path>>(
+        shmem_file: P,
+        capacity: usize,
+    ) -> Result<Self, QueueError>
+    where
+        T: TypeHash,
+    {
+        Self::create_or_open_shared_with_options(shmem_file, capacity, MappingOptions::default())
+    }
+
+    /// Like [`Self::create_or_open_shared`] but backs the segment according
+    /// to `options` (huge pages, prefaulting, `mlock`).
+    pub fn create_or_open_shared_with_options<P: AsRef<Path>>(
+        shmem_file: P,
+        capacity: usize,
+        options: MappingOptions,
+    ) -> Result<Self, QueueError>
+    where
+        T: TypeHash,
+    {
+        let shmem_file = shmem_file.as_ref();
+        let capacity = capacity.next_power_of_two();
+        let identity = TypeIdentity::of::<T>();
+        let (ptr, is_new, _) =
+            shmem_map_create_or_open(shmem_file, Self::byte_size(capacity), options);
+        if is_new {
+            return Ok(Self::from_uninitialized_ptr(ptr, capacity, identity));
+        }
+        match Self::open_initialized(ptr, capacity, &identity) {
+            Ok(q) => Ok(q),
+            Err(e @ QueueError::TypeMismatch { .. }) => Err(e),
+            Err(e) => {
+                tracing::error!(
+                    "issue with preexisting varlen queue at {shmem_file:?}: {e}. Removing and recreating."
+                );
+                crate::memfd::remove_segment(shmem_file);
+                Self::create_or_open_shared_with_options(shmem_file, capacity, options)
+            }
+        }
+    }
+
+    /// Opens an existing queue with whatever capacity it was created with.
+    pub fn open_shared<P: AsRef<Path>>(shmem_file: P) -> Result<Self, QueueError>
+    where
+        T: TypeHash,
+    {
+        let path = shmem_file.as_ref();
+        if !path.exists() {
+            return Err(QueueError::NonExistingFile);
+        }
+        let shmem = ShmemConf::new().flink(path).open()?;
+        let ptr = shmem.as_ptr();
+        #[allow(clippy::cast_ptr_alignment)]
+        let capacity = unsafe { (*ptr.cast::<VarlenHeader>()).queue.len() };
+        let opened = Self::open_initialized(ptr, capacity, &TypeIdentity::of::<T>());
+        if opened.is_ok() {
+            std::mem::forget(shmem);
+        }
+        opened
+    }
+
+    const fn byte_size(capacity: usize) -> usize {
+        size_of::<VarlenHeader>() + capacity
+    }
+
+    fn from_uninitialized_ptr(ptr: *mut u8, capacity: usize, identity: TypeIdentity) -> Self {
+        assert!(align_of::<T>() <= RECORD_ALIGN, "varlen records are only 8 byte aligned");
+        #[allow(clippy::cast_ptr_alignment)]
+        let header = ptr.cast::<VarlenHeader>();
+        unsafe {
+            (*header).queue.queue_type = QueueType::SPMC;
+            (*header).queue.mask = capacity - 1;
+            (*header).queue.elsize = size_of::<T>();
+            (*header).queue.identity = identity;
+            (*header).queue.count = AtomicUsize::new(0);
+            (*header).intent = AtomicUsize::new(0);
+            (*header).tail = AtomicUsize::new(0);
+            (*header).queue.is_initialized = true as u8;
+        }
+        Self { header, ring: unsafe { ptr.add(size_of::<VarlenHeader>()) }, _marker: PhantomData }
+    }
+
+    fn open_initialized(
+        ptr: *mut u8,
+        capacity: usize,
+        identity: &TypeIdentity,
+    ) -> Result<Self, QueueError> {
+        #[allow(clippy::cast_ptr_alignment)]
+        let header = unsafe { &*ptr.cast::<VarlenHeader>() };
+        let mut tries = 0;
+        while !header.queue.is_initialized() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            tries += 1;
+            if tries == 10 {
+                return Err(QueueError::UnInitialized);
+            }
+        }
+        header.queue.identity.verify(identity)?;
+        if header.queue.len() != capacity {
+            return Err(QueueError::TooSmall);
+        }
+        if header.queue.elsize != size_of::<T>() {
+            return Err(QueueError::ElementSizeChanged(header.queue.elsize, size_of::<T>()));
+        }
+        Ok(Self {
+            header,
+            ring: unsafe { ptr.add(size_of::<VarlenHeader>()) },
+            _marker: PhantomData,
+        })
+    }
+
+    #[inline]
+    fn header(&self) -> &VarlenHeader {
+        unsafe { &*self.header }
+    }
+
+    /// Ring size in bytes.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.header().queue.len()
+    }
+
+    /// Committed head, i.e. the total number of bytes ever produced.
+    #[inline]
+    pub fn head(&self) -> usize {
+        self.header().queue.count.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    const fn record_size(len: usize) -> usize {
+        (HEADER_SIZE + Self::FIXED_SIZE + len).next_multiple_of(RECORD_ALIGN)
+    }
+
+    /// Position of the oldest record still in the ring.
+    #[inline]
+    pub fn tail(&self) -> usize {
+        self.header().tail.load(Ordering::Acquire)
+    }
+
+    /// Largest payload accepted by [`Self::produce`]. Records are capped at
+    /// half the ring so a reader always has a full record's worth of slack.
+    #[inline]
+    pub fn max_payload(&self) -> usize {
+        (self.capacity() / 2).saturating_sub(HEADER_SIZE + Self::FIXED_SIZE)
+    }
+
+    /// Appends a record of `msg` and a `len` byte payload filled in by `f`,
+    /// returning the byte position it was written at.
+    pub fn produce<F: FnOnce(&mut [u8])>(
+        &self,
+        msg: &T,
+        len: usize,
+        f: F,
+    ) -> Result<usize, TooLargeError> {
+        let max = self.max_payload();
+        if len > max {
+            return Err(TooLargeError::TooLarge(len, max));
+        }
+        let header = self.header();
+        let capacity = self.capacity();
+        let size = Self::record_size(len);
+        let mut pos = header.queue.count.load(Ordering::Relaxed);
+
+        let padding = capacity - (pos & header.queue.mask);
+        let padding = if padding < size { padding } else { 0 };
+        self.advance_tail(pos + padding + size);
+        if padding != 0 {
+            self.write_record(pos, padding, RecordHeader { len: 0, flags: PADDING }, |_| {});
+            pos += padding;
+        }
+        self.write_record(pos, size, RecordHeader { len: len as u32, flags: 0 }, |rec| unsafe {
+            std::ptr::write(rec.cast::<T>(), *msg);
+            f(std::slice::from_raw_parts_mut(rec.add(Self::FIXED_SIZE), len));
+        });
+
+        #[cfg(feature = "park")]
+        if header.queue.signal_on_produce.load(Ordering::Relaxed) != 0 {
+            crate::park::SIGNAL.signal();
+        }
+        Ok(pos)
+    }
+
+    /// Moves `tail` past the records that writing up to `end` overwrites.
+    /// They are all still intact, only this producer writes the ring.
+    #[inline]
+    #[allow(clippy::cast_ptr_alignment)]
+    fn advance_tail(&self, end: usize) {
+        let header = self.header();
+        let floor = end.saturating_sub(self.capacity());
+        let mut tail = header.tail.load(Ordering::Relaxed);
+        if tail >= floor {
+            return;
+        }
+        while tail < floor {
+            let off = tail & header.queue.mask;
+            let rec = unsafe { std::ptr::read(self.ring.add(off).cast::<RecordHeader>()) };
+            tail += if rec.flags & PADDING != 0 {
+                self.capacity() - off
+            } else {
+                Self::record_size(rec.len as usize)
+            };
+        }
+        header.tail.store(tail, Ordering::Release);
+    }
+
+    #[inline]
+    #[allow(clippy::cast_ptr_alignment)]
+    fn write_record<F: FnOnce(*mut u8)>(&self, pos: usize, size: usize, rec: RecordHeader, f: F) {
+        let header = self.header();
+        header.intent.store(pos + size, Ordering::Release);
+        compiler_fence(Ordering::AcqRel);
+        unsafe {
+            let at = self.ring.add(pos & header.queue.mask);
+            std::ptr::write(at.cast::<RecordHeader>(), rec);
+            f(at.add(HEADER_SIZE));
+        }
+        compiler_fence(Ordering::AcqRel);
+        header.queue.count.store(pos + size, Ordering::Release);
+    }
+
+    /// Whether the bytes of the record at `pos` may have been overwritten.
+    #[inline]
+    fn overrun(&self, pos: usize) -> bool {
+        compiler_fence(Ordering::AcqRel);
+        self.header().intent.load(Ordering::Acquire) > pos + self.capacity()
+    }
+
+    /// Header of the record at `pos` and the position of the next one.
+    #[inline]
+    #[allow(clippy::cast_ptr_alignment)]
+    fn record_at(&self, pos: usize) -> Result<(RecordHeader, usize), ReadError> {
+        if pos >= self.head() {
+            return Err(ReadError::Empty);
+        }
+        let off = pos & self.header().queue.mask;
+        let rec = unsafe { std::ptr::read_volatile(self.ring.add(off).cast::<RecordHeader>()) };
+        if self.overrun(pos) {
+            return Err(ReadError::SpedPast);
+        }
+        let size = if rec.flags & PADDING != 0 {
+            self.capacity() - off
+        } else {
+            Self::record_size(rec.len as usize)
+        };
+        Ok((rec, pos + size))
+    }
+
+    /// Runs `read` on the record at `pos`, whose header is `rec`. The result
+    /// is dropped if the producer overwrote the record meanwhile.
+    #[inline]
+    fn read_body<R, F>(&self, pos: usize, rec: RecordHeader, read: F) -> Result<(T, R), ReadError>
+    where
+        F: FnOnce(&T, &[u8]) -> R,
+    {
+        let at = unsafe { self.ring.add((pos & self.header().queue.mask) + HEADER_SIZE) };
+        let msg = unsafe { std::ptr::read_volatile(at.cast::<T>()) };
+        let payload =
+            unsafe { std::slice::from_raw_parts(at.add(Self::FIXED_SIZE), rec.len as usize) };
+        let r = read(&msg, payload);
+        if self.overrun(pos) {
+            return Err(ReadError::SpedPast);
+        }
+        Ok((msg, r))
+    }
+
+    fn join_group(&self, key: &str, kind: GroupKind) -> GroupMembership {
+        unsafe { &mut *self.header.cast_mut() }.queue.join_group(key, kind)
+    }
+
+    fn leave_group(&self, membership: GroupMembership) {
+        unsafe { &mut *self.header.cast_mut() }.queue.leave_group(membership);
+    }
+
+    pub fn set_signal_on_produce(&self, enabled: bool) {
+        self.header().queue.signal_on_produce.store(enabled as u8, Ordering::Relaxed);
+    }
+
+    pub fn active_groups(&self) -> Vec<(&str, usize)> {
+        self.header().queue.active_groups()
+    }
+
+    pub fn groups(&self) -> Vec<GroupInfo<'_>> {
+        self.header().queue.groups()
+    }
+}
+
+/// Reader of a [`VarlenQueue`], either following the head on its own
+/// (broadcast) or sharing a cursor with the rest of its collaborative group.
+#[derive(Clone, Copy, Debug)]
+pub struct VarlenConsumer<T> {
+    queue: VarlenQueue<T>,
+    pos: usize,
+    group: Option<GroupMembership>,
+    renew_at: flux_timing::Instant,
+    label: &'static str,
+    should_log: bool,
+}
+
+unsafe impl<T> Send for VarlenConsumer<T> {}
+
+impl<T: Copy> VarlenConsumer<T> {
+    /// Starts at the current head on the first read.
+    pub fn new(queue: VarlenQueue<T>, label: &'static str) -> Self {
+        Self {
+            queue,
+            pos: usize::MAX,
+            group: None,
+            renew_at: flux_timing::Instant::ZERO,
+            label,
+            should_log: true,
+        }
+    }
+
+    #[inline]
+    pub fn without_log(self) -> Self {
+        Self { should_log: false, ..self }
+    }
+
+    pub fn set_collaborative_group(&mut self, group_label: &'static str) {
+        self.label = group_label;
+    }
+
+    #[inline]
+    pub fn recover_after_error(&mut self) {
+        self.pos = self.queue.head();
+    }
+
+    /// Rewinds to the oldest record still in the ring, e.g. to read out
+    /// everything a queue holds.
+    #[inline]
+    pub fn seek_oldest(&mut self) {
+        self.pos = self.queue.tail();
+    }
+
+    /// Nonblocking read of the next record. `read` may see a payload that is
+    /// being overwritten, in which case its result is dropped and
+    /// [`ReadError::SpedPast`] returned.
+    #[inline]
+    pub fn try_consume<R, F>(&mut self, read: F) -> Result<(T, R), ReadError>
+    where
+        F: FnOnce(&T, &[u8]) -> R,
+    {
+        if self.pos == usize::MAX {
+            self.recover_after_error();
+        }
+        loop {
+            let (rec, next) = self.queue.record_at(self.pos)?;
+            if rec.flags & PADDING != 0 {
+                self.pos = next;
+                continue;
+            }
+            let out = self.queue.read_body(self.pos, rec, read)?;
+            self.pos = next;
+            return Ok(out);
+        }
+    }
+
+    /// Maybe consume one record with sped-past recovery and logging.
+    #[inline]
+    pub fn consume<R, F>(&mut self, mut read: F) -> Option<(T, R)>
+    where
+        F: FnMut(&T, &[u8]) -> R,
+    {
+        loop {
+            match self.try_consume(&mut read) {
+                Ok(out) => return Some(out),
+                Err(ReadError::SpedPast) => self.log_and_recover(),
+                Err(ReadError::Empty) => return None,
+            }
+        }
+    }
+
+    #[inline(never)]
+    fn log_and_recover(&mut self) {
+        if self.should_log {
+            safe_panic!("VarlenConsumer<{}> got sped past", std::any::type_name::<T>());
+        }
+        self.recover_after_error();
+    }
+
+    #[inline]
+    fn try_init_collaborative(&mut self) -> GroupMembership {
+        if let Some(group) = self.group {
+            return group;
+        }
+        self.init_collaborative()
+    }
+
+    #[inline(never)]
+    fn init_collaborative(&mut self) -> GroupMembership {
+        let group = self.queue.join_group(
+            &format!("{}[{}].{}.collab", binary_name(), current_pid(), self.label),
+            GroupKind::Collaborative,
+        );
+        self.group = Some(group);
+        group
+    }
+
+    /// Keeps the group's lease alive, dropping the membership if the slot
+    /// was reclaimed so the next read rejoins. See
+    /// [`ConsumerBare::renew_group`](super::ConsumerBare::renew_group).
+    #[inline]
+    pub fn renew_group(&mut self) {
+        let Some(group) = self.group else {
+            return;
+        };
+        let header = &self.queue.header().queue;
+        let current = if lease_renewal_due(&mut self.renew_at) {
+            header.renew_group(group, Nanos::now())
+        } else {
+            header.is_group_current(group)
+        };
+        if !current {
+            self.group = None;
+        }
+    }
+
+    /// Leaves the collaborative group, see
+    /// [`ConsumerBare::leave_group`](super::ConsumerBare::leave_group).
+    pub fn leave_group(&mut self) {
+        if let Some(group) = self.group.take() {
+            self.queue.leave_group(group);
+        }
+    }
+
+    /// Claims the next record of the group and reads it. A record whose
+    /// payload got overwritten while being read is lost to the whole group.
+    #[inline]
+    pub fn try_consume_collaborative<R, F>(&mut self, read: F) -> Result<(T, R), ReadError>
+    where
+        F: FnOnce(&T, &[u8]) -> R,
+    {
+        let cursor = unsafe { &*self.try_init_collaborative().cursor };
+        loop {
+            let pos = cursor.load(Ordering::Acquire);
+            let (rec, next) = match self.queue.record_at(pos) {
+                Ok(v) => v,
+                Err(ReadError::SpedPast) => {
+                    cursor.fetch_max(self.queue.head(), Ordering::AcqRel);
+                    return Err(ReadError::SpedPast);
+                }
+                Err(e) => {
+                    self.renew_group();
+                    return Err(e);
+                }
+            };
+            if cursor.compare_exchange(pos, next, Ordering::AcqRel, Ordering::Relaxed).is_err() {
+                continue;
+            }
+            if rec.flags & PADDING == 0 {
+                return self.queue.read_body(pos, rec, read);
+            }
+        }
+    }
+
+    /// Collaborative [`Self::consume`].
+    #[inline]
+    pub fn consume_collaborative<R, F>(&mut self, read: F) -> Option<(T, R)>
+    where
+        F: FnOnce(&T, &[u8]) -> R,
+    {
+        match self.try_consume_collaborative(read) {
+            Ok(out) => Some(out),
+            Err(ReadError::SpedPast) => {
+                if self.should_log {
+                    safe_panic!(
+                        "VarlenConsumer<{}> collaborative got sped past",
+                        std::any::type_name::<T>()
+                    );
+                }
+                None
+            }
+            Err(ReadError::Empty) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(byte: u8) -> impl FnOnce(&mut [u8]) {
+        move |buf| buf.fill(byte)
+    }
+
+    #[test]
+    fn produce_consume() {
+        let q = VarlenQueue::<u64>::new(1024);
+        let mut c = VarlenConsumer::new(q, "test");
+        assert_eq!(c.try_consume(|_, _| ()), Err(ReadError::Empty));
+
+        q.produce(&1, 3, fill(1)).unwrap();
+        q.produce(&2, 0, fill(2)).unwrap();
+        q.produce(&3, 100, fill(3)).unwrap();
+
+        for (msg, len) in [(1, 3), (2, 0), (3, 100)] {
+            let (m, payload) = c.try_consume(|_, p| p.to_vec()).unwrap();
+            assert_eq!(m, msg);
+            assert_eq!(payload.len(), len);
+            assert!(payload.iter().all(|&b| b == msg as u8));
+        }
+        assert_eq!(c.try_consume(|_, _| ()), Err(ReadError::Empty));
+    }
+
+    #[test]
+    fn wraps_with_padding() {
+        let q = VarlenQueue::<u64>::new(256);
+        let mut c = VarlenConsumer::new(q, "test").without_log();
+        c.recover_after_error();
+        for i in 0..100u64 {
+            let len = (i as usize * 7) % q.max_payload();
+            q.produce(&i, len, fill(i as u8)).unwrap();
+            let (m, ok) =
+                c.try_consume(|_, p| p.len() == len && p.iter().all(|&b| b == i as u8)).unwrap();
+            assert_eq!(m, i);
+            assert!(ok);
+        }
+    }
+
+    #[test]
+    fn seek_oldest_after_wrap() {
+        let q = VarlenQueue::<u64>::new(256);
+        for i in 0..50u64 {
+            q.produce(&i, (i as usize * 5) % 64, fill(i as u8)).unwrap();
+        }
+        let mut c = VarlenConsumer::new(q, "test");
+        c.seek_oldest();
+        let mut seen = Vec::new();
+        while let Some((m, ())) = c.consume(|_, _| ()) {
+            seen.push(m);
+        }
+        assert_eq!(seen.last(), Some(&49));
+        assert!(seen.len() > 2);
+        assert!(seen.windows(2).all(|w| w[1] == w[0] + 1));
+        assert!(q.head() - q.tail() <= q.capacity());
+    }
+
+    #[test]
+    fn too_large() {
+        let q = VarlenQueue::<u64>::new(256);
+        let max = q.max_payload();
+        assert!(q.produce(&0, max, fill(0)).is_ok());
+        assert_eq!(q.produce(&0, max + 1, fill(0)), Err(TooLargeError::TooLarge(max + 1, max)));
+    }
+
+    #[test]
+    fn sped_past() {
+        let q = VarlenQueue::<u64>::new(256);
+        let mut c = VarlenConsumer::new(q, "test").without_log();
+        c.recover_after_error();
+        for i in 0..20 {
+            q.produce(&i, 32, fill(0)).unwrap();
+        }
+        assert_eq!(c.try_consume(|_, _| ()), Err(ReadError::SpedPast));
+        assert_eq!(c.consume(|_, _| ()), None);
+
+        q.produce(&42, 8, fill(0)).unwrap();
+        assert_eq!(c.consume(|_, p| p.len()), Some((42, 8)));
+    }
+
+    #[test]
+    fn overwritten_while_reading() {
+        let q = VarlenQueue::<u64>::new(256);
+        let mut c = VarlenConsumer::new(q, "test").without_log();
+        c.recover_after_error();
+        q.produce(&0, 16, fill(0)).unwrap();
+        let res = c.try_consume(|_, _| {
+            for i in 0..10 {
+                q.produce(&i, 32, fill(1)).unwrap();
+            }
+        });
+        assert_eq!(res, Err(ReadError::SpedPast));
+    }
+
+    #[test]
+    fn collaborative_splits_records() {
+        let q = VarlenQueue::<u64>::new(4096);
+        let mut first = VarlenConsumer::new(q, "varlen_collab");
+        let mut second = VarlenConsumer::new(q, "varlen_collab");
+        for i in 0..10 {
+            q.produce(&i, i as usize, fill(i as u8)).unwrap();
+        }
+        let mut seen = Vec::new();
+        for turn in 0.. {
+            let consumer = if turn % 2 == 0 { &mut first } else { &mut second };
+            let Some((m, len)) = consumer.consume_collaborative(|_, p| p.len()) else { break };
+            assert_eq!(len, m as usize);
+            seen.push(m);
+        }
+        assert_eq!(seen, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shared() {
+        let path = Path::new("/dev/shm/varlen_queue_test");
+        let _ = std::fs::remove_file(path);
+        let q = VarlenQueue::<u64>::create_or_open_shared(path, 1000).unwrap();
+        assert_eq!(q.capacity(), 1024);
+        q.produce(&7, 4, fill(7)).unwrap();
+
+        let other = VarlenQueue::<u64>::create_or_open_shared(path, 1024).unwrap();
+        let mut c = VarlenConsumer::new(other, "test");
+        c.pos = 0;
+        assert_eq!(c.consume(|_, p| p.to_vec()), Some((7, vec![7; 4])));
+        assert!(matches!(
+            VarlenQueue::<i64>::create_or_open_shared(path, 1024),
+            Err(QueueError::TypeMismatch { .. })
+        ));
+        let _ = std::fs::remove_file(path);
+    }
+}
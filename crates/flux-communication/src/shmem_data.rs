@@ -42,6 +42,17 @@ impl<T> ShmemData<T> {
         )
         .unwrap_or_else(|_| panic!("couldn't create shmem dir for {}", shmem_file.display()));
 
+        if let Some(mapped) = crate::memfd::map(&shmem_file, std::mem::size_of::<T>()) {
+            let (ptr, is_new, _) = mapped.map_err(|e| {
+                ShmemError::MapOpenFailed(e.raw_os_error().unwrap_or_default().unsigned_abs())
+            })?;
+            let inner = NonNull::new(ptr.cast::<T>()).expect("mmap returned null");
+            if is_new {
+                unsafe { std::ptr::write(inner.as_ptr(), init_f()) };
+            }
+            return Ok(Self { inner });
+        }
+
         match ShmemConf::new().size(std::mem::size_of::<T>()).flink(&shmem_file).create() {
             Ok(shmem) => {
                 let inner = Self::shmem_ptr(shmem);
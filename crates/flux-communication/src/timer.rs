@@ -6,20 +6,28 @@ use std::{
 };
 
 use flux_timing::{Duration, Instant, InternalMessage, Nanos};
-use flux_utils::directories::{local_share_dir, shmem_dir_queues_with_base};
+use flux_utils::directories::{
+    local_share_dir, shmem_dir_histograms_with_base, shmem_dir_queues_with_base,
+};
+use type_hash_derive::TypeHash;
 
-use crate::queue::{Producer, Queue, QueueType};
+use crate::{
+    budget::LatencyBudget,
+    histogram::TimerHistograms,
+    queue::{Producer, Queue, QueueType},
+};
 
 /// A single timing interval measured on one machine.
 ///
-/// `start_t` and `stop_t` must originate from the same clock source
-/// and CPU socket. Cross-socket or cross-machine values are invalid.
+/// `start_t` and `stop_t` must originate from the same clock source.
+/// Cross-machine values are invalid, cross-socket ones are corrected by the
+/// adopted `TscCalibration` and invalid if their sockets were not measured.
 ///
 /// # Warning!
 /// `TimingMessage` may be used to display Nano deltas as 2 instants.
 /// Absolute values have no meaning. Only deltas are valid.
 /// Do not compare `TimingMessage` instances against each other.
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
 #[repr(C)]
 pub struct TimingMessage {
     pub start_t: Instant,
@@ -33,12 +41,12 @@ impl TimingMessage {
     }
 
     pub fn elapsed(&self) -> Duration {
-        Duration(self.stop_t.0.saturating_sub(self.start_t.0))
+        self.stop_t.saturating_sub(self.start_t)
     }
 
     #[inline]
     pub fn is_valid(&self) -> bool {
-        self.start_t.same_socket(&self.stop_t)
+        self.start_t.error_bound(&self.stop_t).is_some()
     }
 }
 
@@ -50,15 +58,53 @@ impl From<TimingMessage> for Duration {
 
 const QUEUE_SIZE: usize = 2usize.pow(13);
 
-/// A reusable timing struct that emits timing intervals to queues:
+/// Where a [`Timer`] sends its intervals.
+#[derive(Clone, Copy, Debug)]
+enum TimerSink {
+    /// One `TimingMessage` per interval, consumed by the timekeeper.
+    Queues { processing: Producer<TimingMessage>, latency: Producer<TimingMessage> },
+    /// Aggregated in place, readers take the histograms as they are.
+    Histograms(TimerHistograms),
+}
+
+impl TimerSink {
+    #[inline]
+    fn processing(&mut self, msg: &TimingMessage) {
+        match self {
+            Self::Queues { processing, .. } => {
+                processing.produce(msg);
+            }
+            Self::Histograms(histograms) => histograms.processing.record(msg.elapsed().into()),
+        }
+    }
+
+    #[inline]
+    fn latency(&mut self, msg: &TimingMessage) {
+        match self {
+            Self::Queues { latency, .. } => {
+                latency.produce(msg);
+            }
+            Self::Histograms(histograms) => histograms.latency.record(msg.elapsed().into()),
+        }
+    }
+}
+
+/// A reusable timing struct that emits timing intervals:
 /// - processing time (local business logic)
 /// - latency (queued / on-the-wire)
+///
+/// By default every interval is pushed to a queue. Timers created with
+/// [`Timer::new_aggregating`] record into shared-memory histograms instead,
+/// which hold exact counts at any message rate.
+///
+/// A timer [`with_budget`](Timer::with_budget) also checks the latencies it
+/// records from an `ingestion_t` against that budget.
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct Timer {
     pub curmsg: TimingMessage,
-    processing_time_producer: Producer<TimingMessage>,
-    latency_producer: Producer<TimingMessage>,
+    sink: TimerSink,
+    budget: Option<LatencyBudget>,
 }
 
 impl Timer {
@@ -75,17 +121,69 @@ impl Timer {
         let _ = std::fs::create_dir_all(&dirstr);
 
         let file = format!("{dirstr}/timing-{name}");
-        let timing_queue = Queue::create_or_open_shared(file, QUEUE_SIZE, QueueType::MPMC);
+        let timing_queue = Queue::create_or_open_shared(&file, QUEUE_SIZE, QueueType::MPMC)
+            .unwrap_or_else(|e| panic!("couldn't open timing queue {file}: {e}"));
 
         let file = format!("{dirstr}/latency-{name}");
-        let latency_queue = Queue::create_or_open_shared(file, QUEUE_SIZE, QueueType::MPMC);
+        let latency_queue = Queue::create_or_open_shared(&file, QUEUE_SIZE, QueueType::MPMC)
+            .unwrap_or_else(|e| panic!("couldn't open latency queue {file}: {e}"));
+
+        Self {
+            curmsg: TimingMessage::default(),
+            sink: TimerSink::Queues {
+                processing: Producer::from(timing_queue),
+                latency: Producer::from(latency_queue),
+            },
+            budget: None,
+        }
+    }
+
+    /// A timer that aggregates into histograms swapped every `interval`
+    /// rather than emitting every interval to a queue.
+    pub fn new_aggregating<A: AsRef<Path>, S: Display>(
+        app_name: A,
+        name: S,
+        interval: Nanos,
+    ) -> Self {
+        Self::new_aggregating_with_base_dir(local_share_dir(), app_name, name, interval)
+    }
+
+    pub fn new_aggregating_with_base_dir<D: AsRef<Path>, A: AsRef<Path>, S: Display>(
+        base_dir: D,
+        app_name: A,
+        name: S,
+        interval: Nanos,
+    ) -> Self {
+        let file = shmem_dir_histograms_with_base(base_dir, app_name).join(name.to_string());
+        let histograms = TimerHistograms::create_or_open_shared(&file, interval)
+            .unwrap_or_else(|e| panic!("couldn't open timer histograms {}: {e}", file.display()));
 
         Self {
             curmsg: TimingMessage::default(),
-            processing_time_producer: Producer::from(timing_queue),
-            latency_producer: Producer::from(latency_queue),
+            sink: TimerSink::Histograms(histograms),
+            budget: None,
+        }
+    }
+
+    /// The histograms of an aggregating timer.
+    pub fn histograms(&self) -> Option<TimerHistograms> {
+        match self.sink {
+            TimerSink::Histograms(histograms) => Some(histograms),
+            TimerSink::Queues { .. } => None,
         }
     }
+
+    /// Checks the latencies recorded from an `ingestion_t` against `budget`.
+    /// Synthetic intervals emitted from nanos are not checked.
+    #[must_use]
+    pub fn with_budget(mut self, budget: LatencyBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    pub fn budget(&self) -> Option<LatencyBudget> {
+        self.budget
+    }
 }
 
 unsafe impl Send for Timer {}
@@ -125,14 +223,24 @@ impl Timer {
     #[inline]
     fn emit_latency(&mut self) {
         if self.curmsg.is_valid() {
-            self.latency_producer.produce(&self.curmsg);
+            self.sink.latency(&self.curmsg);
+        }
+    }
+
+    /// Checks the latency in `curmsg`, which ended `now`, against the budget.
+    #[inline]
+    fn check_budget(&self, now: Instant) {
+        if let Some(budget) = &self.budget &&
+            self.curmsg.is_valid()
+        {
+            budget.check(self.curmsg.elapsed(), now);
         }
     }
 
     #[inline]
     fn emit_processing(&mut self) {
         if self.curmsg.is_valid() {
-            self.processing_time_producer.produce(&self.curmsg);
+            self.sink.processing(&self.curmsg);
         }
     }
 
@@ -160,9 +268,11 @@ impl Timer {
     #[inline]
     pub fn record_processing_and_latency_from(&mut self, ingestion_t: Instant) {
         self.record_processing();
+        let now = self.curmsg.stop_t;
         self.set_stop(self.curmsg.start_t);
         self.set_start(ingestion_t);
         self.emit_latency();
+        self.check_budget(now);
     }
 
     /// Finish processing, then emit latency until now.
@@ -177,6 +287,7 @@ impl Timer {
         self.record_processing();
         self.set_start(ingestion_t);
         self.emit_latency();
+        self.check_budget(self.curmsg.stop_t);
     }
 
     /// Emit a pure latency measurement.
@@ -187,7 +298,10 @@ impl Timer {
     pub fn record_latency_until_now(&mut self, ingestion_t: Instant) {
         let m = TimingMessage { start_t: ingestion_t, stop_t: Instant::now() };
         if m.is_valid() {
-            self.latency_producer.produce(&m);
+            self.sink.latency(&m);
+            if let Some(budget) = &self.budget {
+                budget.check(m.elapsed(), m.stop_t);
+            }
         }
     }
 
@@ -237,22 +351,30 @@ impl Timer {
     #[inline]
     pub fn emit_processing_from_nanos_without_first(&self, start: Nanos, end: Nanos) {
         let delta = end.saturating_sub(start);
-        let rdtsc_dur = Duration::from(delta);
-        self.processing_time_producer.produce_without_first(&TimingMessage {
-            start_t: Instant(0),
-            stop_t: Instant(rdtsc_dur.0),
-        });
+        match &self.sink {
+            TimerSink::Queues { processing, .. } => {
+                processing.produce_without_first(&TimingMessage {
+                    start_t: Instant(0),
+                    stop_t: Instant(Duration::from(delta).0),
+                });
+            }
+            TimerSink::Histograms(histograms) => histograms.processing.record(delta),
+        }
     }
 
     /// Emit a synthetic latency interval directly to the underlying queue.
     #[inline]
     pub fn emit_latency_from_nanos_without_first(&self, start: Nanos, end: Nanos) {
         let delta = end.saturating_sub(start);
-        let rdtsc_dur = Duration::from(delta);
-        self.latency_producer.produce_without_first(&TimingMessage {
-            start_t: Instant(0),
-            stop_t: Instant(rdtsc_dur.0),
-        });
+        match &self.sink {
+            TimerSink::Queues { latency, .. } => {
+                latency.produce_without_first(&TimingMessage {
+                    start_t: Instant(0),
+                    stop_t: Instant(Duration::from(delta).0),
+                });
+            }
+            TimerSink::Histograms(histograms) => histograms.latency.record(delta),
+        }
     }
 
     #[inline]
@@ -0,0 +1,77 @@
+use std::{hint::spin_loop, sync::atomic::AtomicU32, time::Duration};
+
+/// Block while `word` still equals `expected`, for at most `timeout` (forever
+/// if `None`). May return spuriously.
+///
+/// No `FUTEX_PRIVATE_FLAG`: the word may live in shared memory and be woken
+/// from another process.
+pub(crate) fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    #[cfg(target_os = "linux")]
+    {
+        let ts = timeout.map(|t| libc::timespec {
+            tv_sec: t.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+            tv_nsec: t.subsec_nanos() as libc::c_long,
+        });
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                word.as_ptr(),
+                libc::FUTEX_WAIT,
+                expected as libc::c_int,
+                ts.as_ref().map_or(std::ptr::null(), std::ptr::from_ref),
+                std::ptr::null::<libc::c_int>(),
+                0 as libc::c_int,
+            );
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (word, expected);
+        std::thread::sleep(timeout.unwrap_or(Duration::from_millis(1)));
+    }
+}
+
+/// Wake every thread, in any process, waiting on `word`.
+pub(crate) fn futex_wake(word: &AtomicU32) {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAKE,
+            libc::c_int::MAX,
+            std::ptr::null::<libc::timespec>(),
+            std::ptr::null::<libc::c_int>(),
+            0 as libc::c_int,
+        );
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = word;
+}
+
+/// Adaptive backoff for blocking consumers: spin for a short while to catch
+/// bursts, then yield the core, then sleep on a futex until woken.
+#[derive(Debug, Default)]
+pub(crate) struct Backoff {
+    round: u32,
+}
+
+impl Backoff {
+    const SPIN_ROUNDS: u32 = 1 << 10;
+    const YIELD_ROUNDS: u32 = Self::SPIN_ROUNDS + (1 << 6);
+
+    /// Spin or yield once and return `false`, or return `true` once both
+    /// phases are exhausted and the caller should sleep.
+    #[inline]
+    pub(crate) fn snooze(&mut self) -> bool {
+        if self.round < Self::SPIN_ROUNDS {
+            spin_loop();
+        } else if self.round < Self::YIELD_ROUNDS {
+            std::thread::yield_now();
+        } else {
+            return true;
+        }
+        self.round += 1;
+        false
+    }
+}
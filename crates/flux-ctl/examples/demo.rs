@@ -3,6 +3,7 @@
 //! Run with:
 //!   cargo run --example demo -p flux-ctl
 
+use flux::type_hash_derive::TypeHash;
 use flux_communication::{
     ShmemData,
     queue::{Producer, Queue, QueueType},
@@ -12,7 +13,7 @@ use flux_ctl::discovery::scan_base_dir;
 use flux_utils::directories::local_share_dir;
 
 /// Example message types
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
 #[repr(C)]
 struct PriceUpdate {
     price: u64,
@@ -20,7 +21,7 @@ struct PriceUpdate {
     timestamp: u64,
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
 #[repr(C)]
 struct OrderFill {
     order_id: u64,
@@ -45,9 +46,9 @@ fn main() {
     // Create some queues (auto-registered)
     println!("Creating shmem queues...");
     let price_q: Queue<PriceUpdate> =
-        shmem_queue_with_base_dir(&base_dir, app_name, 1024, QueueType::SPMC);
+        shmem_queue_with_base_dir(&base_dir, app_name, 1024, QueueType::SPMC).unwrap();
     let order_q: Queue<OrderFill> =
-        shmem_queue_with_base_dir(&base_dir, app_name, 256, QueueType::MPMC);
+        shmem_queue_with_base_dir(&base_dir, app_name, 256, QueueType::MPMC).unwrap();
 
     // Create shmem data (auto-registered)
     println!("Creating shmem data...");
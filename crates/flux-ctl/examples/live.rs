@@ -27,6 +27,7 @@ use std::{
     time::Duration,
 };
 
+use flux::type_hash_derive::TypeHash;
 use flux_communication::{
     ShmemData, cleanup_shmem,
     queue::{Consumer, Producer, Queue, QueueHeader, QueueType},
@@ -34,7 +35,7 @@ use flux_communication::{
 };
 use flux_utils::directories::{local_share_dir, shmem_dir_with_base};
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
 #[repr(C)]
 struct Quote {
     bid: u64,
@@ -44,7 +45,7 @@ struct Quote {
     sequence: u64,
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
 #[repr(C)]
 struct Trade {
     price: u64,
@@ -53,7 +54,7 @@ struct Trade {
     id: u64,
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
 #[repr(C)]
 struct OrderEvent {
     order_id: u64,
@@ -146,15 +147,15 @@ fn run_primary(flags: Flags) {
     }
 
     let quote_q: Queue<Quote> =
-        shmem_queue_with_base_dir(&base_dir, "market-data", 4096, QueueType::SPMC);
+        shmem_queue_with_base_dir(&base_dir, "market-data", 4096, QueueType::SPMC).unwrap();
     let trade_q: Queue<Trade> =
-        shmem_queue_with_base_dir(&base_dir, "market-data", 1024, QueueType::SPMC);
+        shmem_queue_with_base_dir(&base_dir, "market-data", 1024, QueueType::SPMC).unwrap();
     let _risk: ShmemData<RiskMetrics> =
         ShmemData::open_or_init_with_base_dir(&base_dir, "market-data", RiskMetrics::default)
             .unwrap();
 
     let order_q: Queue<OrderEvent> =
-        shmem_queue_with_base_dir(&base_dir, "order-engine", 2048, QueueType::MPMC);
+        shmem_queue_with_base_dir(&base_dir, "order-engine", 2048, QueueType::MPMC).unwrap();
     let _state: ShmemData<EngineState> =
         ShmemData::open_or_init_with_base_dir(&base_dir, "order-engine", EngineState::default)
             .unwrap();
@@ -245,7 +246,7 @@ fn run_primary(flags: Flags) {
 
             println!("\n  💉 --poison: creating poison-demo queue...");
             let q: Queue<Trade> =
-                shmem_queue_with_base_dir(&base_dir2, "poison-demo", 64, QueueType::SPMC);
+                shmem_queue_with_base_dir(&base_dir2, "poison-demo", 64, QueueType::SPMC).unwrap();
             let mut p = Producer::from(q);
 
             // Write real messages at ~100 msgs/sec for 2 seconds so the
@@ -344,15 +345,15 @@ fn run_worker() {
     // Attach to existing queues (open-or-create reattaches; registration
     // records this PID against the same flink)
     let quote_q: Queue<Quote> =
-        shmem_queue_with_base_dir(&base_dir, "market-data", 4096, QueueType::SPMC);
+        shmem_queue_with_base_dir(&base_dir, "market-data", 4096, QueueType::SPMC).unwrap();
     let trade_q: Queue<Trade> =
-        shmem_queue_with_base_dir(&base_dir, "market-data", 1024, QueueType::SPMC);
+        shmem_queue_with_base_dir(&base_dir, "market-data", 1024, QueueType::SPMC).unwrap();
     let _risk: ShmemData<RiskMetrics> =
         ShmemData::open_or_init_with_base_dir(&base_dir, "market-data", RiskMetrics::default)
             .unwrap();
 
     let order_q: Queue<OrderEvent> =
-        shmem_queue_with_base_dir(&base_dir, "order-engine", 2048, QueueType::MPMC);
+        shmem_queue_with_base_dir(&base_dir, "order-engine", 2048, QueueType::MPMC).unwrap();
     let _state: ShmemData<EngineState> =
         ShmemData::open_or_init_with_base_dir(&base_dir, "order-engine", EngineState::default)
             .unwrap();
@@ -1,16 +1,25 @@
 //! CLI command implementations: `list`, `list_json`, `stats`, `inspect`,
-//! `clean`.
+//! `clean`, `record`, `groups`, `repair`, `latency`, `budgets`,
+//! `calibrate-sockets`.
 
 use std::{io::IsTerminal, path::Path, sync::atomic::Ordering};
 
 use crossterm::style::Stylize;
-use flux_communication::{ShmemKind, cleanup_flink, queue::QueueHeader};
+use flux::persistence::FlightRecorder;
+use flux_communication::{
+    LatencyBudget, QueueError, SharedTscCalibration, ShmemKind,
+    array::ArrayHeader,
+    budget::{BUDGET_WINDOW, BUDGET_WINDOWS},
+    cleanup_flink,
+    histogram::{HistogramSnapshot, TimerHistograms},
+    queue::QueueHeader,
+};
 use serde::Serialize;
-use shared_memory::ShmemConf;
+use shared_memory::{Shmem, ShmemConf};
 
 use super::{
-    DiscoveredEntry, flink_reachable,
-    inspect::{PoisonInfo, backing_file_size, format_bytes},
+    DiscoveredEntry, NumaPlacement, consumer_node, flink_reachable,
+    inspect::{PoisonInfo, backing_file_size, format_bytes, read_consumer_groups, scan_proc_fds},
     scan_base_dir,
 };
 
@@ -216,6 +225,7 @@ pub fn stats(
 
     let mut queues = 0usize;
     let mut arrays = 0usize;
+    let mut maps = 0usize;
     let mut data = 0usize;
     let mut unknown = 0usize;
     let mut total_capacity: u64 = 0;
@@ -225,6 +235,7 @@ pub fn stats(
         match entry.kind {
             ShmemKind::Queue => queues += 1,
             ShmemKind::SeqlockArray => arrays += 1,
+            ShmemKind::SeqlockMap => maps += 1,
             ShmemKind::Data => data += 1,
             ShmemKind::Unknown => unknown += 1,
         }
@@ -266,6 +277,7 @@ pub fn stats(
     println!("  By kind:");
     println!("    Queue:          {queues}");
     println!("    SeqlockArray:   {arrays}");
+    println!("    SeqlockMap:     {maps}");
     println!("    Data:           {data}");
     if unknown > 0 {
         println!("    Unknown:        {unknown}");
@@ -281,7 +293,7 @@ pub fn stats(
 ///
 /// For each matching entry (filtered by `app_filter` and `segment_filter`),
 /// displays kind, status, element size, capacity, flink path, backing file
-/// size, write count (for queues), and poison status.
+/// size, write count (for queues), stored type identity, and poison status.
 ///
 /// Output is colorised when stdout is a terminal.
 pub fn inspect(
@@ -296,6 +308,8 @@ pub fn inspect(
         return Ok(());
     }
 
+    let proc_map = scan_proc_fds();
+
     for entry in &all_entries {
         if !entry.is_visible() {
             continue;
@@ -342,6 +356,13 @@ pub fn inspect(
         println!("  Flink:      {}", entry.flink);
         println!("  Backing:    {backing}");
 
+        let numa = entry
+            .backing_path()
+            .and_then(|backing| NumaPlacement::read(&backing, &entry.pids(&proc_map)));
+        if let Some(ref numa) = numa {
+            println!("  NUMA:       {numa}");
+        }
+
         if entry.kind == ShmemKind::Queue &&
             let Ok(shmem) = ShmemConf::new().flink(&entry.flink).open()
         {
@@ -350,6 +371,23 @@ pub fn inspect(
                 let header = unsafe { &*(shmem.as_ptr() as *const QueueHeader) };
                 if header.is_initialized() {
                     println!("  Writes:     {}", header.count.load(Ordering::Relaxed));
+                    println!("  Identity:   {}", header.identity());
+                    let (repaired, skipped) = header.tombstone_stats();
+                    if repaired != 0 {
+                        println!("  Repaired:   {repaired} slots, skipped {skipped} times");
+                    }
+                }
+            }
+        }
+
+        if matches!(entry.kind, ShmemKind::SeqlockArray | ShmemKind::SeqlockMap) &&
+            let Ok(shmem) = ShmemConf::new().flink(&entry.flink).open()
+        {
+            if shmem.len() >= std::mem::size_of::<ArrayHeader>() {
+                #[allow(clippy::cast_ptr_alignment)]
+                let header = unsafe { &*(shmem.as_ptr() as *const ArrayHeader) };
+                if header.is_initialized() {
+                    println!("  Identity:   {}", header.identity);
                 }
             }
         }
@@ -365,13 +403,41 @@ pub fn inspect(
                 println!("{poison_line}");
             }
         }
+
+        if entry.kind == ShmemKind::Queue &&
+            let Some(segment_node) = numa.as_ref().and_then(NumaPlacement::dominant_node)
+        {
+            warn_cross_node_consumers(entry, segment_node, color);
+        }
         println!();
     }
     Ok(())
 }
+/// Warn about consumer groups whose tile thread runs on a different NUMA node
+/// than the one backing the queue.
+fn warn_cross_node_consumers(entry: &DiscoveredEntry, segment_node: u16, color: bool) {
+    for group in read_consumer_groups(&entry.flink) {
+        let Some(node) = consumer_node(&group.label) else {
+            continue;
+        };
+        if node == segment_node {
+            continue;
+        }
+        let warning = format!(
+            "  ⚠ Cross-node: {} runs on node {node}, segment is on node {segment_node}",
+            group.label
+        );
+        if color {
+            println!("{}", warning.yellow());
+        } else {
+            println!("{warning}");
+        }
+    }
+}
+
 /// Remove stale shared memory segments.
 ///
-/// Recursively searches `base_dir` for `shmem/{queues,data,arrays}/`
+/// Recursively searches `base_dir` for `shmem/{queues,data,arrays,maps}/`
 /// directories, then checks each flink file.  A flink is stale when its
 /// backing shmem can no longer be opened.
 ///
@@ -394,7 +460,7 @@ pub fn clean(
         if app_filter.is_some_and(|f| app_name != f) {
             continue;
         }
-        for subdir in ["queues", "data", "arrays"] {
+        for subdir in ["queues", "data", "arrays", "maps", "varlen", "conflated", "recorder"] {
             let type_dir = shmem_dir.join(subdir);
             let Ok(flink_iter) = std::fs::read_dir(&type_dir) else {
                 continue;
@@ -434,3 +500,427 @@ pub fn clean(
     }
     Ok(())
 }
+
+fn open_recorder(base_dir: &Path, app: &str) -> Result<FlightRecorder, Box<dyn std::error::Error>> {
+    if !flux_utils::directories::shmem_dir_recorder_with_base(base_dir, app).is_dir() {
+        return Err(format!("{app} has no flight recorder").into());
+    }
+    Ok(FlightRecorder::open(base_dir, app)?)
+}
+
+/// Dump the flight recorder of `app` to a persistence file under its data
+/// dir and print the file's path.
+pub fn record_dump(
+    base_dir: &Path,
+    app: &str,
+    reason: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match open_recorder(base_dir, app)?.dump(reason) {
+        Some(path) => println!("Dumped flight recorder of {app} to {}", path.display()),
+        None => println!("Flight recorder of {app} is empty"),
+    }
+    Ok(())
+}
+
+/// Stop or resume recording for `app`, e.g. to keep the rings untouched
+/// while investigating.
+pub fn record_freeze(
+    base_dir: &Path,
+    app: &str,
+    frozen: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let recorder = open_recorder(base_dir, app)?;
+    if frozen {
+        recorder.freeze();
+        println!("Froze flight recorder of {app}");
+    } else {
+        recorder.unfreeze();
+        println!("Resumed flight recorder of {app}");
+    }
+    Ok(())
+}
+
+/// Map the initialized queues of `app` whose type name contains `segment`.
+fn open_queues(
+    base_dir: &Path,
+    app: Option<&str>,
+    segment: Option<&str>,
+) -> Vec<(DiscoveredEntry, Shmem)> {
+    scan_base_dir(base_dir)
+        .into_iter()
+        .filter(|e| e.kind == ShmemKind::Queue && e.is_visible())
+        .filter(|e| app.is_none_or(|a| e.app_name == a))
+        .filter(|e| segment.is_none_or(|s| e.type_name.contains(s)))
+        .filter_map(|e| {
+            let shmem = ShmemConf::new().flink(&e.flink).open().ok()?;
+            (shmem.len() >= std::mem::size_of::<QueueHeader>() &&
+                queue_header(&shmem).is_initialized())
+            .then_some((e, shmem))
+        })
+        .collect()
+}
+
+#[allow(clippy::mut_from_ref)]
+fn queue_header(shmem: &Shmem) -> &mut QueueHeader {
+    #[allow(clippy::cast_ptr_alignment)]
+    unsafe {
+        &mut *shmem.as_ptr().cast::<QueueHeader>()
+    }
+}
+
+/// List the consumer groups of every queue with their kind, owner, member
+/// count and lease state.
+pub fn groups_list(
+    base_dir: &Path,
+    app_filter: Option<&str>,
+    segment_filter: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let color = std::io::stdout().is_terminal();
+    let now = flux_timing::Nanos::now();
+    let mut any = false;
+
+    for (entry, shmem) in open_queues(base_dir, app_filter, segment_filter) {
+        let header = queue_header(&shmem);
+        let groups = header.groups();
+        if groups.is_empty() {
+            continue;
+        }
+        any = true;
+
+        let title = format!("─── {} — {} ───", entry.app_name, entry.type_name);
+        let lease = header.group_lease().map_or_else(|| "none".into(), |l| format!("{l:?}"));
+        if color {
+            println!("{}  lease {lease}", title.bold());
+        } else {
+            println!("{title}  lease {lease}");
+        }
+        println!(
+            "  {:<48} {:<14} {:>8} {:>7} {:>12} {:>12}",
+            "LABEL", "KIND", "OWNER", "MEMBERS", "CURSOR", "LAST ACTIVE"
+        );
+        for g in groups {
+            let idle = if g.last_active.0 == 0 {
+                "-".to_owned()
+            } else {
+                format!("{} ago", now.saturating_sub(g.last_active))
+            };
+            let line = format!(
+                "  {:<48} {:<14} {:>8} {:>7} {:>12} {:>12}",
+                g.label,
+                g.kind.to_string(),
+                g.owner_pid,
+                g.members,
+                g.cursor,
+                idle
+            );
+            if g.expired {
+                let line = format!("{line}  expired");
+                if color {
+                    println!("{}", line.dark_grey());
+                } else {
+                    println!("{line}");
+                }
+            } else {
+                println!("{line}");
+            }
+        }
+        println!();
+    }
+
+    if !any {
+        println!("No consumer groups found");
+    }
+    Ok(())
+}
+
+/// Move the cursor of consumer group `label` on the matching queues of `app`,
+/// to `cursor` or the producer's current write count.
+///
+/// Collaborative groups pick up from the new cursor on their next read;
+/// broadcast consumers keep their own position, for them the cursor only
+/// affects how far producers may run ahead. Moving a checkpoint changes where
+/// its consumer resumes after the next restart.
+pub fn groups_reset(
+    base_dir: &Path,
+    app: &str,
+    segment: &str,
+    label: &str,
+    cursor: Option<usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut n = 0;
+    for (entry, shmem) in open_queues(base_dir, Some(app), Some(segment)) {
+        let header = queue_header(&shmem);
+        let to = cursor.unwrap_or_else(|| header.count.load(Ordering::Relaxed));
+        if header.reset_group(label, to) {
+            println!("Reset {label} on {} to {to}", entry.type_name);
+            n += 1;
+        }
+    }
+    if n == 0 {
+        return Err(format!("no group {label} on {app} {segment}").into());
+    }
+    Ok(())
+}
+
+/// Remove consumer group `label` from the matching queues of `app`, freeing
+/// its slot. Live members rejoin with a fresh cursor.
+pub fn groups_remove(
+    base_dir: &Path,
+    app: &str,
+    segment: &str,
+    label: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut n = 0;
+    for (entry, shmem) in open_queues(base_dir, Some(app), Some(segment)) {
+        if queue_header(&shmem).remove_group(label) {
+            println!("Removed {label} from {}", entry.type_name);
+            n += 1;
+        }
+    }
+    if n == 0 {
+        return Err(format!("no group {label} on {app} {segment}").into());
+    }
+    Ok(())
+}
+
+/// How long a slot must stay at the same odd version before `repair` touches
+/// it; a live producer finishes a write in well under that.
+const REPAIR_GRACE: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Repair seqlock slots that a crashed producer left mid-write on the queues
+/// matching the filters, see [`QueueHeader::repair_poisoned`].
+///
+/// Slots are left alone while a producer in another process still holds a
+/// live lease on the queue, unless `force` is set.
+pub fn repair(
+    base_dir: &Path,
+    app_filter: Option<&str>,
+    segment_filter: Option<&str>,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut any = false;
+    for (entry, shmem) in open_queues(base_dir, app_filter, segment_filter) {
+        let header = queue_header(&shmem);
+        // The whole segment is mapped, slots included.
+        let report = unsafe { header.repair_poisoned(REPAIR_GRACE, force) };
+        if !report.repaired.is_empty() {
+            any = true;
+            println!(
+                "  ✓ {} {}: tombstoned messages {:?}",
+                entry.app_name, entry.type_name, report.repaired
+            );
+        }
+        if report.writer_alive != 0 {
+            any = true;
+            println!(
+                "  ⚠ {} {}: {} poisoned slots, producer {:?} still alive (use --force)",
+                entry.app_name,
+                entry.type_name,
+                report.writer_alive,
+                header.producers()
+            );
+        }
+    }
+    if !any {
+        println!("No poisoned queue slots found");
+    }
+    Ok(())
+}
+
+/// Segments in `dir` of every app, or of `app`, whose name contains `timer`.
+fn open_timer_segments<T>(
+    base_dir: &Path,
+    app: Option<&str>,
+    timer: Option<&str>,
+    dir: fn(&Path, &str) -> std::path::PathBuf,
+    open: fn(&Path) -> Result<T, QueueError>,
+) -> Vec<(String, String, T)> {
+    let Ok(apps) = std::fs::read_dir(base_dir) else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for app_entry in apps.flatten() {
+        let app_name = app_entry.file_name().to_string_lossy().into_owned();
+        if app.is_some_and(|a| a != app_name) {
+            continue;
+        }
+        let Ok(timers) = std::fs::read_dir(dir(base_dir, &app_name)) else {
+            continue;
+        };
+        for timer_entry in timers.flatten() {
+            let name = timer_entry.file_name().to_string_lossy().into_owned();
+            if timer.is_some_and(|t| !name.contains(t)) {
+                continue;
+            }
+            if let Ok(segment) = open(&timer_entry.path()) {
+                out.push((app_name.clone(), name, segment));
+            }
+        }
+    }
+    out.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+    out
+}
+
+/// Histograms of the aggregating timers of every app, or of `app`, whose name
+/// contains `timer`.
+fn open_histograms(
+    base_dir: &Path,
+    app: Option<&str>,
+    timer: Option<&str>,
+) -> Vec<(String, String, TimerHistograms)> {
+    open_timer_segments(
+        base_dir,
+        app,
+        timer,
+        |base, app| flux_utils::directories::shmem_dir_histograms_with_base(base, app),
+        |path| TimerHistograms::open_shared(path),
+    )
+}
+
+/// Print the last finished interval of every aggregating timer: count, rate
+/// and exact percentiles of its processing times and latencies.
+pub fn latency(
+    base_dir: &Path,
+    app_filter: Option<&str>,
+    timer_filter: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let color = std::io::stdout().is_terminal();
+    let now = flux_timing::Nanos::now();
+    let timers = open_histograms(base_dir, app_filter, timer_filter);
+    if timers.is_empty() {
+        println!("No timer histograms found");
+        return Ok(());
+    }
+
+    let mut snapshot = HistogramSnapshot::default();
+    for (app, name, histograms) in timers {
+        let title = format!("─── {app} — {name} ───");
+        let interval = histograms.latency.interval();
+        if color {
+            println!("{}  interval {interval}", title.bold());
+        } else {
+            println!("{title}  interval {interval}");
+        }
+        println!(
+            "  {:<12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12}",
+            "KIND", "COUNT", "RATE/S", "MEAN", "P50", "P99", "P99.9", "MAX", "ENDED"
+        );
+        for (kind, histogram) in
+            [("processing", &histograms.processing), ("latency", &histograms.latency)]
+        {
+            if !histogram.read_last_interval(&mut snapshot) {
+                println!("  {kind:<12} {:>10}", "-");
+                continue;
+            }
+            let ended = format!("{} ago", now.saturating_sub(snapshot.end));
+            if snapshot.is_empty() {
+                println!(
+                    "  {kind:<12} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12}",
+                    0, 0, "-", "-", "-", "-", "-", ended
+                );
+                continue;
+            }
+            println!(
+                "  {kind:<12} {:>10} {:>10.0} {:>10} {:>10} {:>10} {:>10} {:>10} {:>12}",
+                snapshot.count,
+                snapshot.rate(),
+                snapshot.mean().to_string(),
+                snapshot.value_at_quantile(0.5).to_string(),
+                snapshot.value_at_quantile(0.99).to_string(),
+                snapshot.value_at_quantile(0.999).to_string(),
+                flux_timing::Nanos(snapshot.max).to_string(),
+                ended
+            );
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Print the latency budget of every consumer timer that has one: the counts
+/// checked and over budget, the compliance over the last minute and the worst
+/// offenders.
+pub fn budgets(
+    base_dir: &Path,
+    app_filter: Option<&str>,
+    timer_filter: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let color = std::io::stdout().is_terminal();
+    let now = flux_timing::Nanos::now();
+    let budgets = open_timer_segments(
+        base_dir,
+        app_filter,
+        timer_filter,
+        |base, app| flux_utils::directories::shmem_dir_budgets_with_base(base, app),
+        |path| LatencyBudget::open_shared(path),
+    );
+    if budgets.is_empty() {
+        println!("No latency budgets found");
+        return Ok(());
+    }
+
+    let recent = BUDGET_WINDOW.as_secs() * BUDGET_WINDOWS as f64;
+    for (app, name, budget) in budgets {
+        let snapshot = budget.snapshot();
+        let title = format!("─── {app} — {name} ───");
+        let subtitle = format!("budget {}, recent is the last {recent:.0}s", snapshot.budget);
+        if color {
+            println!("{}  {subtitle}", title.bold());
+        } else {
+            println!("{title}  {subtitle}");
+        }
+        println!(
+            "  {:>12} {:>12} {:>12} {:>12} {:>12}",
+            "CHECKED", "OVER", "RECENT", "RECENT OVER", "COMPLIANCE"
+        );
+        let compliance = match snapshot.compliance() {
+            None => "-".to_owned(),
+            Some(c) if color && snapshot.recent_violations != 0 => {
+                format!("{c:.3}%").red().to_string()
+            }
+            Some(c) => format!("{c:.3}%"),
+        };
+        println!(
+            "  {:>12} {:>12} {:>12} {:>12} {:>12}",
+            snapshot.checked,
+            snapshot.violations,
+            snapshot.recent_checked,
+            snapshot.recent_violations,
+            compliance
+        );
+        for offender in &snapshot.worst {
+            println!(
+                "    worst {:>10}  {} ago",
+                offender.latency.to_string(),
+                now.saturating_sub(offender.at)
+            );
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/// Measure how far the TSC of each socket is from the anchor of `app`'s
+/// shared calibration and publish the offsets, see
+/// [`SharedTscCalibration::publish_socket_offsets`].
+///
+/// Pins a thread onto every CPU flux-ctl may run on, so run it under an
+/// affinity that leaves out the app's isolated cores.
+pub fn calibrate_sockets(base_dir: &Path, app: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let shared = SharedTscCalibration::open_or_publish_with_base_dir(base_dir, app)?;
+    // Offsets are converted at the app's rate.
+    shared.adopt();
+    let Some(calibration) = shared.publish_socket_offsets() else {
+        return Err(format!("no TSC calibration published for {app}").into());
+    };
+    println!("{:>8} {:>16} {:>12}", "SOCKET", "OFFSET (ticks)", "ERROR (ticks)");
+    for (socket, (offset, error)) in
+        calibration.socket_offsets.iter().zip(calibration.socket_errors).enumerate()
+    {
+        if error != 0 {
+            println!("{socket:>8} {offset:>16} {error:>12}");
+        }
+    }
+    println!("Published to {app}, its processes adopt the offsets within a minute");
+    Ok(())
+}
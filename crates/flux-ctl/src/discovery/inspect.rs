@@ -1,15 +1,16 @@
 //! Segment inspection: PID info, poison detection, backing file size, queue
-//! stats.
+//! stats, NUMA placement.
 
 use std::{
-    collections::HashMap,
-    path::PathBuf,
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
     sync::atomic::{AtomicU64, Ordering},
 };
 
 pub use flux_communication::is_pid_alive;
 use flux_communication::{ShmemKind, array::ArrayHeader, queue::QueueHeader};
 use flux_timing::Nanos;
+use flux_utils::numa::node_of_cpu;
 use shared_memory::ShmemConf;
 
 use super::DiscoveredEntry;
@@ -112,14 +113,14 @@ pub struct PoisonInfo {
 impl PoisonInfo {
     /// Scan a segment's seqlock buffer for poisoned slots.
     ///
-    /// Works for both `Queue` and `SeqlockArray` — both use the same pattern:
-    /// a `repr(C, align(64))` header followed by `Seqlock<T>` slots where the
-    /// first 8 bytes of each slot are the `AtomicU64` version.
+    /// Works for `Queue`, `SeqlockArray` and `SeqlockMap` — all use the same
+    /// pattern: a `repr(C, align(64))` header followed by `Seqlock<T>` slots
+    /// where the first 8 bytes of each slot are the `AtomicU64` version.
     pub fn check(entry: &DiscoveredEntry) -> Option<Self> {
         let os_id = os_id_from_entry(entry)?;
         match entry.kind {
             ShmemKind::Queue => Self::check_queue(&os_id),
-            ShmemKind::SeqlockArray => Self::check_array(&os_id),
+            ShmemKind::SeqlockArray | ShmemKind::SeqlockMap => Self::check_array(&os_id),
             _ => None,
         }
     }
@@ -134,7 +135,7 @@ impl PoisonInfo {
         let os_id = os_id_from_entry(entry)?;
         match entry.kind {
             ShmemKind::Queue => Self::check_queue_quick(&os_id),
-            ShmemKind::SeqlockArray => Self::check_array_quick(&os_id),
+            ShmemKind::SeqlockArray | ShmemKind::SeqlockMap => Self::check_array_quick(&os_id),
             _ => None,
         }
     }
@@ -494,6 +495,95 @@ pub fn read_consumer_groups(flink: &str) -> Vec<ConsumerGroupInfo> {
         })
         .collect()
 }
+/// Where a segment's pages live, read from `/proc/<pid>/numa_maps` of the
+/// processes mapping it.
+#[derive(Clone, Debug, Default)]
+pub struct NumaPlacement {
+    /// Memory policy as printed by the kernel, e.g. `bind:1` or `default`.
+    pub policy: String,
+    /// Resident pages per node.
+    pub pages: BTreeMap<u16, u64>,
+}
+
+impl NumaPlacement {
+    /// Shared pages show up in every process that has them mapped, so each
+    /// node keeps the highest count seen rather than the sum.
+    pub fn read(backing: &Path, pids: &[u32]) -> Option<Self> {
+        let needle = format!("file={}", backing.display());
+        let mut placement: Option<Self> = None;
+        for pid in pids {
+            let Ok(maps) = std::fs::read_to_string(format!("/proc/{pid}/numa_maps")) else {
+                continue;
+            };
+            for line in maps.lines().filter(|l| l.split_whitespace().any(|f| f == needle)) {
+                let p = placement.get_or_insert_with(Self::default);
+                let mut fields = line.split_whitespace().skip(1);
+                if let Some(policy) = fields.next() {
+                    policy.clone_into(&mut p.policy);
+                }
+                for (node, pages) in fields.filter_map(parse_node_pages) {
+                    let entry = p.pages.entry(node).or_default();
+                    *entry = (*entry).max(pages);
+                }
+            }
+        }
+        placement
+    }
+
+    /// The node holding most of the segment's resident pages.
+    pub fn dominant_node(&self) -> Option<u16> {
+        self.pages.iter().max_by_key(|&(_, pages)| pages).map(|(&node, _)| node)
+    }
+}
+
+impl std::fmt::Display for NumaPlacement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.policy)?;
+        if self.pages.is_empty() {
+            return write!(f, "  (no resident pages)");
+        }
+        for (node, pages) in &self.pages {
+            write!(f, "  N{node}={pages}")?;
+        }
+        write!(f, " pages")
+    }
+}
+
+/// `N1=42` → `(1, 42)`.
+fn parse_node_pages(field: &str) -> Option<(u16, u64)> {
+    let (node, pages) = field.strip_prefix('N')?.split_once('=')?;
+    Some((node.parse().ok()?, pages.parse().ok()?))
+}
+
+/// NUMA node a consumer group's tile thread last ran on.
+///
+/// Group labels look like `binary[PID].TileName.broadcast`; tile threads are
+/// named after the tile, so the thread is found by its `comm` (truncated by
+/// the kernel to 15 bytes) and its node derived from the `processor` field of
+/// its `stat`.
+pub fn consumer_node(label: &str) -> Option<u16> {
+    let start = label.find('[')? + 1;
+    let end = label[start..].find(']')? + start;
+    let pid: u32 = label[start..end].parse().ok()?;
+    let tile = label[end + 1..].strip_prefix('.')?.split('.').next()?;
+    let comm = &tile.as_bytes()[..tile.len().min(15)];
+
+    let tasks = std::fs::read_dir(format!("/proc/{pid}/task")).ok()?;
+    for task in tasks.flatten() {
+        let Ok(name) = std::fs::read(task.path().join("comm")) else {
+            continue;
+        };
+        if name.trim_ascii_end() != comm {
+            continue;
+        }
+        let task_stat = std::fs::read_to_string(task.path().join("stat")).ok()?;
+        // `processor` is field 39; fields after `comm` start at field 3.
+        let cpu = task_stat.rsplit_once(')')?.1.split_whitespace().nth(36)?.parse().ok()?;
+        return node_of_cpu(cpu);
+    }
+    None
+}
+
 /// Format a byte count as a human-readable string using binary units (KiB, MiB,
 /// GiB).
 pub fn format_bytes(bytes: u64) -> String {
@@ -1,4 +1,5 @@
 //! Discovery and inspection of shared memory segments via filesystem scanning.
+//! Segments hosted by a memfd broker are listed through its socket.
 //!
 //! Each segment is represented as a [`DiscoveredEntry`] — a plain owned struct
 //! with no dependency on shared-memory–resident data structures.
@@ -16,30 +17,40 @@ use std::{
     sync::atomic::{AtomicU64, Ordering},
 };
 
-pub use cli::{clean, inspect, list_all, list_json, stats};
+pub use cli::{
+    budgets, calibrate_sockets, clean, groups_list, groups_remove, groups_reset, inspect, latency,
+    list_all, list_json, record_dump, record_freeze, repair, stats,
+};
 pub use flux_communication::is_pid_alive;
-use flux_communication::{ShmemKind, array::ArrayHeader, queue::QueueHeader};
+use flux_communication::{
+    ShmemKind,
+    array::ArrayHeader,
+    memfd::{FdMapping, SEGMENT_SOCKET, SegmentClient},
+    queue::QueueHeader,
+};
 use flux_timing::{Duration, Instant};
 pub use inspect::{
-    ConsumerGroupInfo, PidInfo, PoisonInfo, QueueStats, backing_file_size, format_bytes,
-    read_consumer_groups, resolve_backing_path, scan_proc_fds,
+    ConsumerGroupInfo, NumaPlacement, PidInfo, PoisonInfo, QueueStats, backing_file_size,
+    consumer_node, format_bytes, read_consumer_groups, resolve_backing_path, scan_proc_fds,
 };
 use shared_memory::{Shmem, ShmemConf};
 
 /// A shared-memory segment discovered by walking the filesystem.
 #[derive(Debug, Clone)]
 pub struct DiscoveredEntry {
-    /// The kind of segment (Queue, Data, `SeqlockArray`).
+    /// The kind of segment (Queue, Data, `SeqlockArray`, `SeqlockMap`).
     pub kind: ShmemKind,
     /// Application name — the immediate parent of the `shmem/` directory.
     pub app_name: String,
     /// Type name (the flink filename, which is the short type name).
     pub type_name: String,
-    /// Absolute flink path on disk.
+    /// Absolute flink path on disk, `<broker socket>#<segment name>` for
+    /// memfd segments.
     pub flink: String,
     /// Element size in bytes (from the header, or `shmem.len()` for Data).
     pub elem_size: usize,
-    /// Number of slots (mask+1 for queues, bufsize for arrays, 1 for data).
+    /// Number of slots (mask+1 for queues, bufsize for arrays and maps, 1 for
+    /// data).
     pub capacity: usize,
     /// Total writes to the queue (None for non-queue segments or failed reads).
     pub queue_writes: Option<usize>,
@@ -123,8 +134,8 @@ impl CachedHandle {
                 }
                 (h.elsize, h.mask + 1)
             }
-            ShmemKind::SeqlockArray => {
-                let (es, cap) = read_array_meta(&shmem);
+            ShmemKind::SeqlockArray | ShmemKind::SeqlockMap => {
+                let (es, cap) = read_array_meta(shmem.as_ptr(), shmem.len());
                 if es == 0 {
                     return None;
                 }
@@ -162,7 +173,7 @@ impl CachedHandle {
                 let pq = quick_poison_queue(base, shmem_len);
                 (Some(writes), Some(fill), pq)
             }
-            ShmemKind::SeqlockArray => {
+            ShmemKind::SeqlockArray | ShmemKind::SeqlockMap => {
                 let pq = quick_poison_array(base, shmem_len);
                 (None, None, pq)
             }
@@ -238,11 +249,7 @@ impl ShmemCache {
         // Build the set of flink paths currently on disk.
         let mut live_flinks: HashMap<String, (ShmemKind, String)> = HashMap::new();
         for (app_name, shmem_dir) in &self.cached_dirs {
-            for (subdir, kind) in [
-                ("queues", ShmemKind::Queue),
-                ("data", ShmemKind::Data),
-                ("arrays", ShmemKind::SeqlockArray),
-            ] {
+            for (subdir, kind) in SEGMENT_KINDS {
                 let type_dir = shmem_dir.join(subdir);
                 let Ok(flink_iter) = std::fs::read_dir(&type_dir) else {
                     continue;
@@ -300,9 +307,11 @@ impl ShmemCache {
     }
 }
 
-/// Recursively walk `base_dir` looking for `shmem/{queues,data,arrays}/`
-/// directories at any depth.  For each one found, the immediate parent of
-/// the `shmem/` directory is used as the application name.
+/// Recursively walk `base_dir` looking for `shmem/` directories at any depth.
+///
+/// A `shmem/` directory counts if it holds `queues/`, `data/`, `arrays/` or
+/// `maps/`. For each one found, the immediate parent of the `shmem/`
+/// directory is used as the application name.
 ///
 /// Returns a [`DiscoveredEntry`] for every flink whose backing shmem can
 /// still be opened.  Stale flinks are silently skipped.
@@ -351,20 +360,24 @@ fn find_shmem_dirs_inner(base_dir: &Path, dir: &Path, out: &mut Vec<(String, Pat
 }
 
 fn is_shmem_root(dir: &Path) -> bool {
-    dir.join("queues").is_dir() || dir.join("data").is_dir() || dir.join("arrays").is_dir()
+    ["queues", "data", "arrays", "maps"].iter().any(|sub| dir.join(sub).is_dir()) ||
+        dir.join(SEGMENT_SOCKET).exists()
 }
 
+const SEGMENT_KINDS: [(&str, ShmemKind); 4] = [
+    ("queues", ShmemKind::Queue),
+    ("data", ShmemKind::Data),
+    ("arrays", ShmemKind::SeqlockArray),
+    ("maps", ShmemKind::SeqlockMap),
+];
+
 /// Read all flinks under a single `shmem/` directory and append entries.
 ///
 /// Checks the backing `/dev/shm/` file exists before calling
 /// `ShmemConf::open`, avoiding the crate's 5×50 ms retry loop on stale
 /// segments.
 fn collect_entries(app_name: &str, shmem_dir: &Path, entries: &mut Vec<DiscoveredEntry>) {
-    for (subdir, kind) in [
-        ("queues", ShmemKind::Queue),
-        ("data", ShmemKind::Data),
-        ("arrays", ShmemKind::SeqlockArray),
-    ] {
+    for (subdir, kind) in SEGMENT_KINDS {
         let type_dir = shmem_dir.join(subdir);
         let Ok(flink_iter) = std::fs::read_dir(&type_dir) else {
             continue;
@@ -388,39 +401,96 @@ fn collect_entries(app_name: &str, shmem_dir: &Path, entries: &mut Vec<Discovere
             };
 
             let type_name = flink_entry.file_name().to_string_lossy().to_string();
-            let base = shmem.as_ptr();
-            let shmem_len = shmem.len();
-
-            let (elem_size, capacity, queue_writes, queue_fill, poison_quick) = match kind {
-                ShmemKind::Queue => {
-                    let (es, cap, w, f) = read_queue_meta_with_stats(&shmem);
-                    let pq = quick_poison_queue(base, shmem_len);
-                    (es, cap, w, f, pq)
-                }
-                ShmemKind::SeqlockArray => {
-                    let (es, cap) = read_array_meta(&shmem);
-                    let pq = quick_poison_array(base, shmem_len);
-                    (es, cap, None, None, pq)
-                }
-                ShmemKind::Data => (shmem_len, 1, None, None, None),
-                ShmemKind::Unknown => (0, 0, None, None, None),
-            };
-
-            entries.push(DiscoveredEntry {
+            entries.push(read_entry(
                 kind,
-                app_name: app_name.to_owned(),
+                app_name,
                 type_name,
-                flink: flink_str,
-                elem_size,
-                capacity,
-                queue_writes,
-                queue_fill,
-                backing_path: Some(backing_path),
-                backing_size: shmem_len,
-                poison_quick,
-            });
+                flink_str,
+                Some(backing_path),
+                shmem.as_ptr(),
+                shmem.len(),
+            ));
         }
     }
+    collect_memfd_entries(app_name, shmem_dir, entries);
+}
+
+/// Segments hosted by a memfd broker (see [`flux_communication::memfd`])
+/// have no flinks, they are listed and mapped through the broker's socket.
+/// Their `flink` is `<socket>#<segment name>`, and they have no backing
+/// path in `/dev/shm/`.
+fn collect_memfd_entries(app_name: &str, shmem_dir: &Path, entries: &mut Vec<DiscoveredEntry>) {
+    let socket = shmem_dir.join(SEGMENT_SOCKET);
+    if !socket.exists() {
+        return;
+    }
+    let Ok(mut client) = SegmentClient::connect(&socket) else {
+        return;
+    };
+    let Ok(segments) = client.list() else {
+        return;
+    };
+    for (name, _) in segments {
+        let mut parts = name.rsplit('/');
+        let (Some(type_name), Some(subdir)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Some((_, kind)) = SEGMENT_KINDS.iter().find(|(s, _)| *s == subdir) else {
+            continue;
+        };
+        let Ok(mapping) = client.open(&name, 0).and_then(|(fd, _)| FdMapping::new(&fd)) else {
+            continue;
+        };
+        entries.push(read_entry(
+            *kind,
+            app_name,
+            type_name.to_owned(),
+            format!("{}#{name}", socket.display()),
+            None,
+            mapping.as_ptr(),
+            mapping.len(),
+        ));
+    }
+}
+
+/// Builds the entry of a mapped segment from its header.
+fn read_entry(
+    kind: ShmemKind,
+    app_name: &str,
+    type_name: String,
+    flink: String,
+    backing_path: Option<PathBuf>,
+    base: *const u8,
+    shmem_len: usize,
+) -> DiscoveredEntry {
+    let (elem_size, capacity, queue_writes, queue_fill, poison_quick) = match kind {
+        ShmemKind::Queue => {
+            let (es, cap, w, f) = read_queue_meta_with_stats(base, shmem_len);
+            let pq = quick_poison_queue(base, shmem_len);
+            (es, cap, w, f, pq)
+        }
+        ShmemKind::SeqlockArray | ShmemKind::SeqlockMap => {
+            let (es, cap) = read_array_meta(base, shmem_len);
+            let pq = quick_poison_array(base, shmem_len);
+            (es, cap, None, None, pq)
+        }
+        ShmemKind::Data => (shmem_len, 1, None, None, None),
+        ShmemKind::Unknown => (0, 0, None, None, None),
+    };
+
+    DiscoveredEntry {
+        kind,
+        app_name: app_name.to_owned(),
+        type_name,
+        flink,
+        elem_size,
+        capacity,
+        queue_writes,
+        queue_fill,
+        backing_path,
+        backing_size: shmem_len,
+        poison_quick,
+    }
 }
 
 /// Read a flink file's OS id and derive the `/dev/shm/` backing path.
@@ -437,12 +507,15 @@ fn read_and_resolve_backing(flink: &str) -> Option<(String, PathBuf)> {
     Some((os_id, PathBuf::from(raw)))
 }
 
-fn read_queue_meta_with_stats(shmem: &Shmem) -> (usize, usize, Option<usize>, Option<usize>) {
-    if shmem.len() < std::mem::size_of::<QueueHeader>() {
+fn read_queue_meta_with_stats(
+    base: *const u8,
+    shmem_len: usize,
+) -> (usize, usize, Option<usize>, Option<usize>) {
+    if shmem_len < std::mem::size_of::<QueueHeader>() {
         return (0, 0, None, None);
     }
     #[allow(clippy::cast_ptr_alignment)]
-    let header = unsafe { &*(shmem.as_ptr() as *const QueueHeader) };
+    let header = unsafe { &*base.cast::<QueueHeader>() };
     if !header.is_initialized() || header.elsize == 0 {
         return (0, 0, None, None);
     }
@@ -451,12 +524,12 @@ fn read_queue_meta_with_stats(shmem: &Shmem) -> (usize, usize, Option<usize>, Op
     (header.elsize, header.mask + 1, Some(queue_writes), Some(queue_fill))
 }
 
-fn read_array_meta(shmem: &Shmem) -> (usize, usize) {
-    if shmem.len() < std::mem::size_of::<ArrayHeader>() {
+fn read_array_meta(base: *const u8, shmem_len: usize) -> (usize, usize) {
+    if shmem_len < std::mem::size_of::<ArrayHeader>() {
         return (0, 0);
     }
     #[allow(clippy::cast_ptr_alignment)]
-    let header = unsafe { &*(shmem.as_ptr() as *const ArrayHeader) };
+    let header = unsafe { &*base.cast::<ArrayHeader>() };
     if !header.is_initialized() || header.elsize == 0 {
         return (0, 0);
     }
@@ -524,5 +597,9 @@ pub fn flink_reachable(flink: &str) -> bool {
     if flink.is_empty() {
         return false;
     }
-    std::fs::metadata(flink).map(|m| m.len() > 0).unwrap_or(false)
+    if let Ok(m) = std::fs::metadata(flink) {
+        return m.len() > 0;
+    }
+    // memfd segments live as long as the broker serving them.
+    flink.rsplit_once('#').is_some_and(|(socket, _)| Path::new(socket).exists())
 }
@@ -1,9 +1,11 @@
 //! **flux-ctl** — CLI tool for managing and observing flux shared memory.
 //!
 //! Provides a ratatui TUI (`watch` command, default) and CLI commands (`list`,
-//! `inspect`, `clean`, `scan`) for discovering shared memory segments via
-//! filesystem scanning, viewing per-segment stats (queue writes, poison
-//! status), and cleaning up stale segments.
+//! `inspect`, `clean`, `scan`, `record`, `groups`, `repair`, `latency`,
+//! `budgets`) for discovering shared memory segments via filesystem scanning,
+//! viewing per-segment stats (queue writes, poison status), repairing poisoned
+//! queue slots, reading timer latency histograms and budgets, and cleaning up
+//! stale segments.
 //!
 //! # Modules
 //!
@@ -60,6 +60,81 @@ enum Commands {
         #[arg(short, long)]
         verbose: bool,
     },
+    /// Control an app's flight recorder
+    Record {
+        #[command(subcommand)]
+        action: RecordAction,
+    },
+    /// Repair queue slots a crashed producer left mid-write
+    Repair {
+        /// App name filter
+        app: Option<String>,
+        /// Segment name filter
+        segment: Option<String>,
+        /// Repair even while another producer of the queue is alive
+        #[arg(long)]
+        force: bool,
+    },
+    /// List and manage queue consumer groups
+    Groups {
+        #[command(subcommand)]
+        action: GroupsAction,
+    },
+    /// Show the last interval of aggregating timers' latency histograms
+    Latency {
+        /// App name filter
+        app: Option<String>,
+        /// Timer name filter
+        timer: Option<String>,
+    },
+    /// Show the latency budgets of consumers: violations, compliance over
+    /// the last minute and the worst offenders
+    Budgets {
+        /// App name filter
+        app: Option<String>,
+        /// Timer name filter
+        timer: Option<String>,
+    },
+    /// Measure the TSC offset of every socket and publish it to an app's
+    /// processes. Pins a thread onto every CPU this may run on, so run it
+    /// under `taskset` leaving out isolated cores
+    CalibrateSockets { app: String },
+}
+
+#[derive(Subcommand)]
+enum RecordAction {
+    /// Dump the recorded messages to a file under the app's data dir
+    Dump {
+        app: String,
+        /// Recorded in the dump's file name
+        #[arg(long, default_value = "manual")]
+        reason: String,
+    },
+    /// Stop recording, keeping the rings as they are
+    Freeze { app: String },
+    /// Resume recording
+    Unfreeze { app: String },
+}
+
+#[derive(Subcommand)]
+enum GroupsAction {
+    /// List consumer groups with their kind, owner and lease state
+    List {
+        /// App name filter
+        app: Option<String>,
+        /// Segment name filter
+        segment: Option<String>,
+    },
+    /// Move a group's cursor, by default to the producer's write count
+    Reset {
+        app: String,
+        segment: String,
+        label: String,
+        #[arg(long)]
+        cursor: Option<usize>,
+    },
+    /// Remove a group and free its slot
+    Remove { app: String, segment: String, label: String },
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -84,5 +159,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::Watch { app } => tui::run(&base_dir, app.as_deref()),
         Commands::Clean { force, app } => discovery::clean(&base_dir, app.as_deref(), force),
         Commands::Stats { app, verbose } => discovery::stats(&base_dir, app.as_deref(), verbose),
+        Commands::Record { action } => match action {
+            RecordAction::Dump { app, reason } => discovery::record_dump(&base_dir, &app, &reason),
+            RecordAction::Freeze { app } => discovery::record_freeze(&base_dir, &app, true),
+            RecordAction::Unfreeze { app } => discovery::record_freeze(&base_dir, &app, false),
+        },
+        Commands::Repair { app, segment, force } => {
+            discovery::repair(&base_dir, app.as_deref(), segment.as_deref(), force)
+        }
+        Commands::Groups { action } => match action {
+            GroupsAction::List { app, segment } => {
+                discovery::groups_list(&base_dir, app.as_deref(), segment.as_deref())
+            }
+            GroupsAction::Reset { app, segment, label, cursor } => {
+                discovery::groups_reset(&base_dir, &app, &segment, &label, cursor)
+            }
+            GroupsAction::Remove { app, segment, label } => {
+                discovery::groups_remove(&base_dir, &app, &segment, &label)
+            }
+        },
+        Commands::Latency { app, timer } => {
+            discovery::latency(&base_dir, app.as_deref(), timer.as_deref())
+        }
+        Commands::Budgets { app, timer } => {
+            discovery::budgets(&base_dir, app.as_deref(), timer.as_deref())
+        }
+        Commands::CalibrateSockets { app } => discovery::calibrate_sockets(&base_dir, &app),
     }
 }
@@ -1,3 +1,5 @@
+use std::fmt::Write as _;
+
 use flux_communication::ShmemKind;
 use ratatui::{
     prelude::*,
@@ -781,18 +783,24 @@ pub fn render_tiles(area: Rect, buf: &mut Buffer, app: &mut App) {
                         )
                     },
                     |s| {
-                        (
-                            format!(
-                                "  {:<30} util:{:5.1}%  avg:{:<10} min:{:<10} max:{:<10} loops:{}",
-                                truncate_str(name, 30),
-                                s.utilisation * 100.0,
-                                s.busy_avg,
-                                s.busy_min,
-                                s.busy_max,
-                                s.loop_count,
-                            ),
-                            Color::White,
-                        )
+                        let mut text = format!(
+                            "  {:<30} util:{:5.1}%  avg:{:<10} min:{:<10} max:{:<10} loops:{}",
+                            truncate_str(name, 30),
+                            s.utilisation * 100.0,
+                            s.busy_avg,
+                            s.busy_min,
+                            s.busy_max,
+                            s.loop_count,
+                        );
+                        if s.missed_deadlines == 0 {
+                            return (text, Color::White);
+                        }
+                        let _ = write!(
+                            text,
+                            "  missed:{} (max late {})",
+                            s.missed_deadlines, s.lateness_max
+                        );
+                        (text, Color::Yellow)
                     },
                 );
                 let text_style = if is_selected {
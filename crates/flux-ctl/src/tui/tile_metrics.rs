@@ -37,6 +37,8 @@ pub struct TileStats {
     pub busy_min: Duration,
     pub busy_max: Duration,
     pub loop_count: u64,
+    pub missed_deadlines: u64,
+    pub lateness_max: Duration,
 }
 
 /// Internal state for a single tile.
@@ -78,6 +80,8 @@ impl TileData {
         let mut busy_sum = 0u64;
         let mut busy_count = 0u32;
         let mut loop_count = 0u64;
+        let mut missed_deadlines = 0u64;
+        let mut lateness_max = 0u64;
         let mut sample_count = 0usize;
 
         for sample in &self.samples {
@@ -88,6 +92,8 @@ impl TileData {
             total_busy += sample.busy_ticks;
             total_ticks += sample.total_ticks();
             loop_count += sample.loop_count as u64;
+            missed_deadlines += u64::from(sample.missed_deadlines);
+            lateness_max = lateness_max.max(sample.lateness_max);
 
             if sample.busy_count > 0 {
                 busy_min = busy_min.min(sample.busy_min());
@@ -111,6 +117,8 @@ impl TileData {
             busy_min: Duration(if busy_min == u64::MAX { 0 } else { busy_min }),
             busy_max: Duration(busy_max),
             loop_count,
+            missed_deadlines,
+            lateness_max: Duration(lateness_max),
         })
     }
 }
@@ -0,0 +1,29 @@
+use flux_communication::{LatencyBudget, Timer, cleanup_shmem};
+use flux_ctl::discovery::budgets;
+use flux_timing::{Duration, Instant, Nanos};
+use flux_utils::directories::shmem_dir_budgets_with_base;
+use tempfile::tempdir;
+
+#[test]
+fn prints_consumer_budgets() {
+    let tmp = tempdir().unwrap();
+    let base = tmp.path();
+    let file = shmem_dir_budgets_with_base(base, "budget-app").join("router-Order");
+    let budget = LatencyBudget::create_or_open_shared(&file, Nanos::from_micros(5)).unwrap();
+    let mut timer =
+        Timer::new_with_base_dir(base, "budget-app", "router-Order").with_budget(budget);
+
+    let late = Instant(Instant::now().0 - Duration::from(Nanos::from_millis(1)).0);
+    timer.record_latency_until_now(late);
+    timer.record_latency_until_now(Instant::now());
+
+    let snapshot = budget.snapshot();
+    assert_eq!((snapshot.checked, snapshot.violations), (2, 1));
+    assert_eq!(snapshot.compliance(), Some(50.0));
+    assert!(snapshot.worst[0].latency >= Nanos::from_millis(1));
+
+    budgets(base, Some("budget-app"), None).unwrap();
+    budgets(base, Some("budget-app"), Some("nobody")).unwrap();
+
+    cleanup_shmem(base);
+}
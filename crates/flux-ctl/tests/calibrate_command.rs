@@ -0,0 +1,22 @@
+use flux_communication::{SharedTscCalibration, cleanup_shmem};
+use flux_ctl::discovery::calibrate_sockets;
+use flux_timing::MAX_SOCKETS;
+use tempfile::tempdir;
+
+#[test]
+fn publishes_socket_offsets_on_request() {
+    let tmp = tempdir().unwrap();
+    let base = tmp.path();
+    let shared = SharedTscCalibration::open_or_publish_with_base_dir(base, "calib-app").unwrap();
+    let published = shared.read();
+    // Opening a spine never pins threads onto the app's cores.
+    assert_eq!(published.socket_errors, [0; MAX_SOCKETS]);
+
+    calibrate_sockets(base, "calib-app").unwrap();
+    let measured = shared.read();
+    assert_eq!(measured.nanos_per_tick, published.nanos_per_tick);
+    #[cfg(target_os = "linux")]
+    assert_ne!(measured.socket_errors, [0; MAX_SOCKETS]);
+
+    cleanup_shmem(base);
+}
@@ -0,0 +1,43 @@
+use flux_communication::{
+    cleanup_shmem,
+    queue::{Consumer, GroupKind, Queue, QueueType},
+};
+use flux_ctl::discovery::{groups_list, groups_remove, groups_reset};
+use tempfile::tempdir;
+
+#[test]
+fn reset_and_remove_groups_by_label() {
+    let tmp = tempdir().unwrap();
+    let base = tmp.path();
+    let flink = base.join("groups-app").join("shmem").join("queues").join("Orders");
+    let queue = Queue::<u64>::create_or_open_shared(&flink, 64, QueueType::SPMC).unwrap();
+
+    let mut consumer = Consumer::new(queue, "matcher");
+    assert!(!consumer.consume_collaborative(|_| {}));
+    let groups = queue.groups();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].kind, GroupKind::Collaborative);
+    assert_eq!(groups[0].members, 1);
+    let label = groups[0].label.to_owned();
+
+    groups_list(base, Some("groups-app"), None).unwrap();
+
+    groups_reset(base, "groups-app", "Orders", &label, Some(17)).unwrap();
+    assert_eq!(queue.groups()[0].cursor, 17);
+    assert!(groups_reset(base, "groups-app", "Orders", "nobody", None).is_err());
+    assert!(groups_reset(base, "groups-app", "Trades", &label, None).is_err());
+
+    groups_remove(base, "groups-app", "Orders", &label).unwrap();
+    assert!(queue.groups().is_empty());
+    assert!(groups_remove(base, "groups-app", "Orders", &label).is_err());
+
+    // The removed member rejoins on its next empty read.
+    assert!(!consumer.consume_collaborative(|_| {}));
+    assert!(!consumer.consume_collaborative(|_| {}));
+    assert_eq!(queue.groups().len(), 1);
+
+    consumer.leave_group();
+    assert!(queue.groups().is_empty());
+
+    cleanup_shmem(base);
+}
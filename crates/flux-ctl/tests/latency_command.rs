@@ -0,0 +1,32 @@
+use flux_communication::{Timer, cleanup_shmem};
+use flux_ctl::discovery::latency;
+use flux_timing::Nanos;
+use tempfile::tempdir;
+
+#[test]
+fn prints_aggregating_timers() {
+    let tmp = tempdir().unwrap();
+    let base = tmp.path();
+    let mut timer = Timer::new_aggregating_with_base_dir(
+        base,
+        "latency-app",
+        "matcher",
+        Nanos::from_millis(10),
+    );
+    for _ in 0..100 {
+        timer.time(|| std::hint::black_box(0));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(15));
+    timer.time(|| std::hint::black_box(0));
+    // Published once the next interval is over as well.
+    std::thread::sleep(std::time::Duration::from_millis(15));
+
+    let interval = timer.histograms().unwrap().processing.last_interval().unwrap();
+    assert_eq!(interval.count, 100);
+    assert!(timer.histograms().unwrap().latency.last_interval().is_none());
+
+    latency(base, Some("latency-app"), None).unwrap();
+    latency(base, Some("latency-app"), Some("nobody")).unwrap();
+
+    cleanup_shmem(base);
+}
@@ -0,0 +1,40 @@
+use std::os::unix::fs::PermissionsExt;
+
+use flux_communication::{
+    ShmemKind,
+    memfd::{segment_socket_with_base, serve_segments},
+    queue::{Producer, Queue, QueueType},
+};
+use flux_ctl::discovery::scan_base_dir;
+use tempfile::tempdir;
+
+// Segments hosted as memfds have no flinks; discovery finds them through the
+// broker socket.
+#[test]
+fn lists_memfd_segments_through_the_broker() {
+    let tmp = tempdir().unwrap();
+    let base = tmp.path();
+    let socket = segment_socket_with_base(base, "memfd-app");
+    serve_segments(&socket).unwrap();
+    // Only the owner may connect.
+    assert_eq!(std::fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o600);
+
+    let flink = base.join("memfd-app").join("shmem").join("queues").join("Orders");
+    let queue = Queue::<u64>::create_or_open_shared(&flink, 64, QueueType::SPMC).unwrap();
+    assert!(!flink.exists());
+    let mut producer = Producer::from(queue);
+    for i in 0..5 {
+        producer.produce(&i);
+    }
+
+    let entries = scan_base_dir(base);
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!(entry.kind, ShmemKind::Queue);
+    assert_eq!(entry.app_name, "memfd-app");
+    assert_eq!(entry.type_name, "Orders");
+    assert_eq!(entry.capacity, 64);
+    assert_eq!(entry.queue_writes, Some(5));
+    assert!(entry.backing_path.is_none());
+    assert!(entry.is_visible());
+}
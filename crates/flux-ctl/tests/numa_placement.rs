@@ -0,0 +1,52 @@
+use flux::core_affinity::{CoreId, set_for_current};
+use flux_communication::{
+    MappingOptions, cleanup_shmem,
+    queue::{Queue, QueueType},
+};
+use flux_ctl::discovery::{NumaPlacement, consumer_node, resolve_backing_path};
+use flux_utils::numa::node_of_cpu;
+use tempfile::tempdir;
+
+#[test]
+fn placement_read_from_own_numa_maps() {
+    let tmp = tempdir().unwrap();
+    let flink = tmp.path().join("numa-app").join("shmem").join("queues").join("Ticks");
+
+    let node = node_of_cpu(0);
+    let options = MappingOptions { prefault: true, numa_node: node, ..Default::default() };
+    let _queue =
+        Queue::<u64>::create_or_open_shared_with_options(&flink, 1024, QueueType::SPMC, options)
+            .unwrap();
+
+    let backing = resolve_backing_path(flink.to_str().unwrap()).unwrap();
+    let Some(placement) = NumaPlacement::read(&backing, &[std::process::id()]) else {
+        // Kernel built without NUMA support: no numa_maps to read.
+        assert!(!std::path::Path::new("/proc/self/numa_maps").exists());
+        return;
+    };
+    assert!(!placement.policy.is_empty());
+    assert!(placement.pages.values().sum::<u64>() > 0, "prefaulted pages are resident");
+    if let Some(node) = node {
+        assert_eq!(placement.dominant_node(), Some(node));
+        assert_eq!(placement.policy, format!("bind:{node}"));
+    }
+
+    cleanup_shmem(tmp.path());
+}
+
+#[test]
+fn consumer_node_follows_tile_thread() {
+    let label = format!("bin[{}].numa-probe.broadcast", std::process::id());
+    let found = std::thread::Builder::new()
+        .name("numa-probe".to_owned())
+        .spawn(move || {
+            set_for_current(CoreId { id: 0 });
+            consumer_node(&label)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(found, node_of_cpu(0));
+
+    assert_eq!(consumer_node("no-pid-label.broadcast"), None);
+}
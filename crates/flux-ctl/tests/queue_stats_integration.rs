@@ -90,6 +90,30 @@ fn data_segments_have_no_queue_stats() {
     }
 }
 
+#[test]
+fn seqlock_maps_are_discovered() {
+    let tmp = tempdir().unwrap();
+    let base = tmp.path();
+
+    let map =
+        flux_communication::shmem_map_with_base_dir::<_, _, u64, u64>(base, "mapapp", 100).unwrap();
+    map.insert(1, 10).unwrap();
+
+    let entries = scan_base_dir(base);
+    assert_eq!(entries.len(), 1);
+
+    let entry = &entries[0];
+    assert_eq!(entry.kind, flux_communication::ShmemKind::SeqlockMap);
+    assert_eq!(entry.app_name, "mapapp");
+    assert_eq!(entry.type_name, "MapSlot<u64, u64>");
+    assert_eq!(entry.capacity, 128);
+    assert_eq!(entry.poison_quick, Some(false));
+
+    if let Ok(mut shmem) = ShmemConf::new().flink(&entry.flink).open() {
+        shmem.set_owner(true);
+    }
+}
+
 /// Smoke test: create a real shmem queue with consumer groups registered,
 /// then read them back through `read_consumer_groups` — the same path the
 /// TUI detail view uses.
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use flux_communication::{
+    cleanup_shmem,
+    queue::{Consumer, Producer, Queue, QueueHeader, QueueType},
+};
+use flux_ctl::discovery::{PoisonInfo, repair, scan_base_dir};
+use shared_memory::ShmemConf;
+use tempfile::tempdir;
+
+#[test]
+fn repair_tombstones_slot_left_mid_write() {
+    let tmp = tempdir().unwrap();
+    let base = tmp.path();
+    let flink = base.join("repair-app").join("shmem").join("queues").join("Fills");
+    let queue = Queue::<u64>::create_or_open_shared(&flink, 16, QueueType::SPMC).unwrap();
+    let mut producer = Producer::from(queue);
+    let mut consumer = Consumer::new(queue, "booker");
+    let mut seen = Vec::new();
+
+    assert!(!consumer.consume(|m| seen.push(*m)));
+    producer.produce(&1);
+    assert!(consumer.consume(|m| seen.push(*m)));
+
+    // Kill the producer between bumping the count and finishing the write.
+    let shmem = ShmemConf::new().flink(&flink).open().unwrap();
+    #[allow(clippy::cast_ptr_alignment)]
+    let header = unsafe { &*shmem.as_ptr().cast::<QueueHeader>() };
+    let count = header.count.fetch_add(1, Ordering::Relaxed);
+    #[allow(clippy::cast_ptr_alignment)]
+    let slot = unsafe {
+        &*shmem
+            .as_ptr()
+            .add(size_of::<QueueHeader>() + (count & header.mask) * header.elsize)
+            .cast::<AtomicU64>()
+    };
+    slot.fetch_add(1, Ordering::Relaxed);
+
+    let entries = scan_base_dir(base);
+    assert_eq!(PoisonInfo::check(&entries[0]).map(|p| p.n_poisoned), Some(1));
+    assert!(!consumer.consume(|m| seen.push(*m)), "consumer stalls on the poisoned slot");
+
+    repair(base, Some("repair-app"), None, false).unwrap();
+    assert!(PoisonInfo::check(&entries[0]).is_none());
+    assert_eq!(header.tombstone_stats(), (1, 0));
+
+    producer.produce(&3);
+    assert!(consumer.consume(|m| seen.push(*m)));
+    assert_eq!(seen, [1, 3]);
+    assert_eq!(header.tombstone_stats(), (1, 1));
+
+    cleanup_shmem(base);
+}
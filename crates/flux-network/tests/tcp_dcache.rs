@@ -12,11 +12,12 @@ use flux::{
     communication::{ShmemData, cleanup_shmem},
     spine::{DCacheRead, ScopedSpine, SpineAdapter, SpineProducerWithDCache},
     tile::{Tile, TileConfig, TileInfo, attach_tile},
+    type_hash_derive::TypeHash,
 };
 use flux_network::tcp::{PollEvent, SendBehavior, TcpConnector};
 use spine_derive::from_spine;
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, TypeHash)]
 #[repr(C)]
 struct Payload([u8; 8]);
 
@@ -6,11 +6,12 @@ mod counting;
 pub use counting::CountingAllocator;
 #[cfg(feature = "alloc-profile")]
 pub(super) use counting::read;
+use type_hash_derive::TypeHash;
 
 /// The allocation analogue of `PerfSample`: two monotonic per-thread byte
 /// counts.
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, TypeHash)]
 pub struct AllocSample {
     pub allocated: u64,
     pub freed: u64,
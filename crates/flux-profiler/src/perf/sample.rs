@@ -4,6 +4,8 @@
 //!
 //! [`Schema`]: super::Schema
 
+use type_hash_derive::TypeHash;
+
 /// Max counters read per call. Bounds the hot-path array and the streamed
 /// queue element; the PMU's general-purpose counter budget is usually the
 /// tighter limit.
@@ -11,7 +13,7 @@ pub const MAX_EVENTS: usize = 8;
 
 /// One call's raw counter values, positional by [`Schema`](super::Schema) slot.
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, TypeHash)]
 pub struct PerfSample {
     pub vals: [u64; MAX_EVENTS],
 }
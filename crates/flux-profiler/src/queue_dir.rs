@@ -5,6 +5,7 @@ use flux_communication::{
     queue::{Queue, QueueType},
 };
 use flux_utils::directories::{local_share_dir, shmem_dir_queues};
+use type_hash::TypeHash;
 
 use super::{
     allocator::AllocSample,
@@ -12,7 +13,7 @@ use super::{
     perf::{PerfSample, Schema},
 };
 
-pub(super) trait RingEntry: Copy + Default {
+pub(super) trait RingEntry: Copy + Default + TypeHash {
     const PREFIX: &'static str;
 }
 
@@ -97,7 +98,8 @@ impl QueueDir {
         // shared, or two producers would write the same ring and corrupt each
         // other.
         let _ = cleanup_flink(&path);
-        Queue::create_or_open_shared(path, RING_CAPACITY, QueueType::SPMC)
+        Queue::create_or_open_shared(&path, RING_CAPACITY, QueueType::SPMC)
+            .unwrap_or_else(|e| panic!("couldn't create ring {}: {e}", path.display()))
     }
 
     pub(super) fn event_threads(&self) -> Vec<String> {
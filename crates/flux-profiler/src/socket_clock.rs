@@ -1,8 +1,8 @@
-use std::mem;
+use flux_timing::{
+    Duration, MAX_SOCKETS, Nanos, SOCKET_SHIFT, TSC_MASK, TscCalibration, read_tsc_and_node,
+};
 
-use flux_timing::{Duration, Nanos, SOCKET_SHIFT, TSC_MASK, read_tsc_and_node};
-
-pub(crate) const MAX_NODES: usize = 1 << (64 - SOCKET_SHIFT);
+pub(crate) const MAX_NODES: usize = MAX_SOCKETS;
 
 #[derive(Clone, Copy)]
 struct Node {
@@ -15,29 +15,9 @@ pub struct SocketClocks {
 }
 
 impl SocketClocks {
-    fn single_socket() -> Self {
-        let (wall_ns, tsc, _) = sample();
-        Self { nodes: [Node { tsc, wall_ns }; MAX_NODES] }
-    }
-
-    /// Per-CPU calibration; needs Linux thread-affinity to pin onto each core.
-    #[cfg(target_os = "linux")]
-    pub fn calibrate() -> Self {
-        // Do in a different thread to avoid changing the caller's CPU affinity
-        std::thread::Builder::new()
-            .name("socket-clock-calib".to_owned())
-            .spawn(Self::calibrate_per_cpu)
-            .map_or_else(
-                |_| Self::single_socket(),
-                |handle| handle.join().unwrap_or_else(|_| Self::single_socket()),
-            )
-    }
-
-    /// Single-socket machines (e.g. macOS) have no cross-socket skew to
-    /// correct.
-    #[cfg(not(target_os = "linux"))]
+    /// Per-socket calibration, see [`TscCalibration::measure_sockets`].
     pub fn calibrate() -> Self {
-        Self::single_socket()
+        Self::from(&TscCalibration::measure_sockets())
     }
 
     #[cfg(test)]
@@ -45,28 +25,6 @@ impl SocketClocks {
         Self { nodes: [Node { tsc: 0, wall_ns: 0 }; MAX_NODES] }
     }
 
-    #[cfg(target_os = "linux")]
-    fn calibrate_per_cpu() -> Self {
-        let (wall_ns, tsc, _) = sample();
-        let mut nodes = [Node { tsc, wall_ns }; MAX_NODES];
-        let mut max_node = 0;
-        for cpu in allowed_cpus() {
-            pin_to(cpu);
-            let (wall_ns, tsc, node) = sample();
-            max_node = max_node.max(node);
-            nodes[node & (MAX_NODES - 1)] = Node { tsc, wall_ns };
-        }
-        if max_node >= MAX_NODES {
-            tracing::warn!(
-                numa_nodes = max_node + 1,
-                tag_buckets = MAX_NODES,
-                "more NUMA nodes than the profiler's socket tag can distinguish; marks from \
-                 nodes that alias onto a different socket may carry inaccurate timestamps"
-            );
-        }
-        Self { nodes }
-    }
-
     /// ntpd corrects the wall clock but not the TSC, so an anchor drifts. One
     /// sample re-anchors every node: the gaps between sockets' TSCs are fixed
     /// at boot, so the drift is shared.
@@ -91,6 +49,18 @@ impl SocketClocks {
     }
 }
 
+/// A socket's timeline is the anchor's, shifted by its offset: its own TSC
+/// reads `anchor_tsc - offset` at the anchor's wall clock.
+impl From<&TscCalibration> for SocketClocks {
+    fn from(calibration: &TscCalibration) -> Self {
+        let nodes = calibration.socket_offsets.map(|offset| Node {
+            tsc: calibration.anchor_tsc.saturating_add_signed(-offset),
+            wall_ns: calibration.anchor_wall.0,
+        });
+        Self { nodes }
+    }
+}
+
 fn sample() -> (u64, u64, usize) {
     let t0 = Nanos::now().0;
     let (tsc, node) = read_tsc_and_node();
@@ -98,29 +68,9 @@ fn sample() -> (u64, u64, usize) {
     (t0.midpoint(t1), tsc & TSC_MASK, node as usize)
 }
 
-/// CPUs from the current thread's affinity mask. Empty if the query fails.
-#[cfg(target_os = "linux")]
-fn allowed_cpus() -> impl Iterator<Item = usize> {
-    let set = unsafe {
-        let mut set: libc::cpu_set_t = mem::zeroed();
-        libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &raw mut set);
-        set
-    };
-    (0..libc::CPU_SETSIZE as usize).filter(move |&c| unsafe { libc::CPU_ISSET(c, &set) })
-}
-
-#[cfg(target_os = "linux")]
-fn pin_to(cpu: usize) {
-    unsafe {
-        let mut set: libc::cpu_set_t = mem::zeroed();
-        libc::CPU_SET(cpu, &mut set);
-        libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &raw const set);
-    }
-}
-
 #[cfg(test)]
 mod tests {
-    use flux_timing::{Nanos, SOCKET_SHIFT};
+    use flux_timing::{Instant, Nanos, SOCKET_SHIFT, TscCalibration};
 
     use super::{MAX_NODES, Node, SocketClocks};
 
@@ -183,6 +133,20 @@ mod tests {
         assert_eq!(c.nodes[1].wall_ns - c.nodes[0].wall_ns, 500);
     }
 
+    #[test]
+    fn resolves_like_the_calibration() {
+        let mut cal = TscCalibration::measure();
+        cal.socket_offsets[1] = 7_000;
+        let c = SocketClocks::from(&cal);
+        for p in [packed(0, cal.anchor_tsc + 3_000), packed(1, cal.anchor_tsc - 2_000)] {
+            assert!(c.resolve_ns(p).abs_diff(cal.to_wall(Instant(p)).0) <= 1);
+        }
+        assert_eq!(
+            c.resolve_ns(packed(1, cal.anchor_tsc - 2_000)),
+            c.resolve_ns(packed(0, cal.anchor_tsc + 5_000))
+        );
+    }
+
     #[test]
     fn calibrate_wall_clock() {
         let before = Nanos::now().0;
@@ -112,6 +112,13 @@ impl<T: Statisticable> Statistics<T> {
         self.tot
     }
 
+    /// Adds a datapoint that was aggregated elsewhere, e.g. one interval of a
+    /// timer's histogram.
+    pub fn push_datapoint(&mut self, datapoint: DataPoint<T>) {
+        self.datapoints.push(datapoint);
+        self.got_one = true;
+    }
+
     pub fn reset(&mut self) {
         self.measurements.clear();
         self.min = u64::MAX;
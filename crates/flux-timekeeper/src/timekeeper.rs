@@ -4,11 +4,14 @@ use crossterm::event::KeyCode;
 use flux::{
     TimingMessage,
     communication::{
+        LatencyBudget, attach_tsc_calibration,
+        histogram::{HistogramSnapshot, IntervalCursor, TimerHistograms},
         queue::{Consumer, Queue},
         shmem_dir_queues_string,
     },
     persistence::Persistable,
     timing::{Duration, Instant, Nanos, Repeater},
+    utils::directories::{local_share_dir, shmem_dir_budgets, shmem_dir_histograms},
 };
 use ratatui::{
     prelude::*,
@@ -27,11 +30,22 @@ use crate::{
 const NUM_DATAPOINTS: usize = 750;
 const SAMPLES_PER_PERCENTILE: usize = 128;
 
+/// Where the intervals of a timer come from.
+#[derive(Clone, Debug)]
+pub enum TimerSource {
+    /// Every interval, as the timer emitted it.
+    Queues { latency: Consumer<TimingMessage>, processing: Consumer<TimingMessage> },
+    /// Histograms of an aggregating timer, with where reading each of them
+    /// got to.
+    Histograms { histograms: TimerHistograms, latency: IntervalCursor, processing: IntervalCursor },
+}
+
 #[derive(Clone, Debug)]
 pub struct TimerDataState {
-    pub latency_consumer: Consumer<TimingMessage>,
-    pub processing_consumer: Consumer<TimingMessage>,
+    pub source: TimerSource,
     pub direction: Direction,
+    /// Budget the timer checks its latencies against, if it has one.
+    pub budget: Option<LatencyBudget>,
 
     reuse_buf: Vec<f64>,
 }
@@ -41,14 +55,62 @@ impl TimerDataState {
         latency_consumer: Consumer<TimingMessage>,
         processing_consumer: Consumer<TimingMessage>,
     ) -> Self {
+        Self::with_source(TimerSource::Queues {
+            latency: latency_consumer.without_log(),
+            processing: processing_consumer.without_log(),
+        })
+    }
+
+    pub fn from_histograms(histograms: TimerHistograms) -> Self {
+        Self::with_source(TimerSource::Histograms {
+            histograms,
+            latency: IntervalCursor::default(),
+            processing: IntervalCursor::default(),
+        })
+    }
+
+    fn with_source(source: TimerSource) -> Self {
         Self {
-            latency_consumer: latency_consumer.without_log(),
-            processing_consumer: processing_consumer.without_log(),
+            source,
             direction: Direction::Vertical,
+            budget: None,
             reuse_buf: Vec::with_capacity(1024),
         }
     }
 
+    /// Title of a histogram timer's plot, with the exact percentiles of its
+    /// last interval.
+    fn interval_title(name: &str, interval: &HistogramSnapshot) -> Option<String> {
+        (!interval.is_empty()).then(|| {
+            format!(
+                "  {name}  |  last interval p50={}  p99={}  p99.9={}  max={}  ",
+                interval.value_at_quantile(0.5),
+                interval.value_at_quantile(0.99),
+                interval.value_at_quantile(0.999),
+                Nanos(interval.max),
+            )
+        })
+    }
+
+    /// Budget, compliance over the last minute and worst offender, appended
+    /// to the latency title.
+    fn budget_title(&self) -> Option<String> {
+        let snapshot = self.budget?.snapshot();
+        let compliance =
+            snapshot.compliance().map_or_else(|| "-".to_owned(), |c| format!("{c:.3}%"));
+        let worst =
+            snapshot.worst.first().map_or_else(|| "-".to_owned(), |o| o.latency.to_string());
+        Some(format!(
+            "|  budget={}  compliance={compliance}  over={}  worst={worst}  ",
+            snapshot.budget, snapshot.violations
+        ))
+    }
+
+    /// Whether the timer went over its budget within the last minute.
+    pub fn is_over_budget(&self) -> bool {
+        self.budget.is_some_and(|b| b.snapshot().recent_violations != 0)
+    }
+
     #[allow(clippy::too_many_lines)]
     pub fn report(
         &mut self,
@@ -199,29 +261,40 @@ impl TimerDataState {
             "  Msg/s  ".to_string()
         };
 
+        let (latency_interval, processing_interval) = match &self.source {
+            TimerSource::Histograms { latency, processing, .. } => (
+                Self::interval_title("Latency", latency.last()),
+                Self::interval_title("Processing", processing.last()),
+            ),
+            TimerSource::Queues { .. } => (None, None),
+        };
+
         let percentiles =
             self.window_percentiles(&data.data_processing, plot_settings, |d| d.avg as f64);
-        let processing_title = if let Some((p90, p99)) = percentiles {
-            format!(
+        let processing_title = match (processing_interval, percentiles) {
+            (Some(title), _) => title,
+            (None, Some((p90, p99))) => format!(
                 "  Processing (mean plotted)  |  sample p90={}  sample p99={}  ",
                 Nanos::from(p90 as u128),
                 Nanos::from(p99 as u128)
-            )
-        } else {
-            "  Processing  ".to_string()
+            ),
+            (None, None) => "  Processing  ".to_string(),
         };
 
         let percentiles =
             self.window_percentiles(&data.data_latency, plot_settings, |d| d.avg as f64);
-        let latency_title = if let Some((p90, p99)) = percentiles {
-            format!(
+        let mut latency_title = match (latency_interval, percentiles) {
+            (Some(title), _) => title,
+            (None, Some((p90, p99))) => format!(
                 "  Latency (mean plotted)  |  sample p90={}  sample p99={}  ",
                 Nanos::from(p90 as u128),
                 Nanos::from(p99 as u128)
-            )
-        } else {
-            "  Latency  ".to_string()
+            ),
+            (None, None) => "  Latency  ".to_string(),
         };
+        if let Some(budget) = self.budget_title() {
+            latency_title.push_str(&budget);
+        }
 
         match (latency_plot, processing_plot) {
             (None, None) => unreachable!(),
@@ -330,11 +403,25 @@ impl TimerData {
     }
 
     pub fn handle_messages(&mut self, state: &mut TimerDataState) {
-        self.stats_latency.handle_messages(&mut state.latency_consumer);
-        self.stats_processing.handle_messages(&mut state.processing_consumer);
-        self.tot_processing += Duration(self.stats_processing.tot());
-        self.stats_latency.register_datapoint(state.latency_consumer.queue_message_count());
-        self.stats_processing.register_datapoint(state.processing_consumer.queue_message_count());
+        match &mut state.source {
+            TimerSource::Queues { latency, processing } => {
+                self.stats_latency.handle_messages(latency);
+                self.stats_processing.handle_messages(processing);
+                self.tot_processing += Duration(self.stats_processing.tot());
+                self.stats_latency.register_datapoint(latency.queue_message_count());
+                self.stats_processing.register_datapoint(processing.queue_message_count());
+            }
+            TimerSource::Histograms { histograms, latency, processing } => {
+                while let Some(interval) = latency.next(&histograms.latency) {
+                    self.stats_latency.push_datapoint(interval_datapoint(interval));
+                }
+                while let Some(interval) = processing.next(&histograms.processing) {
+                    let datapoint = interval_datapoint(interval);
+                    self.tot_processing += Duration(datapoint.tot);
+                    self.stats_processing.push_datapoint(datapoint);
+                }
+            }
+        }
         if let Some(datapoint) = self
             .stats_latency
             .datapoints
@@ -378,6 +465,23 @@ impl TimerData {
     }
 }
 
+/// One finished interval of a histogram as a datapoint.
+fn interval_datapoint(snapshot: &HistogramSnapshot) -> DataPoint<Duration> {
+    let ticks = |nanos: u64| Duration::from(Nanos(nanos)).0;
+    DataPoint {
+        avg: ticks(snapshot.mean().0),
+        min: if snapshot.is_empty() { 0 } else { ticks(snapshot.min) },
+        max: ticks(snapshot.max),
+        median: ticks(snapshot.value_at_quantile(0.5).0),
+        tot: ticks(snapshot.sum),
+        n_samples: snapshot.count as usize,
+        vline: false,
+        rate: MsgPer10Sec((snapshot.rate() * 10.0) as u64),
+        time: snapshot.end.0,
+        _p: std::marker::PhantomData,
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct TimerDatas {
     pub data: Vec<TimerData>,
@@ -485,7 +589,11 @@ impl TimeKeeper {
             .map(|data| {
                 let name = data.name.clone();
                 max = max.max(name.len());
-                ListItem::from(name)
+                if self.timers.get(&data.name).is_some_and(TimerDataState::is_over_budget) {
+                    ListItem::from(name).red()
+                } else {
+                    ListItem::from(name)
+                }
             })
             .collect();
         let mut slot_time_data = self.timer_data.data.iter_mut().filter(|d| !d.is_empty());
@@ -639,9 +747,46 @@ impl TimeKeeper {
         }
     }
 
+    /// Picks up the histograms of aggregating timers.
+    pub fn check_new_histograms(&mut self, app_name: &str) {
+        let Ok(entries) = std::fs::read_dir(shmem_dir_histograms(app_name)) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if self.timer_data.contains(&name) {
+                continue;
+            }
+            let Ok(histograms) = TimerHistograms::open_shared(entry.path()) else {
+                continue;
+            };
+            let data =
+                TimerData::new(name, SAMPLES_PER_PERCENTILE, NUM_DATAPOINTS, self.clock_overhead);
+            self.timers.insert(data.name.clone(), TimerDataState::from_histograms(histograms));
+            self.timer_data.push(data);
+        }
+    }
+
+    /// Picks up the latency budgets of timers that have one.
+    pub fn check_new_budgets(&mut self, app_name: &str) {
+        let dir = shmem_dir_budgets(app_name);
+        for (name, state) in &mut self.timers {
+            if state.budget.is_none() {
+                state.budget = LatencyBudget::open_shared(dir.join(name)).ok();
+            }
+        }
+    }
+
     pub fn update(&mut self, app_name: &str) {
         if self.update_queue_checker.fired() {
+            // Intervals are converted here, so with the app's rate and socket
+            // offsets.
+            if let Err(e) = attach_tsc_calibration(local_share_dir(), app_name) {
+                flux::tracing::warn!("couldn't attach TSC calibration of {app_name}: {e}");
+            }
             self.check_new_queues(app_name);
+            self.check_new_histograms(app_name);
+            self.check_new_budgets(app_name);
         }
 
         for data in &mut self.timer_data.data {
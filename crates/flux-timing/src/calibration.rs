@@ -0,0 +1,358 @@
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use type_hash_derive::TypeHash;
+
+use crate::{
+    Instant, Nanos, OurClockForNanos,
+    global_clock::{nanos_per_tick, nanos_to_ticks, scale, set_nanos_per_tick},
+    instant::{MAX_SOCKETS, SOCKET_SHIFT, TSC_MASK, read_tsc_and_node},
+};
+
+/// Reads per CPU when measuring sockets; the one with the narrowest wall
+/// clock window is kept.
+const SAMPLES_PER_CPU: usize = 8;
+
+// The socket offsets and errors of the adopted calibration, for `Instant`.
+static SOCKET_OFFSETS: [AtomicI64; MAX_SOCKETS] = [const { AtomicI64::new(0) }; MAX_SOCKETS];
+static SOCKET_ERRORS: [AtomicU64; MAX_SOCKETS] = [const { AtomicU64::new(0) }; MAX_SOCKETS];
+// The adopted anchor as the wall clock at a corrected TSC of 0, so that one
+// store moves it. `UNANCHORED` until adopted or measured on first use.
+static WALL_AT_ZERO: AtomicI64 = AtomicI64::new(UNANCHORED);
+const UNANCHORED: i64 = i64::MIN;
+
+#[inline]
+pub(crate) fn socket_offset(socket: usize) -> i64 {
+    SOCKET_OFFSETS[socket].load(Ordering::Relaxed)
+}
+
+#[inline]
+pub(crate) fn socket_error(socket: usize) -> u64 {
+    SOCKET_ERRORS[socket].load(Ordering::Relaxed)
+}
+
+/// The wall clock at corrected TSC `tsc`, by the adopted anchor.
+#[inline]
+pub(crate) fn wall_at(tsc: u64) -> Nanos {
+    let wall_at_zero = match WALL_AT_ZERO.load(Ordering::Relaxed) {
+        UNANCHORED => measure_wall_at_zero(),
+        wall_at_zero => wall_at_zero,
+    };
+    Nanos(scale(tsc, nanos_per_tick()).saturating_add_signed(wall_at_zero))
+}
+
+/// An anchor adopted in the meantime wins over our own measurement.
+#[cold]
+fn measure_wall_at_zero() -> i64 {
+    let measured = TscCalibration::measure().wall_at_zero();
+    match WALL_AT_ZERO.compare_exchange(UNANCHORED, measured, Ordering::Relaxed, Ordering::Relaxed)
+    {
+        Ok(_) => measured,
+        Err(adopted) => adopted,
+    }
+}
+
+/// How TSC ticks map to nanoseconds and to the wall clock.
+///
+/// Each process measures its own rate on first use, and two measurements of
+/// the same TSC disagree by a few ppm. Processes that [`adopt`](Self::adopt)
+/// one calibration convert the same `Instant` to the same nanos, and by
+/// [`Instant::to_wall`] to the same wall clock time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, TypeHash)]
+#[repr(C)]
+pub struct TscCalibration {
+    /// Nanoseconds per tick times `2^32`.
+    pub nanos_per_tick: u64,
+    /// A TSC read and the wall clock at the same moment.
+    pub anchor_tsc: u64,
+    pub anchor_wall: Nanos,
+    /// How far the wall clock had moved from where the previous anchor put
+    /// it, at the last [`reanchor`](Self::reanchor). ntpd slews the wall
+    /// clock but not the TSC.
+    pub drift_ns: i64,
+    /// Ticks to add to a TSC read on socket `i` to bring it onto the
+    /// timeline of the anchor. All zero unless measured.
+    pub socket_offsets: [i64; MAX_SOCKETS],
+    /// Ticks the sample behind `socket_offsets[i]` may be off by, 0 for a
+    /// socket that was not measured.
+    pub socket_errors: [u64; MAX_SOCKETS],
+}
+
+impl TscCalibration {
+    /// The rate in use by this process, anchored now. Every socket is taken
+    /// to share the anchor's timeline.
+    pub fn measure() -> Self {
+        let s = sample();
+        Self {
+            nanos_per_tick: nanos_per_tick(),
+            anchor_tsc: s.tsc,
+            anchor_wall: s.wall,
+            drift_ns: 0,
+            socket_offsets: [0; MAX_SOCKETS],
+            socket_errors: [0; MAX_SOCKETS],
+        }
+    }
+
+    /// Like [`measure`](Self::measure), and measures how far the TSC of each
+    /// socket is from the anchor's by reading it, and the wall clock, on
+    /// every CPU we may run on.
+    ///
+    /// Needs Linux thread-affinity to pin onto each core, elsewhere this is
+    /// `measure`. The pinned thread competes with whatever runs on those
+    /// cores, isolated or spinning ones included, so keep this off the
+    /// startup of latency-critical processes.
+    pub fn measure_sockets() -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            // Do in a different thread to avoid changing the caller's CPU affinity
+            std::thread::Builder::new()
+                .name("tsc-socket-calib".to_owned())
+                .spawn(measure_per_cpu)
+                .ok()
+                .and_then(|handle| handle.join().ok())
+                .unwrap_or_else(Self::measure)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Self::measure()
+    }
+
+    /// Makes this the rate of every tick to nanos conversion in the process,
+    /// its socket offsets the ones cross-socket `Instant` deltas are
+    /// corrected by, and its anchor the one of [`Instant::to_wall`].
+    pub fn adopt(&self) {
+        set_nanos_per_tick(self.nanos_per_tick);
+        for socket in 0..MAX_SOCKETS {
+            SOCKET_OFFSETS[socket].store(self.socket_offsets[socket], Ordering::Relaxed);
+            SOCKET_ERRORS[socket].store(self.socket_errors[socket], Ordering::Relaxed);
+        }
+        WALL_AT_ZERO.store(self.wall_at_zero(), Ordering::Relaxed);
+    }
+
+    fn wall_at_zero(&self) -> i64 {
+        self.anchor_wall.0 as i64 - scale(self.anchor_tsc, self.nanos_per_tick) as i64
+    }
+
+    /// Parts per million the rate of `other` is off from ours.
+    pub fn rate_error_ppm(&self, other: &Self) -> f64 {
+        (other.nanos_per_tick as f64 - self.nanos_per_tick as f64) / self.nanos_per_tick as f64 *
+            1e6
+    }
+
+    /// Whether `tsc`, read now, comes from before the anchor, i.e. the
+    /// machine rebooted since.
+    #[inline]
+    pub const fn is_from_before(&self, tsc: u64) -> bool {
+        tsc < self.anchor_tsc
+    }
+
+    /// The wall clock at `instant`.
+    pub fn to_wall(&self, instant: Instant) -> Nanos {
+        let socket = (instant.0 >> SOCKET_SHIFT) as usize;
+        let tsc = (instant.0 & TSC_MASK).saturating_add_signed(self.socket_offsets[socket]);
+        if tsc >= self.anchor_tsc {
+            Nanos(self.anchor_wall.0 + scale(tsc - self.anchor_tsc, self.nanos_per_tick))
+        } else {
+            Nanos(
+                self.anchor_wall
+                    .0
+                    .saturating_sub(scale(self.anchor_tsc - tsc, self.nanos_per_tick)),
+            )
+        }
+    }
+
+    /// The wall clock now, minus where the anchor puts it.
+    pub fn drift(&self) -> i64 {
+        let s = sample();
+        s.wall.0 as i64 - self.to_wall(s.instant()).0 as i64
+    }
+
+    /// Moves the anchor to now and records the drift since the last one.
+    pub fn reanchor(&mut self) -> i64 {
+        let s = sample();
+        self.drift_ns = s.wall.0 as i64 - self.to_wall(s.instant()).0 as i64;
+        self.anchor_tsc = s.tsc.saturating_add_signed(self.socket_offsets[s.socket]);
+        self.anchor_wall = s.wall;
+        self.drift_ns
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    tsc: u64,
+    socket: usize,
+    /// Halfway between the wall clock reads around `tsc`.
+    wall: Nanos,
+    /// Nanos between those reads.
+    window: u64,
+}
+
+impl Sample {
+    #[inline]
+    const fn instant(&self) -> Instant {
+        Instant(((self.socket as u64) << SOCKET_SHIFT) | self.tsc)
+    }
+}
+
+/// A TSC read between two reads of the wall clock. The wall clock is never
+/// the mock.
+fn sample() -> Sample {
+    let t0 = OurClockForNanos::System.raw();
+    let (tsc, node) = read_tsc_and_node();
+    let t1 = OurClockForNanos::System.raw();
+    Sample {
+        tsc: tsc & TSC_MASK,
+        socket: node as usize & (MAX_SOCKETS - 1),
+        wall: Nanos(t0.midpoint(t1)),
+        window: t1.saturating_sub(t0),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn measure_per_cpu() -> TscCalibration {
+    let mut best: [Option<Sample>; MAX_SOCKETS] = [None; MAX_SOCKETS];
+    let mut max_node = 0;
+    for cpu in allowed_cpus() {
+        pin_to(cpu);
+        for _ in 0..SAMPLES_PER_CPU {
+            let (_, node) = read_tsc_and_node();
+            max_node = max_node.max(node as usize);
+            let s = sample();
+            let kept = &mut best[s.socket];
+            if kept.is_none_or(|k| s.window < k.window) {
+                *kept = Some(s);
+            }
+        }
+    }
+    if max_node >= MAX_SOCKETS {
+        tracing::warn!(
+            numa_nodes = max_node + 1,
+            tag_buckets = MAX_SOCKETS,
+            "more NUMA nodes than an Instant's socket tag can distinguish; deltas between \
+             nodes that alias onto the same socket are not corrected"
+        );
+    }
+    let Some(anchor) = best.iter().flatten().next().copied() else {
+        return TscCalibration::measure();
+    };
+    let mut calibration = TscCalibration {
+        anchor_tsc: anchor.tsc,
+        anchor_wall: anchor.wall,
+        ..TscCalibration::measure()
+    };
+    for (socket, s) in best.iter().enumerate() {
+        let Some(s) = s else { continue };
+        // Where the anchor's TSC stood when this socket read its own.
+        let since_anchor = s.wall.0 as i64 - anchor.wall.0 as i64;
+        let ticks = nanos_to_ticks(since_anchor.unsigned_abs()) as i64;
+        let expected = anchor.tsc as i64 + if since_anchor < 0 { -ticks } else { ticks };
+        calibration.socket_offsets[socket] = expected - s.tsc as i64;
+        calibration.socket_errors[socket] = nanos_to_ticks(s.window.div_ceil(2)).max(1);
+    }
+    calibration
+}
+
+/// CPUs from the current thread's affinity mask. Empty if the query fails.
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> impl Iterator<Item = usize> {
+    let set = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &raw mut set);
+        set
+    };
+    (0..libc::CPU_SETSIZE as usize).filter(move |&c| unsafe { libc::CPU_ISSET(c, &set) })
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to(cpu: usize) {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &raw const set);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wall_clock_from_anchor() {
+        let cal = TscCalibration::measure();
+        let one_ms = crate::Duration::from_millis(1);
+        let later = cal.to_wall(Instant(cal.anchor_tsc) + one_ms);
+        assert!((later.0 - cal.anchor_wall.0).abs_diff(1_000_000) < 10, "{later:?}");
+        let earlier = cal.to_wall(Instant(cal.anchor_tsc) - one_ms);
+        assert!((cal.anchor_wall.0 - earlier.0).abs_diff(1_000_000) < 10, "{earlier:?}");
+        assert!(cal.drift().abs() < 1_000_000, "{}", cal.drift());
+    }
+
+    #[test]
+    fn reanchor_records_drift() {
+        let mut cal = TscCalibration::measure();
+        // An anchor that put the wall clock a second ahead.
+        cal.anchor_wall.0 += 1_000_000_000;
+        let drift = cal.reanchor();
+        assert!(drift.abs_diff(-1_000_000_000) < 1_000_000, "{drift}");
+        assert_eq!(cal.drift_ns, drift);
+        assert!(cal.drift().abs() < 1_000_000, "{}", cal.drift());
+    }
+
+    #[test]
+    fn socket_offsets_shift_onto_socket_zero() {
+        let mut cal = TscCalibration::measure();
+        cal.socket_offsets[1] = -1_000;
+        let tsc = cal.anchor_tsc + 5_000;
+        assert_eq!(
+            cal.to_wall(Instant((1 << SOCKET_SHIFT) | tsc)),
+            cal.to_wall(Instant(tsc - 1_000))
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn measure_sockets_covers_our_socket() {
+        let before = OurClockForNanos::System.now();
+        let cal = TscCalibration::measure_sockets();
+        let after = OurClockForNanos::System.now();
+        assert!(before.0 <= cal.anchor_wall.0 && cal.anchor_wall.0 <= after.0);
+        assert_ne!(cal.socket_errors[Instant::now_with_socket().socket()], 0);
+        assert!(cal.drift().abs() < 1_000_000, "{}", cal.drift());
+    }
+
+    #[test]
+    fn now_is_tagged_with_the_cached_socket() {
+        std::thread::spawn(|| {
+            assert_eq!(Instant::now().socket(), 0);
+            let (_, node) = read_tsc_and_node();
+            assert_eq!(Instant::now_with_socket().socket(), node as usize % MAX_SOCKETS);
+            let socket = Instant::cache_thread_socket();
+            assert_eq!(socket, node as usize % MAX_SOCKETS);
+            let now = Instant::now();
+            assert_eq!(now.socket(), socket);
+            assert!(Instant::now() >= now);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn cross_socket_deltas_are_corrected() {
+        let mut cal = TscCalibration::measure();
+        cal.socket_offsets[2] = 5_000;
+        cal.socket_errors[0] = 10;
+        cal.socket_errors[2] = 20;
+        cal.adopt();
+
+        let on_0 = Instant(1_000_000);
+        // 1_001_000 on socket 0's timeline.
+        let on_2 = Instant((2 << SOCKET_SHIFT) | 996_000);
+        assert_eq!(on_2 - on_0, crate::Duration(1_000));
+        assert_eq!(on_2.elapsed_since(on_0), crate::Duration(1_000));
+        assert_eq!(on_0.saturating_sub(on_2), crate::Duration(0));
+        assert_eq!(on_2.error_bound(&on_0), Some(crate::Duration(30)));
+        assert_eq!(on_0.error_bound(&Instant(5)), Some(crate::Duration(0)));
+        assert_eq!(on_0.error_bound(&Instant(3 << SOCKET_SHIFT)), None);
+        assert!(on_2 > on_0);
+        assert!(Instant((2 << SOCKET_SHIFT) | 994_000) < on_0);
+        assert!(Instant((3 << SOCKET_SHIFT) | 10) < on_0);
+    }
+}
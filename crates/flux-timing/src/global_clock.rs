@@ -1,5 +1,8 @@
 use std::{
-    sync::{Arc, OnceLock},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -45,6 +48,13 @@ static GLOBAL_CLOCK: OnceLock<OurClockForNanos> = OnceLock::new();
 // never mocked
 static GLOBAL_CLOCK_NON_MOCKED: OnceLock<Clock> = OnceLock::new();
 
+// The `Instant` side of the mock, with the `mock` feature.
+#[cfg(feature = "mock")]
+static TSC_MOCK: OnceLock<Arc<Mock>> = OnceLock::new();
+
+/// Mocks the `Nanos` clock and, with the `mock` feature, `Instant` too: both
+/// then read the returned handle, at one tick per nanosecond, so advancing
+/// it moves them in step and tick conversions are exact.
 #[inline]
 pub fn init_global_with_mock() -> Arc<Mock> {
     let (mock, controller) = Clock::mock();
@@ -52,22 +62,70 @@ pub fn init_global_with_mock() -> Arc<Mock> {
     // this is in some effort to never not have 2 threads racing to initialize
     // different mocks and/or global clock before mock
     assert_eq!(mock.raw(), 0, "Do not initialize the global mock clock from 2 different threads");
+    #[cfg(feature = "mock")]
+    {
+        set_nanos_per_tick(ONE as u64);
+        let _ = TSC_MOCK.set(controller.clone());
+    }
     controller.increment(1);
     controller
 }
 
+/// The mocked TSC, if [`init_global_with_mock`] ran.
+#[cfg(feature = "mock")]
+#[inline]
+pub(crate) fn mocked_tsc() -> Option<u64> {
+    TSC_MOCK.get().map(|mock| mock.value())
+}
+
+#[cfg(not(feature = "mock"))]
+#[inline(always)]
+pub(crate) const fn mocked_tsc() -> Option<u64> {
+    None
+}
+
+/// Waiting on a mocked clock would never end, so under the mock this moves
+/// it on to `deadline` and returns true. Without the `mock` feature it is
+/// always false.
+#[cfg(feature = "mock")]
+#[inline]
+pub fn advance_mock_to(deadline: crate::Instant) -> bool {
+    let Some(mock) = TSC_MOCK.get() else {
+        return false;
+    };
+    let target = deadline.0 & crate::TSC_MASK;
+    let now = mock.value();
+    if target > now {
+        mock.increment(target - now);
+    }
+    true
+}
+
+#[cfg(not(feature = "mock"))]
+#[inline(always)]
+pub const fn advance_mock_to(_deadline: crate::Instant) -> bool {
+    false
+}
+
 #[inline]
 pub fn global_clock() -> &'static OurClockForNanos {
     GLOBAL_CLOCK.get_or_init(|| OurClockForNanos::System)
 }
 
+/// Whether `Nanos` reads a mock, with or without the `mock` feature.
+#[inline]
+pub(crate) fn nanos_mocked() -> bool {
+    matches!(global_clock(), OurClockForNanos::Clock(_))
+}
+
 #[inline]
 pub fn global_clock_not_mocked() -> &'static Clock {
     GLOBAL_CLOCK_NON_MOCKED.get_or_init(Clock::new)
 }
 
-static NANOS_PER_TICK: OnceLock<u64> = OnceLock::new();
-static TICKS_PER_NANO: OnceLock<u64> = OnceLock::new();
+// 0 until measured on first use, or adopted from a shared `TscCalibration`.
+static NANOS_PER_TICK: AtomicU64 = AtomicU64::new(0);
+static TICKS_PER_NANO: AtomicU64 = AtomicU64::new(0);
 /// A tick is a fraction of a nanosecond - 0.2330078 of one at 4.3GHz - and an
 /// integer cannot hold that, so the rate is kept multiplied by
 /// `2^FRACTION_BITS` and shifted back down after each conversion. Whatever is
@@ -78,12 +136,39 @@ static TICKS_PER_NANO: OnceLock<u64> = OnceLock::new();
 ///   16 bits                                                       5.7 s/day
 ///   24 bits                                                       22 ms/day
 ///   32 bits                                                       86 us/day
-const FRACTION_BITS: u32 = 32;
+pub(crate) const FRACTION_BITS: u32 = 32;
 const ONE: u128 = 1 << FRACTION_BITS;
 
 #[inline]
-fn nanos_per_tick() -> u64 {
-    *NANOS_PER_TICK.get_or_init(|| global_clock_not_mocked().delta_as_nanos(0, ONE as u64))
+pub(crate) fn nanos_per_tick() -> u64 {
+    match NANOS_PER_TICK.load(Ordering::Relaxed) {
+        0 => measure_nanos_per_tick(),
+        rate => rate,
+    }
+}
+
+/// A rate adopted in the meantime wins over our own measurement.
+#[cold]
+fn measure_nanos_per_tick() -> u64 {
+    let measured = global_clock_not_mocked().delta_as_nanos(0, ONE as u64);
+    match NANOS_PER_TICK.compare_exchange(0, measured, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => measured,
+        Err(adopted) => adopted,
+    }
+}
+
+/// Replaces the process' rate. `Duration`s taken before keep their ticks, so
+/// they are off by the difference in rates, a few ppm between two
+/// measurements of the same TSC.
+pub(crate) fn set_nanos_per_tick(rate: u64) {
+    assert!(rate != 0, "a TSC rate of 0 nanos per tick");
+    // A mocked TSC ticks in nanos, whatever calibration is adopted.
+    #[cfg(feature = "mock")]
+    if TSC_MOCK.get().is_some() {
+        return;
+    }
+    NANOS_PER_TICK.store(rate, Ordering::Relaxed);
+    TICKS_PER_NANO.store((ONE * ONE / u128::from(rate)) as u64, Ordering::Relaxed);
 }
 
 /// Rates are stored times `ONE`, so `nanos_per_tick()` holds `0.233 * ONE`.
@@ -94,11 +179,18 @@ fn nanos_per_tick() -> u64 {
 /// ```
 #[inline]
 fn ticks_per_nano() -> u64 {
-    *TICKS_PER_NANO.get_or_init(|| (ONE * ONE / u128::from(nanos_per_tick())) as u64)
+    match TICKS_PER_NANO.load(Ordering::Relaxed) {
+        0 => {
+            let rate = (ONE * ONE / u128::from(nanos_per_tick())) as u64;
+            let _ = TICKS_PER_NANO.compare_exchange(0, rate, Ordering::Relaxed, Ordering::Relaxed);
+            rate
+        }
+        rate => rate,
+    }
 }
 
 #[inline]
-fn scale(value: u64, rate: u64) -> u64 {
+pub(crate) fn scale(value: u64, rate: u64) -> u64 {
     ((u128::from(value) * u128::from(rate)) >> FRACTION_BITS) as u64
 }
 
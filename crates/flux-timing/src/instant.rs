@@ -1,22 +1,31 @@
-use std::ops::{Add, AddAssign, Sub};
+use std::{
+    cell::Cell,
+    ops::{Add, AddAssign, Sub},
+};
 
 use serde::{Deserialize, Serialize};
 use type_hash_derive::TypeHash;
 
 use crate::{
     Duration, Nanos,
-    global_clock::{global_clock_not_mocked, nanos_to_ticks, ticks_to_nanos},
+    calibration::{socket_error, socket_offset, wall_at},
+    global_clock::{global_clock_not_mocked, mocked_tsc, nanos_to_ticks, ticks_to_nanos},
 };
 
 pub const SOCKET_SHIFT: u32 = 62;
 pub const TSC_MASK: u64 = (1 << SOCKET_SHIFT) - 1;
 pub const SOCKET_MASK: u64 = !TSC_MASK;
+/// Sockets the top bits of an [`Instant`] can tell apart.
+pub const MAX_SOCKETS: usize = 1 << (64 - SOCKET_SHIFT);
 
 /// Linux sets `IA32_TSC_AUX` to `(numa_node << 12) | cpu`, so one rdtscp reads
 /// both the TSC and the NUMA node of the calling core.
 #[cfg(target_arch = "x86_64")]
 #[inline]
 pub fn read_tsc_and_node() -> (u64, u16) {
+    if let Some(tsc) = mocked_tsc() {
+        return (tsc, 0);
+    }
     let mut aux: u32 = 0;
     let tsc = unsafe { core::arch::x86_64::__rdtscp(&raw mut aux) };
     (tsc, (aux >> 12) as u16)
@@ -25,10 +34,21 @@ pub fn read_tsc_and_node() -> (u64, u16) {
 #[cfg(not(target_arch = "x86_64"))]
 #[inline]
 pub fn read_tsc_and_node() -> (u64, u16) {
-    (global_clock_not_mocked().raw(), 0)
+    (mocked_tsc().unwrap_or_else(|| global_clock_not_mocked().raw()), 0)
+}
+
+thread_local! {
+    /// Socket bits [`Instant::now`] tags this thread's instants with.
+    static SOCKET_TAG: Cell<u64> = const { Cell::new(0) };
+}
+
+#[inline]
+fn socket_tag(node: u16) -> u64 {
+    (u64::from(node) % MAX_SOCKETS as u64) << SOCKET_SHIFT
 }
 
-// Socket is in the top 2 bits, rdtscp counter in lower 62
+// Socket is in the top 2 bits, TSC counter in lower 62. Deltas between
+// sockets are corrected by the offsets of the adopted `TscCalibration`.
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, Hash, PartialEq, TypeHash)]
 #[repr(C)]
 pub struct Instant(pub u64);
@@ -36,9 +56,32 @@ impl Instant {
     pub const MAX: Self = Self(u64::MAX);
     pub const ZERO: Self = Self(0);
 
+    /// The TSC, tagged with the socket [`cache_thread_socket`] stored for the
+    /// calling thread, socket 0 if it never did. A plain `rdtsc`, so it stays
+    /// cheap enough for the hot path.
+    ///
+    /// [`cache_thread_socket`]: Self::cache_thread_socket
     #[inline]
     pub fn now() -> Self {
-        Self(global_clock_not_mocked().raw())
+        let tsc = mocked_tsc().unwrap_or_else(|| global_clock_not_mocked().raw());
+        Self((tsc & TSC_MASK) | SOCKET_TAG.get())
+    }
+
+    /// Like [`now`](Self::now), tagged with the socket of the calling core as
+    /// read by `rdtscp`, for threads that may run on any socket.
+    #[inline]
+    pub fn now_with_socket() -> Self {
+        let (tsc, node) = read_tsc_and_node();
+        Self((tsc & TSC_MASK) | socket_tag(node))
+    }
+
+    /// Stores the socket of the calling core for the [`now`](Self::now) of
+    /// this thread and returns it. Meant for threads pinned to one core, a
+    /// thread that moves to another socket keeps tagging with the old one.
+    pub fn cache_thread_socket() -> usize {
+        let (_, node) = read_tsc_and_node();
+        SOCKET_TAG.set(socket_tag(node));
+        Self(socket_tag(node)).socket()
     }
 
     #[inline]
@@ -46,11 +89,45 @@ impl Instant {
         Self(self.0 & TSC_MASK)
     }
 
+    #[inline]
+    pub const fn socket(&self) -> usize {
+        (self.0 >> SOCKET_SHIFT) as usize
+    }
+
     #[inline]
     pub fn same_socket(&self, other: &Self) -> bool {
         (self.0 & SOCKET_MASK) == (other.0 & SOCKET_MASK)
     }
 
+    /// The counter moved onto the calibration's common timeline.
+    #[inline]
+    fn corrected(self) -> u64 {
+        (self.0 & TSC_MASK).saturating_add_signed(socket_offset(self.socket()))
+    }
+
+    /// How far a delta between `self` and `other` may be off after the
+    /// socket correction: zero on one socket, `None` when a socket's offset
+    /// was never measured and the delta is meaningless.
+    #[inline]
+    pub fn error_bound(&self, other: &Self) -> Option<Duration> {
+        if self.same_socket(other) {
+            return Some(Duration(0));
+        }
+        let (ours, theirs) = (socket_error(self.socket()), socket_error(other.socket()));
+        (ours != 0 && theirs != 0).then_some(Duration(ours + theirs))
+    }
+
+    /// The wall clock at this instant, by the anchor of the adopted
+    /// [`TscCalibration`](crate::TscCalibration), or our own measured on
+    /// first use. Under the mock this is the mocked `Nanos::now`.
+    #[inline]
+    pub fn to_wall(&self) -> Nanos {
+        if mocked_tsc().is_some() {
+            return Nanos(self.0 & TSC_MASK);
+        }
+        wall_at(self.corrected())
+    }
+
     #[inline]
     pub fn elapsed(&self) -> Duration {
         let curt = Self::now();
@@ -69,7 +146,11 @@ impl Instant {
 
     #[inline]
     pub fn saturating_sub(&self, other: Self) -> Duration {
-        Duration(self.0.saturating_sub(other.0))
+        if self.same_socket(&other) {
+            Duration(self.0.saturating_sub(other.0))
+        } else {
+            Duration(self.corrected().saturating_sub(other.corrected()))
+        }
     }
 }
 
@@ -82,8 +163,14 @@ impl PartialOrd for Instant {
 }
 
 impl Ord for Instant {
+    /// By the corrected counter across sockets, so a deadline taken on one
+    /// socket is reached on another.
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.0.cmp(&other.0)
+        if self.same_socket(other) {
+            self.0.cmp(&other.0)
+        } else {
+            self.corrected().cmp(&other.corrected()).then(self.0.cmp(&other.0))
+        }
     }
 }
 
@@ -91,7 +178,7 @@ impl Sub for Instant {
     type Output = Duration;
 
     fn sub(self, rhs: Self) -> Duration {
-        Duration(self.0.saturating_sub(rhs.0))
+        self.saturating_sub(rhs)
     }
 }
 
@@ -1,3 +1,4 @@
+mod calibration;
 mod duration;
 mod global_clock;
 mod ingestion_time;
@@ -6,16 +7,20 @@ mod internal_message;
 mod nanos;
 mod publish_delta;
 mod repeater;
+mod schedule;
 mod tracking_timestamp;
 
+pub use calibration::TscCalibration;
 pub use duration::Duration;
 pub use global_clock::{
-    Clock, OurClockForNanos, global_clock, global_clock_not_mocked, init_global_with_mock,
+    Clock, OurClockForNanos, advance_mock_to, global_clock, global_clock_not_mocked,
+    init_global_with_mock,
 };
 pub use ingestion_time::IngestionTime;
-pub use instant::{Instant, SOCKET_SHIFT, TSC_MASK, read_tsc_and_node};
+pub use instant::{Instant, MAX_SOCKETS, SOCKET_SHIFT, TSC_MASK, read_tsc_and_node};
 pub use internal_message::InternalMessage;
 pub use nanos::Nanos;
 pub use publish_delta::PublishDelta;
 pub use repeater::Repeater;
+pub use schedule::{CatchUp, Schedule, ScheduleParseError, ScheduleSpec, Weekdays, Zone};
 pub use tracking_timestamp::{TrackingTimestamp, UNREGISTERED_TILE_ID};
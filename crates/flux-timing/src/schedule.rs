@@ -0,0 +1,519 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{
+    Datelike, FixedOffset, Local, LocalResult, NaiveTime, TimeZone, Timelike, Utc, Weekday,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Instant, Nanos, global_clock::nanos_mocked};
+
+/// When a [`Schedule`] fires.
+///
+/// Parsed from strings like `every 1s`, `every 1m` (the top of each minute),
+/// `every 1h + 30m` (half past each hour), `14:30 UTC`, `mon-fri 09:30:00
+/// local` or `sat,sun 12:00 +02:00`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduleSpec {
+    /// On every multiple of `interval` since the epoch, shifted by `offset`,
+    /// like [`Nanos::round_to_interval`].
+    Aligned { interval: Nanos, offset: Nanos },
+    /// Daily at `at` in `zone`, on `weekdays` only.
+    Daily { at: NaiveTime, zone: Zone, weekdays: Weekdays },
+}
+
+/// Time zone of a [`ScheduleSpec::Daily`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    Utc,
+    Fixed(FixedOffset),
+    /// The system's time zone, daylight saving included. A time skipped by a
+    /// clock change fires an hour later that day.
+    Local,
+}
+
+/// Days of the week a [`ScheduleSpec::Daily`] fires on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weekdays(u8);
+
+impl Weekdays {
+    pub const ALL: Self = Self(0x7f);
+
+    #[inline]
+    pub const fn only(day: Weekday) -> Self {
+        Self(1 << day.num_days_from_monday())
+    }
+
+    /// `from` through `to`, wrapping past Sunday.
+    pub fn range(from: Weekday, to: Weekday) -> Self {
+        let mut days = Self::only(from);
+        let mut day = from;
+        while day != to {
+            day = day.succ();
+            days = days.with(day);
+        }
+        days
+    }
+
+    #[inline]
+    pub const fn with(self, day: Weekday) -> Self {
+        Self(self.0 | Self::only(day).0)
+    }
+
+    #[inline]
+    pub const fn contains(self, day: Weekday) -> bool {
+        self.0 & Self::only(day).0 != 0
+    }
+}
+
+impl Default for Weekdays {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl ScheduleSpec {
+    /// Every `interval` on its wall-clock boundaries.
+    pub fn every(interval: Nanos) -> Self {
+        Self::every_with_offset(interval, Nanos(0))
+    }
+
+    /// Every `interval`, `offset` past its wall-clock boundaries.
+    pub fn every_with_offset(interval: Nanos, offset: Nanos) -> Self {
+        assert!(interval.0 > 0, "schedule interval must be > 0");
+        Self::Aligned { interval, offset: Nanos(offset.0 % interval.0) }
+    }
+
+    /// Every day at `at` in `zone`.
+    pub fn daily(at: NaiveTime, zone: Zone) -> Self {
+        Self::Daily { at, zone, weekdays: Weekdays::ALL }
+    }
+
+    /// The first fire strictly after `t`.
+    pub fn next_after(&self, t: Nanos) -> Nanos {
+        match *self {
+            Self::Aligned { interval, offset } => {
+                if t < offset {
+                    return offset;
+                }
+                Nanos(((t.0 - offset.0) / interval.0 + 1) * interval.0 + offset.0)
+            }
+            Self::Daily { at, zone, weekdays } => match zone {
+                Zone::Utc => next_daily(&Utc, t, at, weekdays),
+                Zone::Fixed(offset) => next_daily(&offset, t, at, weekdays),
+                Zone::Local => next_daily(&Local, t, at, weekdays),
+            },
+        }
+    }
+
+    /// Fires after `from` up to and including `to`.
+    fn fires_between(&self, from: Nanos, to: Nanos) -> u64 {
+        match *self {
+            Self::Aligned { interval, .. } => {
+                let first = self.next_after(from);
+                if first > to {
+                    return 0;
+                }
+                (to.0 - first.0) / interval.0 + 1
+            }
+            Self::Daily { .. } => {
+                let mut n = 0;
+                let mut t = self.next_after(from);
+                while t <= to {
+                    n += 1;
+                    t = self.next_after(t);
+                }
+                n
+            }
+        }
+    }
+}
+
+/// The first `at` on one of `weekdays` in `tz` strictly after `t`, or
+/// `Nanos(u64::MAX)` if there is none.
+fn next_daily<Tz: TimeZone>(tz: &Tz, t: Nanos, at: NaiveTime, weekdays: Weekdays) -> Nanos {
+    let mut date = tz.timestamp_nanos(t.0 as i64).date_naive();
+    // A week and a day: the day `t` is on may already be past `at`.
+    for _ in 0..8 {
+        if weekdays.contains(date.weekday()) {
+            let local = date.and_time(at);
+            let fire = match tz.from_local_datetime(&local) {
+                LocalResult::Single(fire) | LocalResult::Ambiguous(fire, _) => Some(fire),
+                LocalResult::None => {
+                    tz.from_local_datetime(&(local + chrono::Duration::hours(1))).earliest()
+                }
+            };
+            if let Some(fire) = fire.and_then(|f| f.timestamp_nanos_opt()) &&
+                fire as u64 > t.0
+            {
+                return Nanos(fire as u64);
+            }
+        }
+        let Some(next) = date.succ_opt() else { break };
+        date = next;
+    }
+    Nanos(u64::MAX)
+}
+
+/// What a [`Schedule`] does about fires it missed, because it was not polled
+/// in time or the clock jumped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CatchUp {
+    /// Fire once for all of them and continue with the first fire after now.
+    #[default]
+    Skip,
+    /// Fire once per missed fire, on consecutive polls.
+    Each,
+}
+
+/// Longest a [`Schedule`] polls the TSC alone before reading the clock.
+const MAX_TSC_WAIT: Nanos = Nanos::from_millis(1);
+
+/// Fires on wall-clock aligned times, see [`ScheduleSpec`].
+///
+/// Polled like a [`Repeater`](crate::Repeater), but unlike it never drifts
+/// with the loop: every fire is due at a time the spec fixes up front, read
+/// from the global clock so mocked time drives it too. The first fire is the
+/// first one after the schedule was created.
+///
+/// [`fired`](Self::fired) polls the TSC against a deadline at most 1ms out
+/// on the way to `next`, and reads the global clock only once that passes,
+/// so rate error and slew never delay a fire by more. A mocked clock is read
+/// on every poll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    spec: ScheduleSpec,
+    catch_up: CatchUp,
+    next: Nanos,
+    /// When `fired` reads the clock next, `ZERO` until it first did.
+    due: Instant,
+    last: Nanos,
+    missed: u64,
+}
+
+impl Schedule {
+    pub fn new(spec: ScheduleSpec) -> Self {
+        Self::starting_at(spec, Nanos::now())
+    }
+
+    /// A schedule whose first fire is the first one after `now`.
+    pub fn starting_at(spec: ScheduleSpec, now: Nanos) -> Self {
+        Self {
+            spec,
+            catch_up: CatchUp::default(),
+            next: spec.next_after(now),
+            due: Instant::ZERO,
+            last: Nanos(0),
+            missed: 0,
+        }
+    }
+
+    pub fn with_catch_up(mut self, catch_up: CatchUp) -> Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    #[inline]
+    pub fn fired(&mut self) -> bool {
+        let tsc = Instant::now();
+        if tsc < self.due {
+            return false;
+        }
+        let now = Nanos::now();
+        let fired = self.fired_at(now);
+        if !nanos_mocked() {
+            self.due = tsc + self.next.saturating_sub(now).min(MAX_TSC_WAIT);
+        }
+        fired
+    }
+
+    #[inline]
+    pub fn fired_at(&mut self, now: Nanos) -> bool {
+        if now < self.next {
+            return false;
+        }
+        self.fire(now);
+        true
+    }
+
+    #[cold]
+    fn fire(&mut self, now: Nanos) {
+        self.last = self.next;
+        match self.catch_up {
+            CatchUp::Skip => {
+                self.missed = self.spec.fires_between(self.next, now);
+                self.next = self.spec.next_after(now);
+            }
+            CatchUp::Each => {
+                self.missed = 0;
+                self.next = self.spec.next_after(self.next);
+            }
+        }
+    }
+
+    /// When the next fire is due.
+    #[inline]
+    pub fn next_fire(&self) -> Nanos {
+        self.next
+    }
+
+    /// When the last fire was due, which is behind now by however late it
+    /// was polled.
+    #[inline]
+    pub fn last_fire(&self) -> Nanos {
+        self.last
+    }
+
+    /// Fires the last one stood in for, always 0 with [`CatchUp::Each`].
+    #[inline]
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    #[inline]
+    pub fn spec(&self) -> ScheduleSpec {
+        self.spec
+    }
+
+    /// Skips the fires due until now.
+    pub fn reset(&mut self) {
+        self.next = self.spec.next_after(Nanos::now());
+        self.due = Instant::ZERO;
+    }
+}
+
+/// Why a [`ScheduleSpec`] did not parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScheduleParseError {
+    spec: String,
+    reason: &'static str,
+}
+
+impl fmt::Display for ScheduleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid schedule {:?}: {}", self.spec, self.reason)
+    }
+}
+
+impl std::error::Error for ScheduleParseError {}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    s.parse().ok()
+}
+
+fn parse_weekdays(s: &str) -> Option<Weekdays> {
+    let mut days = Weekdays(0);
+    for part in s.split(',') {
+        let part = match part.split_once('-') {
+            Some((from, to)) => Weekdays::range(parse_weekday(from)?, parse_weekday(to)?),
+            None => Weekdays::only(parse_weekday(part)?),
+        };
+        days = Weekdays(days.0 | part.0);
+    }
+    Some(days)
+}
+
+fn parse_zone(s: &str) -> Option<Zone> {
+    match s {
+        "UTC" | "utc" | "Z" => Some(Zone::Utc),
+        "local" | "LOCAL" => Some(Zone::Local),
+        _ => s.parse::<FixedOffset>().ok().map(Zone::Fixed),
+    }
+}
+
+impl FromStr for ScheduleSpec {
+    type Err = ScheduleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |reason| ScheduleParseError { spec: s.to_owned(), reason };
+        let spec = s.trim();
+
+        if let Some(every) = spec.strip_prefix("every ") {
+            let (interval, offset) = every.split_once('+').unwrap_or((every, "0s"));
+            let interval: Nanos = interval.parse().map_err(|_| err("bad interval"))?;
+            let offset: Nanos = offset.parse().map_err(|_| err("bad offset"))?;
+            if interval.0 == 0 {
+                return Err(err("interval must be > 0"));
+            }
+            return Ok(Self::every_with_offset(interval, offset));
+        }
+
+        let mut words = spec.split_whitespace().peekable();
+        let weekdays = match words.peek() {
+            Some(word) if !word.contains(':') => {
+                let days = if *word == "daily" {
+                    Weekdays::ALL
+                } else {
+                    parse_weekdays(word).ok_or_else(|| err("bad weekdays"))?
+                };
+                words.next();
+                days
+            }
+            _ => Weekdays::ALL,
+        };
+        let at = words.next().ok_or_else(|| err("missing time of day"))?;
+        let at = NaiveTime::parse_from_str(at, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(at, "%H:%M"))
+            .map_err(|_| err("bad time of day, expected HH:MM[:SS]"))?;
+        let zone = match words.next() {
+            Some(zone) => {
+                parse_zone(zone).ok_or_else(|| err("bad zone, expected UTC, local or +HH:MM"))?
+            }
+            None => Zone::Utc,
+        };
+        if words.next().is_some() {
+            return Err(err("trailing input"));
+        }
+        Ok(Self::Daily { at, zone, weekdays })
+    }
+}
+
+impl fmt::Display for ScheduleSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Aligned { interval, offset } => {
+                write!(f, "every {}", humantime::format_duration(interval.into()))?;
+                if offset.0 != 0 {
+                    write!(f, " + {}", humantime::format_duration(offset.into()))?;
+                }
+                Ok(())
+            }
+            Self::Daily { at, zone, weekdays } => {
+                if weekdays != Weekdays::ALL {
+                    let mut first = true;
+                    for day in (0..7).filter_map(|d| Weekday::try_from(d).ok()) {
+                        if weekdays.contains(day) {
+                            write!(f, "{}{day}", if first { "" } else { "," })?;
+                            first = false;
+                        }
+                    }
+                    write!(f, " ")?;
+                }
+                write!(f, "{:02}:{:02}:{:02} ", at.hour(), at.minute(), at.second())?;
+                match zone {
+                    Zone::Utc => write!(f, "UTC"),
+                    Zone::Fixed(offset) => write!(f, "{offset}"),
+                    Zone::Local => write!(f, "local"),
+                }
+            }
+        }
+    }
+}
+
+impl Serialize for ScheduleSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ScheduleSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> Nanos {
+        Nanos::from_rfc3339(s).unwrap()
+    }
+
+    #[test]
+    fn aligned_fires_on_the_boundaries() {
+        let spec: ScheduleSpec = "every 1m".parse().unwrap();
+        let mut schedule = Schedule::starting_at(spec, utc("2026-03-02T10:15:42.5Z"));
+        assert_eq!(schedule.next_fire(), utc("2026-03-02T10:16:00Z"));
+        assert!(!schedule.fired_at(utc("2026-03-02T10:15:59.999Z")));
+        assert!(schedule.fired_at(utc("2026-03-02T10:16:00.003Z")));
+        assert_eq!(schedule.last_fire(), utc("2026-03-02T10:16:00Z"));
+        assert_eq!(schedule.next_fire(), utc("2026-03-02T10:17:00Z"));
+
+        let spec: ScheduleSpec = "every 1h + 30m".parse().unwrap();
+        assert_eq!(spec.next_after(utc("2026-03-02T10:30:00Z")), utc("2026-03-02T11:30:00Z"));
+        assert_eq!(spec.next_after(utc("2026-03-02T10:29:59Z")), utc("2026-03-02T10:30:00Z"));
+    }
+
+    #[test]
+    fn catch_up_skips_or_fires_each_missed() {
+        let spec = ScheduleSpec::every(Nanos::from_secs(1));
+        let start = utc("2026-03-02T10:00:00.5Z");
+        let late = utc("2026-03-02T10:00:04.2Z");
+
+        let mut skip = Schedule::starting_at(spec, start);
+        assert!(skip.fired_at(late));
+        assert_eq!(skip.missed(), 3);
+        assert_eq!(skip.last_fire(), utc("2026-03-02T10:00:01Z"));
+        assert!(!skip.fired_at(late));
+        assert_eq!(skip.next_fire(), utc("2026-03-02T10:00:05Z"));
+
+        let mut each = Schedule::starting_at(spec, start).with_catch_up(CatchUp::Each);
+        let mut fires = Vec::new();
+        while each.fired_at(late) {
+            fires.push(each.last_fire());
+        }
+        assert_eq!(
+            fires,
+            (1..=4).map(|s| utc(&format!("2026-03-02T10:00:0{s}Z"))).collect::<Vec<_>>()
+        );
+        assert_eq!(each.missed(), 0);
+    }
+
+    #[test]
+    fn fired_polls_the_tsc_until_the_deadline() {
+        let mut schedule = Schedule::new(ScheduleSpec::every(Nanos::from_secs(3600)));
+        assert_eq!(schedule.due, Instant::ZERO);
+        assert!(!schedule.fired());
+        // Never further out than `MAX_TSC_WAIT`, however far the fire is.
+        let armed = Nanos::from(schedule.due - Instant::now());
+        assert!(armed > Nanos(0) && armed <= MAX_TSC_WAIT, "{armed:?}");
+
+        // Past the deadline the clock has the last word, and re-arms it.
+        let stale = Instant::now() - Nanos::from_secs(1);
+        schedule.due = stale;
+        assert!(!schedule.fired());
+        assert!(schedule.due > Instant::now());
+    }
+
+    #[test]
+    fn daily_times_follow_their_zone_and_weekdays() {
+        let spec: ScheduleSpec = "14:30 UTC".parse().unwrap();
+        assert_eq!(spec.next_after(utc("2026-03-02T14:29:00Z")), utc("2026-03-02T14:30:00Z"));
+        assert_eq!(spec.next_after(utc("2026-03-02T14:30:00Z")), utc("2026-03-03T14:30:00Z"));
+
+        let spec: ScheduleSpec = "14:30 +02:00".parse().unwrap();
+        assert_eq!(spec.next_after(utc("2026-03-02T12:00:00Z")), utc("2026-03-02T12:30:00Z"));
+
+        // 2026-03-06 is a Friday.
+        let spec: ScheduleSpec = "mon-fri 09:30:15".parse().unwrap();
+        assert_eq!(spec.next_after(utc("2026-03-06T10:00:00Z")), utc("2026-03-09T09:30:15Z"));
+        let spec: ScheduleSpec = "sat,sun 12:00 UTC".parse().unwrap();
+        assert_eq!(spec.next_after(utc("2026-03-02T00:00:00Z")), utc("2026-03-07T12:00:00Z"));
+
+        let mut schedule =
+            Schedule::starting_at("daily 00:00 UTC".parse().unwrap(), utc("2026-03-02T01:00:00Z"));
+        assert!(schedule.fired_at(utc("2026-03-05T06:00:00Z")));
+        assert_eq!((schedule.last_fire(), schedule.missed()), (utc("2026-03-03T00:00:00Z"), 2));
+        assert_eq!(schedule.next_fire(), utc("2026-03-06T00:00:00Z"));
+    }
+
+    #[test]
+    fn specs_round_trip_through_strings() {
+        for spec in [
+            "every 1s",
+            "every 1h + 30m",
+            "14:30:00 UTC",
+            "Mon,Tue,Wed,Thu,Fri 09:30:00 local",
+            "Sat 12:00:00 +02:00",
+        ] {
+            let parsed: ScheduleSpec = spec.parse().unwrap();
+            assert_eq!(parsed.to_string(), spec);
+            assert_eq!(parsed.to_string().parse::<ScheduleSpec>().unwrap(), parsed);
+        }
+        for bad in
+            ["every", "every 0s", "every soon", "25:00", "fri-", "14:30 Mars", "14:30 UTC now"]
+        {
+            assert!(bad.parse::<ScheduleSpec>().is_err(), "{bad} parsed");
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use flux_timing::{Nanos, Schedule, init_global_with_mock};
+
+// One test: the mock is global to the process.
+#[test]
+fn mocked_jump_fires_a_daily_schedule() {
+    let mock = init_global_with_mock();
+    mock.increment(Nanos::from_rfc3339("2026-03-02T10:00:00Z").unwrap().0);
+
+    let mut schedule = Schedule::new("daily 00:00 UTC".parse().unwrap());
+    assert!(!schedule.fired());
+    assert!(!schedule.fired());
+
+    // A day on, with no real time passing in between.
+    mock.increment(Nanos::from_secs(24 * 3600).0);
+    assert!(schedule.fired());
+    assert_eq!(schedule.last_fire(), Nanos::from_rfc3339("2026-03-03T00:00:00Z").unwrap());
+}
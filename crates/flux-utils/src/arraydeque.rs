@@ -0,0 +1,434 @@
+use core::mem::MaybeUninit;
+use std::{fmt::Debug, ops::Index};
+
+use type_hash::{TypeHash, fnv1a64_str, hash_layout_of, hash_u64};
+
+use crate::ArrayVec;
+
+/// A fixed-capacity ring buffer whose elements are `Copy`, and which is
+/// itself `Copy`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct ArrayDeque<T: Copy, const N: usize> {
+    head: usize,
+    len: usize,
+    data: [MaybeUninit<T>; N],
+}
+
+impl<T: Copy, const N: usize> ArrayDeque<T, N> {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self { head: 0, len: 0, data: [MaybeUninit::uninit(); N] }
+    }
+
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline(always)]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline(always)]
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Position in `data` of the `i`-th element, `i <= N`.
+    #[inline(always)]
+    const fn wrap(&self, i: usize) -> usize {
+        let pos = self.head + i;
+        if pos >= N { pos - N } else { pos }
+    }
+
+    /// Panics if full.
+    #[inline(always)]
+    pub fn push_back(&mut self, value: T) {
+        assert!(self.try_push_back(value).is_none(), "push_back capacity overflow");
+    }
+
+    /// Returns back the element if the capacity is exhausted, otherwise
+    /// returns None.
+    #[inline]
+    pub fn try_push_back(&mut self, value: T) -> Option<T> {
+        if self.is_full() {
+            return Some(value);
+        }
+        let pos = self.wrap(self.len);
+        unsafe {
+            self.data.get_unchecked_mut(pos).write(value);
+        }
+        self.len += 1;
+        None
+    }
+
+    /// Panics if full.
+    #[inline(always)]
+    pub fn push_front(&mut self, value: T) {
+        assert!(self.try_push_front(value).is_none(), "push_front capacity overflow");
+    }
+
+    /// Returns back the element if the capacity is exhausted, otherwise
+    /// returns None.
+    #[inline]
+    pub fn try_push_front(&mut self, value: T) -> Option<T> {
+        if self.is_full() {
+            return Some(value);
+        }
+        self.head = self.wrap(N - 1);
+        unsafe {
+            self.data.get_unchecked_mut(self.head).write(value);
+        }
+        self.len += 1;
+        None
+    }
+
+    #[inline]
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let value = unsafe { self.data.get_unchecked(self.head).assume_init_read() };
+        self.head = self.wrap(1);
+        self.len -= 1;
+        Some(value)
+    }
+
+    #[inline]
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.len -= 1;
+        Some(unsafe { self.data.get_unchecked(self.wrap(self.len)).assume_init_read() })
+    }
+
+    /// Pushes to the back, evicting and returning the front element if full.
+    #[inline]
+    pub fn push_back_overwrite(&mut self, value: T) -> Option<T> {
+        let evicted = if self.is_full() { self.pop_front() } else { None };
+        self.push_back(value);
+        evicted
+    }
+
+    #[inline]
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    #[inline]
+    pub fn back(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        Some(unsafe { self.data.get_unchecked(self.wrap(index)).assume_init_ref() })
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        let pos = self.wrap(index);
+        Some(unsafe { self.data.get_unchecked_mut(pos).assume_init_mut() })
+    }
+
+    /// The elements front to back, as the two contiguous runs they occupy.
+    #[inline]
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let first = self.len.min(N - self.head);
+        let data = self.data.as_ptr().cast::<T>();
+        unsafe {
+            (
+                core::slice::from_raw_parts(data.add(self.head), first),
+                core::slice::from_raw_parts(data, self.len - first),
+            )
+        }
+    }
+
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        let (a, b) = self.as_slices();
+        a.iter().chain(b.iter())
+    }
+}
+
+pub type Iter<'a, T> = core::iter::Chain<core::slice::Iter<'a, T>, core::slice::Iter<'a, T>>;
+
+impl<T: Copy, const N: usize> Default for ArrayDeque<T, N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy + Debug, const N: usize> core::fmt::Debug for ArrayDeque<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Copy, const N: usize> Index<usize> for ArrayDeque<T, N> {
+    type Output = T;
+
+    #[inline(always)]
+    fn index(&self, idx: usize) -> &Self::Output {
+        self.get(idx).expect("index out of bounds")
+    }
+}
+
+impl<T: Copy + PartialEq, const N: usize> PartialEq for ArrayDeque<T, N> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Copy + Eq, const N: usize> Eq for ArrayDeque<T, N> {}
+
+impl<'a, T: Copy, const N: usize> IntoIterator for &'a ArrayDeque<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct IntoIter<T: Copy, const N: usize> {
+    deque: ArrayDeque<T, N>,
+}
+
+impl<T: Copy, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.deque.pop_front()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.deque.len, Some(self.deque.len))
+    }
+}
+
+impl<T: Copy, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    #[inline]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.deque.pop_back()
+    }
+}
+
+impl<T: Copy, const N: usize> IntoIterator for ArrayDeque<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { deque: self }
+    }
+}
+
+impl<T: Copy, const N: usize> Extend<T> for ArrayDeque<T, N> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for t in iter {
+            self.push_back(t);
+        }
+    }
+}
+
+impl<T: Copy, const N: usize> FromIterator<T> for ArrayDeque<T, N> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut d = Self::new();
+        d.extend(iter);
+        d
+    }
+}
+
+impl<T: Copy, const N: usize> From<ArrayVec<T, N>> for ArrayDeque<T, N> {
+    #[inline]
+    fn from(v: ArrayVec<T, N>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<T: Copy, const N: usize> From<ArrayDeque<T, N>> for ArrayVec<T, N> {
+    #[inline]
+    fn from(d: ArrayDeque<T, N>) -> Self {
+        d.into_iter().collect()
+    }
+}
+
+impl<T: TypeHash + Copy, const N: usize> TypeHash for ArrayDeque<T, N> {
+    const TYPE_HASH: u64 = {
+        let mut h = 0xcbf2_9ce4_8422_2325u64;
+        h = fnv1a64_str(h, "ArrayDeque");
+        h = hash_u64(h, T::TYPE_HASH);
+        h = hash_u64(h, N as u64);
+        h = hash_layout_of::<Self>(h);
+        h
+    };
+}
+
+mod serde_impl {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ArrayDeque;
+    use crate::ArrayVec;
+
+    impl<T, const N: usize> Serialize for ArrayDeque<T, N>
+    where
+        T: Copy + Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.collect_seq(self.iter())
+        }
+    }
+
+    impl<'de, T, const N: usize> Deserialize<'de> for ArrayDeque<T, N>
+    where
+        T: Copy + Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            ArrayVec::<T, N>::deserialize(deserializer).map(Self::from)
+        }
+    }
+}
+
+// Same wire format as an `ArrayVec` of the elements front to back.
+#[cfg(feature = "wincode")]
+mod wincode_impl {
+    use std::mem::MaybeUninit;
+
+    use wincode::{
+        config::ConfigCore,
+        io::{Reader, Writer},
+    };
+
+    use super::ArrayDeque;
+    use crate::ArrayVec;
+
+    unsafe impl<T, C, const N: usize> wincode::SchemaWrite<C> for ArrayDeque<T, N>
+    where
+        C: ConfigCore,
+        T: Copy + wincode::SchemaWrite<C, Src = T>,
+    {
+        type Src = Self;
+
+        #[inline]
+        fn size_of(src: &Self::Src) -> wincode::WriteResult<usize> {
+            <ArrayVec<T, N> as wincode::SchemaWrite<C>>::size_of(&ArrayVec::from(*src))
+        }
+
+        #[inline]
+        fn write(writer: impl Writer, src: &Self::Src) -> wincode::WriteResult<()> {
+            <ArrayVec<T, N> as wincode::SchemaWrite<C>>::write(writer, &ArrayVec::from(*src))
+        }
+    }
+
+    unsafe impl<'de, T, C, const N: usize> wincode::SchemaRead<'de, C> for ArrayDeque<T, N>
+    where
+        C: ConfigCore,
+        T: Copy + wincode::SchemaRead<'de, C, Dst = T>,
+    {
+        type Dst = Self;
+
+        fn read(
+            reader: impl Reader<'de>,
+            dst: &mut MaybeUninit<Self::Dst>,
+        ) -> wincode::ReadResult<()> {
+            let v = <ArrayVec<T, N> as wincode::SchemaRead<'de, C>>::get(reader)?;
+            dst.write(Self::from(v));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around() {
+        let mut d: ArrayDeque<u32, 3> = ArrayDeque::new();
+        assert_eq!(d.pop_front(), None);
+        d.push_back(1);
+        d.push_back(2);
+        d.push_front(0);
+        assert!(d.is_full());
+        assert_eq!(d.try_push_back(3), Some(3));
+        assert_eq!(d.pop_front(), Some(0));
+        d.push_back(3);
+        assert_eq!(d.pop_front(), Some(1));
+        d.push_back(4);
+        assert_eq!(d.as_slices(), (&[2, 3][..], &[4][..]));
+        assert_eq!(d.iter().copied().collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!((d.front(), d.back(), d[1]), (Some(&2), Some(&4), 3));
+        assert_eq!(d.pop_back(), Some(4));
+        assert_eq!(d.into_iter().collect::<Vec<_>>(), [2, 3]);
+    }
+
+    #[test]
+    fn overwrite_evicts_the_oldest() {
+        let mut d: ArrayDeque<u32, 2> = ArrayDeque::new();
+        assert_eq!(d.push_back_overwrite(1), None);
+        assert_eq!(d.push_back_overwrite(2), None);
+        assert_eq!(d.push_back_overwrite(3), Some(1));
+        assert_eq!(d, [2, 3].into_iter().collect());
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let mut d: ArrayDeque<u32, 4> = (0..4).collect();
+        d.pop_front();
+        d.push_back(4);
+        let json = serde_json::to_string(&d).unwrap();
+        assert_eq!(json, "[1,2,3,4]");
+        assert_eq!(serde_json::from_str::<ArrayDeque<u32, 4>>(&json).unwrap(), d);
+        assert!(serde_json::from_str::<ArrayDeque<u32, 2>>(&json).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "wincode"))]
+mod wincode_tests {
+    use super::*;
+
+    #[test]
+    fn same_wire_format_as_arrayvec() {
+        let mut d: ArrayDeque<u32, 4> = (0..4).collect();
+        d.pop_front();
+        d.push_back(4);
+        let bytes = wincode::serialize(&d).unwrap();
+        let v: ArrayVec<u32, 4> = (1..5).collect();
+        assert_eq!(bytes, wincode::serialize(&v).unwrap());
+        assert_eq!(wincode::deserialize::<ArrayDeque<u32, 4>>(bytes.as_slice()).unwrap(), d);
+    }
+}
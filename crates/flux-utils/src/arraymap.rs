@@ -0,0 +1,472 @@
+use core::mem::MaybeUninit;
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
+
+use rustc_hash::FxHasher;
+use type_hash::{TypeHash, fnv1a64_str, hash_layout_of, hash_u64};
+
+/// A fixed-capacity open-addressing hash map whose keys and values are
+/// `Copy`, and which is itself `Copy`.
+///
+/// `N` must be a power of two. Keys are hashed with `FxHasher`, which is
+/// unseeded, so a map in shared memory probes the same slots in every
+/// process. Removals shift the following entries back instead of leaving
+/// tombstones, so lookups never degrade with churn.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct ArrayMap<K: Copy, V: Copy, const N: usize> {
+    len: usize,
+    occupied: [bool; N],
+    keys: [MaybeUninit<K>; N],
+    values: [MaybeUninit<V>; N],
+}
+
+impl<K: Copy + Eq + Hash, V: Copy, const N: usize> ArrayMap<K, V, N> {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        assert!(N.is_power_of_two(), "ArrayMap capacity must be a power of two");
+        Self {
+            len: 0,
+            occupied: [false; N],
+            keys: [MaybeUninit::uninit(); N],
+            values: [MaybeUninit::uninit(); N],
+        }
+    }
+
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline(always)]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline(always)]
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.occupied = [false; N];
+        self.len = 0;
+    }
+
+    #[inline(always)]
+    fn ideal(key: &K) -> usize {
+        let mut hasher = FxHasher::default();
+        key.hash(&mut hasher);
+        hasher.finish() as usize & (N - 1)
+    }
+
+    #[inline(always)]
+    unsafe fn key_at(&self, pos: usize) -> &K {
+        unsafe { self.keys.get_unchecked(pos).assume_init_ref() }
+    }
+
+    /// Slot of `key` if present, otherwise the free slot ending its probe
+    /// sequence, if any.
+    #[inline]
+    fn find(&self, key: &K) -> Result<usize, Option<usize>> {
+        let mut pos = Self::ideal(key);
+        for _ in 0..N {
+            if !self.occupied[pos] {
+                return Err(Some(pos));
+            }
+            if unsafe { self.key_at(pos) } == key {
+                return Ok(pos);
+            }
+            pos = (pos + 1) & (N - 1);
+        }
+        Err(None)
+    }
+
+    /// Inserts or updates `key`, returning the previous value. Panics if full
+    /// and `key` is new.
+    #[inline]
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let Ok(prev) = self.try_insert(key, value) else {
+            panic!("insert capacity overflow");
+        };
+        prev
+    }
+
+    /// Like [`Self::insert`], returning the entry back if the capacity is
+    /// exhausted.
+    #[inline]
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>, (K, V)> {
+        match self.find(&key) {
+            Ok(pos) => {
+                let slot = unsafe { self.values.get_unchecked_mut(pos) };
+                let prev = unsafe { slot.assume_init_read() };
+                slot.write(value);
+                Ok(Some(prev))
+            }
+            Err(Some(pos)) => {
+                self.occupied[pos] = true;
+                self.keys[pos].write(key);
+                self.values[pos].write(value);
+                self.len += 1;
+                Ok(None)
+            }
+            Err(None) => Err((key, value)),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let pos = self.find(key).ok()?;
+        Some(unsafe { self.values.get_unchecked(pos).assume_init_ref() })
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let pos = self.find(key).ok()?;
+        Some(unsafe { self.values.get_unchecked_mut(pos).assume_init_mut() })
+    }
+
+    #[inline]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_ok()
+    }
+
+    #[inline]
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let mut hole = self.find(key).ok()?;
+        let value = unsafe { self.values.get_unchecked(hole).assume_init_read() };
+        self.occupied[hole] = false;
+        self.len -= 1;
+
+        // Shift back the entries whose probe sequence crosses the hole.
+        let mut pos = hole;
+        loop {
+            pos = (pos + 1) & (N - 1);
+            if !self.occupied[pos] {
+                return Some(value);
+            }
+            let ideal = Self::ideal(unsafe { self.key_at(pos) });
+            let dist_from_ideal = pos.wrapping_sub(ideal) & (N - 1);
+            let dist_to_hole = pos.wrapping_sub(hole) & (N - 1);
+            if dist_from_ideal >= dist_to_hole {
+                self.occupied[hole] = true;
+                self.keys[hole] = self.keys[pos];
+                self.values[hole] = self.values[pos];
+                self.occupied[pos] = false;
+                hole = pos;
+            }
+        }
+    }
+
+    /// Entries in slot order.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, K, V, N> {
+        Iter { map: self, pos: 0 }
+    }
+
+    #[inline]
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    #[inline]
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+impl<K: Copy + Eq + Hash, V: Copy, const N: usize> Default for ArrayMap<K, V, N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Copy + Eq + Hash + Debug, V: Copy + Debug, const N: usize> core::fmt::Debug
+    for ArrayMap<K, V, N>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Copy + Eq + Hash, V: Copy + PartialEq, const N: usize> PartialEq for ArrayMap<K, V, N> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: Copy + Eq + Hash, V: Copy + Eq, const N: usize> Eq for ArrayMap<K, V, N> {}
+
+pub struct Iter<'a, K: Copy, V: Copy, const N: usize> {
+    map: &'a ArrayMap<K, V, N>,
+    pos: usize,
+}
+
+impl<'a, K: Copy, V: Copy, const N: usize> Iterator for Iter<'a, K, V, N> {
+    type Item = (&'a K, &'a V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < N {
+            let pos = self.pos;
+            self.pos += 1;
+            if self.map.occupied[pos] {
+                return Some(unsafe {
+                    (
+                        self.map.keys.get_unchecked(pos).assume_init_ref(),
+                        self.map.values.get_unchecked(pos).assume_init_ref(),
+                    )
+                });
+            }
+        }
+        None
+    }
+}
+
+impl<'a, K: Copy + Eq + Hash, V: Copy, const N: usize> IntoIterator for &'a ArrayMap<K, V, N> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, N>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Copy + Eq + Hash, V: Copy, const N: usize> Extend<(K, V)> for ArrayMap<K, V, N> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash, V: Copy, const N: usize> FromIterator<(K, V)> for ArrayMap<K, V, N> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut m = Self::new();
+        m.extend(iter);
+        m
+    }
+}
+
+impl<K: TypeHash + Copy, V: TypeHash + Copy, const N: usize> TypeHash for ArrayMap<K, V, N> {
+    const TYPE_HASH: u64 = {
+        let mut h = 0xcbf2_9ce4_8422_2325u64;
+        h = fnv1a64_str(h, "ArrayMap");
+        h = hash_u64(h, K::TYPE_HASH);
+        h = hash_u64(h, V::TYPE_HASH);
+        h = hash_u64(h, N as u64);
+        h = hash_layout_of::<Self>(h);
+        h
+    };
+}
+
+mod serde_impl {
+    use core::{fmt, hash::Hash};
+
+    use serde::{
+        Deserialize, Deserializer, Serialize, Serializer,
+        de::{Error as DeError, MapAccess, Visitor},
+    };
+
+    use super::ArrayMap;
+
+    impl<K, V, const N: usize> Serialize for ArrayMap<K, V, N>
+    where
+        K: Copy + Eq + Hash + Serialize,
+        V: Copy + Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.collect_map(self.iter())
+        }
+    }
+
+    impl<'de, K, V, const N: usize> Deserialize<'de> for ArrayMap<K, V, N>
+    where
+        K: Copy + Eq + Hash + Deserialize<'de>,
+        V: Copy + Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            struct ArrayMapVisitor<K, V, const N: usize>(core::marker::PhantomData<(K, V)>);
+
+            impl<'de, K, V, const N: usize> Visitor<'de> for ArrayMapVisitor<K, V, N>
+            where
+                K: Copy + Eq + Hash + Deserialize<'de>,
+                V: Copy + Deserialize<'de>,
+            {
+                type Value = ArrayMap<K, V, N>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "a map with at most {N} entries")
+                }
+
+                fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+                where
+                    A: MapAccess<'de>,
+                {
+                    let mut out = ArrayMap::<K, V, N>::new();
+                    while let Some((k, v)) = map.next_entry::<K, V>()? {
+                        if out.try_insert(k, v).is_err() {
+                            return Err(A::Error::custom("ArrayMap capacity exceeded"));
+                        }
+                    }
+                    Ok(out)
+                }
+            }
+
+            deserializer.deserialize_map(ArrayMapVisitor::<K, V, N>(core::marker::PhantomData))
+        }
+    }
+}
+
+// Same wire format as an `ArrayVec` of the `(key, value)` entries.
+#[cfg(feature = "wincode")]
+mod wincode_impl {
+    use std::{hash::Hash, mem::MaybeUninit};
+
+    use wincode::{
+        config::ConfigCore,
+        io::{Reader, Writer},
+    };
+
+    use super::ArrayMap;
+    use crate::ArrayVec;
+
+    fn entries<K: Copy + Eq + Hash, V: Copy, const N: usize>(
+        map: &ArrayMap<K, V, N>,
+    ) -> ArrayVec<(K, V), N> {
+        map.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    unsafe impl<K, V, C, const N: usize> wincode::SchemaWrite<C> for ArrayMap<K, V, N>
+    where
+        C: ConfigCore,
+        K: Copy + Eq + Hash,
+        V: Copy,
+        (K, V): wincode::SchemaWrite<C, Src = (K, V)>,
+    {
+        type Src = Self;
+
+        #[inline]
+        fn size_of(src: &Self::Src) -> wincode::WriteResult<usize> {
+            <ArrayVec<(K, V), N> as wincode::SchemaWrite<C>>::size_of(&entries(src))
+        }
+
+        #[inline]
+        fn write(writer: impl Writer, src: &Self::Src) -> wincode::WriteResult<()> {
+            <ArrayVec<(K, V), N> as wincode::SchemaWrite<C>>::write(writer, &entries(src))
+        }
+    }
+
+    unsafe impl<'de, K, V, C, const N: usize> wincode::SchemaRead<'de, C> for ArrayMap<K, V, N>
+    where
+        C: ConfigCore,
+        K: Copy + Eq + Hash,
+        V: Copy,
+        (K, V): wincode::SchemaRead<'de, C, Dst = (K, V)>,
+    {
+        type Dst = Self;
+
+        fn read(
+            reader: impl Reader<'de>,
+            dst: &mut MaybeUninit<Self::Dst>,
+        ) -> wincode::ReadResult<()> {
+            let entries = <ArrayVec<(K, V), N> as wincode::SchemaRead<'de, C>>::get(reader)?;
+            dst.write(entries.into_iter().collect());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn insert_get_remove() {
+        let mut m: ArrayMap<u32, u64, 4> = ArrayMap::new();
+        assert_eq!(m.insert(1, 10), None);
+        assert_eq!(m.insert(1, 11), Some(10));
+        for k in 2..5 {
+            m.insert(k, u64::from(k) * 10);
+        }
+        assert!(m.is_full());
+        assert_eq!(m.try_insert(5, 50), Err((5, 50)));
+        assert_eq!(m.insert(4, 41), Some(40));
+        assert_eq!(m.get(&1), Some(&11));
+        *m.get_mut(&2).unwrap() += 1;
+        assert_eq!(m.remove(&2), Some(21));
+        assert_eq!(m.remove(&2), None);
+        assert!(!m.contains_key(&2));
+        assert_eq!(m.len(), 3);
+        let mut keys: Vec<_> = m.keys().copied().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, [1, 3, 4]);
+    }
+
+    // Removals must keep every remaining key reachable, whatever the
+    // collisions, checked against `HashMap` on a pseudo-random workload.
+    #[test]
+    fn matches_hashmap_under_churn() {
+        let mut m: ArrayMap<u64, u64, 16> = ArrayMap::new();
+        let mut reference = HashMap::new();
+        let mut x = 0x9e37_79b9_7f4a_7c15u64;
+        for i in 0..10_000 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            let key = x % 24;
+            if x.is_multiple_of(3) {
+                assert_eq!(m.remove(&key), reference.remove(&key));
+            } else if m.try_insert(key, i).is_ok() {
+                reference.insert(key, i);
+            } else {
+                assert_eq!(reference.len(), 16);
+            }
+            assert_eq!(m.len(), reference.len());
+        }
+        for key in 0..24 {
+            assert_eq!(m.get(&key), reference.get(&key));
+        }
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let m: ArrayMap<u32, u32, 4> = [(1, 2), (3, 4)].into_iter().collect();
+        let json = serde_json::to_string(&m).unwrap();
+        assert_eq!(serde_json::from_str::<ArrayMap<u32, u32, 4>>(&json).unwrap(), m);
+        assert!(serde_json::from_str::<ArrayMap<u32, u32, 1>>(&json).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "wincode"))]
+mod wincode_tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let m: ArrayMap<u32, u64, 8> = (0..6).map(|k| (k, u64::from(k) << 32)).collect();
+        let bytes = wincode::serialize(&m).unwrap();
+        assert_eq!(wincode::deserialize::<ArrayMap<u32, u64, 8>>(bytes.as_slice()).unwrap(), m);
+    }
+}
@@ -0,0 +1,278 @@
+use core::ops::Deref;
+use std::fmt::Debug;
+
+use type_hash::{TypeHash, fnv1a64_str, hash_layout_of, hash_u64};
+
+use crate::ArrayVec;
+
+/// A fixed-capacity set kept as a sorted [`ArrayVec`], with binary search
+/// lookups and ordered iteration. Derefs to the sorted slice.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(C)]
+pub struct ArraySet<T: Copy, const N: usize> {
+    items: ArrayVec<T, N>,
+}
+
+impl<T: Copy + Ord, const N: usize> ArraySet<T, N> {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self { items: ArrayVec::new() }
+    }
+
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline(always)]
+    pub const fn is_full(&self) -> bool {
+        self.items.is_full()
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Returns whether `value` was newly inserted. Panics if full and
+    /// `value` is new.
+    #[inline]
+    pub fn insert(&mut self, value: T) -> bool {
+        let Ok(inserted) = self.try_insert(value) else {
+            panic!("insert capacity overflow");
+        };
+        inserted
+    }
+
+    /// Like [`Self::insert`], returning the value back if the capacity is
+    /// exhausted.
+    #[inline]
+    pub fn try_insert(&mut self, value: T) -> Result<bool, T> {
+        let Err(pos) = self.items.binary_search(&value) else {
+            return Ok(false);
+        };
+        if self.items.try_push(value).is_some() {
+            return Err(value);
+        }
+        self.items.as_mut_slice()[pos..].rotate_right(1);
+        Ok(true)
+    }
+
+    /// Returns whether `value` was present.
+    #[inline]
+    pub fn remove(&mut self, value: &T) -> bool {
+        let Ok(pos) = self.items.binary_search(value) else {
+            return false;
+        };
+        self.items.as_mut_slice()[pos..].rotate_left(1);
+        self.items.pop();
+        true
+    }
+
+    #[inline]
+    pub fn contains(&self, value: &T) -> bool {
+        self.items.binary_search(value).is_ok()
+    }
+
+    #[inline(always)]
+    pub fn as_slice(&self) -> &[T] {
+        self.items.as_slice()
+    }
+}
+
+impl<T: Copy + Ord, const N: usize> Default for ArraySet<T, N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const N: usize> Deref for ArraySet<T, N> {
+    type Target = [T];
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        self.items.as_slice()
+    }
+}
+
+impl<T: Copy + Debug, const N: usize> core::fmt::Debug for ArraySet<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.items.iter()).finish()
+    }
+}
+
+impl<'a, T: Copy, const N: usize> IntoIterator for &'a ArraySet<T, N> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<T: Copy, const N: usize> IntoIterator for ArraySet<T, N> {
+    type Item = T;
+    type IntoIter = crate::arrayvec::IntoIter<T, N>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl<T: Copy + Ord, const N: usize> Extend<T> for ArraySet<T, N> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for t in iter {
+            self.insert(t);
+        }
+    }
+}
+
+impl<T: Copy + Ord, const N: usize> FromIterator<T> for ArraySet<T, N> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut s = Self::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl<T: TypeHash + Copy, const N: usize> TypeHash for ArraySet<T, N> {
+    const TYPE_HASH: u64 = {
+        let mut h = 0xcbf2_9ce4_8422_2325u64;
+        h = fnv1a64_str(h, "ArraySet");
+        h = hash_u64(h, T::TYPE_HASH);
+        h = hash_u64(h, N as u64);
+        h = hash_layout_of::<Self>(h);
+        h
+    };
+}
+
+// Both formats are the `ArrayVec` ones; decoding re-sorts and drops
+// duplicates, so any sequence within capacity is accepted.
+mod serde_impl {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::ArraySet;
+    use crate::ArrayVec;
+
+    impl<T, const N: usize> Serialize for ArraySet<T, N>
+    where
+        T: Copy + Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            self.items.serialize(serializer)
+        }
+    }
+
+    impl<'de, T, const N: usize> Deserialize<'de> for ArraySet<T, N>
+    where
+        T: Copy + Ord + Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            ArrayVec::<T, N>::deserialize(deserializer).map(|items| items.into_iter().collect())
+        }
+    }
+}
+
+#[cfg(feature = "wincode")]
+mod wincode_impl {
+    use std::mem::MaybeUninit;
+
+    use wincode::{
+        config::ConfigCore,
+        io::{Reader, Writer},
+    };
+
+    use super::ArraySet;
+    use crate::ArrayVec;
+
+    unsafe impl<T, C, const N: usize> wincode::SchemaWrite<C> for ArraySet<T, N>
+    where
+        C: ConfigCore,
+        T: Copy + wincode::SchemaWrite<C, Src = T>,
+    {
+        type Src = Self;
+
+        #[inline]
+        fn size_of(src: &Self::Src) -> wincode::WriteResult<usize> {
+            <ArrayVec<T, N> as wincode::SchemaWrite<C>>::size_of(&src.items)
+        }
+
+        #[inline]
+        fn write(writer: impl Writer, src: &Self::Src) -> wincode::WriteResult<()> {
+            <ArrayVec<T, N> as wincode::SchemaWrite<C>>::write(writer, &src.items)
+        }
+    }
+
+    unsafe impl<'de, T, C, const N: usize> wincode::SchemaRead<'de, C> for ArraySet<T, N>
+    where
+        C: ConfigCore,
+        T: Copy + Ord + wincode::SchemaRead<'de, C, Dst = T>,
+    {
+        type Dst = Self;
+
+        fn read(
+            reader: impl Reader<'de>,
+            dst: &mut MaybeUninit<Self::Dst>,
+        ) -> wincode::ReadResult<()> {
+            let items = <ArrayVec<T, N> as wincode::SchemaRead<'de, C>>::get(reader)?;
+            dst.write(items.into_iter().collect());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_sorted_and_unique() {
+        let mut s: ArraySet<u32, 4> = ArraySet::new();
+        assert!(s.insert(3));
+        assert!(s.insert(1));
+        assert!(!s.insert(3));
+        assert!(s.insert(2));
+        assert!(s.insert(0));
+        assert_eq!(s.as_slice(), [0, 1, 2, 3]);
+        assert_eq!(s.try_insert(9), Err(9));
+        assert_eq!(s.try_insert(2), Ok(false));
+        assert!(s.remove(&1));
+        assert!(!s.remove(&1));
+        assert!(s.contains(&2) && !s.contains(&1));
+        assert_eq!(s.first(), Some(&0));
+        assert_eq!(s.into_iter().collect::<Vec<_>>(), [0, 2, 3]);
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let s: ArraySet<u32, 4> = [4, 2, 9].into_iter().collect();
+        let json = serde_json::to_string(&s).unwrap();
+        assert_eq!(json, "[2,4,9]");
+        assert_eq!(serde_json::from_str::<ArraySet<u32, 4>>("[9,2,4,2]").unwrap(), s);
+        assert!(serde_json::from_str::<ArraySet<u32, 2>>(&json).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "wincode"))]
+mod wincode_tests {
+    use super::*;
+
+    #[test]
+    fn same_wire_format_as_arrayvec() {
+        let s: ArraySet<u32, 4> = [4, 2, 9].into_iter().collect();
+        let bytes = wincode::serialize(&s).unwrap();
+        let v: ArrayVec<u32, 4> = [2, 4, 9].into_iter().collect();
+        assert_eq!(bytes, wincode::serialize(&v).unwrap());
+        assert_eq!(wincode::deserialize::<ArraySet<u32, 4>>(bytes.as_slice()).unwrap(), s);
+    }
+}
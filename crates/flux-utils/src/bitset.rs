@@ -0,0 +1,359 @@
+use std::fmt::Debug;
+
+use type_hash::{TypeHash, fnv1a64_str, hash_layout_of, hash_u64};
+
+/// A fixed-capacity set of the integers below `64 * N`, stored as `N`
+/// 64-bit words.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct BitSet<const N: usize> {
+    words: [u64; N],
+}
+
+impl<const N: usize> BitSet<N> {
+    /// Number of representable elements.
+    pub const BITS: usize = 64 * N;
+
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self { words: [0; N] }
+    }
+
+    #[inline(always)]
+    pub const fn capacity(&self) -> usize {
+        Self::BITS
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    #[inline(always)]
+    pub fn clear(&mut self) {
+        self.words = [0; N];
+    }
+
+    /// Panics if `i` is out of range.
+    #[inline(always)]
+    fn locate(i: usize) -> (usize, u64) {
+        assert!(i < Self::BITS, "bit {i} out of range for BitSet of {} bits", Self::BITS);
+        (i / 64, 1 << (i % 64))
+    }
+
+    /// Returns whether `i` was newly inserted. Panics if `i` is out of range.
+    #[inline]
+    pub fn insert(&mut self, i: usize) -> bool {
+        let (word, mask) = Self::locate(i);
+        let was_set = self.words[word] & mask != 0;
+        self.words[word] |= mask;
+        !was_set
+    }
+
+    /// Returns whether `i` was present.
+    #[inline]
+    pub fn remove(&mut self, i: usize) -> bool {
+        if i >= Self::BITS {
+            return false;
+        }
+        let (word, mask) = Self::locate(i);
+        let was_set = self.words[word] & mask != 0;
+        self.words[word] &= !mask;
+        was_set
+    }
+
+    #[inline]
+    pub fn contains(&self, i: usize) -> bool {
+        i < Self::BITS && {
+            let (word, mask) = Self::locate(i);
+            self.words[word] & mask != 0
+        }
+    }
+
+    /// Smallest element.
+    #[inline]
+    pub fn first(&self) -> Option<usize> {
+        self.iter().next()
+    }
+
+    /// Elements in ascending order.
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, N> {
+        Iter { words: &self.words, word: 0, bits: self.words.first().copied().unwrap_or(0) }
+    }
+
+    #[inline]
+    pub fn union_with(&mut self, other: &Self) {
+        for (w, o) in self.words.iter_mut().zip(other.words) {
+            *w |= o;
+        }
+    }
+
+    #[inline]
+    pub fn intersect_with(&mut self, other: &Self) {
+        for (w, o) in self.words.iter_mut().zip(other.words) {
+            *w &= o;
+        }
+    }
+
+    #[inline]
+    pub fn difference_with(&mut self, other: &Self) {
+        for (w, o) in self.words.iter_mut().zip(other.words) {
+            *w &= !o;
+        }
+    }
+
+    #[inline]
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.words.iter().zip(other.words).all(|(&w, o)| w & !o == 0)
+    }
+
+    #[inline(always)]
+    pub const fn as_words(&self) -> &[u64; N] {
+        &self.words
+    }
+
+    #[inline(always)]
+    pub const fn from_words(words: [u64; N]) -> Self {
+        Self { words }
+    }
+}
+
+impl<const N: usize> Default for BitSet<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Debug for BitSet<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+pub struct Iter<'a, const N: usize> {
+    words: &'a [u64; N],
+    word: usize,
+    bits: u64,
+}
+
+impl<const N: usize> Iterator for Iter<'_, N> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        while self.bits == 0 {
+            self.word += 1;
+            if self.word >= N {
+                return None;
+            }
+            self.bits = self.words[self.word];
+        }
+        let bit = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some(self.word * 64 + bit)
+    }
+}
+
+impl<'a, const N: usize> IntoIterator for &'a BitSet<N> {
+    type Item = usize;
+    type IntoIter = Iter<'a, N>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<const N: usize> Extend<usize> for BitSet<N> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for i in iter {
+            self.insert(i);
+        }
+    }
+}
+
+impl<const N: usize> FromIterator<usize> for BitSet<N> {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut s = Self::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl<const N: usize> TypeHash for BitSet<N> {
+    const TYPE_HASH: u64 = {
+        let mut h = 0xcbf2_9ce4_8422_2325u64;
+        h = fnv1a64_str(h, "BitSet");
+        h = hash_u64(h, N as u64);
+        h = hash_layout_of::<Self>(h);
+        h
+    };
+}
+
+mod serde_impl {
+    use core::fmt;
+
+    use serde::{
+        Deserialize, Deserializer, Serialize, Serializer,
+        de::{Error as DeError, SeqAccess, Visitor},
+    };
+
+    use super::BitSet;
+
+    /// Serialized as the sequence of its elements.
+    impl<const N: usize> Serialize for BitSet<N> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.collect_seq(self.iter())
+        }
+    }
+
+    impl<'de, const N: usize> Deserialize<'de> for BitSet<N> {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            struct BitSetVisitor<const N: usize>;
+
+            impl<'de, const N: usize> Visitor<'de> for BitSetVisitor<N> {
+                type Value = BitSet<N>;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "a sequence of integers below {}", BitSet::<N>::BITS)
+                }
+
+                fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                where
+                    A: SeqAccess<'de>,
+                {
+                    let mut out = BitSet::<N>::new();
+                    while let Some(i) = seq.next_element::<usize>()? {
+                        if i >= BitSet::<N>::BITS {
+                            return Err(A::Error::custom("BitSet capacity exceeded"));
+                        }
+                        out.insert(i);
+                    }
+                    Ok(out)
+                }
+            }
+
+            deserializer.deserialize_seq(BitSetVisitor::<N>)
+        }
+    }
+}
+
+// Fixed size on the wire: the words as an array.
+#[cfg(feature = "wincode")]
+mod wincode_impl {
+    use std::mem::MaybeUninit;
+
+    use wincode::{
+        config::ConfigCore,
+        io::{Reader, Writer},
+    };
+
+    use super::BitSet;
+
+    unsafe impl<C: ConfigCore, const N: usize> wincode::SchemaWrite<C> for BitSet<N> {
+        type Src = Self;
+
+        #[inline]
+        fn size_of(src: &Self::Src) -> wincode::WriteResult<usize> {
+            <[u64; N] as wincode::SchemaWrite<C>>::size_of(&src.words)
+        }
+
+        #[inline]
+        fn write(writer: impl Writer, src: &Self::Src) -> wincode::WriteResult<()> {
+            <[u64; N] as wincode::SchemaWrite<C>>::write(writer, &src.words)
+        }
+    }
+
+    unsafe impl<'de, C: ConfigCore, const N: usize> wincode::SchemaRead<'de, C> for BitSet<N> {
+        type Dst = Self;
+
+        fn read(
+            reader: impl Reader<'de>,
+            dst: &mut MaybeUninit<Self::Dst>,
+        ) -> wincode::ReadResult<()> {
+            let words = <[u64; N] as wincode::SchemaRead<'de, C>>::get(reader)?;
+            dst.write(Self { words });
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_remove_iter() {
+        let mut s: BitSet<2> = BitSet::new();
+        assert_eq!(s.capacity(), 128);
+        assert!(s.insert(3));
+        assert!(!s.insert(3));
+        s.extend([64, 127, 0]);
+        assert_eq!(s.iter().collect::<Vec<_>>(), [0, 3, 64, 127]);
+        assert_eq!((s.len(), s.first()), (4, Some(0)));
+        assert!(s.remove(0));
+        assert!(!s.remove(0));
+        assert!(!s.remove(500));
+        assert!(!s.contains(500));
+        assert!(s.contains(127));
+    }
+
+    #[test]
+    #[should_panic(expected = "bit 128 out of range")]
+    fn insert_out_of_range() {
+        BitSet::<2>::new().insert(128);
+    }
+
+    #[test]
+    fn set_operations() {
+        let left: BitSet<1> = [1, 2, 3].into_iter().collect();
+        let right: BitSet<1> = [2, 3, 4].into_iter().collect();
+        let mut union = left;
+        union.union_with(&right);
+        let mut inter = left;
+        inter.intersect_with(&right);
+        let mut diff = left;
+        diff.difference_with(&right);
+        assert_eq!(union.iter().collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(inter.iter().collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(diff.iter().collect::<Vec<_>>(), [1]);
+        assert!(inter.is_subset(&left) && !left.is_subset(&right));
+    }
+
+    #[test]
+    fn serde_roundtrip() {
+        let s: BitSet<2> = [5, 70].into_iter().collect();
+        let json = serde_json::to_string(&s).unwrap();
+        assert_eq!(json, "[5,70]");
+        assert_eq!(serde_json::from_str::<BitSet<2>>(&json).unwrap(), s);
+        assert!(serde_json::from_str::<BitSet<1>>(&json).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "wincode"))]
+mod wincode_tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let s: BitSet<2> = [5, 70].into_iter().collect();
+        let bytes = wincode::serialize(&s).unwrap();
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(wincode::deserialize::<BitSet<2>>(bytes.as_slice()).unwrap(), s);
+    }
+}
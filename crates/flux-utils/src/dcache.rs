@@ -4,16 +4,22 @@ use std::{
     mem::size_of_val,
     sync::{
         Arc,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicUsize, Ordering, compiler_fence},
     },
 };
 
+use type_hash::{TypeHash, fnv1a64_str, hash_layout_of};
+
 #[derive(Debug, Clone, Copy)]
 pub struct DCacheRef {
     pub offset: usize,
     pub len: usize,
 }
 
+/// Set in the offset of refs into the overflow region of a [`DCachePtr`].
+/// Offsets are positions in a ring that only grow, they never get near it.
+const OVERFLOW: usize = 1 << (usize::BITS - 1);
+
 impl DCacheRef {
     pub const NONE: Self = Self { offset: 0, len: 0 };
 
@@ -21,6 +27,22 @@ impl DCacheRef {
     pub fn is_none(self) -> bool {
         self.len == 0
     }
+
+    /// Whether the payload lives in the overflow region, see
+    /// [`DCachePtr::write_payload`].
+    #[inline]
+    pub fn is_overflow(self) -> bool {
+        self.offset & OVERFLOW != 0
+    }
+}
+
+impl TypeHash for DCacheRef {
+    const TYPE_HASH: u64 = {
+        let mut h = 0xcbf2_9ce4_8422_2325u64;
+        h = fnv1a64_str(h, "DCacheRef");
+        h = hash_layout_of::<Self>(h);
+        h
+    };
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -35,6 +57,8 @@ pub enum DCacheError {
     SpedPast,
     #[error("reserving zero")]
     ReserveZero,
+    #[error("payload lives in an overflow region this dcache doesn't have")]
+    NoOverflowRegion,
 }
 
 /// Ring buffer data storage inspired by Firedancer's `dcache`. Provides
@@ -97,6 +121,25 @@ impl DCache {
         Ok(r)
     }
 
+    /// Writes the concatenation of `parts` as one payload, e.g. a header and
+    /// a body coming from different places, without staging it elsewhere.
+    #[inline]
+    pub fn write_vectored(&self, parts: &[&[u8]]) -> Result<DCacheRef, DCacheError> {
+        let mut reservation = self.reserve_writer(parts.iter().map(|p| p.len()).sum())?;
+        for part in parts {
+            reservation.write(part);
+        }
+        Ok(reservation.commit())
+    }
+
+    /// Reserves `len` bytes to be filled incrementally through the returned
+    /// handle, see [`DCacheReservation`].
+    #[inline]
+    pub fn reserve_writer(&self, len: usize) -> Result<DCacheReservation<'_>, DCacheError> {
+        let dref = self.reserve(len)?;
+        Ok(DCacheReservation { dcache: self, dref, written: 0 })
+    }
+
     /// Reserves a slot of `len` bytes and returns a [`DCacheRef`].
     #[inline]
     pub fn reserve(&self, len: usize) -> Result<DCacheRef, DCacheError> {
@@ -166,6 +209,29 @@ impl DCache {
         Ok(unsafe { f(std::slice::from_raw_parts(base.add(offset_ix), r.len)) })
     }
 
+    /// Like [`Self::map`], but fails with [`DCacheError::SpedPast`] if a
+    /// writer may have overwritten the region while `f` ran. Unlike the
+    /// queue epoch check this holds for payloads of any size.
+    #[inline]
+    pub fn map_checked<T, F>(&self, r: DCacheRef, f: F) -> Result<T, DCacheError>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        let out = self.map(r, f)?;
+        if self.overrun(r) {
+            return Err(DCacheError::SpedPast);
+        }
+        Ok(out)
+    }
+
+    /// Whether reservations reached the region of `r` again. Writers only
+    /// write inside their reservations, so the region is intact until then.
+    #[inline]
+    fn overrun(&self, r: DCacheRef) -> bool {
+        compiler_fence(Ordering::AcqRel);
+        self.reserved.load(Ordering::Acquire) > (r.offset & !OVERFLOW) + self.capacity()
+    }
+
     #[inline]
     pub fn capacity(&self) -> usize {
         size_of_val(&self.data)
@@ -188,8 +254,129 @@ impl DCache {
     }
 }
 
+/// Reserved dcache bytes that are filled front to back in any number of
+/// steps, e.g. as a socket becomes readable, and then published by passing
+/// [`Self::commit`]'s ref on (e.g. to `produce_with_dref`).
+///
+/// Nothing is published until then: dropping the handle or calling
+/// [`Self::abort`] leaves the reserved bytes unused. Writing past the
+/// reservation is a bug, caught by a debug assertion and truncated in
+/// release builds.
+#[must_use = "reserved bytes are only published through `commit`"]
+pub struct DCacheReservation<'a> {
+    dcache: &'a DCache,
+    dref: DCacheRef,
+    written: usize,
+}
+
+impl DCacheReservation<'_> {
+    /// Number of reserved bytes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.dref.len
+    }
+
+    /// Always false, zero byte reservations are refused.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.dref.len == 0
+    }
+
+    /// Number of bytes filled so far.
+    #[inline]
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Number of bytes left to fill.
+    #[inline]
+    pub fn remaining(&self) -> usize {
+        self.dref.len - self.written
+    }
+
+    /// Whether every reserved byte was filled.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.written == self.dref.len
+    }
+
+    /// Appends `bytes`.
+    #[inline]
+    pub fn write(&mut self, bytes: &[u8]) {
+        let remaining = self.remaining();
+        debug_assert!(
+            bytes.len() <= remaining,
+            "writing {} bytes with {remaining} of {} reserved left",
+            bytes.len(),
+            self.dref.len
+        );
+        let n = bytes.len().min(remaining);
+        self.unfilled()[..n].copy_from_slice(&bytes[..n]);
+        self.written += n;
+    }
+
+    /// Calls `f` with the unfilled rest of the reservation and advances by
+    /// the number of bytes it reports, e.g. `|buf| stream.read(buf)`.
+    #[inline]
+    pub fn fill<F, E>(&mut self, f: F) -> Result<usize, E>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, E>,
+    {
+        let remaining = self.remaining();
+        let n = f(self.unfilled())?;
+        debug_assert!(
+            n <= remaining,
+            "filled {n} bytes with {remaining} of {} reserved left",
+            self.dref.len
+        );
+        self.written += n.min(remaining);
+        Ok(n)
+    }
+
+    #[inline]
+    fn unfilled(&mut self) -> &mut [u8] {
+        let base = self.dcache.data.get().cast::<u8>();
+        let offset_ix = self.dref.offset & (self.dcache.capacity() - 1);
+        unsafe {
+            std::slice::from_raw_parts_mut(base.add(offset_ix + self.written), self.remaining())
+        }
+    }
+
+    /// Ref to the bytes filled so far, to be published. A partially filled
+    /// reservation yields a shorter payload.
+    #[inline]
+    pub fn commit(self) -> DCacheRef {
+        DCacheRef { offset: self.dref.offset, len: self.written }
+    }
+
+    /// Gives up on the reservation, the same as dropping it.
+    #[inline]
+    pub fn abort(self) {}
+}
+
+impl std::fmt::Debug for DCacheReservation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DCacheReservation")
+            .field("dref", &self.dref)
+            .field("written", &self.written)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle to a [`DCache`], optionally paired with an overflow region for the
+/// rare payloads larger than the `mtu` the dcache was sized for.
+///
+/// Payloads up to the `mtu` can rely on the queue epoch check for loss
+/// detection, since the dcache holds a queue's worth of them. Larger ones go
+/// to the overflow region, where [`DCache::map_checked`] detects loss
+/// instead, so they neither lap the dcache early nor force it to be sized
+/// for the worst case.
 #[derive(Clone, Copy)]
-pub struct DCachePtr(*const DCache);
+pub struct DCachePtr {
+    dcache: *const DCache,
+    overflow: Option<*const DCache>,
+    mtu: usize,
+}
 
 unsafe impl Send for DCachePtr {}
 unsafe impl Sync for DCachePtr {}
@@ -197,7 +384,71 @@ unsafe impl Sync for DCachePtr {}
 impl DCachePtr {
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn from_raw(ptr: *const DCache) -> Self {
-        Self(ptr)
+        Self { dcache: ptr, overflow: None, mtu: usize::MAX }
+    }
+
+    /// Sends payloads larger than `mtu` to `overflow`.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn with_overflow(self, overflow: *const DCache, mtu: usize) -> Self {
+        Self { overflow: Some(overflow), mtu, ..self }
+    }
+
+    #[inline]
+    pub fn overflow(&self) -> Option<&DCache> {
+        self.overflow.map(|p| unsafe { &*p })
+    }
+
+    /// Writes a `len` byte payload into the dcache, or into the overflow
+    /// region if it is larger than the `mtu` and there is one.
+    #[inline]
+    pub fn write_payload<F>(&self, len: usize, f: F) -> Result<DCacheRef, DCacheError>
+    where
+        F: FnOnce(&mut [u8]),
+    {
+        match self.overflow() {
+            Some(overflow) if len > self.mtu => {
+                let r = overflow.write(len, f)?;
+                Ok(DCacheRef { offset: r.offset | OVERFLOW, len: r.len })
+            }
+            _ => self.write(len, f),
+        }
+    }
+
+    /// Like [`Self::write_payload`] for the concatenation of `parts`.
+    #[inline]
+    pub fn write_payload_vectored(&self, parts: &[&[u8]]) -> Result<DCacheRef, DCacheError> {
+        let mut reservation = self.reserve_payload(parts.iter().map(|p| p.len()).sum())?;
+        for part in parts {
+            reservation.write(part);
+        }
+        Ok(reservation.commit())
+    }
+
+    /// Like [`DCache::reserve_writer`], in the overflow region if `len` is
+    /// larger than the `mtu` and there is one.
+    #[inline]
+    pub fn reserve_payload(&self, len: usize) -> Result<DCacheReservation<'_>, DCacheError> {
+        match self.overflow() {
+            Some(overflow) if len > self.mtu => {
+                let mut reservation = overflow.reserve_writer(len)?;
+                reservation.dref.offset |= OVERFLOW;
+                Ok(reservation)
+            }
+            _ => self.reserve_writer(len),
+        }
+    }
+
+    /// Applies `f` to a payload written by [`Self::write_payload`]. Payloads
+    /// in the overflow region are checked for being overwritten meanwhile.
+    #[inline]
+    pub fn map_payload<T, F>(&self, r: DCacheRef, f: F) -> Result<T, DCacheError>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        if r.is_overflow() {
+            return self.overflow().ok_or(DCacheError::NoOverflowRegion)?.map_checked(r, f);
+        }
+        self.map(r, f)
     }
 }
 
@@ -205,13 +456,13 @@ impl std::ops::Deref for DCachePtr {
     type Target = DCache;
 
     fn deref(&self) -> &DCache {
-        unsafe { &*self.0 }
+        unsafe { &*self.dcache }
     }
 }
 
 impl std::fmt::Debug for DCachePtr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "DCachePtr({:p})", self.0)
+        write!(f, "DCachePtr({:p}, overflow: {:?})", self.dcache, self.overflow)
     }
 }
 
@@ -351,6 +602,94 @@ mod tests {
         }
     }
 
+    #[test]
+    fn map_checked_detects_laps() {
+        let dc = DCache::new(256);
+        let r = dc.write(100, |s| s.fill(1)).unwrap();
+        assert_eq!(dc.map_checked(r, <[u8]>::len).unwrap(), 100);
+        let _ = dc.write(100, |s| s.fill(2)).unwrap();
+        assert!(dc.map_checked(r, <[u8]>::len).is_ok());
+        // The third write wraps onto the region of the first.
+        let _ = dc.write(100, |s| s.fill(3)).unwrap();
+        assert!(matches!(dc.map_checked(r, |_| ()), Err(DCacheError::SpedPast)));
+    }
+
+    #[test]
+    fn oversized_payloads_go_to_overflow() {
+        let dc = DCache::new(256);
+        let overflow = DCache::new(4096);
+        let ptr = unsafe {
+            DCachePtr::from_raw(Arc::as_ptr(&dc)).with_overflow(Arc::as_ptr(&overflow), 64)
+        };
+        let small = ptr.write_payload(64, |s| s.fill(1)).unwrap();
+        let large = ptr.write_payload(1000, |s| s.fill(2)).unwrap();
+        assert!(!small.is_overflow());
+        assert!(large.is_overflow());
+        assert_eq!(ptr.map_payload(small, <[u8]>::to_vec).unwrap(), [1; 64]);
+        assert_eq!(ptr.map_payload(large, <[u8]>::to_vec).unwrap(), [2; 1000]);
+
+        let without = unsafe { DCachePtr::from_raw(Arc::as_ptr(&dc)) };
+        assert!(!without.write_payload(1000, |_| ()).is_ok_and(DCacheRef::is_overflow));
+        assert!(matches!(without.map_payload(large, |_| ()), Err(DCacheError::NoOverflowRegion)));
+    }
+
+    #[test]
+    fn vectored_writes_concatenate_parts() {
+        let dc = DCache::new(256);
+        let r = dc.write_vectored(&[b"head", b"", b"body"]).unwrap();
+        assert_eq!(dc.map(r, <[u8]>::to_vec).unwrap(), b"headbody");
+        assert!(matches!(dc.write_vectored(&[b"", b""]), Err(DCacheError::ReserveZero)));
+
+        let overflow = DCache::new(4096);
+        let ptr = unsafe {
+            DCachePtr::from_raw(Arc::as_ptr(&dc)).with_overflow(Arc::as_ptr(&overflow), 64)
+        };
+        let large = ptr.write_payload_vectored(&[&[1; 10], &[2; 90]]).unwrap();
+        assert!(large.is_overflow());
+        let expected: Vec<u8> = [[1; 10].as_slice(), &[2; 90]].concat();
+        assert_eq!(ptr.map_payload(large, <[u8]>::to_vec).unwrap(), expected);
+    }
+
+    #[test]
+    fn reservations_fill_incrementally() {
+        let dc = DCache::new(256);
+        let mut source: &[u8] = b"0123456789";
+        let mut reservation = dc.reserve_writer(12).unwrap();
+        reservation.write(b"<");
+        while reservation.remaining() > 1 {
+            // A reader handing out at most 3 bytes at a time, like a socket.
+            let n = reservation
+                .fill(|buf| {
+                    let len = buf.len().min(3);
+                    std::io::Read::read(&mut source, &mut buf[..len])
+                })
+                .unwrap();
+            assert!(n <= 3);
+        }
+        reservation.write(b">");
+        assert!(reservation.is_complete());
+        let r = reservation.commit();
+        assert_eq!(dc.map(r, <[u8]>::to_vec).unwrap(), b"<0123456789>");
+
+        // Partial commits publish what was written, aborts publish nothing
+        // but still consume the space.
+        let mut partial = dc.reserve_writer(64).unwrap();
+        partial.write(b"abc");
+        let r = partial.commit();
+        assert_eq!(dc.map(r, <[u8]>::to_vec).unwrap(), b"abc");
+        dc.reserve_writer(64).unwrap().abort();
+        let next = dc.reserve(1).unwrap();
+        assert_eq!(next.offset, r.offset + 128);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "writing 5 bytes with 4 of 4 reserved left")]
+    fn writing_past_a_reservation_panics() {
+        let dc = DCache::new(256);
+        dc.reserve_writer(4).unwrap().write(b"01234");
+    }
+
     #[test]
     fn spsc() {
         const N: usize = 16;
@@ -67,3 +67,65 @@ pub fn shmem_dir_arrays_with_base<D: AsRef<Path>, S: AsRef<Path>>(
 ) -> PathBuf {
     shmem_dir_with_base(base_dir, app_name).join("arrays")
 }
+
+pub fn shmem_dir_maps<S: AsRef<Path>>(app_name: S) -> PathBuf {
+    shmem_dir(app_name).join("maps")
+}
+
+pub fn shmem_dir_maps_with_base<D: AsRef<Path>, S: AsRef<Path>>(
+    base_dir: D,
+    app_name: S,
+) -> PathBuf {
+    shmem_dir_with_base(base_dir, app_name).join("maps")
+}
+
+pub fn shmem_dir_varlen<S: AsRef<Path>>(app_name: S) -> PathBuf {
+    shmem_dir(app_name).join("varlen")
+}
+
+pub fn shmem_dir_varlen_with_base<D: AsRef<Path>, S: AsRef<Path>>(
+    base_dir: D,
+    app_name: S,
+) -> PathBuf {
+    shmem_dir_with_base(base_dir, app_name).join("varlen")
+}
+
+pub fn shmem_dir_conflated<S: AsRef<Path>>(app_name: S) -> PathBuf {
+    shmem_dir(app_name).join("conflated")
+}
+
+pub fn shmem_dir_conflated_with_base<D: AsRef<Path>, S: AsRef<Path>>(
+    base_dir: D,
+    app_name: S,
+) -> PathBuf {
+    shmem_dir_with_base(base_dir, app_name).join("conflated")
+}
+
+pub fn shmem_dir_histograms<S: AsRef<Path>>(app_name: S) -> PathBuf {
+    shmem_dir(app_name).join("histograms")
+}
+
+pub fn shmem_dir_histograms_with_base<D: AsRef<Path>, S: AsRef<Path>>(
+    base_dir: D,
+    app_name: S,
+) -> PathBuf {
+    shmem_dir_with_base(base_dir, app_name).join("histograms")
+}
+
+pub fn shmem_dir_budgets<S: AsRef<Path>>(app_name: S) -> PathBuf {
+    shmem_dir(app_name).join("budgets")
+}
+
+pub fn shmem_dir_budgets_with_base<D: AsRef<Path>, S: AsRef<Path>>(
+    base_dir: D,
+    app_name: S,
+) -> PathBuf {
+    shmem_dir_with_base(base_dir, app_name).join("budgets")
+}
+
+pub fn shmem_dir_recorder_with_base<D: AsRef<Path>, S: AsRef<Path>>(
+    base_dir: D,
+    app_name: S,
+) -> PathBuf {
+    shmem_dir_with_base(base_dir, app_name).join("recorder")
+}
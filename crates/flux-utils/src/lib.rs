@@ -1,15 +1,27 @@
+mod arraydeque;
+mod arraymap;
+mod arrayset;
 mod arrayvec;
 mod assert;
+mod bitset;
 mod dcache;
 pub mod directories;
 mod namespace;
+pub mod numa;
 mod shared_vector;
 mod thread;
 mod vsync;
 
+pub use arraydeque::ArrayDeque;
+pub use arraymap::ArrayMap;
+pub use arrayset::ArraySet;
 pub use arrayvec::{ArrayStr, ArrayVec};
-pub use dcache::{DCache, DCacheError, DCachePtr, DCacheRef};
-pub use namespace::{ShortTypename, short_typename};
+pub use bitset::BitSet;
+pub use dcache::{DCache, DCacheError, DCachePtr, DCacheRef, DCacheReservation};
+pub use namespace::{SHORT_TYPENAME_CAP, ShortTypename, short_typename};
 pub use shared_vector::SharedVector;
-pub use thread::{ThreadNiceness, get_tid, thread_boot};
-pub use vsync::vsync;
+pub use thread::{
+    BootError, BootStep, BootSummary, RealtimeProfile, SchedPolicy, ThreadNiceness, get_tid,
+    parse_cpu_list, thread_boot, thread_boot_with_profile,
+};
+pub use vsync::{Pace, Pacer, vsync};
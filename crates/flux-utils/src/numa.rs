@@ -0,0 +1,14 @@
+use std::path::Path;
+
+/// NUMA node the given cpu belongs to, from
+/// `/sys/devices/system/cpu/cpu<N>/node<M>`. `None` on non-NUMA kernels or
+/// for unknown cpus.
+pub fn node_of_cpu(cpu: usize) -> Option<u16> {
+    let dir = std::fs::read_dir(format!("/sys/devices/system/cpu/cpu{cpu}")).ok()?;
+    dir.flatten().find_map(|entry| entry.file_name().to_str()?.strip_prefix("node")?.parse().ok())
+}
+
+/// Whether `node` exists on this machine.
+pub fn node_exists(node: u16) -> bool {
+    Path::new(&format!("/sys/devices/system/node/node{node}")).is_dir()
+}
@@ -1,5 +1,7 @@
+use std::{fmt, io};
+
 use core_affinity::CoreId;
-use tracing::warn;
+use tracing::{info, warn};
 
 #[derive(Clone, Copy, Debug)]
 pub enum ThreadNiceness {
@@ -22,35 +24,335 @@ impl ThreadNiceness {
     }
 }
 
+/// Real-time scheduling policy, see `sched(7)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// `SCHED_FIFO`: runs until it blocks or yields.
+    Fifo,
+    /// `SCHED_RR`: like `Fifo`, time-sliced among threads of equal priority.
+    RoundRobin,
+}
+
+/// Real-time hardening applied by [`thread_boot_with_profile`] on top of
+/// affinity and niceness. Every step is opt-in; the default profile does
+/// nothing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RealtimeProfile {
+    /// Policy and priority (1-99) for the thread.
+    pub sched: Option<(SchedPolicy, i32)>,
+    /// `mlockall(MCL_CURRENT | MCL_FUTURE)` for the process.
+    pub mlock_all: bool,
+    /// Per-thread timer slack in nanoseconds, the kernel default is 50µs.
+    pub timer_slack_ns: Option<u64>,
+    /// `PR_SET_THP_DISABLE`, so khugepaged never stalls the process
+    /// compacting memory into huge pages. Process-wide, not per thread: it
+    /// also keeps segments mapped with huge pages requested (see
+    /// `MappingOptions::huge_pages`) on small pages, in every tile of the
+    /// process.
+    pub disable_thp: bool,
+    /// Report IRQs allowed to run on the pinned core.
+    pub check_irq_affinity: bool,
+}
+
+impl RealtimeProfile {
+    /// Everything a latency-critical tile wants: `SCHED_FIFO` at `priority`,
+    /// locked memory, 1ns timer slack and the IRQ affinity check.
+    ///
+    /// THP stays enabled since disabling it is process-wide, see
+    /// [`Self::disable_thp`].
+    pub const fn hot(priority: i32) -> Self {
+        Self {
+            sched: Some((SchedPolicy::Fifo, priority)),
+            mlock_all: true,
+            timer_slack_ns: Some(1),
+            disable_thp: false,
+            check_irq_affinity: true,
+        }
+    }
+
+    pub const fn with_sched(mut self, policy: SchedPolicy, priority: i32) -> Self {
+        self.sched = Some((policy, priority));
+        self
+    }
+
+    pub const fn with_mlock_all(mut self) -> Self {
+        self.mlock_all = true;
+        self
+    }
+
+    pub const fn with_timer_slack_ns(mut self, slack_ns: u64) -> Self {
+        self.timer_slack_ns = Some(slack_ns);
+        self
+    }
+
+    /// See [`Self::disable_thp`], affects the whole process.
+    pub const fn with_thp_disabled(mut self) -> Self {
+        self.disable_thp = true;
+        self
+    }
+
+    pub const fn with_irq_affinity_check(mut self) -> Self {
+        self.check_irq_affinity = true;
+        self
+    }
+}
+
+/// A step of [`thread_boot_with_profile`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootStep {
+    Affinity,
+    Niceness,
+    Scheduler,
+    MemoryLock,
+    TimerSlack,
+    TransparentHugePages,
+    IrqAffinity,
+}
+
+impl fmt::Display for BootStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Affinity => "affinity",
+            Self::Niceness => "niceness",
+            Self::Scheduler => "scheduler",
+            Self::MemoryLock => "mlockall",
+            Self::TimerSlack => "timer slack",
+            Self::TransparentHugePages => "thp",
+            Self::IrqAffinity => "irq affinity",
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BootError {
+    #[error("missing {capability}: {source}")]
+    MissingCapability { capability: &'static str, source: io::Error },
+    #[error(transparent)]
+    Os(#[from] io::Error),
+    #[error("priority {0} out of range {1}..={2}")]
+    InvalidPriority(i32, i32, i32),
+    #[error("couldn't pin to core {0}")]
+    Pinning(usize),
+    #[error("no pinned core to check")]
+    NotPinned,
+    #[error("irqs {irqs:?} may run on core {core}")]
+    IrqsOnCore { core: usize, irqs: Vec<u32> },
+    #[error("only supported on linux")]
+    Unsupported,
+}
+
+/// Outcome of every step [`thread_boot_with_profile`] attempted, in order.
+#[derive(Debug, Default)]
+pub struct BootSummary {
+    pub steps: Vec<(BootStep, Result<(), BootError>)>,
+}
+
+impl BootSummary {
+    pub fn is_ok(&self) -> bool {
+        self.steps.iter().all(|(_, r)| r.is_ok())
+    }
+
+    pub fn outcome(&self, step: BootStep) -> Option<&Result<(), BootError>> {
+        self.steps.iter().find(|(s, _)| *s == step).map(|(_, r)| r)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = (BootStep, &BootError)> {
+        self.steps.iter().filter_map(|(s, r)| r.as_ref().err().map(|e| (*s, e)))
+    }
+
+    /// One line at info level, or at warn level if a step failed.
+    pub fn log(&self) {
+        if self.steps.is_empty() {
+            return;
+        }
+        if self.is_ok() {
+            info!(summary = %self, "thread boot");
+        } else {
+            warn!(summary = %self, "thread boot incomplete");
+        }
+    }
+
+    fn record(&mut self, step: BootStep, outcome: Result<(), BootError>) {
+        self.steps.push((step, outcome));
+    }
+}
+
+impl fmt::Display for BootSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (step, outcome)) in self.steps.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            match outcome {
+                Ok(()) => write!(f, "{step} ok")?,
+                Err(e) => write!(f, "{step} failed ({e})")?,
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(target_os = "linux")]
 const fn validate_thread_niceness(niceness: i32) {
     assert!(niceness >= -20 && niceness <= 19, "thread niceness must be between -20 and 19");
 }
 
+/// Maps `EPERM` to the capability the call needs.
 #[cfg(target_os = "linux")]
-fn set_thread_niceness(niceness: Option<ThreadNiceness>) {
-    if let Some(niceness) = niceness {
-        let niceness = niceness.value();
-        validate_thread_niceness(niceness);
-        let code = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) };
-        if code != 0 {
-            let error = std::io::Error::last_os_error();
-            warn!(niceness, %error, "couldn't set thread niceness");
+fn os_error(capability: &'static str) -> BootError {
+    let source = io::Error::last_os_error();
+    if source.raw_os_error() == Some(libc::EPERM) {
+        BootError::MissingCapability { capability, source }
+    } else {
+        BootError::Os(source)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_thread_niceness(niceness: ThreadNiceness) -> Result<(), BootError> {
+    let niceness = niceness.value();
+    validate_thread_niceness(niceness);
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) } != 0 {
+        return Err(os_error("CAP_SYS_NICE"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_scheduler(policy: SchedPolicy, priority: i32) -> Result<(), BootError> {
+    let policy = match policy {
+        SchedPolicy::Fifo => libc::SCHED_FIFO,
+        SchedPolicy::RoundRobin => libc::SCHED_RR,
+    };
+    let (min, max) =
+        unsafe { (libc::sched_get_priority_min(policy), libc::sched_get_priority_max(policy)) };
+    if !(min..=max).contains(&priority) {
+        return Err(BootError::InvalidPriority(priority, min, max));
+    }
+    let param = libc::sched_param { sched_priority: priority };
+    // Pid 0 is the calling thread.
+    if unsafe { libc::sched_setscheduler(0, policy, &raw const param) } != 0 {
+        return Err(os_error("CAP_SYS_NICE or an RLIMIT_RTPRIO"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn lock_memory() -> Result<(), BootError> {
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } != 0 {
+        let source = io::Error::last_os_error();
+        return Err(match source.raw_os_error() {
+            Some(libc::EPERM | libc::ENOMEM) => BootError::MissingCapability {
+                capability: "CAP_IPC_LOCK or a large enough RLIMIT_MEMLOCK",
+                source,
+            },
+            _ => BootError::Os(source),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_timer_slack(slack_ns: u64) -> Result<(), BootError> {
+    // 0 would reset the slack to the default instead.
+    let slack = libc::c_ulong::try_from(slack_ns.max(1)).unwrap_or(libc::c_ulong::MAX);
+    if unsafe { libc::prctl(libc::PR_SET_TIMERSLACK, slack) } != 0 {
+        return Err(BootError::Os(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn disable_thp() -> Result<(), BootError> {
+    if unsafe { libc::prctl(libc::PR_SET_THP_DISABLE, 1 as libc::c_ulong, 0, 0, 0) } != 0 {
+        return Err(BootError::Os(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Parses a `cpulist` such as `0-3,8,10-11`.
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((from, to)) => cpus.extend(from.parse::<usize>().ok()?..=to.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
         }
     }
+    Some(cpus)
+}
+
+/// IRQs whose `smp_affinity_list` includes `core`.
+#[cfg(target_os = "linux")]
+fn irqs_on_core(core: usize) -> Result<Vec<u32>, BootError> {
+    let mut irqs = Vec::new();
+    for entry in std::fs::read_dir("/proc/irq")? {
+        let path = entry?.path();
+        let Some(irq) = path.file_name().and_then(|n| n.to_str()?.parse::<u32>().ok()) else {
+            continue;
+        };
+        let Ok(list) = std::fs::read_to_string(path.join("smp_affinity_list")) else {
+            continue;
+        };
+        if parse_cpu_list(&list).is_some_and(|cpus| cpus.contains(&core)) {
+            irqs.push(irq);
+        }
+    }
+    irqs.sort_unstable();
+    Ok(irqs)
+}
+
+#[cfg(target_os = "linux")]
+fn check_irq_affinity(core: Option<usize>) -> Result<(), BootError> {
+    let core = core.ok_or(BootError::NotPinned)?;
+    let irqs = irqs_on_core(core)?;
+    if irqs.is_empty() { Ok(()) } else { Err(BootError::IrqsOnCore { core, irqs }) }
+}
+
+#[cfg(target_os = "linux")]
+fn apply_profile(summary: &mut BootSummary, core: Option<usize>, profile: &RealtimeProfile) {
+    if let Some((policy, priority)) = profile.sched {
+        summary.record(BootStep::Scheduler, set_scheduler(policy, priority));
+    }
+    if profile.mlock_all {
+        summary.record(BootStep::MemoryLock, lock_memory());
+    }
+    if let Some(slack_ns) = profile.timer_slack_ns {
+        summary.record(BootStep::TimerSlack, set_timer_slack(slack_ns));
+    }
+    if profile.disable_thp {
+        summary.record(BootStep::TransparentHugePages, disable_thp());
+    }
+    if profile.check_irq_affinity {
+        summary.record(BootStep::IrqAffinity, check_irq_affinity(core));
+    }
 }
 
 #[cfg(not(target_os = "linux"))]
-fn set_thread_niceness(niceness: Option<ThreadNiceness>) {
-    if let Some(niceness) = niceness {
-        warn!(?niceness, "thread niceness setting only supported on linux");
+fn set_thread_niceness(_niceness: ThreadNiceness) -> Result<(), BootError> {
+    Err(BootError::Unsupported)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn apply_profile(summary: &mut BootSummary, _core: Option<usize>, profile: &RealtimeProfile) {
+    let requested = [
+        (BootStep::Scheduler, profile.sched.is_some()),
+        (BootStep::MemoryLock, profile.mlock_all),
+        (BootStep::TimerSlack, profile.timer_slack_ns.is_some()),
+        (BootStep::TransparentHugePages, profile.disable_thp),
+        (BootStep::IrqAffinity, profile.check_irq_affinity),
+    ];
+    for (step, _) in requested.into_iter().filter(|(_, on)| *on) {
+        summary.record(step, Err(BootError::Unsupported));
     }
 }
 
-fn set_thread_affinity(core: usize) {
+fn set_thread_affinity(core: usize) -> Result<(), BootError> {
     if !core_affinity::set_for_current(CoreId { id: core }) {
-        warn!(?core, "couldn't set core affinity");
+        return Err(BootError::Pinning(core));
     }
+    flux_timing::Instant::cache_thread_socket();
+    Ok(())
 }
 
 #[cfg(target_os = "linux")]
@@ -63,10 +365,78 @@ pub fn get_tid() -> i64 {
     0
 }
 
-pub fn thread_boot(core: Option<usize>, niceness: Option<ThreadNiceness>) {
+pub fn thread_boot(core: Option<usize>, niceness: Option<ThreadNiceness>) -> BootSummary {
+    thread_boot_with_profile(core, niceness, &RealtimeProfile::default())
+}
+
+/// Pins and prioritises the calling thread, then applies `profile`. Failed
+/// steps don't stop the boot; they are logged in one summary line and
+/// returned.
+pub fn thread_boot_with_profile(
+    core: Option<usize>,
+    niceness: Option<ThreadNiceness>,
+    profile: &RealtimeProfile,
+) -> BootSummary {
+    let mut summary = BootSummary::default();
     if let Some(core) = core {
-        set_thread_affinity(core);
+        summary.record(BootStep::Affinity, set_thread_affinity(core));
+    }
+    if let Some(niceness) = niceness {
+        summary.record(BootStep::Niceness, set_thread_niceness(niceness));
+    }
+    apply_profile(&mut summary, core, profile);
+    summary.log();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), Some(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("a-3"), None);
     }
 
-    set_thread_niceness(niceness);
+    #[test]
+    fn default_profile_does_nothing() {
+        let summary = thread_boot(None, None);
+        assert!(summary.steps.is_empty());
+        assert!(summary.is_ok());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn every_requested_step_is_reported() {
+        // Run on a scratch thread, the steps change the calling thread.
+        std::thread::spawn(|| {
+            let profile = RealtimeProfile::default()
+                .with_timer_slack_ns(1)
+                .with_sched(SchedPolicy::RoundRobin, 1000)
+                .with_irq_affinity_check();
+            let summary = thread_boot_with_profile(None, None, &profile);
+            let steps: Vec<_> = summary.steps.iter().map(|(s, _)| *s).collect();
+            assert_eq!(steps, [BootStep::Scheduler, BootStep::TimerSlack, BootStep::IrqAffinity]);
+            assert!(summary.outcome(BootStep::TimerSlack).unwrap().is_ok());
+            assert!(matches!(
+                summary.outcome(BootStep::Scheduler),
+                Some(Err(BootError::InvalidPriority(1000, 1, 99)))
+            ));
+            assert!(matches!(
+                summary.outcome(BootStep::IrqAffinity),
+                Some(Err(BootError::NotPinned))
+            ));
+            assert_eq!(summary.failures().count(), 2);
+            assert_eq!(
+                summary.to_string(),
+                "scheduler failed (priority 1000 out of range 1..=99), timer slack ok, irq \
+                 affinity failed (no pinned core to check)"
+            );
+        })
+        .join()
+        .unwrap();
+    }
 }
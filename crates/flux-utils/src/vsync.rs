@@ -1,4 +1,4 @@
-use flux_timing::{Duration, Instant};
+use flux_timing::{Duration, Instant, advance_mock_to};
 
 #[inline(always)]
 pub fn vsync<F, R>(duration: Option<Duration>, f: F) -> R
@@ -10,7 +10,7 @@ where
             let start_t = Instant::now();
             let out = f();
             let el = start_t.elapsed();
-            if el < duration {
+            if el < duration && !advance_mock_to(start_t + duration) {
                 std::thread::sleep((duration - el).into());
             }
             out
@@ -18,3 +18,159 @@ where
         _ => f(),
     }
 }
+
+/// Outcome of waiting for a [`Pacer`] deadline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pace {
+    OnTime,
+    /// The deadline had passed by `lateness`. The whole periods missed,
+    /// `skipped`, are dropped rather than run back to back.
+    Missed {
+        lateness: Duration,
+        skipped: u64,
+    },
+}
+
+/// Paces a loop to a fixed period against absolute deadlines, so the loop
+/// does not drift by the time spent working or oversleeping.
+///
+/// Waits sleep until `spin_margin` before the deadline, which covers the
+/// scheduler's wakeup slop, and spin on [`Instant`] for the rest. Under a
+/// mocked clock they advance the mock instead, see
+/// [`advance_mock_to`](flux_timing::advance_mock_to).
+#[derive(Clone, Copy, Debug)]
+pub struct Pacer {
+    period: Duration,
+    spin_margin: Duration,
+    next: Instant,
+}
+
+impl Pacer {
+    /// First deadline one `period` from now.
+    pub fn new(period: Duration) -> Self {
+        assert!(period != Duration(0), "pacing period must not be zero");
+        Self { period, spin_margin: Duration::from_micros(100), next: Instant::now() + period }
+    }
+
+    /// Spin instead of sleeping for the last `margin` before each deadline.
+    pub const fn with_spin_margin(mut self, margin: Duration) -> Self {
+        self.spin_margin = margin;
+        self
+    }
+
+    #[inline]
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+
+    #[inline]
+    pub const fn next_deadline(&self) -> Instant {
+        self.next
+    }
+
+    /// Restarts the schedule with the next deadline one period from now.
+    pub fn reset(&mut self) {
+        self.next = Instant::now() + self.period;
+    }
+
+    /// Runs `f`, then waits for the end of the period.
+    #[inline]
+    pub fn pace<F, R>(&mut self, f: F) -> (R, Pace)
+    where
+        F: FnOnce() -> R,
+    {
+        let out = f();
+        (out, self.wait())
+    }
+
+    /// Waits for the next deadline and schedules the one after it, or
+    /// returns right away if it already passed.
+    pub fn wait(&mut self) -> Pace {
+        let deadline = self.next;
+        let now = Instant::now();
+        if now > deadline {
+            let lateness = now - deadline;
+            let skipped = lateness.0 / self.period.0;
+            self.next = deadline + self.period * (skipped + 1);
+            return Pace::Missed { lateness, skipped };
+        }
+        self.next = deadline + self.period;
+        if advance_mock_to(deadline) {
+            return Pace::OnTime;
+        }
+        let remaining = deadline - now;
+        if remaining > self.spin_margin {
+            sleep_for((remaining - self.spin_margin).into());
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+        Pace::OnTime
+    }
+}
+
+/// Sleeps against an absolute `CLOCK_MONOTONIC` target, so interrupted
+/// sleeps resume without stretching the total.
+#[cfg(target_os = "linux")]
+fn sleep_for(duration: std::time::Duration) {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &raw mut ts) };
+    let nanos = ts.tv_nsec as u64 + u64::from(duration.subsec_nanos());
+    ts.tv_sec += (duration.as_secs() + nanos / 1_000_000_000) as libc::time_t;
+    ts.tv_nsec = (nanos % 1_000_000_000) as libc::c_long;
+    while unsafe {
+        libc::clock_nanosleep(
+            libc::CLOCK_MONOTONIC,
+            libc::TIMER_ABSTIME,
+            &raw const ts,
+            std::ptr::null_mut(),
+        )
+    } == libc::EINTR
+    {}
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sleep_for(duration: std::time::Duration) {
+    std::thread::sleep(duration);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadlines_do_not_drift() {
+        let period = Duration::from_millis(1);
+        let mut pacer = Pacer::new(period);
+        let first = pacer.next_deadline();
+        let start = Instant::now();
+        let mut periods = 0;
+        for _ in 0..20 {
+            // Work that a relative sleep would add to every period.
+            let ((), pace) =
+                pacer.pace(|| std::thread::sleep(std::time::Duration::from_micros(200)));
+            periods += match pace {
+                Pace::OnTime => 1,
+                Pace::Missed { skipped, .. } => skipped + 1,
+            };
+        }
+        assert_eq!(pacer.next_deadline(), first + period * periods);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= period * (periods - 1), "{elapsed}");
+    }
+
+    #[test]
+    fn missed_periods_are_skipped() {
+        let period = Duration::from_millis(1);
+        let mut pacer = Pacer::new(period);
+        let first = pacer.next_deadline();
+        let ((), pace) = pacer.pace(|| std::thread::sleep(std::time::Duration::from_micros(3500)));
+        let Pace::Missed { lateness, skipped } = pace else {
+            panic!("expected a miss, got {pace:?}")
+        };
+        assert!(lateness >= Duration::from_micros(2500), "{lateness}");
+        assert!(skipped >= 2);
+        // Back on the original grid.
+        assert_eq!(pacer.next_deadline(), first + period * (skipped + 1));
+    }
+}
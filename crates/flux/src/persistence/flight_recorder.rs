@@ -0,0 +1,477 @@
+use std::{
+    cell::RefCell,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex, Once,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+};
+
+use flux_communication::{
+    QueueError, ShmemData, TooLargeError, cleanup_flink, is_pid_alive, memfd,
+    queue::{VarlenConsumer, VarlenQueue},
+};
+use flux_timing::{InternalMessage, Nanos};
+use flux_utils::{directories::shmem_dir_recorder_with_base, short_typename};
+use serde::{Deserialize, Serialize};
+use type_hash_derive::TypeHash;
+
+use crate::{
+    persistence::Persistable,
+    spine::{DCacheRead, FluxSpine, SpineAdapter, SpineConsumer, SpineDCacheConsumer},
+    tile::{Tile, TileName},
+};
+
+/// Ring size of a recorded queue unless set with `with_capacity`.
+pub const DEFAULT_RECORDER_CAPACITY: usize = 16 << 20;
+/// How far back a dump reaches unless set with `with_window`.
+pub const DEFAULT_RECORDER_WINDOW: Nanos = Nanos::from_mins(5);
+const DUMP_FORMAT_UTC: &str = "%Y-%m-%d_%H:%M:%S_utc";
+
+/// State shared by every process recording for an app.
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct FlightRecorderControl {
+    frozen: AtomicBool,
+    last_dump: AtomicU64,
+}
+
+/// Fixed part of a ring record. The record payload is the raw
+/// `InternalMessage<T>` followed by its dcache payload, if any.
+#[derive(Clone, Copy, Debug, TypeHash)]
+#[repr(C)]
+pub struct FlightEntry {
+    publish_t: Nanos,
+    message_len: u32,
+}
+
+/// One recorded message as stored in a dump.
+#[allow(clippy::unsafe_derive_deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FlightRecord {
+    /// Short type name of the queue's message type.
+    pub queue: String,
+    pub publish_t: Nanos,
+    /// Raw bytes of the `InternalMessage<T>`.
+    pub message: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl FlightRecord {
+    /// The recorded message, if it was recorded from the queue of `T`.
+    pub fn decode<T: Copy>(&self) -> Option<InternalMessage<T>> {
+        if self.queue != short_typename::<T>().as_str() ||
+            self.message.len() != size_of::<InternalMessage<T>>()
+        {
+            return None;
+        }
+        Some(unsafe { std::ptr::read_unaligned(self.message.as_ptr().cast()) })
+    }
+}
+
+impl Persistable for FlightRecord {
+    const PERSIST_DIR: &'static str = "flight_recorder";
+}
+
+/// Always-on black box of an app's spine traffic.
+///
+/// Recorded queues are mirrored into byte rings in shared memory, so they
+/// survive a crash and can be dumped by any process, `flux-ctl` included.
+/// A ring takes a single producer, so each [`FlightRing`] gets its own,
+/// named after the queue, the process and the ring's number in it. A dump
+/// freezes recording, writes the last `window` worth of messages of every
+/// ring into a [`FlightRecord`] persistence file and resumes recording.
+pub struct FlightRecorder {
+    base_dir: PathBuf,
+    app_name: String,
+    control: ShmemData<FlightRecorderControl>,
+    window: Nanos,
+}
+
+impl FlightRecorder {
+    pub fn open<D: AsRef<Path>, S: AsRef<str>>(
+        base_dir: D,
+        app_name: S,
+    ) -> Result<Self, QueueError> {
+        let base_dir = base_dir.as_ref().to_path_buf();
+        let app_name = app_name.as_ref().to_owned();
+        let control =
+            ShmemData::open_or_init_with_base_dir(&base_dir, &app_name, Default::default)?;
+        Ok(Self { base_dir, app_name, control, window: DEFAULT_RECORDER_WINDOW })
+    }
+
+    pub fn with_window(self, window: Nanos) -> Self {
+        Self { window, ..self }
+    }
+
+    /// A new ring recording the queue of `T` for the calling process. Rings
+    /// of `T` left by dead processes are removed once they hold nothing
+    /// within the window.
+    ///
+    /// Fails with [`QueueError::TooLarge`] if an `InternalMessage<T>` does not
+    /// fit in a record of a ring of `capacity` bytes.
+    pub fn ring<T: Copy>(&self, capacity: usize) -> Result<FlightRing<T>, QueueError> {
+        static RINGS: AtomicU32 = AtomicU32::new(0);
+        let queue_name = short_typename::<T>();
+        self.remove_stale_rings(queue_name.as_str());
+        let name = format!(
+            "{queue_name}-{}-{}",
+            std::process::id(),
+            RINGS.fetch_add(1, Ordering::Relaxed)
+        );
+        let queue = VarlenQueue::create_or_open_shared(self.rings_dir().join(name), capacity)?;
+        let message_len = size_of::<InternalMessage<T>>();
+        if message_len > queue.max_payload() {
+            return Err(TooLargeError::TooLarge(message_len, queue.max_payload()).into());
+        }
+        Ok(FlightRing { queue, control: self.control.copy_ptr(), _marker: PhantomData })
+    }
+
+    fn rings_dir(&self) -> PathBuf {
+        shmem_dir_recorder_with_base(&self.base_dir, &self.app_name)
+    }
+
+    fn remove_stale_rings(&self, queue_name: &str) {
+        let Ok(dir) = std::fs::read_dir(self.rings_dir()) else {
+            return;
+        };
+        let from = Nanos(Nanos::now().0.saturating_sub(self.window.0));
+        for entry in dir.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some((queue, pid)) = parse_ring_name(&file_name) else {
+                continue;
+            };
+            if queue != queue_name || is_pid_alive(pid) {
+                continue;
+            }
+            let path = entry.path();
+            let newest = VarlenQueue::<FlightEntry>::open_shared(&path)
+                .ok()
+                .and_then(|ring| Self::ring_records(ring, queue).last().map(|r| r.publish_t));
+            if newest.is_none_or(|t| t < from) {
+                if let Err(e) = cleanup_flink(&path) {
+                    tracing::warn!("couldn't remove stale flight recorder ring: {e}");
+                }
+                memfd::remove_segment(&path);
+            }
+        }
+    }
+
+    fn ring_records(ring: VarlenQueue<FlightEntry>, queue: &str) -> Vec<FlightRecord> {
+        let mut out = Vec::new();
+        let mut consumer = VarlenConsumer::new(ring, "flight_recorder").without_log();
+        consumer.seek_oldest();
+        while let Some((entry, (message, payload))) = consumer.consume(|entry, bytes| {
+            let (message, payload) = bytes.split_at(entry.message_len as usize);
+            (message.to_vec(), payload.to_vec())
+        }) {
+            out.push(FlightRecord {
+                queue: queue.to_owned(),
+                publish_t: entry.publish_t,
+                message,
+                payload,
+            });
+        }
+        out
+    }
+
+    pub fn freeze(&self) {
+        self.control.frozen.store(true, Ordering::Release);
+    }
+
+    pub fn unfreeze(&self) {
+        self.control.frozen.store(false, Ordering::Release);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.control.frozen.load(Ordering::Acquire)
+    }
+
+    /// Every record of the last `window`, oldest first.
+    pub fn records(&self) -> Vec<FlightRecord> {
+        let mut out = Vec::new();
+        let Ok(dir) = std::fs::read_dir(self.rings_dir()) else {
+            return out;
+        };
+        for entry in dir.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some((queue, _)) = parse_ring_name(&file_name) else {
+                continue;
+            };
+            let Ok(ring) = VarlenQueue::<FlightEntry>::open_shared(entry.path()) else {
+                continue;
+            };
+            out.extend(Self::ring_records(ring, queue));
+        }
+        out.sort_by_key(|r| r.publish_t);
+        if let Some(last) = out.last() {
+            let from = Nanos(last.publish_t.0.saturating_sub(self.window.0));
+            out.retain(|r| r.publish_t >= from);
+        }
+        out
+    }
+
+    /// Freezes the rings and writes their content to
+    /// `data/<app>/flight_recorder/<utc time>_<reason>.bin`, returning the
+    /// path unless there was nothing to dump.
+    pub fn dump(&self, reason: &str) -> Option<PathBuf> {
+        let was_frozen = self.control.frozen.swap(true, Ordering::AcqRel);
+        let now = Nanos::now();
+        self.control.last_dump.store(now.0, Ordering::Relaxed);
+        let records = self.records();
+        if !was_frozen {
+            self.unfreeze();
+        }
+        if records.is_empty() {
+            return None;
+        }
+        let filename = format!("{}_{reason}", now.with_fmt_utc(DUMP_FORMAT_UTC));
+        let path = FlightRecord::persist_dir_with_base_dir(&self.base_dir, &self.app_name)
+            .join(&filename)
+            .with_added_extension("bin");
+        FlightRecord::persist_in_base_dir(
+            &self.base_dir,
+            &self.app_name,
+            &records,
+            Some(3),
+            Some(filename),
+        );
+        tracing::warn!("flight recorder of {} dumped to {}", self.app_name, path.display());
+        Some(path)
+    }
+
+    /// [`Self::dump`] unless any process dumped within the last `window`, so
+    /// a sustained breach doesn't dump on every message.
+    pub fn trigger(&self, reason: &str) -> Option<PathBuf> {
+        let now = Nanos::now().0;
+        let last = self.control.last_dump.load(Ordering::Relaxed);
+        if now.saturating_sub(last) < self.window.0 ||
+            self.control
+                .last_dump
+                .compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+        {
+            return None;
+        }
+        self.dump(reason)
+    }
+
+    /// Dump this recorder from the panic hook. Chains to the previous hook and
+    /// is only installed once per recorder.
+    pub fn dump_on_panic(&self) {
+        static HOOK: Once = Once::new();
+        static RECORDERS: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+        {
+            let mut recorders = RECORDERS.lock().unwrap();
+            let key = (self.base_dir.clone(), self.app_name.clone());
+            if !recorders.contains(&key) {
+                recorders.push(key);
+            }
+        }
+        HOOK.call_once(|| {
+            let original_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |panic_info| {
+                if let Ok(recorders) = RECORDERS.try_lock() {
+                    for (base_dir, app_name) in recorders.iter() {
+                        if let Ok(recorder) = Self::open(base_dir, app_name) {
+                            recorder.dump("panic");
+                        }
+                    }
+                }
+                original_hook(panic_info);
+            }));
+        });
+    }
+}
+
+/// Queue name and pid of a ring named `<queue>-<pid>-<n>`.
+fn parse_ring_name(name: &str) -> Option<(&str, u32)> {
+    let (rest, n) = name.rsplit_once('-')?;
+    let (queue, pid) = rest.rsplit_once('-')?;
+    n.parse::<u32>().ok()?;
+    Some((queue, pid.parse().ok()?))
+}
+
+/// Producer side of a ring recording the queue of `T`.
+#[derive(Debug)]
+pub struct FlightRing<T> {
+    queue: VarlenQueue<FlightEntry>,
+    control: ShmemData<FlightRecorderControl>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T> Send for FlightRing<T> {}
+
+impl<T: Copy> FlightRing<T> {
+    /// Records `msg` and its dcache payload, which is truncated if it exceeds
+    /// what the ring accepts. Skipped while the recorder is frozen.
+    pub fn record(&self, msg: &InternalMessage<T>, payload: &[u8]) {
+        if self.control.frozen.load(Ordering::Relaxed) {
+            return;
+        }
+        let message_len = size_of::<InternalMessage<T>>();
+        let payload =
+            &payload[..payload.len().min(self.queue.max_payload().saturating_sub(message_len))];
+        let entry = FlightEntry { publish_t: msg.publish_t(), message_len: message_len as u32 };
+        let _ = self.queue.produce(&entry, message_len + payload.len(), |buf| unsafe {
+            std::ptr::copy_nonoverlapping(
+                std::ptr::from_ref(msg).cast::<u8>(),
+                buf.as_mut_ptr(),
+                message_len,
+            );
+            buf[message_len..].copy_from_slice(payload);
+        });
+    }
+}
+
+/// Shared setup of the recorder tiles.
+struct RecorderState<T> {
+    base_dir: PathBuf,
+    capacity: usize,
+    window: Nanos,
+    dump_if: Option<fn(&InternalMessage<T>) -> bool>,
+    recorder: Option<(FlightRecorder, FlightRing<T>)>,
+    /// Copy of the dcache payload until the message is known to be intact.
+    scratch: RefCell<Vec<u8>>,
+}
+
+impl<T: Copy> RecorderState<T> {
+    fn new(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            capacity: DEFAULT_RECORDER_CAPACITY,
+            window: DEFAULT_RECORDER_WINDOW,
+            dump_if: None,
+            recorder: None,
+            scratch: RefCell::default(),
+        }
+    }
+
+    fn init(&mut self, app_name: &str) {
+        let opened = FlightRecorder::open(&self.base_dir, app_name)
+            .and_then(|r| Ok((r.ring(self.capacity)?, r)));
+        match opened {
+            Ok((ring, recorder)) => {
+                let recorder = recorder.with_window(self.window);
+                recorder.dump_on_panic();
+                self.recorder = Some((recorder, ring));
+            }
+            Err(e) => tracing::error!(
+                "couldn't open flight recorder for {}, not recording: {e}",
+                short_typename::<T>()
+            ),
+        }
+    }
+
+    #[inline]
+    fn record(&self, msg: &InternalMessage<T>, payload: &[u8]) {
+        let Some((recorder, ring)) = &self.recorder else {
+            return;
+        };
+        ring.record(msg, payload);
+        if self.dump_if.is_some_and(|breached| breached(msg)) {
+            recorder.trigger("threshold");
+        }
+    }
+}
+
+macro_rules! recorder_builders {
+    () => {
+        pub fn new_with_base_dir<D: AsRef<Path>>(base_dir: D) -> Self {
+            Self { state: RecorderState::new(base_dir.as_ref()) }
+        }
+
+        /// Ring size in bytes.
+        pub fn with_capacity(mut self, capacity: usize) -> Self {
+            self.state.capacity = capacity;
+            self
+        }
+
+        pub fn with_window(mut self, window: Nanos) -> Self {
+            self.state.window = window;
+            self
+        }
+
+        /// Dump the recorder when `breached` returns true for a message, at
+        /// most once per window.
+        pub fn dump_if(mut self, breached: fn(&InternalMessage<T>) -> bool) -> Self {
+            self.state.dump_if = Some(breached);
+            self
+        }
+    };
+}
+
+/// Records every message of the spine queue of `T`.
+pub struct FlightRecorderTile<T> {
+    state: RecorderState<T>,
+}
+
+impl<T: Copy> FlightRecorderTile<T> {
+    recorder_builders!();
+}
+
+impl<S, T> Tile<S> for FlightRecorderTile<T>
+where
+    S: FluxSpine,
+    S::Consumers: AsMut<SpineConsumer<T>>,
+    T: 'static + Copy + Send,
+{
+    fn name(&self) -> TileName {
+        TileName::from_str_truncate("FlightRecorder")
+    }
+
+    fn try_init(&mut self, _adapter: &mut SpineAdapter<S>) -> bool {
+        self.state.init(S::app_name());
+        true
+    }
+
+    fn loop_body(&mut self, adapter: &mut SpineAdapter<S>) {
+        adapter.consume_internal_message(|msg: &mut InternalMessage<T>, _| {
+            self.state.record(msg, &[]);
+        });
+    }
+}
+
+/// Records every message of the dcache-backed spine queue of `T` together
+/// with its payload.
+pub struct DCacheFlightRecorderTile<T> {
+    state: RecorderState<T>,
+}
+
+impl<T: Copy> DCacheFlightRecorderTile<T> {
+    recorder_builders!();
+}
+
+impl<S, T> Tile<S> for DCacheFlightRecorderTile<T>
+where
+    S: FluxSpine,
+    S::Consumers: AsMut<SpineDCacheConsumer<T>>,
+    T: 'static + Copy + Send,
+{
+    fn name(&self) -> TileName {
+        TileName::from_str_truncate("FlightRecorder")
+    }
+
+    fn try_init(&mut self, _adapter: &mut SpineAdapter<S>) -> bool {
+        self.state.init(S::app_name());
+        true
+    }
+
+    fn loop_body(&mut self, adapter: &mut SpineAdapter<S>) {
+        let state = &self.state;
+        adapter.consume_with_dcache_internal_message(
+            |_, payload| {
+                let mut scratch = state.scratch.borrow_mut();
+                scratch.clear();
+                scratch.extend_from_slice(payload);
+            },
+            |read, _| match read {
+                DCacheRead::Ok((msg, ())) => state.record(&msg, &state.scratch.borrow()),
+                DCacheRead::NoRef(msg) | DCacheRead::Lost(msg) => state.record(&msg, &[]),
+                DCacheRead::Empty | DCacheRead::SpedPast => {}
+            },
+        );
+    }
+}
@@ -1,6 +1,10 @@
+mod flight_recorder;
 mod persistable;
 mod persisting_tile;
 
+pub use flight_recorder::{
+    DCacheFlightRecorderTile, FlightRecord, FlightRecorder, FlightRecorderTile, FlightRing,
+};
 use flux_timing::Nanos;
 pub use persistable::{Persistable, read, write};
 pub use persisting_tile::PersistingQueueTile;
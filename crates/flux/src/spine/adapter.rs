@@ -3,14 +3,16 @@ use std::sync::{
     atomic::{AtomicUsize, Ordering},
 };
 
-use flux_timing::{IngestionTime, InternalMessage};
-use flux_utils::DCacheError;
+use flux_communication::{FullError, TooLargeError};
+use flux_timing::{IngestionTime, InternalMessage, Nanos};
+use flux_utils::{DCacheError, DCachePtr, DCacheRef};
 use signal_hook::consts::SIGINT;
 
 use crate::{
     spine::{
-        DCacheRead, FluxSpine, SpineConsumer, SpineDCacheConsumer, SpineProducer,
-        SpineProducerWithDCache, SpineProducers,
+        DCacheRead, FluxSpine, SpineConflatingConsumer, SpineConflatingQueue, SpineConsumer,
+        SpineConsumers, SpineDCacheConsumer, SpineProducer, SpineProducerWithDCache,
+        SpineProducers, SpineVarlenConsumer, SpineVarlenQueue,
     },
     tile::Tile,
 };
@@ -25,6 +27,14 @@ pub struct SpineAdapter<S: FluxSpine> {
     waker_registered: bool,
 }
 
+// Consumers are `Copy` and can't leave their groups on drop themselves; the
+// adapter owns them for the lifetime of the tile.
+impl<S: FluxSpine> Drop for SpineAdapter<S> {
+    fn drop(&mut self) {
+        self.consumers.leave_groups();
+    }
+}
+
 impl<S: FluxSpine> SpineAdapter<S> {
     #[inline]
     pub fn connect_tile<Tl: Tile<S>>(tile: &Tl, spine: &mut S) -> Self {
@@ -135,6 +145,66 @@ impl<S: FluxSpine> SpineAdapter<S> {
         Ok(())
     }
 
+    #[inline]
+    pub fn produce_with_dcache_vectored<T>(
+        &mut self,
+        data: T,
+        parts: &[&[u8]],
+    ) -> Result<(), DCacheError>
+    where
+        T: 'static + Copy,
+        S::Producers: SpineProducers + AsRef<SpineProducerWithDCache<T>>,
+    {
+        self.producers.produce_with_dcache_vectored(data, parts)?;
+        self.did_work = true;
+        Ok(())
+    }
+
+    /// Produces `data` with a payload already in the dcache, typically the
+    /// ref committed by a [`DCacheReservation`](flux_utils::DCacheReservation)
+    /// of [`Self::dcache_ptr`].
+    #[inline]
+    pub fn produce_with_dref<T>(&mut self, data: T, dref: DCacheRef, send_ts: Nanos)
+    where
+        T: 'static + Copy,
+        S::Producers: SpineProducers + AsRef<SpineProducerWithDCache<T>>,
+    {
+        self.producers.produce_with_dref(data, dref, send_ts);
+        self.did_work = true;
+    }
+
+    /// Dcache of the queue of `T`, to reserve payloads in ahead of producing.
+    #[inline]
+    pub fn dcache_ptr<T>(&self) -> DCachePtr
+    where
+        T: 'static + Copy,
+        S::Producers: AsRef<SpineProducerWithDCache<T>>,
+    {
+        self.producers.as_ref().dcache_ptr()
+    }
+
+    #[inline]
+    pub fn produce_varlen<T, F>(&mut self, data: T, len: usize, f: F) -> Result<(), TooLargeError>
+    where
+        T: Copy,
+        S::Producers: SpineProducers + AsRef<SpineVarlenQueue<T>>,
+        F: FnOnce(&mut [u8]),
+    {
+        self.producers.produce_varlen(data, len, f)?;
+        self.did_work = true;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn produce_conflated<T: Copy>(&mut self, data: T) -> Result<(), FullError>
+    where
+        S::Producers: SpineProducers + AsRef<SpineConflatingQueue<T>>,
+    {
+        self.producers.produce_conflated(data)?;
+        self.did_work = true;
+        Ok(())
+    }
+
     #[inline]
     pub fn consume<T, F>(&mut self, mut f: F)
     where
@@ -317,6 +387,72 @@ impl<S: FluxSpine> SpineAdapter<S> {
         handle(result, &mut self.producers);
     }
 
+    /// Consume every available record of the `#[queue(varlen)]` queue of `T`.
+    /// `read` gets the zero-copy payload, `handle` what `read` extracted from
+    /// it once the record was verified intact.
+    #[inline]
+    pub fn consume_varlen<T, R, F, G>(&mut self, mut read: F, mut handle: G)
+    where
+        T: 'static + Copy,
+        S::Consumers: AsMut<SpineVarlenConsumer<T>>,
+        S::Producers: SpineProducers,
+        F: FnMut(&T, &[u8]) -> R,
+        G: FnMut(T, R, &mut S::Producers),
+    {
+        let c: &mut SpineVarlenConsumer<T> = self.consumers.as_mut();
+        while let Some((msg, r)) = c.consume(&mut self.producers, &mut read) {
+            self.did_work = true;
+            handle(msg, r, &mut self.producers);
+        }
+    }
+
+    /// Consume one record of the `#[queue(varlen)]` queue of `T` from the
+    /// shared collaborative cursor.
+    #[inline]
+    pub fn consume_varlen_collaborative<T, R, F, G>(&mut self, read: F, handle: G) -> bool
+    where
+        T: 'static + Copy,
+        S::Consumers: AsMut<SpineVarlenConsumer<T>>,
+        S::Producers: SpineProducers,
+        F: FnOnce(&T, &[u8]) -> R,
+        G: FnOnce(T, R, &mut S::Producers),
+    {
+        let c: &mut SpineVarlenConsumer<T> = self.consumers.as_mut();
+        let Some((msg, r)) = c.consume_collaborative(&mut self.producers, read) else {
+            return false;
+        };
+        self.did_work = true;
+        handle(msg, r, &mut self.producers);
+        true
+    }
+
+    /// Consume the latest value of every key of the
+    /// `#[queue(conflate(key = ...))]` queue of `T` updated since the last
+    /// call, each key once.
+    #[inline]
+    pub fn consume_conflated<T, F>(&mut self, f: F)
+    where
+        T: 'static + Copy,
+        S::Consumers: AsMut<SpineConflatingConsumer<T>>,
+        S::Producers: SpineProducers,
+        F: FnMut(T, &mut S::Producers),
+    {
+        let c: &mut SpineConflatingConsumer<T> = self.consumers.as_mut();
+        if c.consume(&mut self.producers, f) > 0 {
+            self.did_work = true;
+        }
+    }
+
+    /// Makes the next [`Self::consume_conflated`] of `T` return every key
+    /// produced so far, e.g. from `Tile::init` to start from the full state.
+    pub fn replay_conflated<T: 'static + Copy>(&mut self)
+    where
+        S::Consumers: AsMut<SpineConflatingConsumer<T>>,
+    {
+        let c: &mut SpineConflatingConsumer<T> = self.consumers.as_mut();
+        c.replay_latest();
+    }
+
     /// Override the collaborative group label for queue `T`. By default each
     /// tile instance gets a unique label (`TileType-N`) set automatically at
     /// attach time. Group label can be set in `Tile::init` to share a group
@@ -343,6 +479,39 @@ impl<S: FluxSpine> SpineAdapter<S> {
         c.inner.set_collaborative_group(group_label);
     }
 
+    pub fn set_collaborative_group_varlen<T: 'static + Copy>(&mut self, group_label: &'static str)
+    where
+        S::Consumers: AsMut<SpineVarlenConsumer<T>>,
+    {
+        let c: &mut SpineVarlenConsumer<T> = self.consumers.as_mut();
+        c.inner.set_collaborative_group(group_label);
+    }
+
+    /// Commits everything consumed from the `T` queue so far. After a
+    /// restart the tile resumes right after it, as long as those messages
+    /// are still in the ring.
+    ///
+    /// ```ignore
+    /// adapter.consume(|order: Order, _| self.book.apply(order));
+    /// adapter.commit::<Order>();
+    /// ```
+    pub fn commit<T: 'static + Copy>(&mut self)
+    where
+        S::Consumers: AsMut<SpineConsumer<T>>,
+    {
+        let c: &mut SpineConsumer<T> = self.consumers.as_mut();
+        c.commit();
+    }
+
+    /// [`Self::commit`] for a dcache queue.
+    pub fn commit_dcache<T: 'static + Copy>(&mut self)
+    where
+        S::Consumers: AsMut<SpineDCacheConsumer<T>>,
+    {
+        let c: &mut SpineDCacheConsumer<T> = self.consumers.as_mut();
+        c.commit();
+    }
+
     #[inline]
     pub fn consume_internal_message<T: 'static + Copy, F>(&mut self, mut f: F)
     where
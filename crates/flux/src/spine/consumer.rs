@@ -1,15 +1,34 @@
 use std::{ops::Deref, path::Path};
 
-use flux_timing::InternalMessage;
-use flux_utils::{DCachePtr, short_typename};
+use flux_timing::{InternalMessage, Nanos};
+use flux_utils::{DCachePtr, directories::shmem_dir_budgets_with_base, short_typename};
 
 use crate::{
     Timer,
-    communication::{ReadError, queue},
-    spine::{DCacheMsg, FluxSpine, SpineProducers, SpineQueue},
+    communication::{LatencyBudget, ReadError, TimeoutError, queue},
+    spine::{
+        DCacheMsg, FluxSpine, SpineConflatingQueue, SpineProducers, SpineQueue, SpineVarlenQueue,
+    },
     tile::Tile,
 };
 
+/// The timer of `tile` consuming `T`, with its budget if it has one.
+fn consumer_timer<D, S, Tl, T>(base_dir: D, tile: &Tl, budget: Option<Nanos>) -> Timer
+where
+    D: AsRef<Path>,
+    S: FluxSpine,
+    Tl: Tile<S>,
+{
+    let name = format!("{}-{}", tile.name(), short_typename::<T>());
+    let Some(budget) = budget else {
+        return Timer::new_with_base_dir(base_dir, S::app_name(), name);
+    };
+    let file = shmem_dir_budgets_with_base(&base_dir, S::app_name()).join(&name);
+    let latency_budget = LatencyBudget::create_or_open_shared(&file, budget)
+        .unwrap_or_else(|e| panic!("couldn't open latency budget {}: {e}", file.display()));
+    Timer::new_with_base_dir(base_dir, S::app_name(), name).with_budget(latency_budget)
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct SpineConsumer<T: 'static + Copy> {
     timer: Timer,
@@ -27,6 +46,23 @@ impl<T: 'static + Copy> Deref for SpineConsumer<T> {
 impl<T: 'static + Copy> SpineConsumer<T> {
     #[inline]
     pub fn attach<D, S, Tl>(base_dir: D, tile: &Tl, queue: SpineQueue<T>) -> Self
+    where
+        D: AsRef<Path>,
+        S: FluxSpine,
+        Tl: Tile<S>,
+    {
+        Self::attach_with_budget::<_, S, _>(base_dir, tile, queue, None)
+    }
+
+    /// [`Self::attach`] with the latencies checked against `budget`, see
+    /// [`LatencyBudget`].
+    #[inline]
+    pub fn attach_with_budget<D, S, Tl>(
+        base_dir: D,
+        tile: &Tl,
+        queue: SpineQueue<T>,
+        budget: Option<Nanos>,
+    ) -> Self
     where
         D: AsRef<Path>,
         S: FluxSpine,
@@ -34,15 +70,27 @@ impl<T: 'static + Copy> SpineConsumer<T> {
     {
         let label: &'static str = Box::leak(tile.name().as_str().to_owned().into_boxed_str());
 
-        let timer = Timer::new_with_base_dir(
-            base_dir,
-            S::app_name(),
-            format!("{}-{}", tile.name(), short_typename::<T>()),
-        );
+        let timer = consumer_timer::<_, S, _, T>(base_dir, tile, budget);
 
         Self { timer, inner: queue::Consumer::new(queue, label) }
     }
 
+    /// See [`queue::ConsumerBare::renew_group`].
+    #[inline]
+    pub fn renew_group(&mut self) {
+        self.inner.renew_group();
+    }
+
+    /// See [`queue::ConsumerBare::leave_group`].
+    pub fn leave_group(&mut self) {
+        self.inner.leave_group();
+    }
+
+    /// See [`queue::ConsumerBare::commit`].
+    pub fn commit(&mut self) {
+        self.inner.commit();
+    }
+
     #[inline]
     pub fn consume<P, F>(&mut self, producers: &mut P, mut f: F) -> bool
     where
@@ -57,6 +105,40 @@ impl<T: 'static + Copy> SpineConsumer<T> {
         })
     }
 
+    /// Blocking [`Self::consume`] for consumers off the hot path, returning
+    /// [`TimeoutError`] once `deadline` has passed without a message.
+    pub fn consume_until<P, F>(
+        &mut self,
+        producers: &mut P,
+        deadline: Nanos,
+        mut f: F,
+    ) -> Result<(), TimeoutError>
+    where
+        P: SpineProducers,
+        F: FnMut(T, &mut P),
+    {
+        self.inner.consume_until(deadline, |m| {
+            *producers.timestamp_mut().ingestion_t_mut() = m.ingestion_time();
+            self.timer.start();
+            f(m.into_data(), producers);
+            self.timer.record_processing_and_latency_from(producers.timestamp().ingestion_t.into());
+        })
+    }
+
+    /// [`Self::consume_until`] with a deadline `timeout` from now.
+    pub fn consume_timeout<P, F>(
+        &mut self,
+        producers: &mut P,
+        timeout: std::time::Duration,
+        f: F,
+    ) -> Result<(), TimeoutError>
+    where
+        P: SpineProducers,
+        F: FnMut(T, &mut P),
+    {
+        self.consume_until(producers, Nanos::now() + Nanos::from(timeout.as_nanos()), f)
+    }
+
     #[inline]
     pub fn consume_maybe_track<P, F>(&mut self, producers: &mut P, mut f: F) -> bool
     where
@@ -253,20 +335,50 @@ impl<T: 'static + Copy> SpineDCacheConsumer<T> {
         queue: SpineQueue<DCacheMsg<T>>,
         dcache: DCachePtr,
     ) -> Self
+    where
+        D: AsRef<Path>,
+        S: FluxSpine,
+        Tl: Tile<S>,
+    {
+        Self::attach_with_budget::<_, S, _>(base_dir, tile, queue, dcache, None)
+    }
+
+    /// [`Self::attach`] with the latencies checked against `budget`, see
+    /// [`LatencyBudget`].
+    #[inline]
+    pub fn attach_with_budget<D, S, Tl>(
+        base_dir: D,
+        tile: &Tl,
+        queue: SpineQueue<DCacheMsg<T>>,
+        dcache: DCachePtr,
+        budget: Option<Nanos>,
+    ) -> Self
     where
         D: AsRef<Path>,
         S: FluxSpine,
         Tl: Tile<S>,
     {
         let label: &'static str = Box::leak(tile.name().as_str().to_owned().into_boxed_str());
-        let timer = Timer::new_with_base_dir(
-            base_dir,
-            S::app_name(),
-            format!("{}-{}", tile.name(), short_typename::<T>()),
-        );
+        let timer = consumer_timer::<_, S, _, T>(base_dir, tile, budget);
         Self { timer, inner: queue::Consumer::new(queue, label), dcache }
     }
 
+    /// See [`queue::ConsumerBare::renew_group`].
+    #[inline]
+    pub fn renew_group(&mut self) {
+        self.inner.renew_group();
+    }
+
+    /// See [`queue::ConsumerBare::leave_group`].
+    pub fn leave_group(&mut self) {
+        self.inner.leave_group();
+    }
+
+    /// See [`queue::ConsumerBare::commit`].
+    pub fn commit(&mut self) {
+        self.inner.commit();
+    }
+
     #[inline]
     pub fn consume<P, R, F>(&mut self, producers: &mut P, mut read: F) -> DCacheRead<T, R>
     where
@@ -323,7 +435,8 @@ impl<T: 'static + Copy> SpineDCacheConsumer<T> {
                 }
                 let user_msg = msg.with_data(msg.data().data);
                 self.timer.start();
-                let Ok(extracted) = self.dcache.map(dref, |payload| read(&user_msg, payload))
+                let Ok(extracted) =
+                    self.dcache.map_payload(dref, |payload| read(&user_msg, payload))
                 else {
                     return DCacheRead::Lost(user_msg);
                 };
@@ -362,7 +475,8 @@ impl<T: 'static + Copy> SpineDCacheConsumer<T> {
                     }
                     let user_msg = msg.with_data(msg.data().data);
                     self.timer.start();
-                    let Ok(extracted) = self.dcache.map(dref, |payload| read(&user_msg, payload))
+                    let Ok(extracted) =
+                        self.dcache.map_payload(dref, |payload| read(&user_msg, payload))
                     else {
                         return DCacheRead::Lost(user_msg);
                     };
@@ -380,3 +494,144 @@ impl<T: 'static + Copy> SpineDCacheConsumer<T> {
         }
     }
 }
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpineVarlenConsumer<T: 'static + Copy> {
+    timer: Timer,
+    pub inner: queue::VarlenConsumer<InternalMessage<T>>,
+}
+
+impl<T: 'static + Copy> SpineVarlenConsumer<T> {
+    #[inline]
+    pub fn attach<D, S, Tl>(base_dir: D, tile: &Tl, queue: SpineVarlenQueue<T>) -> Self
+    where
+        D: AsRef<Path>,
+        S: FluxSpine,
+        Tl: Tile<S>,
+    {
+        Self::attach_with_budget::<_, S, _>(base_dir, tile, queue, None)
+    }
+
+    /// [`Self::attach`] with the latencies checked against `budget`, see
+    /// [`LatencyBudget`].
+    #[inline]
+    pub fn attach_with_budget<D, S, Tl>(
+        base_dir: D,
+        tile: &Tl,
+        queue: SpineVarlenQueue<T>,
+        budget: Option<Nanos>,
+    ) -> Self
+    where
+        D: AsRef<Path>,
+        S: FluxSpine,
+        Tl: Tile<S>,
+    {
+        let label: &'static str = Box::leak(tile.name().as_str().to_owned().into_boxed_str());
+        let timer = consumer_timer::<_, S, _, T>(base_dir, tile, budget);
+        Self { timer, inner: queue::VarlenConsumer::new(queue, label) }
+    }
+
+    /// See [`queue::ConsumerBare::renew_group`].
+    #[inline]
+    pub fn renew_group(&mut self) {
+        self.inner.renew_group();
+    }
+
+    /// See [`queue::ConsumerBare::leave_group`].
+    pub fn leave_group(&mut self) {
+        self.inner.leave_group();
+    }
+
+    /// Reads the next record, recovering from being sped past. `read` may see
+    /// a payload that the producer is overwriting; its result is then dropped
+    /// and the next record read instead.
+    #[inline]
+    pub fn consume<P, R, F>(&mut self, producers: &mut P, mut read: F) -> Option<(T, R)>
+    where
+        P: SpineProducers,
+        F: FnMut(&T, &[u8]) -> R,
+    {
+        self.timer.start();
+        let (msg, r) = self.inner.consume(|msg, payload| read(msg.data(), payload))?;
+        self.record(producers, &msg);
+        Some((msg.into_data(), r))
+    }
+
+    /// Claims and reads the next record of the collaborative group.
+    #[inline]
+    pub fn consume_collaborative<P, R, F>(&mut self, producers: &mut P, read: F) -> Option<(T, R)>
+    where
+        P: SpineProducers,
+        F: FnOnce(&T, &[u8]) -> R,
+    {
+        self.timer.start();
+        let (msg, r) =
+            self.inner.consume_collaborative(|msg, payload| read(msg.data(), payload))?;
+        self.record(producers, &msg);
+        Some((msg.into_data(), r))
+    }
+
+    #[inline]
+    fn record<P: SpineProducers>(&mut self, producers: &mut P, msg: &InternalMessage<T>) {
+        let ingestion_t = msg.ingestion_time();
+        *producers.timestamp_mut().ingestion_t_mut() = ingestion_t;
+        self.timer.record_processing_and_latency_from(ingestion_t.into());
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpineConflatingConsumer<T: 'static + Copy> {
+    timer: Timer,
+    pub inner: queue::ConflatingConsumer<InternalMessage<T>>,
+}
+
+impl<T: 'static + Copy> SpineConflatingConsumer<T> {
+    #[inline]
+    pub fn attach<D, S, Tl>(base_dir: D, tile: &Tl, queue: SpineConflatingQueue<T>) -> Self
+    where
+        D: AsRef<Path>,
+        S: FluxSpine,
+        Tl: Tile<S>,
+    {
+        Self::attach_with_budget::<_, S, _>(base_dir, tile, queue, None)
+    }
+
+    /// [`Self::attach`] with the latencies checked against `budget`, see
+    /// [`LatencyBudget`].
+    #[inline]
+    pub fn attach_with_budget<D, S, Tl>(
+        base_dir: D,
+        tile: &Tl,
+        queue: SpineConflatingQueue<T>,
+        budget: Option<Nanos>,
+    ) -> Self
+    where
+        D: AsRef<Path>,
+        S: FluxSpine,
+        Tl: Tile<S>,
+    {
+        let timer = consumer_timer::<_, S, _, T>(base_dir, tile, budget);
+        Self { timer, inner: queue::ConflatingConsumer::new(queue) }
+    }
+
+    /// See [`queue::ConflatingConsumer::replay_latest`].
+    pub fn replay_latest(&mut self) {
+        self.inner.replay_latest();
+    }
+
+    /// Hands `f` the latest value of every key updated since the last call,
+    /// returning how many keys there were.
+    #[inline]
+    pub fn consume<P, F>(&mut self, producers: &mut P, mut f: F) -> usize
+    where
+        P: SpineProducers,
+        F: FnMut(T, &mut P),
+    {
+        self.inner.consume(|m| {
+            *producers.timestamp_mut().ingestion_t_mut() = m.ingestion_time();
+            self.timer.start();
+            f(m.into_data(), producers);
+            self.timer.record_processing_and_latency_from(producers.timestamp().ingestion_t.into());
+        })
+    }
+}
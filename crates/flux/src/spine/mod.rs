@@ -6,34 +6,80 @@ mod standalone_producer;
 use std::path::Path;
 
 pub use adapter::SpineAdapter;
-pub use consumer::{DCacheRead, SpineConsumer, SpineDCacheConsumer};
+pub use consumer::{
+    DCacheRead, SpineConflatingConsumer, SpineConsumer, SpineDCacheConsumer, SpineVarlenConsumer,
+};
 use flux_timing::{IngestionTime, InternalMessage, Nanos, TrackingTimestamp};
 use flux_utils::{DCacheError, DCachePtr, DCacheRef, directories::shmem_dir};
 pub use scoped::ScopedSpine;
 pub use standalone_producer::{StandaloneDCacheProducer, StandaloneProducer};
+use type_hash_derive::TypeHash;
 
 use crate::{
-    communication::queue::{self},
+    communication::{
+        FullError, MappingOptions, TooLargeError,
+        queue::{self},
+    },
     tile::{Tile, TileName},
 };
 
 pub type SpineProducer<T> = queue::Producer<InternalMessage<T>>;
 pub type SpineQueue<T> = queue::Queue<InternalMessage<T>>;
+/// Byte-ring queue of `#[queue(varlen)]` fields, shared by the spine and its
+/// producers.
+pub type SpineVarlenQueue<T> = queue::VarlenQueue<InternalMessage<T>>;
+/// Last-value-per-key queue of `#[queue(conflate(key = ...))]` fields.
+pub type SpineConflatingQueue<T> = queue::ConflatingQueue<InternalMessage<T>>;
 
 #[derive(Clone, Copy, Debug, serde::Deserialize)]
 pub struct QueueParams {
     pub size: usize,
+    /// `huge_pages`, `prefault` and `mlock`, set inline next to `size`.
+    #[serde(flatten)]
+    pub mapping: MappingOptions,
+    /// Latency budget of the queue's consumers, e.g. `"5us"`.
+    #[serde(default)]
+    pub budget: Option<Nanos>,
 }
 
 #[derive(Clone, Copy, Debug, serde::Deserialize)]
 pub struct DCacheQueueParams {
     pub size: usize,
     pub mtu: usize,
+    /// Bytes of the overflow region taking payloads larger than `mtu`, 0 for
+    /// none.
+    #[serde(default)]
+    pub overflow: usize,
+    #[serde(flatten)]
+    pub mapping: MappingOptions,
+    /// Latency budget of the queue's consumers, e.g. `"5us"`.
+    #[serde(default)]
+    pub budget: Option<Nanos>,
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+pub struct VarlenQueueParams {
+    /// Ring size in bytes, rounded up to a power of two.
+    pub capacity: usize,
+    #[serde(flatten)]
+    pub mapping: MappingOptions,
+    /// Latency budget of the queue's consumers, e.g. `"5us"`.
+    #[serde(default)]
+    pub budget: Option<Nanos>,
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize)]
+pub struct ConflatingQueueParams {
+    /// Maximum number of distinct keys, rounded up to a power of two.
+    pub keys: usize,
+    /// Latency budget of the queue's consumers, e.g. `"5us"`.
+    #[serde(default)]
+    pub budget: Option<Nanos>,
 }
 
 /// Wire type for dcache-backed queues. Internal to the spine; users see `T`
 /// and `&[u8]` at consume sites.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, TypeHash)]
 pub struct DCacheMsg<T> {
     pub data: T,
     dref: DCacheRef,
@@ -110,12 +156,30 @@ pub trait SpineProducers {
     {
         let ts = self.timestamp().with_new_publish_delta();
         let p: &SpineProducerWithDCache<T> = self.as_ref();
-        let dref = if let Some((len, f)) = payload { Some(p.dcache.write(len, f)?) } else { None };
+        let dref =
+            if let Some((len, f)) = payload { Some(p.dcache.write_payload(len, f)?) } else { None };
         let msg = InternalMessage::new(ts, DCacheMsg::new(data, dref));
         p.inner.produce_without_first(&msg);
         Ok(())
     }
 
+    /// Produces `data` with the concatenation of `parts` as payload.
+    fn produce_with_dcache_vectored<T: 'static + Copy>(
+        &self,
+        data: T,
+        parts: &[&[u8]],
+    ) -> Result<(), DCacheError>
+    where
+        Self: AsRef<SpineProducerWithDCache<T>>,
+    {
+        let ts = self.timestamp().with_new_publish_delta();
+        let p: &SpineProducerWithDCache<T> = self.as_ref();
+        let dref = p.dcache.write_payload_vectored(parts)?;
+        let msg = InternalMessage::new(ts, DCacheMsg::new(data, Some(dref)));
+        p.inner.produce_without_first(&msg);
+        Ok(())
+    }
+
     fn produce_with_dref<T: 'static + Copy>(&self, data: T, dref: DCacheRef, send_ts: Nanos)
     where
         Self: AsRef<SpineProducerWithDCache<T>>,
@@ -137,15 +201,85 @@ pub trait SpineProducers {
     {
         let ts = self.timestamp().with_ingestion_t(ingestion_t);
         let p: &SpineProducerWithDCache<T> = self.as_ref();
-        let dref = if let Some((len, f)) = payload { Some(p.dcache.write(len, f)?) } else { None };
+        let dref =
+            if let Some((len, f)) = payload { Some(p.dcache.write_payload(len, f)?) } else { None };
         let msg = InternalMessage::new(ts, DCacheMsg::new(data, dref));
         p.inner.produce_without_first(&msg);
         Ok(())
     }
+
+    /// Produces `data` with a `len` byte payload written by `f` straight into
+    /// the ring of a `#[queue(varlen)]` queue.
+    fn produce_varlen<T: Copy, F: FnOnce(&mut [u8])>(
+        &self,
+        data: T,
+        len: usize,
+        f: F,
+    ) -> Result<(), TooLargeError>
+    where
+        Self: AsRef<SpineVarlenQueue<T>>,
+    {
+        let msg = InternalMessage::new(self.timestamp().with_new_publish_delta(), data);
+        self.as_ref().produce(&msg, len, f)?;
+        Ok(())
+    }
+
+    /// Overwrites the latest `data` of its key in a
+    /// `#[queue(conflate(key = ...))]` queue. Fails once the queue holds as
+    /// many other keys as it has room for.
+    fn produce_conflated<T: Copy>(&self, data: T) -> Result<(), FullError>
+    where
+        Self: AsRef<SpineConflatingQueue<T>>,
+    {
+        let msg = InternalMessage::new(self.timestamp().with_new_publish_delta(), data);
+        self.as_ref().produce(&msg)?;
+        Ok(())
+    }
+}
+
+/// Moves the park signal into the shared memory of `app_name`.
+///
+/// Produces in any process of the app then wake tiles parked in the others.
+/// No-op without the `park` feature. Called by `#[from_spine]` on creation.
+pub fn share_park_signal(base_dir: &Path, app_name: &str) {
+    #[cfg(feature = "park")]
+    if let Err(e) = crate::park::SIGNAL.attach_shared(base_dir, app_name) {
+        tracing::warn!(
+            "couldn't share park signal of {app_name}, parking stays process-local: {e}"
+        );
+    }
+    #[cfg(not(feature = "park"))]
+    let _ = (base_dir, app_name);
+}
+
+/// Adopts the TSC calibration shared by the processes of `app_name`.
+///
+/// Ours is published if we are the first, see
+/// [`SharedTscCalibration`](crate::communication::SharedTscCalibration).
+/// Called by `#[from_spine]` on creation.
+pub fn share_tsc_calibration(base_dir: &Path, app_name: &str) {
+    if let Err(e) = crate::communication::attach_tsc_calibration(base_dir, app_name) {
+        tracing::warn!(
+            "couldn't share TSC calibration of {app_name}, conversions use our own: {e}"
+        );
+    }
+}
+
+/// Implemented by the generated consumers struct of a spine.
+pub trait SpineConsumers {
+    /// Leaves the consumer groups of every queue, see
+    /// [`queue::ConsumerBare::leave_group`]. Called when the tile's
+    /// [`SpineAdapter`] is dropped.
+    fn leave_groups(&mut self);
+
+    /// Renews the consumer group leases of every queue, see
+    /// [`queue::ConsumerBare::renew_group`]. Called periodically by the tile
+    /// loop, so tiles that are parked or don't poll a queue keep their groups.
+    fn renew_groups(&mut self);
 }
 
 pub trait FluxSpine: Sized + Send {
-    type Consumers: Clone + Send;
+    type Consumers: SpineConsumers + Clone + Send;
     type Producers: SpineProducers + Clone + Send;
 
     fn attach_consumers<Tl: Tile<Self>>(&mut self, tile: &Tl) -> Self::Consumers;
@@ -43,7 +43,7 @@ impl<T: 'static + Copy> StandaloneDCacheProducer<T> {
     ) -> Result<(), DCacheError> {
         let ts = self.timestamp.with_ingestion_t(ingestion_t);
         let dref = if let Some((len, f)) = payload {
-            Some(self.inner.dcache.write(len, f)?)
+            Some(self.inner.dcache.write_payload(len, f)?)
         } else {
             None
         };
@@ -1,7 +1,8 @@
 use std::{fmt::Display, path::Path};
 
 use flux_communication::shmem_dir_queues_string_with_base;
-use flux_timing::{IngestionTime, Instant, Nanos, global_clock_not_mocked};
+use flux_timing::{Duration, IngestionTime, Instant, Nanos};
+use type_hash_derive::TypeHash;
 
 use crate::communication::queue::{Producer, Queue, QueueType};
 
@@ -9,7 +10,7 @@ const QUEUE_SIZE: usize = 4096;
 const SAMPLE_WINDOW: u32 = 1024;
 
 /// Aggregated loop metrics over a sampling window
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
 #[repr(C)]
 #[cfg_attr(feature = "wincode", derive(wincode_derive::SchemaRead, wincode_derive::SchemaWrite))]
 pub struct TileSample {
@@ -21,6 +22,11 @@ pub struct TileSample {
     pub busy_sum: u64,
     pub busy_count: u32,
     pub loop_count: u32,
+    /// Paced loops that overran their deadline, see
+    /// [`TileMetrics::missed_deadline`].
+    pub missed_deadlines: u32,
+    pub lateness_max: u64,
+    pub lateness_sum: u64,
 }
 
 impl TileSample {
@@ -32,6 +38,9 @@ impl TileSample {
         self.busy_sum = 0;
         self.busy_count = 0;
         self.loop_count = 0;
+        self.missed_deadlines = 0;
+        self.lateness_max = 0;
+        self.lateness_sum = 0;
     }
 
     #[inline]
@@ -55,6 +64,15 @@ impl TileSample {
         if self.busy_count == 0 { 0 } else { self.busy_sum / self.busy_count as u64 }
     }
 
+    #[inline]
+    pub fn lateness_avg(&self) -> u64 {
+        if self.missed_deadlines == 0 {
+            0
+        } else {
+            self.lateness_sum / self.missed_deadlines as u64
+        }
+    }
+
     /// `self.busy_min` will be `u64::MAX` if no entries.
     #[inline]
     pub fn busy_min(&self) -> u64 {
@@ -65,7 +83,8 @@ impl TileSample {
 /// Per-tile loop instrumentation.
 ///
 /// Emits a `TileSample` to `producer` every `SAMPLE_WINDOW` iterations.
-/// Tracks busy time, idle time, and some per-work-iteration min/max/avg.
+/// Tracks busy time, idle time, some per-work-iteration min/max/avg, and
+/// the deadlines missed by paced loops.
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct TileMetrics {
@@ -84,7 +103,8 @@ impl TileMetrics {
         let _ = std::fs::create_dir_all(&dirstr);
 
         let file = format!("{dirstr}/tilemetrics-{tile_name}");
-        let queue = Queue::create_or_open_shared(file, QUEUE_SIZE, QueueType::SPMC);
+        let queue = Queue::create_or_open_shared(&file, QUEUE_SIZE, QueueType::SPMC)
+            .unwrap_or_else(|e| panic!("couldn't open tile metrics queue {file}: {e}"));
 
         Self {
             latest_begin: Instant::default(),
@@ -97,17 +117,18 @@ impl TileMetrics {
     pub fn begin(&mut self, now: IngestionTime) {
         self.latest_begin = now.internal();
         if self.sample.loop_count == 0 {
-            self.sample.window_start = now.real();
+            self.sample.window_start = now.internal().to_wall();
         }
     }
 
     #[inline]
     pub fn end(&mut self, did_work: bool) {
         if did_work {
-            let ticks = Instant::now().0.saturating_sub(self.latest_begin.0);
+            let ticks = Instant::now().saturating_sub(self.latest_begin);
             // Convert TSC ticks → nanoseconds so busy_ticks is in the same
-            // unit as total_ticks() (which is derived from Nanos).
-            let nanos = global_clock_not_mocked().delta_as_nanos(0, ticks);
+            // unit as total_ticks() (which is derived from Nanos), at the
+            // app's shared rate.
+            let nanos = Nanos::from(ticks).0;
 
             self.sample.busy_ticks += nanos;
             self.sample.busy_sum += nanos;
@@ -128,9 +149,19 @@ impl TileMetrics {
         }
     }
 
+    /// Records a paced loop that started its wait `lateness` past the
+    /// deadline.
+    #[inline]
+    pub fn missed_deadline(&mut self, lateness: Duration) {
+        let nanos = Nanos::from(lateness).0;
+        self.sample.missed_deadlines += 1;
+        self.sample.lateness_sum += nanos;
+        self.sample.lateness_max = self.sample.lateness_max.max(nanos);
+    }
+
     #[inline]
     fn emit_and_reset(&mut self) {
-        self.sample.window_end = Nanos::now();
+        self.sample.window_end = Instant::now().to_wall();
         self.producer.produce(&self.sample);
         self.sample.reset();
     }
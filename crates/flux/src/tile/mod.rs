@@ -2,15 +2,28 @@ pub mod metrics;
 
 use core::sync::atomic::Ordering;
 
-use flux_timing::{Duration, IngestionTime};
-use flux_utils::{ShortTypename, ThreadNiceness, get_tid, short_typename, thread_boot, vsync};
+use flux_communication::attached_tsc_calibration;
+use flux_timing::{Duration, IngestionTime, Nanos, Repeater};
+use flux_utils::{
+    Pace, Pacer, RealtimeProfile, ShortTypename, ThreadNiceness, get_tid, short_typename,
+    thread_boot_with_profile,
+};
 use tracing::{Level, info, span};
 
 use crate::{
-    spine::{FluxSpine, ScopedSpine, SpineAdapter},
+    spine::{FluxSpine, ScopedSpine, SpineAdapter, SpineConsumers},
     tile::metrics::TileMetrics,
 };
 
+/// How often tiles check that the shared TSC calibration was re-anchored to
+/// the wall clock, see
+/// [`SharedTscCalibration::maintain`](crate::communication::SharedTscCalibration::maintain).
+const TSC_REANCHOR_INTERVAL: Nanos = Nanos::from_secs(60);
+/// How often tiles renew the consumer group leases of their queues, and the
+/// longest a parked tile sleeps. Well below the default group lease, see
+/// [`Queue::set_group_lease`](crate::communication::queue::Queue::set_group_lease).
+const GROUP_RENEW_INTERVAL: Nanos = Nanos::from_secs(1);
+
 pub type TileID = u16;
 pub type TileName = ShortTypename;
 
@@ -20,23 +33,45 @@ pub struct TileConfig {
     thread_niceness: Option<ThreadNiceness>,
     min_loop_duration: Option<Duration>,
     metrics: bool,
+    realtime: RealtimeProfile,
 }
 
 impl TileConfig {
     pub fn new(core: usize, thread_niceness: Option<ThreadNiceness>) -> Self {
-        Self { core: Some(core), thread_niceness, min_loop_duration: None, metrics: true }
+        Self {
+            core: Some(core),
+            thread_niceness,
+            min_loop_duration: None,
+            metrics: true,
+            realtime: RealtimeProfile::default(),
+        }
     }
 
     /// Boot a tile with a background (non-hot-path) config.
-    /// Supports optional vsync pacing and inherits the process niceness.
+    /// Runs a loop every `min_loop_duration` if set, on deadlines that don't
+    /// drift (see [`Pacer`]), and inherits the process niceness.
     pub fn background(core: Option<usize>, min_loop_duration: Option<Duration>) -> Self {
-        Self { core, thread_niceness: None, min_loop_duration, metrics: true }
+        Self {
+            core,
+            thread_niceness: None,
+            min_loop_duration,
+            metrics: true,
+            realtime: RealtimeProfile::default(),
+        }
     }
 
     pub fn without_metrics(mut self) -> Self {
         self.metrics = false;
         self
     }
+
+    /// Real-time hardening applied when the tile thread boots, e.g.
+    /// [`RealtimeProfile::hot`]. The outcome of every step is logged in the
+    /// tile's boot summary.
+    pub fn with_realtime(mut self, profile: RealtimeProfile) -> Self {
+        self.realtime = profile;
+        self
+    }
 }
 
 /// Tile is a fixed execution unit pinned to a CPU core.
@@ -65,7 +100,8 @@ pub trait Tile<S: FluxSpine>: Send + Sized {
 }
 
 /// Boot and run a tile thread.
-/// Configures affinity and niceness, then executes the tile lifecycle.
+/// Configures affinity, niceness and the real-time profile, then executes the
+/// tile lifecycle.
 /// Does not exit until the global stop flag is set.
 pub fn attach_tile<'a, S, T>(tile: T, spine: &mut ScopedSpine<'a, '_, S>, config: TileConfig)
 where
@@ -110,7 +146,7 @@ where
 
     move || {
         let _span = span!(Level::INFO, "", tile = %tile.name()).entered();
-        thread_boot(config.core, config.thread_niceness);
+        thread_boot_with_profile(config.core, config.thread_niceness, &config.realtime);
 
         while !tile.try_init(&mut adapter) {
             if stop_flag.load(Ordering::Relaxed) != 0 {
@@ -125,6 +161,9 @@ where
         #[cfg(feature = "park")]
         let mut expected = crate::park::SIGNAL.read_counter();
 
+        let mut pacer = config.min_loop_duration.filter(|d| *d != Duration(0)).map(Pacer::new);
+        let mut reanchor = Repeater::every(TSC_REANCHOR_INTERVAL.into());
+        let mut renew_groups = Repeater::every(GROUP_RENEW_INTERVAL.into());
         loop {
             let ingestion_t = IngestionTime::now();
 
@@ -132,16 +171,31 @@ where
                 m.begin(ingestion_t);
             }
 
-            vsync(config.min_loop_duration, || {
-                adapter.begin_loop(ingestion_t);
-                tile.loop_body(&mut adapter);
-            });
+            adapter.begin_loop(ingestion_t);
+            tile.loop_body(&mut adapter);
 
             let worked = adapter.did_work();
             if let Some(m) = &mut metrics {
                 m.end(worked);
             }
 
+            if let Some(pacer) = &mut pacer &&
+                let Pace::Missed { lateness, .. } = pacer.wait() &&
+                let Some(m) = &mut metrics
+            {
+                m.missed_deadline(lateness);
+            }
+
+            if reanchor.fired_at(ingestion_t.internal()) &&
+                let Some(calibration) = attached_tsc_calibration()
+            {
+                calibration.maintain(TSC_REANCHOR_INTERVAL);
+            }
+
+            if renew_groups.fired_at(ingestion_t.internal()) {
+                adapter.consumers.renew_groups();
+            }
+
             if stop_flag.load(Ordering::Relaxed) != 0 {
                 break;
             }
@@ -149,7 +203,7 @@ where
             #[cfg(feature = "park")]
             {
                 if !worked && !adapter.waker_registered() {
-                    crate::park::SIGNAL.park(expected);
+                    crate::park::SIGNAL.park_timeout(expected, GROUP_RENEW_INTERVAL.into());
                 }
                 expected = crate::park::SIGNAL.read_counter();
             }
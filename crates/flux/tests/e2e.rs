@@ -8,12 +8,13 @@ use flux::{
     persistence::Persistable,
     spine::{SpineAdapter, SpineQueue},
     tile::{Tile, TileConfig, TileInfo, attach_tile},
+    type_hash_derive::TypeHash,
 };
 use flux_timing::Duration;
 use serde::{Deserialize, Serialize};
 use spine_derive::from_spine;
 
-#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize, TypeHash)]
 #[repr(C)]
 struct TestMsg(u64);
 
@@ -21,7 +22,7 @@ impl Persistable for TestMsg {
     const PERSIST_DIR: &'static str = "test_msg";
 }
 
-#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize, TypeHash)]
 #[repr(C)]
 struct OtherTestMsg(u8);
 
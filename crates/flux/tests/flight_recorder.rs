@@ -0,0 +1,141 @@
+use flux::{
+    communication::{QueueError, ShmemData},
+    persistence::{FlightRecord, FlightRecorder, Persistable},
+    spine::{SpineAdapter, SpineQueue},
+    tile::{Tile, TileConfig, TileInfo, attach_tile},
+    type_hash_derive::TypeHash,
+};
+use flux_timing::{Duration, InternalMessage, TrackingTimestamp};
+use spine_derive::from_spine;
+
+#[test]
+fn record_dump_and_decode() {
+    let tmp = tempfile::tempdir().unwrap();
+    let recorder = FlightRecorder::open(tmp.path(), "recorder-test").unwrap();
+    let ring = recorder.ring::<u64>(1 << 12).unwrap();
+    for i in 0..100u64 {
+        let msg = InternalMessage::new(TrackingTimestamp::new(0), i);
+        ring.record(&msg, &i.to_le_bytes()[..(i % 8) as usize]);
+    }
+
+    let path = recorder.dump("test").unwrap();
+    assert!(!recorder.is_frozen());
+    let filename = path.file_stem().unwrap().to_string_lossy().into_owned();
+    let records = FlightRecord::load_with_base_dir(tmp.path(), "recorder-test", filename)
+        .expect("dump is a persistence file");
+
+    // The ring wrapped, only the newest messages are left.
+    assert!(records.len() < 100);
+    assert!(!records.is_empty());
+    let values: Vec<u64> = records.iter().map(|r| r.decode::<u64>().unwrap().into_data()).collect();
+    assert!(values.windows(2).all(|w| w[1] == w[0] + 1));
+    assert_eq!(values.last(), Some(&99));
+    for (record, v) in records.iter().zip(&values) {
+        assert_eq!(record.payload, v.to_le_bytes()[..(v % 8) as usize]);
+    }
+    assert!(records[0].decode::<u32>().is_none());
+}
+
+#[test]
+fn frozen_ring_skips_records() {
+    let tmp = tempfile::tempdir().unwrap();
+    let recorder = FlightRecorder::open(tmp.path(), "recorder-freeze").unwrap();
+    let ring = recorder.ring::<u32>(1 << 10).unwrap();
+    ring.record(&InternalMessage::new(TrackingTimestamp::new(0), 1), &[]);
+    recorder.freeze();
+    ring.record(&InternalMessage::new(TrackingTimestamp::new(0), 2), &[]);
+    assert_eq!(recorder.records().len(), 1);
+
+    recorder.unfreeze();
+    assert!(recorder.trigger("first").is_some());
+    assert!(recorder.trigger("second").is_none(), "triggers are rate limited");
+}
+
+#[test]
+fn recorders_of_the_same_queue_get_their_own_rings() {
+    let tmp = tempfile::tempdir().unwrap();
+    std::thread::scope(|s| {
+        for writer in 0..2u64 {
+            let base_dir = tmp.path();
+            s.spawn(move || {
+                let recorder = FlightRecorder::open(base_dir, "recorder-writers").unwrap();
+                let ring = recorder.ring::<u64>(1 << 16).unwrap();
+                for i in 0..500u64 {
+                    let v = 2 * i + writer;
+                    ring.record(
+                        &InternalMessage::new(TrackingTimestamp::new(0), v),
+                        &v.to_le_bytes(),
+                    );
+                }
+            });
+        }
+    });
+
+    let recorder = FlightRecorder::open(tmp.path(), "recorder-writers").unwrap();
+    let records = recorder.records();
+    assert!(records.iter().all(|r| r.queue == "u64"));
+    let mut values: Vec<u64> = records
+        .iter()
+        .map(|r| {
+            let v = r.decode::<u64>().unwrap().into_data();
+            assert_eq!(r.payload, v.to_le_bytes());
+            v
+        })
+        .collect();
+    values.sort_unstable();
+    assert_eq!(values, (0..1000).collect::<Vec<_>>());
+}
+
+#[test]
+fn ring_rejects_messages_larger_than_a_record() {
+    let tmp = tempfile::tempdir().unwrap();
+    let recorder = FlightRecorder::open(tmp.path(), "recorder-too-large").unwrap();
+    let err = recorder.ring::<[u8; 1024]>(1 << 10).unwrap_err();
+    assert!(matches!(err, QueueError::TooLarge(_)), "{err}");
+}
+
+#[derive(Clone, Copy, Debug, Default, TypeHash)]
+#[repr(C)]
+struct Tick(u64);
+
+#[from_spine("recorder-spine-test")]
+#[derive(Debug)]
+struct RecordedSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(record, size(1024))]
+    pub ticks: SpineQueue<Tick>,
+}
+
+struct Ticker {
+    recorder: FlightRecorder,
+    n: u64,
+}
+
+impl Tile<RecordedSpine> for Ticker {
+    fn loop_body(&mut self, adapter: &mut SpineAdapter<RecordedSpine>) {
+        adapter.produce(Tick(self.n));
+        self.n += 1;
+        if self.recorder.records().iter().any(|r| r.decode::<Tick>().is_some()) {
+            adapter.request_stop_scope();
+        }
+    }
+}
+
+#[test]
+fn recorded_queue_attaches_recorder() {
+    let tmp = tempfile::tempdir().unwrap();
+    let spine = RecordedSpine::new_with_base_dir(tmp.path(), None);
+    let recorder = FlightRecorder::open(tmp.path(), "recorder-spine-test").unwrap();
+
+    spine.start_no_persist(None, None, |scoped| {
+        attach_tile(
+            Ticker { recorder, n: 0 },
+            scoped,
+            TileConfig::background(None, Some(Duration::from_millis(1))),
+        );
+    });
+
+    let recorder = FlightRecorder::open(tmp.path(), "recorder-spine-test").unwrap();
+    let path = recorder.dump("manual").expect("ticks were recorded");
+    assert!(path.exists());
+}
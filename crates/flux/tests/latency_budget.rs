@@ -0,0 +1,98 @@
+use flux::{
+    communication::{LatencyBudget, ShmemData},
+    spine::{QueueParams, SpineAdapter, SpineQueue},
+    tile::{Tile, TileInfo},
+    timing::{Duration, IngestionTime, Instant, Nanos},
+    type_hash_derive::TypeHash,
+    utils::directories::shmem_dir_budgets_with_base,
+};
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Order {
+    id: u64,
+}
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Quote {
+    id: u64,
+}
+
+#[from_spine("latency-budget-test-app")]
+#[derive(Debug)]
+struct BudgetSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(64), budget = "5us")]
+    pub orders: SpineQueue<Order>,
+    #[queue(size(64))]
+    pub quotes: SpineQueue<Quote>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Gateway;
+
+impl Tile<BudgetSpine> for Gateway {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<BudgetSpine>) {}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Router;
+
+impl Tile<BudgetSpine> for Router {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<BudgetSpine>) {}
+}
+
+#[test]
+fn consumers_check_latencies_against_the_queue_budget() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let config = BudgetSpineConfig::default();
+    assert_eq!(config.orders.budget, Some(Nanos::from_micros(5)));
+    assert_eq!(config.quotes.budget, None);
+
+    let mut spine = BudgetSpine::new_with_base_dir(tmp.path(), None);
+    let mut gateway = SpineAdapter::connect_tile(&Gateway, &mut spine);
+    let mut router = SpineAdapter::connect_tile(&Router, &mut spine);
+
+    let dir = shmem_dir_budgets_with_base(tmp.path(), "latency-budget-test-app");
+    assert!(!dir.join("Router-Quote").exists());
+    let budget = LatencyBudget::open_shared(dir.join("Router-Order")).unwrap();
+    assert_eq!(budget.budget(), Nanos::from_micros(5));
+
+    router.consume(|_: Order, _| {});
+    let now = IngestionTime::now();
+    let stale = IngestionTime::new(
+        now.real() - Nanos::from_millis(1),
+        Instant(now.internal().0 - Duration::from(Nanos::from_millis(1)).0),
+    );
+    for id in 0..4 {
+        gateway.set_ingestion_time(if id % 2 == 0 { stale } else { IngestionTime::now() });
+        gateway.produce(Order { id });
+    }
+    let mut consumed = 0;
+    router.consume(|_: Order, _| consumed += 1);
+    assert_eq!(consumed, 4);
+
+    let snapshot = budget.snapshot();
+    assert_eq!(snapshot.checked, 4);
+    assert!(snapshot.violations >= 2, "{snapshot:?}");
+    assert!(snapshot.worst[0].latency >= Nanos::from_millis(1), "{snapshot:?}");
+    assert!(snapshot.compliance().unwrap() <= 50.0);
+}
+
+#[test]
+fn config_sets_budgets_of_queues_without_one() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let defaults = BudgetSpineConfig::default();
+    let config = BudgetSpineConfig {
+        quotes: QueueParams { budget: Some(Nanos::from_micros(20)), ..defaults.quotes },
+        ..defaults
+    };
+    let mut spine = BudgetSpine::new_with_base_dir_and_config(tmp.path(), None, config);
+    let _router = SpineAdapter::connect_tile(&Router, &mut spine);
+
+    let dir = shmem_dir_budgets_with_base(tmp.path(), "latency-budget-test-app");
+    let budget = LatencyBudget::open_shared(dir.join("Router-Quote")).unwrap();
+    assert_eq!(budget.budget(), Nanos::from_micros(20));
+}
@@ -0,0 +1,74 @@
+#[cfg(feature = "mock")]
+mod tests {
+    use flux::{
+        communication::{
+            queue::{Consumer, Queue},
+            shmem_dir_queues_string_with_base,
+        },
+        tile::metrics::{TileMetrics, TileSample},
+        timing::{
+            Duration, IngestionTime, Instant, Nanos, Repeater, Schedule, ScheduleSpec,
+            init_global_with_mock,
+        },
+        utils::{Pace, Pacer},
+    };
+
+    // One test: the mock is global to the process.
+    #[test]
+    fn mock_drives_every_clock_in_step() {
+        let mock = init_global_with_mock();
+
+        let (t0, n0) = (Instant::now(), Nanos::now());
+        mock.increment(1_500);
+        assert_eq!(Instant::now() - t0, Duration::from_nanos(1_500));
+        assert_eq!(Nanos::now() - n0, Nanos(1_500));
+        assert_eq!(Instant::now().to_wall(), Nanos::now());
+        assert_eq!(Duration::from_micros(3), Duration(3_000));
+
+        let mut repeater = Repeater::every(Duration::from_micros(10));
+        repeater.reset();
+        mock.increment(9_999);
+        assert!(!repeater.fired());
+        mock.increment(1);
+        assert!(repeater.fired());
+
+        let mut schedule = Schedule::new("every 1ms".parse::<ScheduleSpec>().unwrap());
+        let boundary =
+            Nanos::now().round_to_interval(Nanos::from_millis(1)) + Nanos::from_millis(1);
+        assert_eq!(schedule.next_fire(), boundary);
+        mock.increment((boundary - Nanos::now()).0 - 1);
+        assert!(!schedule.fired());
+        mock.increment(1);
+        assert!(schedule.fired());
+        assert_eq!(Nanos::now(), schedule.last_fire());
+
+        let mut pacer = Pacer::new(Duration::from_micros(100));
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(pacer.wait(), Pace::OnTime);
+        }
+        assert_eq!(Instant::now() - start, Duration::from_micros(1_000));
+        mock.increment(250_000);
+        assert_eq!(pacer.wait(), Pace::Missed { lateness: Duration::from_micros(150), skipped: 1 });
+
+        let tmp = tempfile::tempdir().expect("create temp dir");
+        let mut metrics = TileMetrics::new(tmp.path(), "mock-clock-test-app", "Mocked");
+        let dir = shmem_dir_queues_string_with_base(tmp.path(), "mock-clock-test-app");
+        let queue =
+            Queue::<TileSample>::try_open_shared(format!("{dir}/tilemetrics-Mocked")).unwrap();
+        let mut consumer = Consumer::new(queue, "mock-clock-test").without_log();
+        assert!(!consumer.consume(|_| ()));
+        for i in 0..1024u64 {
+            metrics.begin(IngestionTime::now());
+            mock.increment(100 + i % 2 * 600);
+            metrics.end(true);
+        }
+        let mut samples = Vec::new();
+        while consumer.consume(|s| samples.push(*s)) {}
+        assert_eq!(samples.len(), 1);
+        let sample = samples[0];
+        assert_eq!((sample.busy_min(), sample.busy_max), (100, 700));
+        assert_eq!(sample.busy_ticks, 512 * 800);
+        assert_eq!(sample.total_ticks(), 512 * 800);
+    }
+}
@@ -73,4 +73,50 @@ mod tests {
 
         assert!(found, "mio waker was not woken");
     }
+
+    const SHARED_DIR_ENV: &str = "FLUX_PARK_SHARED_DIR";
+
+    #[test]
+    fn test_park_cross_process() {
+        // Child side: re-executed below with the parent's base dir.
+        if let Ok(dir) = std::env::var(SHARED_DIR_ENV) {
+            let signal = Signal::new();
+            signal.attach_shared(&dir, "park_test").unwrap();
+            signal.signal();
+            return;
+        }
+
+        let tmp = tempfile::tempdir().expect("create temp dir");
+        let signal = Arc::new(Signal::new());
+        signal.attach_shared(tmp.path(), "park_test").unwrap();
+        assert!(signal.is_shared());
+
+        let signal_clone = signal.clone();
+        let parked = Arc::new(AtomicBool::new(false));
+        let parked_clone = parked.clone();
+        let counter = signal.read_counter();
+
+        let handle = thread::spawn(move || {
+            parked_clone.store(true, Ordering::Release);
+            signal_clone.park(counter);
+            parked_clone.store(false, Ordering::Release);
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(parked.load(Ordering::Acquire));
+
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::test_park_cross_process", "--nocapture"])
+            .env(SHARED_DIR_ENV, tmp.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while parked.load(Ordering::Acquire) {
+            assert!(std::time::Instant::now() < deadline, "not woken by the other process");
+            thread::sleep(Duration::from_millis(1));
+        }
+        handle.join().unwrap();
+    }
 }
@@ -0,0 +1,115 @@
+use flux::{
+    communication::{ShmemData, cleanup_shmem, queue::GroupKind},
+    spine::{SpineAdapter, SpineQueue},
+    tile::{Tile, TileInfo},
+    type_hash_derive::TypeHash,
+};
+use spine_derive::from_spine;
+
+const SHARED_DIR_ENV: &str = "FLUX_CHECKPOINT_TEST_DIR";
+const STEP_ENV: &str = "FLUX_CHECKPOINT_TEST_STEP";
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Job(u64);
+
+#[from_spine("spine-checkpoint-test-app")]
+#[derive(Debug)]
+struct CheckpointSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(64))]
+    pub jobs: SpineQueue<Job>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Feeder;
+
+impl Tile<CheckpointSpine> for Feeder {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<CheckpointSpine>) {}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Worker;
+
+impl Tile<CheckpointSpine> for Worker {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<CheckpointSpine>) {}
+}
+
+fn produce(spine: &mut CheckpointSpine, jobs: std::ops::Range<u64>) {
+    let mut adapter = SpineAdapter::connect_tile(&Feeder, spine);
+    for i in jobs {
+        adapter.produce(Job(i));
+    }
+}
+
+fn consume_all(adapter: &mut SpineAdapter<CheckpointSpine>) -> Vec<u64> {
+    let mut seen = Vec::new();
+    adapter.consume(|job: Job, _| seen.push(job.0));
+    seen
+}
+
+/// One run of the consuming process, re-executed by the test for each step.
+fn worker_step(spine: &mut CheckpointSpine, step: &str) {
+    let mut adapter = SpineAdapter::connect_tile(&Worker, spine);
+    match step {
+        // First run: no checkpoint yet, starts at the head.
+        "first" => {
+            assert!(consume_all(&mut adapter).is_empty());
+            produce(spine, 0..10);
+            let mut seen = Vec::new();
+            adapter.consume_n(4, |job: Job, _| seen.push(job.0));
+            assert_eq!(seen, [0, 1, 2, 3]);
+            adapter.commit::<Job>();
+        }
+        // Restarted while 4..20 were produced: picks up right after the
+        // commit.
+        "resume" => {
+            assert_eq!(consume_all(&mut adapter), (4..20).collect::<Vec<_>>());
+            adapter.commit::<Job>();
+        }
+        // Down for longer than the ring holds: starts at the oldest message
+        // still there.
+        "lost" => {
+            assert_eq!(consume_all(&mut adapter), (156..220).collect::<Vec<_>>());
+        }
+        _ => unreachable!("unknown step {step}"),
+    }
+}
+
+fn run_worker(dir: &std::path::Path, step: &str) {
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "consumer_resumes_from_checkpoint", "--nocapture"])
+        .env(SHARED_DIR_ENV, dir)
+        .env(STEP_ENV, step)
+        .status()
+        .unwrap();
+    assert!(status.success(), "worker step {step} failed");
+}
+
+#[test]
+fn consumer_resumes_from_checkpoint() {
+    if let Ok(dir) = std::env::var(SHARED_DIR_ENV) {
+        let mut spine = CheckpointSpine::new_with_base_dir(&dir, None);
+        worker_step(&mut spine, &std::env::var(STEP_ENV).unwrap());
+        return;
+    }
+
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let base = tmp.path();
+    let mut spine = CheckpointSpine::new_with_base_dir(base, None);
+
+    run_worker(base, "first");
+    produce(&mut spine, 10..20);
+    run_worker(base, "resume");
+
+    let checkpoints: Vec<_> =
+        spine.jobs.groups().into_iter().filter(|g| g.kind == GroupKind::Checkpoint).collect();
+    assert_eq!(checkpoints.len(), 1);
+    assert_eq!(checkpoints[0].cursor, 20);
+    assert!(!checkpoints[0].expired, "checkpoints outlive their process");
+
+    produce(&mut spine, 20..220);
+    run_worker(base, "lost");
+
+    cleanup_shmem(base);
+}
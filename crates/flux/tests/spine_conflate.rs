@@ -0,0 +1,75 @@
+use flux::{
+    communication::{FullError, ShmemData},
+    spine::SpineAdapter,
+    tile::{Tile, TileInfo},
+    type_hash_derive::TypeHash,
+};
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, TypeHash)]
+#[repr(C)]
+struct TopOfBook {
+    symbol: u32,
+    bid: u64,
+}
+
+#[from_spine("spine-conflate-test-app")]
+#[derive(Debug)]
+struct ConflateSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(conflate(key = |tob| tob.symbol), size(4))]
+    pub books: SpineQueue<TopOfBook>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Feed;
+
+impl Tile<ConflateSpine> for Feed {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<ConflateSpine>) {}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Strategy;
+
+impl Tile<ConflateSpine> for Strategy {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<ConflateSpine>) {}
+}
+
+fn drain(adapter: &mut SpineAdapter<ConflateSpine>) -> Vec<TopOfBook> {
+    let mut seen = Vec::new();
+    adapter.consume_conflated(|tob: TopOfBook, _| seen.push(tob));
+    seen.sort_unstable_by_key(|tob| tob.symbol);
+    seen
+}
+
+#[test]
+fn consumers_get_the_latest_book_per_symbol() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    assert_eq!(ConflateSpineConfig::default().books.keys, 4);
+
+    let mut spine = ConflateSpine::new_with_base_dir(tmp.path(), None);
+    let mut feed = SpineAdapter::connect_tile(&Feed, &mut spine);
+    let mut strategy = SpineAdapter::connect_tile(&Strategy, &mut spine);
+
+    for bid in 0..100 {
+        for symbol in 0..3 {
+            feed.produce_conflated(TopOfBook { symbol, bid }).unwrap();
+        }
+    }
+    let latest: Vec<_> = (0..3).map(|symbol| TopOfBook { symbol, bid: 99 }).collect();
+    assert_eq!(drain(&mut strategy), latest);
+    assert!(strategy.did_work());
+    assert!(drain(&mut strategy).is_empty());
+
+    feed.produce_conflated(TopOfBook { symbol: 2, bid: 100 }).unwrap();
+    assert_eq!(drain(&mut strategy), [TopOfBook { symbol: 2, bid: 100 }]);
+
+    // A tile attached later starts from the full state after a replay.
+    let mut late = SpineAdapter::connect_tile(&Strategy, &mut spine);
+    assert!(drain(&mut late).is_empty());
+    late.replay_conflated::<TopOfBook>();
+    assert_eq!(drain(&mut late).len(), 3);
+
+    feed.produce_conflated(TopOfBook { symbol: 3, bid: 0 }).unwrap();
+    assert_eq!(feed.produce_conflated(TopOfBook { symbol: 4, bid: 0 }), Err(FullError::Full));
+}
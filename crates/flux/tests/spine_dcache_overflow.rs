@@ -0,0 +1,101 @@
+use flux::{
+    communication::ShmemData,
+    spine::{DCacheRead, SpineAdapter},
+    tile::{Tile, TileInfo},
+    type_hash_derive::TypeHash,
+};
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Snapshot {
+    seq: u64,
+}
+
+#[from_spine("spine-dcache-overflow-test-app")]
+#[derive(Debug)]
+struct OverflowSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(64), mtu(128), overflow(1 << 12))]
+    pub snapshots: SpineQueue<Snapshot>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Publisher;
+
+impl Tile<OverflowSpine> for Publisher {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<OverflowSpine>) {}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Reader;
+
+impl Tile<OverflowSpine> for Reader {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<OverflowSpine>) {}
+}
+
+fn publish(adapter: &mut SpineAdapter<OverflowSpine>, seq: u64, len: usize) {
+    adapter
+        .produce_with_dcache(Snapshot { seq }, Some((len, |buf: &mut [u8]| buf.fill(seq as u8))))
+        .unwrap();
+}
+
+/// `Some(len)` for intact payloads, `None` for lost ones.
+fn read_all(adapter: &mut SpineAdapter<OverflowSpine>) -> Vec<(u64, Option<usize>)> {
+    let mut seen = Vec::new();
+    adapter.consume_with_dcache(
+        |msg: Snapshot, payload| {
+            payload.iter().all(|&b| b == msg.seq as u8).then_some(payload.len())
+        },
+        |read, _| match read {
+            DCacheRead::Ok((msg, len)) => seen.push((msg.seq, Some(len.expect("payload intact")))),
+            DCacheRead::Lost(msg) => seen.push((msg.seq, None)),
+            DCacheRead::NoRef(_) | DCacheRead::SpedPast => panic!("unexpected {read:?}"),
+            DCacheRead::Empty => {}
+        },
+    );
+    seen
+}
+
+#[test]
+fn payloads_above_the_mtu_use_the_overflow_region() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    assert_eq!(OverflowSpineConfig::default().snapshots.overflow, 1 << 12);
+
+    let mut spine = OverflowSpine::new_with_base_dir(tmp.path(), None);
+    assert!(spine.snapshots_dcache.overflow().is_some());
+    let mut publisher = SpineAdapter::connect_tile(&Publisher, &mut spine);
+    let mut reader = SpineAdapter::connect_tile(&Reader, &mut spine);
+    assert!(read_all(&mut reader).is_empty());
+
+    publish(&mut publisher, 1, 100);
+    publish(&mut publisher, 2, 3000);
+    publish(&mut publisher, 3, 128);
+    assert_eq!(read_all(&mut reader), [(1, Some(100)), (2, Some(3000)), (3, Some(128))]);
+}
+
+// Four 2000 byte snapshots lap the 4096 byte overflow region well before the
+// queue, the first two must be reported lost as a whole.
+#[test]
+fn lapped_overflow_payloads_are_lost() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let mut spine = OverflowSpine::new_with_base_dir(tmp.path(), None);
+    let mut publisher = SpineAdapter::connect_tile(&Publisher, &mut spine);
+    let mut reader = SpineAdapter::connect_tile(&Reader, &mut spine);
+    assert!(read_all(&mut reader).is_empty());
+
+    for seq in 0..4 {
+        publish(&mut publisher, seq, 2000);
+        publish(&mut publisher, 100 + seq, 64);
+    }
+    assert_eq!(read_all(&mut reader), [
+        (0, None),
+        (100, Some(64)),
+        (1, None),
+        (101, Some(64)),
+        (2, Some(2000)),
+        (102, Some(64)),
+        (3, Some(2000)),
+        (103, Some(64)),
+    ]);
+}
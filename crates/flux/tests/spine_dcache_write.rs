@@ -0,0 +1,101 @@
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+};
+
+use flux::{
+    communication::ShmemData,
+    spine::{DCacheRead, SpineAdapter},
+    tile::{Tile, TileInfo},
+    timing::Nanos,
+    type_hash_derive::TypeHash,
+};
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, TypeHash)]
+#[repr(C)]
+struct Frame {
+    id: u64,
+}
+
+#[from_spine("spine-dcache-write-test-app")]
+#[derive(Debug)]
+struct FrameSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(64), mtu(256))]
+    pub frames: SpineQueue<Frame>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Gateway;
+
+impl Tile<FrameSpine> for Gateway {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<FrameSpine>) {}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Handler;
+
+impl Tile<FrameSpine> for Handler {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<FrameSpine>) {}
+}
+
+fn read_all(adapter: &mut SpineAdapter<FrameSpine>) -> Vec<(u64, Vec<u8>)> {
+    let mut seen = Vec::new();
+    adapter.consume_with_dcache(
+        |_: Frame, payload| payload.to_vec(),
+        |read, _| match read {
+            DCacheRead::Ok((frame, payload)) => seen.push((frame.id, payload)),
+            DCacheRead::Empty => {}
+            _ => panic!("unexpected {read:?}"),
+        },
+    );
+    seen
+}
+
+#[test]
+fn header_and_body_are_written_without_staging() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let mut spine = FrameSpine::new_with_base_dir(tmp.path(), None);
+    let mut gateway = SpineAdapter::connect_tile(&Gateway, &mut spine);
+    let mut handler = SpineAdapter::connect_tile(&Handler, &mut spine);
+    assert!(read_all(&mut handler).is_empty());
+
+    let header = 7u32.to_le_bytes();
+    gateway.produce_with_dcache_vectored(Frame { id: 1 }, &[&header, b"payload"]).unwrap();
+    assert!(gateway.did_work());
+    assert_eq!(read_all(&mut handler), [(1, b"\x07\0\0\0payload".to_vec())]);
+}
+
+#[test]
+fn reservations_are_filled_from_a_socket_and_published_by_ref() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let mut spine = FrameSpine::new_with_base_dir(tmp.path(), None);
+    let mut gateway = SpineAdapter::connect_tile(&Gateway, &mut spine);
+    let mut handler = SpineAdapter::connect_tile(&Handler, &mut spine);
+    assert!(read_all(&mut handler).is_empty());
+
+    let (mut tx, mut rx) = UnixStream::pair().unwrap();
+    rx.set_nonblocking(true).unwrap();
+    let dcache = gateway.dcache_ptr::<Frame>();
+
+    // The frame arrives in two parts, the reservation is filled as each
+    // becomes readable.
+    let mut reservation = dcache.reserve_payload(10).unwrap();
+    reservation.write(b"#");
+    tx.write_all(b"0123").unwrap();
+    assert_eq!(reservation.fill(|buf| rx.read(buf)).unwrap(), 4);
+    let would_block = reservation.fill(|buf| rx.read(buf)).unwrap_err();
+    assert_eq!(would_block.kind(), io::ErrorKind::WouldBlock);
+    tx.write_all(b"45678").unwrap();
+    assert_eq!(reservation.fill(|buf| rx.read(buf)).unwrap(), 5);
+    assert!(reservation.is_complete());
+    gateway.produce_with_dref(Frame { id: 2 }, reservation.commit(), Nanos::now());
+
+    // An aborted frame is never seen by consumers.
+    let mut aborted = dcache.reserve_payload(10).unwrap();
+    aborted.write(b"partial");
+    aborted.abort();
+
+    assert_eq!(read_all(&mut handler), [(2, b"#012345678".to_vec())]);
+}
@@ -0,0 +1,74 @@
+use flux::{
+    communication::{ShmemData, cleanup_shmem, queue::GroupKind},
+    spine::{SpineAdapter, SpineQueue},
+    tile::{Tile, TileConfig, TileInfo, attach_tile},
+    type_hash_derive::TypeHash,
+};
+use flux_timing::Duration;
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Job(u64);
+
+#[from_spine("spine-groups-test-app")]
+#[derive(Debug)]
+struct GroupsSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(64))]
+    pub jobs: SpineQueue<Job>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Feeder(u64);
+
+impl Tile<GroupsSpine> for Feeder {
+    fn loop_body(&mut self, adapter: &mut SpineAdapter<GroupsSpine>) {
+        adapter.produce(Job(self.0));
+        self.0 += 1;
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Worker(u64);
+
+impl Tile<GroupsSpine> for Worker {
+    fn loop_body(&mut self, adapter: &mut SpineAdapter<GroupsSpine>) {
+        adapter.consume(|_: Job, _| self.0 += 1);
+        if self.0 >= 20 {
+            adapter.request_stop_scope();
+        }
+    }
+}
+
+// Tiles leave their consumer groups when they exit, so the slots don't
+// linger until the lease runs out.
+#[test]
+fn tiles_leave_groups_on_exit() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let base = tmp.path();
+    let mut spine = GroupsSpine::new_with_base_dir(base, None);
+
+    std::thread::scope(|scope| {
+        let mut scoped = flux::spine::ScopedSpine::new(&mut spine, scope, None, None);
+        attach_tile(Worker::default(), &mut scoped, TileConfig::background(None, None));
+        attach_tile(
+            Feeder::default(),
+            &mut scoped,
+            TileConfig::background(None, Some(Duration::from_millis(1))),
+        );
+    });
+
+    assert!(spine.jobs.groups().is_empty(), "groups left behind: {:?}", spine.jobs.groups());
+
+    // A consumer attached outside a tile keeps its group until it leaves.
+    let mut adapter = SpineAdapter::connect_tile(&Worker::default(), &mut spine);
+    adapter.consume(|_: Job, _| {});
+    let groups = spine.jobs.groups();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].kind, GroupKind::Broadcast);
+    drop(adapter);
+    assert!(spine.jobs.groups().is_empty());
+
+    cleanup_shmem(base);
+}
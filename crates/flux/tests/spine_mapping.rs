@@ -0,0 +1,77 @@
+use flux::{
+    communication::{MappingOptions, ShmemData, cleanup_shmem},
+    spine::SpineQueue,
+    tile::TileInfo,
+    type_hash_derive::TypeHash,
+};
+use flux_utils::numa::node_of_cpu;
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Tick(u64);
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Blob(u64);
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Plain(u64);
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Pinned(u64);
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Placed(u64);
+
+#[from_spine("spine-mapping-test-app")]
+#[derive(Debug)]
+struct MappingSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(1024), huge_pages, prefault, mlock)]
+    pub ticks: SpineQueue<Tick>,
+    #[queue(size(64), mtu(256), prefault)]
+    pub blobs: SpineQueue<Blob>,
+    #[queue(size(64))]
+    pub plain: SpineQueue<Plain>,
+    #[queue(size(64), producer_core = 0)]
+    pub pinned: SpineQueue<Pinned>,
+    #[queue(size(64), numa_node = 0, producer_core = 0)]
+    pub placed: SpineQueue<Placed>,
+}
+
+#[test]
+fn queue_attributes_set_mapping_options() {
+    let config = MappingSpineConfig::default();
+    assert_eq!(config.ticks.mapping, MappingOptions {
+        huge_pages: true,
+        prefault: true,
+        mlock: true,
+        numa_node: None,
+    });
+    assert_eq!(config.blobs.mapping, MappingOptions { prefault: true, ..Default::default() });
+    assert_eq!(config.plain.mapping, MappingOptions::default());
+    assert_eq!(config.pinned.mapping.numa_node, node_of_cpu(0));
+    assert_eq!(config.placed.mapping.numa_node, Some(0));
+}
+
+// Huge pages and mlock may be unavailable (THP disabled for shmem, low
+// RLIMIT_MEMLOCK); the spine must still come up on normal pages.
+#[test]
+fn spine_opens_with_mapping_options() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let base = tmp.path();
+
+    let spine = MappingSpine::new_with_base_dir(base, None);
+    assert_eq!(spine.ticks.n_slots(), 1024);
+    assert_eq!(spine.blobs.n_slots(), 64);
+
+    // Reopening maps the existing segments with the same options.
+    let reopened = MappingSpine::new_with_base_dir(base, None);
+    assert_eq!(reopened.ticks.n_slots(), 1024);
+
+    cleanup_shmem(base);
+}
@@ -0,0 +1,88 @@
+use std::os::fd::AsRawFd;
+
+use flux::{
+    communication::{
+        ShmemData,
+        memfd::{
+            SEGMENT_BROKER_FD_ENV, child_connection, segment_socket_with_base, serve_segments,
+            use_inherited_segment_broker,
+        },
+    },
+    spine::{SpineAdapter, SpineQueue},
+    tile::{Tile, TileInfo},
+    type_hash_derive::TypeHash,
+};
+use spine_derive::from_spine;
+
+const APP: &str = "spine-memfd-test-app";
+const CHILD_DIR_ENV: &str = "FLUX_MEMFD_TEST_DIR";
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Job(u64);
+
+#[from_spine("spine-memfd-test-app")]
+#[derive(Debug)]
+struct MemfdSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(64))]
+    pub jobs: SpineQueue<Job>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Feeder;
+
+impl Tile<MemfdSpine> for Feeder {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<MemfdSpine>) {}
+}
+
+#[derive(Clone, Copy, Default)]
+struct Worker;
+
+impl Tile<MemfdSpine> for Worker {
+    fn loop_body(&mut self, _adapter: &mut SpineAdapter<MemfdSpine>) {}
+}
+
+// The child sees none of the host's files: it gets a base dir of its own and
+// reaches the segments only through the inherited broker connection.
+#[test]
+fn child_shares_segments_through_the_broker() {
+    if let Ok(dir) = std::env::var(CHILD_DIR_ENV) {
+        assert!(use_inherited_segment_broker().unwrap());
+        let mut spine = MemfdSpine::new_with_base_dir(&dir, None);
+        let mut adapter = SpineAdapter::connect_tile(&Feeder, &mut spine);
+        for i in 0..10 {
+            adapter.produce(Job(i));
+        }
+        return;
+    }
+
+    let host_dir = tempfile::tempdir().expect("create temp dir");
+    let child_dir = tempfile::tempdir().expect("create temp dir");
+    serve_segments(segment_socket_with_base(host_dir.path(), APP)).unwrap();
+
+    let mut spine = MemfdSpine::new_with_base_dir(host_dir.path(), None);
+    let mut adapter = SpineAdapter::connect_tile(&Worker, &mut spine);
+    adapter.consume(|_: Job, _| {});
+
+    let connection = child_connection().unwrap();
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "child_shares_segments_through_the_broker", "--nocapture"])
+        .env(CHILD_DIR_ENV, child_dir.path())
+        .env(SEGMENT_BROKER_FD_ENV, connection.as_raw_fd().to_string())
+        .status()
+        .unwrap();
+    drop(connection);
+    assert!(status.success());
+
+    let mut seen = Vec::new();
+    adapter.consume(|job: Job, _| seen.push(job.0));
+    assert_eq!(seen, (0..10).collect::<Vec<_>>());
+
+    // Neither side left flinks behind.
+    for dir in [host_dir.path(), child_dir.path()] {
+        let queues = dir.join(APP).join("shmem").join("queues");
+        let flinks = std::fs::read_dir(&queues).map(Iterator::count).unwrap_or(0);
+        assert_eq!(flinks, 0, "flinks in {}", queues.display());
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::{Arc, Mutex};
+
+use flux::{
+    communication::{ShmemData, cleanup_shmem},
+    spine::SpineAdapter,
+    tile::{Tile, TileConfig, TileInfo, attach_tile},
+    type_hash_derive::TypeHash,
+};
+use flux_timing::Duration;
+use flux_utils::directories::shmem_dir_varlen_with_base;
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Frame {
+    seq: u64,
+}
+
+#[from_spine("spine-varlen-test-app")]
+#[derive(Debug)]
+struct VarlenSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(varlen, capacity = 1 << 16)]
+    pub frames: SpineQueue<Frame>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Writer {
+    seq: u64,
+}
+
+impl Tile<VarlenSpine> for Writer {
+    fn loop_body(&mut self, adapter: &mut SpineAdapter<VarlenSpine>) {
+        let len = (self.seq % 200) as usize;
+        let byte = self.seq as u8;
+        adapter
+            .produce_varlen(Frame { seq: self.seq }, len, |buf| buf.fill(byte))
+            .expect("payload fits");
+        self.seq += 1;
+    }
+}
+
+#[derive(Clone)]
+struct Reader {
+    received: Arc<Mutex<Vec<(u64, usize)>>>,
+}
+
+impl Tile<VarlenSpine> for Reader {
+    fn loop_body(&mut self, adapter: &mut SpineAdapter<VarlenSpine>) {
+        let mut received = self.received.lock().unwrap();
+        adapter.consume_varlen(
+            |frame: &Frame, payload| {
+                payload.iter().all(|&b| b == frame.seq as u8).then_some(payload.len())
+            },
+            |frame, len, _| received.push((frame.seq, len.expect("payload intact"))),
+        );
+        if received.len() >= 100 {
+            adapter.request_stop_scope();
+        }
+    }
+}
+
+#[test]
+fn varlen_queue_through_spine() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let base = tmp.path();
+    let config = VarlenSpineConfig::default();
+    assert_eq!(config.frames.capacity, 1 << 16);
+
+    let mut spine = VarlenSpine::new_with_base_dir(base, None);
+    let received = Arc::new(Mutex::new(Vec::new()));
+
+    std::thread::scope(|scope| {
+        let mut scoped = flux::spine::ScopedSpine::new(&mut spine, scope, None, None);
+        attach_tile(
+            Reader { received: received.clone() },
+            &mut scoped,
+            TileConfig::background(None, None),
+        );
+        attach_tile(
+            Writer::default(),
+            &mut scoped,
+            TileConfig::background(None, Some(Duration::from_millis(1))),
+        );
+    });
+
+    let received = std::mem::take(&mut *received.lock().unwrap());
+    assert!(received.len() >= 100);
+    for pair in received.windows(2) {
+        assert!(pair[1].0 > pair[0].0, "records out of order: {pair:?}");
+    }
+    for &(seq, len) in &received {
+        assert_eq!(len, (seq % 200) as usize);
+    }
+    assert!(shmem_dir_varlen_with_base(base, "spine-varlen-test-app").is_dir());
+
+    cleanup_shmem(base);
+}
@@ -0,0 +1,34 @@
+use flux::{
+    communication::{
+        queue::{Consumer, Queue},
+        shmem_dir_queues_string_with_base,
+    },
+    tile::metrics::{TileMetrics, TileSample},
+    timing::{Duration, IngestionTime},
+};
+
+#[test]
+fn missed_deadlines_are_reported_per_window() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let mut metrics = TileMetrics::new(tmp.path(), "tile-pacing-test-app", "Paced");
+    let dir = shmem_dir_queues_string_with_base(tmp.path(), "tile-pacing-test-app");
+    let queue = Queue::<TileSample>::try_open_shared(format!("{dir}/tilemetrics-Paced")).unwrap();
+    let mut consumer = Consumer::new(queue, "tile-pacing-test").without_log();
+    assert!(!consumer.consume(|_| ()));
+
+    for i in 0..1024u64 {
+        metrics.begin(IngestionTime::now());
+        metrics.end(false);
+        if i % 256 == 0 {
+            metrics.missed_deadline(Duration::from_micros(100 * (i / 256 + 1)));
+        }
+    }
+    let mut samples = Vec::new();
+    while consumer.consume(|s| samples.push(*s)) {}
+    assert_eq!(samples.len(), 1);
+    let sample = samples[0];
+    assert_eq!(sample.loop_count, 1024);
+    assert_eq!(sample.missed_deadlines, 4);
+    assert!(sample.lateness_max.abs_diff(400_000) < 1_000, "{}", sample.lateness_max);
+    assert!(sample.lateness_avg().abs_diff(250_000) < 1_000, "{}", sample.lateness_avg());
+}
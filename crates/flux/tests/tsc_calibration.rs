@@ -0,0 +1,41 @@
+use flux::{
+    communication::{SharedTscCalibration, ShmemData, attached_tsc_calibration},
+    spine::SpineQueue,
+    tile::TileInfo,
+    timing::{Duration, Nanos, TscCalibration},
+    type_hash_derive::TypeHash,
+};
+use spine_derive::from_spine;
+
+#[derive(Clone, Copy, Default, Debug, TypeHash)]
+#[repr(C)]
+struct Tick {
+    id: u64,
+}
+
+#[from_spine("tsc-calibration-test-app")]
+#[derive(Debug)]
+struct CalibratedSpine {
+    pub tile_info: ShmemData<TileInfo>,
+    #[queue(size(16))]
+    pub ticks: SpineQueue<Tick>,
+}
+
+#[test]
+fn spine_adopts_the_published_calibration() {
+    let tmp = tempfile::tempdir().expect("create temp dir");
+    let published =
+        SharedTscCalibration::open_or_publish_with_base_dir(tmp.path(), "tsc-calibration-test-app")
+            .unwrap()
+            .read();
+    // Our own measurement, a little off from the one published first.
+    TscCalibration { nanos_per_tick: published.nanos_per_tick + 1_000, ..published }.adopt();
+    assert_ne!(Nanos::from(Duration(1 << 32)).0, published.nanos_per_tick);
+
+    let _spine = CalibratedSpine::new_with_base_dir(tmp.path(), None);
+
+    let attached = attached_tsc_calibration().expect("attached on spine creation");
+    assert_eq!(attached.read(), published);
+    // 2^32 ticks convert to exactly the rate.
+    assert_eq!(Nanos::from(Duration(1 << 32)).0, published.nanos_per_tick);
+}
@@ -90,38 +90,143 @@ mod kw {
     syn::custom_keyword!(mtu);
 }
 
-fn get_queue_config(attrs: &[Attribute]) -> (bool, Option<Expr>, bool, Option<Expr>) {
-    let mut is_persistent = false;
-    let mut size_expr: Option<Expr> = None;
-    let mut is_spmc = false;
-    let mut mtu_expr: Option<Expr> = None;
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct QueueConfig {
+    is_persistent: bool,
+    size_expr: Option<Expr>,
+    is_spmc: bool,
+    mtu_expr: Option<Expr>,
+    overflow_expr: Option<Expr>,
+    is_varlen: bool,
+    capacity_expr: Option<Expr>,
+    conflate_key_expr: Option<Expr>,
+    is_recorded: bool,
+    huge_pages: bool,
+    prefault: bool,
+    mlock: bool,
+    numa_node_expr: Option<Expr>,
+    producer_core_expr: Option<Expr>,
+    budget_expr: Option<Expr>,
+}
+
+impl QueueConfig {
+    /// The consumers' latency budget: a string like `"5us"`, or anything
+    /// that converts into `Nanos`.
+    fn budget_tokens(&self) -> proc_macro2::TokenStream {
+        match &self.budget_expr {
+            Some(Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(s), .. })) => {
+                let msg = format!("invalid latency budget {:?}", s.value());
+                quote! { Some(#s.parse::<::flux::timing::Nanos>().expect(#msg)) }
+            }
+            Some(expr) => {
+                quote! { Some(::core::convert::Into::<::flux::timing::Nanos>::into(#expr)) }
+            }
+            None => quote! { None },
+        }
+    }
+
+    fn mapping_tokens(&self) -> proc_macro2::TokenStream {
+        let Self { huge_pages, prefault, mlock, .. } = self;
+        // An explicit node wins; otherwise follow the producing tile's core.
+        let numa_node = match (&self.numa_node_expr, &self.producer_core_expr) {
+            (Some(node), _) => quote! { Some(#node) },
+            (None, Some(core)) => quote! { ::flux::utils::numa::node_of_cpu(#core) },
+            (None, None) => quote! { None },
+        };
+        quote! {
+            ::flux::communication::MappingOptions {
+                huge_pages: #huge_pages,
+                prefault: #prefault,
+                mlock: #mlock,
+                numa_node: #numa_node,
+            }
+        }
+    }
+}
+
+fn get_queue_config(attrs: &[Attribute]) -> QueueConfig {
+    let mut config = QueueConfig::default();
 
     for attr in attrs {
         if attr.path().is_ident("queue") {
             attr.parse_nested_meta(|meta| {
                 if meta.path.is_ident("persist") {
-                    is_persistent = true;
+                    config.is_persistent = true;
                     return Ok(());
                 }
                 if meta.path.is_ident("size") {
                     let content;
                     parenthesized!(content in meta.input);
                     let lit: Expr = content.parse()?;
-                    size_expr = Some(lit);
+                    config.size_expr = Some(lit);
                     return Ok(());
                 }
                 if meta.path.is_ident("flavour") {
                     let content;
                     parenthesized!(content in meta.input);
                     let s: LitStr = content.parse()?;
-                    is_spmc = s.value() == "spmc";
+                    config.is_spmc = s.value() == "spmc";
                     return Ok(());
                 }
                 if meta.path.is_ident("mtu") {
                     let content;
                     parenthesized!(content in meta.input);
                     let lit: Expr = content.parse()?;
-                    mtu_expr = Some(lit);
+                    config.mtu_expr = Some(lit);
+                    return Ok(());
+                }
+                if meta.path.is_ident("overflow") {
+                    let content;
+                    parenthesized!(content in meta.input);
+                    let lit: Expr = content.parse()?;
+                    config.overflow_expr = Some(lit);
+                    return Ok(());
+                }
+                if meta.path.is_ident("record") {
+                    config.is_recorded = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("varlen") {
+                    config.is_varlen = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("conflate") {
+                    meta.parse_nested_meta(|inner| {
+                        if inner.path.is_ident("key") {
+                            config.conflate_key_expr = Some(inner.value()?.parse()?);
+                            return Ok(());
+                        }
+                        Err(inner.error("expected `key = ...`"))
+                    })?;
+                    return Ok(());
+                }
+                if meta.path.is_ident("capacity") {
+                    config.capacity_expr = Some(meta.value()?.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("huge_pages") {
+                    config.huge_pages = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("prefault") {
+                    config.prefault = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("mlock") {
+                    config.mlock = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("numa_node") {
+                    config.numa_node_expr = Some(meta.value()?.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("producer_core") {
+                    config.producer_core_expr = Some(meta.value()?.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("budget") {
+                    config.budget_expr = Some(meta.value()?.parse()?);
                     return Ok(());
                 }
                 Err(meta.error("unrecognized repr"))
@@ -130,8 +235,9 @@ fn get_queue_config(attrs: &[Attribute]) -> (bool, Option<Expr>, bool, Option<Ex
         }
     }
 
-    (is_persistent, size_expr, is_spmc, mtu_expr)
+    config
 }
+
 #[allow(clippy::too_many_lines)]
 #[proc_macro_attribute]
 pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -147,12 +253,15 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut consumer_fields = Punctuated::<_, Comma>::new();
     let mut producer_fields = Punctuated::<_, Comma>::new();
     let mut consumer_init = Punctuated::<_, Comma>::new();
+    let mut leave_groups = Vec::<proc_macro2::TokenStream>::new();
+    let mut renew_groups = Vec::<proc_macro2::TokenStream>::new();
     let mut producer_init = Punctuated::<_, Comma>::new();
 
     let mut as_ref_impls = Vec::<proc_macro2::TokenStream>::new();
     let mut as_mut_impls = Vec::<proc_macro2::TokenStream>::new();
     let mut spine_as_ref_impls = Vec::<proc_macro2::TokenStream>::new();
     let mut persisting = Vec::<proc_macro2::TokenStream>::new();
+    let mut recording = Vec::<proc_macro2::TokenStream>::new();
     let mut message_types = Vec::<proc_macro2::TokenStream>::new();
     let mut ffi_check_items = Vec::<proc_macro2::TokenStream>::new();
 
@@ -174,8 +283,24 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
             ffi_check_items
                 .push(quote_spanned! { inner_ty_span => fn #check_fn(var: *const #inner_ty); });
 
-            let (is_persistent, _size_expr_opt, _is_spmc, mtu_expr) =
-                get_queue_config(&field.attrs);
+            let config = get_queue_config(&field.attrs);
+            let budget_ident = format_ident!("{}_budget", field_ident);
+            let has_mapping = config.huge_pages ||
+                config.prefault ||
+                config.mlock ||
+                config.numa_node_expr.is_some() ||
+                config.producer_core_expr.is_some();
+            let QueueConfig {
+                is_persistent,
+                mtu_expr,
+                overflow_expr,
+                is_varlen,
+                capacity_expr,
+                is_recorded,
+                conflate_key_expr,
+                ..
+            } = config;
+            let is_conflated = conflate_key_expr.is_some();
 
             if is_persistent && mtu_expr.is_some() {
                 return syn::Error::new_spanned(
@@ -186,7 +311,153 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                 .into();
             }
 
-            if mtu_expr.is_some() {
+            if is_varlen && (is_persistent || mtu_expr.is_some()) {
+                return syn::Error::new_spanned(
+                    field_ident,
+                    "varlen cannot be combined with persist or mtu: the payload lives in the queue's own ring",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            if is_varlen && is_recorded {
+                return syn::Error::new_spanned(
+                    field_ident,
+                    "record cannot be combined with varlen: the flight recorder only mirrors fixed-size and dcache-backed queues",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            if is_conflated && (is_persistent || is_recorded || is_varlen || mtu_expr.is_some()) {
+                return syn::Error::new_spanned(
+                    field_ident,
+                    "conflate cannot be combined with persist, record, varlen or mtu: only the latest message per key is kept",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            if is_conflated && has_mapping {
+                return syn::Error::new_spanned(
+                    field_ident,
+                    "huge_pages, prefault, mlock, numa_node and producer_core are not supported on conflate queues",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            if overflow_expr.is_some() && mtu_expr.is_none() {
+                return syn::Error::new_spanned(
+                    field_ident,
+                    "overflow is only valid on dcache-backed queues, i.e. together with mtu",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            if capacity_expr.is_some() && !is_varlen {
+                return syn::Error::new_spanned(
+                    field_ident,
+                    "capacity is only valid on varlen queues",
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            // Conflating consumers don't join consumer groups.
+            if !is_conflated {
+                leave_groups.push(quote! { self.#field_ident.leave_group(); });
+                renew_groups.push(quote! { self.#field_ident.renew_group(); });
+            }
+
+            if is_varlen {
+                // ── variable-length byte-ring queue ───────────────────────
+                consumer_fields.push(quote! {
+                    pub #field_ident : ::flux::spine::SpineVarlenConsumer<#inner_ty>
+                });
+                producer_fields.push(quote! {
+                    pub #field_ident : ::flux::spine::SpineVarlenQueue<#inner_ty>
+                });
+
+                consumer_init.push(quote! {
+                    #field_ident : ::flux::spine::SpineVarlenConsumer::attach_with_budget::<_, #struct_ident, _>(
+                        &spine.base_dir, tile, spine.#field_ident, spine.#budget_ident)
+                });
+                producer_init.push(quote! { #field_ident : spine.#field_ident });
+
+                as_ref_impls.push(quote! {
+                    impl AsRef<::flux::spine::SpineVarlenQueue<#inner_ty>> for #producers_ident {
+                        fn as_ref(&self) -> &::flux::spine::SpineVarlenQueue<#inner_ty> {
+                            &self.#field_ident
+                        }
+                    }
+                });
+
+                as_mut_impls.push(quote! {
+                    impl AsMut<::flux::spine::SpineVarlenConsumer<#inner_ty>> for #consumers_ident {
+                        fn as_mut(&mut self) -> &mut ::flux::spine::SpineVarlenConsumer<#inner_ty> {
+                            &mut self.#field_ident
+                        }
+                    }
+                    impl AsRef<::flux::spine::SpineVarlenConsumer<#inner_ty>> for #consumers_ident {
+                        fn as_ref(&self) -> &::flux::spine::SpineVarlenConsumer<#inner_ty> {
+                            &self.#field_ident
+                        }
+                    }
+                });
+
+                spine_as_ref_impls.push(quote! {
+                    impl AsRef<::flux::spine::SpineVarlenQueue<#inner_ty>> for #struct_ident {
+                        fn as_ref(&self) -> &::flux::spine::SpineVarlenQueue<#inner_ty> {
+                            &self.#field_ident
+                        }
+                    }
+                });
+            } else if is_conflated {
+                // ── last-value-per-key queue ──────────────────────────────
+                consumer_fields.push(quote! {
+                    pub #field_ident : ::flux::spine::SpineConflatingConsumer<#inner_ty>
+                });
+                producer_fields.push(quote! {
+                    pub #field_ident : ::flux::spine::SpineConflatingQueue<#inner_ty>
+                });
+
+                consumer_init.push(quote! {
+                    #field_ident : ::flux::spine::SpineConflatingConsumer::attach_with_budget::<_, #struct_ident, _>(
+                        &spine.base_dir, tile, spine.#field_ident, spine.#budget_ident)
+                });
+                producer_init.push(quote! { #field_ident : spine.#field_ident });
+
+                as_ref_impls.push(quote! {
+                    impl AsRef<::flux::spine::SpineConflatingQueue<#inner_ty>> for #producers_ident {
+                        fn as_ref(&self) -> &::flux::spine::SpineConflatingQueue<#inner_ty> {
+                            &self.#field_ident
+                        }
+                    }
+                });
+
+                as_mut_impls.push(quote! {
+                    impl AsMut<::flux::spine::SpineConflatingConsumer<#inner_ty>> for #consumers_ident {
+                        fn as_mut(&mut self) -> &mut ::flux::spine::SpineConflatingConsumer<#inner_ty> {
+                            &mut self.#field_ident
+                        }
+                    }
+                    impl AsRef<::flux::spine::SpineConflatingConsumer<#inner_ty>> for #consumers_ident {
+                        fn as_ref(&self) -> &::flux::spine::SpineConflatingConsumer<#inner_ty> {
+                            &self.#field_ident
+                        }
+                    }
+                });
+
+                spine_as_ref_impls.push(quote! {
+                    impl AsRef<::flux::spine::SpineConflatingQueue<#inner_ty>> for #struct_ident {
+                        fn as_ref(&self) -> &::flux::spine::SpineConflatingQueue<#inner_ty> {
+                            &self.#field_ident
+                        }
+                    }
+                });
+            } else if mtu_expr.is_some() {
                 // ── dcache-backed queue ───────────────────────────────────
                 let dcache_ident = format_ident!("{}_dcache", field_ident);
 
@@ -198,8 +469,8 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                 });
 
                 consumer_init.push(quote! {
-                    #field_ident : ::flux::spine::SpineDCacheConsumer::attach::<_, #struct_ident, _>(
-                        &spine.base_dir, tile, spine.#field_ident, spine.#dcache_ident)
+                    #field_ident : ::flux::spine::SpineDCacheConsumer::attach_with_budget::<_, #struct_ident, _>(
+                        &spine.base_dir, tile, spine.#field_ident, spine.#dcache_ident, spine.#budget_ident)
                 });
                 producer_init.push(quote! {
                     #field_ident : ::flux::spine::SpineProducerWithDCache::new(
@@ -267,8 +538,8 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                     .push(quote! { pub #field_ident : ::flux::spine::SpineProducer<#inner_ty> });
 
                 consumer_init.push(quote! {
-                    #field_ident : ::flux::spine::SpineConsumer::attach::<_, #struct_ident, _>(
-                        &spine.base_dir, tile, spine.#field_ident)
+                    #field_ident : ::flux::spine::SpineConsumer::attach_with_budget::<_, #struct_ident, _>(
+                        &spine.base_dir, tile, spine.#field_ident, spine.#budget_ident)
                 });
                 producer_init.push(quote! {
                     #field_ident : ::flux::communication::queue::Producer::from(spine.#field_ident)
@@ -300,6 +571,26 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                 });
             }
 
+            if is_recorded {
+                let recorder_tile = if mtu_expr.is_some() {
+                    quote! { ::flux::persistence::DCacheFlightRecorderTile::<#inner_ty> }
+                } else {
+                    quote! { ::flux::persistence::FlightRecorderTile::<#inner_ty> }
+                };
+                recording.push(quote! {
+                    let last_core = ::flux::core_affinity::get_core_ids().unwrap().last().unwrap().id;
+                    let cfg = ::flux::tile::TileConfig::background(
+                        Some(last_core),
+                        Some(::flux::timing::Duration::from_millis(10)),
+                    );
+                    ::flux::tile::attach_tile(
+                        #recorder_tile::new_with_base_dir(&scoped.spine.base_dir),
+                        &mut scoped,
+                        cfg,
+                    );
+                });
+            }
+
             if is_persistent {
                 persisting.push(quote! {
                                 let last_core = ::flux::core_affinity::get_core_ids().unwrap().last().unwrap().id;
@@ -350,8 +641,22 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
             new_struct_field_names.push(quote! { tile_info });
         } else if let Type::Path(tp) = &field.ty {
             if tp.path.segments.last().is_some_and(|s| s.ident == "SpineQueue") {
-                let (_is_persistent, size_expr_opt, is_spmc, mtu_expr_opt) =
-                    get_queue_config(&field.attrs);
+                let queue_config = get_queue_config(&field.attrs);
+                let mapping = queue_config.mapping_tokens();
+                let budget = queue_config.budget_tokens();
+                let budget_ident = format_ident!("{}_budget", field_ident);
+                new_let_stmts.push(quote! { let #budget_ident = config.#field_ident.budget; });
+                new_struct_field_names.push(quote! { #budget_ident });
+                let QueueConfig {
+                    size_expr: size_expr_opt,
+                    is_spmc,
+                    mtu_expr: mtu_expr_opt,
+                    overflow_expr,
+                    is_varlen,
+                    capacity_expr,
+                    conflate_key_expr,
+                    ..
+                } = queue_config;
                 let size_arg = size_expr_opt
                     .map_or_else(|| quote! { 2usize.pow(15) }, |expr| quote! { #expr });
                 let queue_type = if is_spmc {
@@ -359,23 +664,83 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                 } else {
                     quote! { ::flux::communication::queue::QueueType::MPMC }
                 };
-                if let Some(mtu_expr) = mtu_expr_opt {
+                if is_varlen {
+                    let capacity_arg = capacity_expr
+                        .map_or_else(|| quote! { 1usize << 20 }, |expr| quote! { #expr });
+                    config_fields.push(quote! {
+                        pub #field_ident: ::flux::spine::VarlenQueueParams
+                    });
+                    config_defaults.push(quote! {
+                        #field_ident: ::flux::spine::VarlenQueueParams {
+                            capacity: #capacity_arg,
+                            mapping: #mapping,
+                            budget: #budget,
+                        }
+                    });
+                    new_let_stmts.push(quote! {
+                        let #field_ident = ::flux::communication::shmem_varlen_queue_with_base_dir_and_options(
+                            &base_dir,
+                            &format!("{}{}", #app_name_tokens, path_suffix),
+                            config.#field_ident.capacity,
+                            config.#field_ident.mapping,
+                        ).expect("couldn't open or create spine varlen queue");
+                    });
+                    new_struct_field_names.push(quote! { #field_ident });
+                } else if let Some(key_expr) = conflate_key_expr &&
+                    let PathArguments::AngleBracketed(targs) =
+                        &tp.path.segments.last().unwrap().arguments &&
+                    let Some(GenericArgument::Type(inner_ty)) = targs.args.first()
+                {
+                    // `size` is the number of keys; the key function gets `&T`
+                    // and returns anything that converts into a `u64`.
+                    config_fields.push(quote! {
+                        pub #field_ident: ::flux::spine::ConflatingQueueParams
+                    });
+                    config_defaults.push(quote! {
+                        #field_ident: ::flux::spine::ConflatingQueueParams {
+                            keys: #size_arg,
+                            budget: #budget,
+                        }
+                    });
+                    new_let_stmts.push(quote! {
+                        let #field_ident = ::flux::communication::shmem_conflating_queue_with_base_dir(
+                            &base_dir,
+                            &format!("{}{}", #app_name_tokens, path_suffix),
+                            config.#field_ident.keys,
+                            |msg: &::flux::timing::InternalMessage<#inner_ty>| -> u64 {
+                                let key: fn(&#inner_ty) -> _ = #key_expr;
+                                ::core::convert::Into::<u64>::into(key(msg.data()))
+                            },
+                        ).expect("couldn't open or create spine conflating queue");
+                    });
+                    new_struct_field_names.push(quote! { #field_ident });
+                } else if let Some(mtu_expr) = mtu_expr_opt {
                     let dcache_ident = format_ident!("{}_dcache", field_ident);
+                    let overflow_arg =
+                        overflow_expr.map_or_else(|| quote! { 0 }, |expr| quote! { #expr });
                     config_fields.push(quote! {
                         pub #field_ident: ::flux::spine::DCacheQueueParams
                     });
                     config_defaults.push(quote! {
-                        #field_ident: ::flux::spine::DCacheQueueParams { size: #size_arg, mtu: #mtu_expr }
+                        #field_ident: ::flux::spine::DCacheQueueParams {
+                            size: #size_arg,
+                            mtu: #mtu_expr,
+                            overflow: #overflow_arg,
+                            mapping: #mapping,
+                            budget: #budget,
+                        }
                     });
                     new_let_stmts.push(quote! {
                         let (#field_ident, #dcache_ident) =
-                            ::flux::communication::shmem_queue_dcache_with_base_dir(
+                            ::flux::communication::shmem_queue_dcache_with_base_dir_and_overflow(
                                 &base_dir,
                                 &format!("{}{}", #app_name_tokens, path_suffix),
                                 config.#field_ident.size,
                                 config.#field_ident.mtu,
+                                config.#field_ident.overflow,
                                 #queue_type,
-                            );
+                                config.#field_ident.mapping,
+                            ).expect("couldn't open or create spine dcache queue");
                     });
                     new_struct_field_names.push(quote! { #field_ident });
                     new_struct_field_names.push(quote! { #dcache_ident });
@@ -384,15 +749,20 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                         pub #field_ident: ::flux::spine::QueueParams
                     });
                     config_defaults.push(quote! {
-                        #field_ident: ::flux::spine::QueueParams { size: #size_arg }
+                        #field_ident: ::flux::spine::QueueParams {
+                            size: #size_arg,
+                            mapping: #mapping,
+                            budget: #budget,
+                        }
                     });
                     new_let_stmts.push(quote! {
-                        let #field_ident = ::flux::communication::shmem_queue_with_base_dir(
+                        let #field_ident = ::flux::communication::shmem_queue_with_base_dir_and_options(
                             &base_dir,
                             &format!("{}{}", #app_name_tokens, path_suffix),
                             config.#field_ident.size,
                             #queue_type,
-                        );
+                            config.#field_ident.mapping,
+                        ).expect("couldn't open or create spine queue");
                     });
                     new_struct_field_names.push(quote! { #field_ident });
                 }
@@ -425,14 +795,17 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
         ) -> Self {
             let path_suffix = path_suffix.unwrap_or(&"");
             let base_dir = base_dir.as_ref().to_path_buf();
+            let app_name = format!("{}{}", #app_name_tokens, path_suffix);
+            ::flux::spine::share_park_signal(&base_dir, &app_name);
+            ::flux::spine::share_tsc_calibration(&base_dir, &app_name);
             #(#new_let_stmts)*
             Self { #(#new_struct_field_names),* }
         }
     };
 
     // Reconstruct the input struct without #[queue] attributes on its fields.
-    // For SpineQueue fields with `mtu`, also inject a `{field}_dcache: DCachePtr`
-    // field.
+    // Every SpineQueue field gets a `{field}_budget: Option<Nanos>` field for
+    // its consumers, those with `mtu` also a `{field}_dcache: DCachePtr` field.
     let input_attrs = &input.attrs;
     let vis = &input.vis;
     let struct_ident = &input.ident;
@@ -450,14 +823,24 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
 
                 // For dcache queue fields, rewrite SpineQueue<T> → SpineQueue<DCacheMsg<T>>
                 // and inject the private dcache handle field immediately after.
+                // Varlen fields become SpineVarlenQueue<T>, conflate fields
+                // SpineConflatingQueue<T>.
                 if let Type::Path(tp) = ty &&
                     tp.path.segments.last().is_some_and(|s| s.ident == "SpineQueue") &&
                     let PathArguments::AngleBracketed(ref targs) =
                         tp.path.segments.last().unwrap().arguments &&
                     let Some(GenericArgument::Type(inner_ty)) = targs.args.first()
                 {
-                    let (_, _, _, mtu_opt) = get_queue_config(&f.attrs);
-                    if mtu_opt.is_some() {
+                    let config = get_queue_config(&f.attrs);
+                    let budget_ident = format_ident!("{}_budget", ident.as_ref().unwrap());
+                    all_fields.push(quote! { #budget_ident: Option<::flux::timing::Nanos> });
+                    if config.is_varlen {
+                        let new_ty = quote! { ::flux::spine::SpineVarlenQueue<#inner_ty> };
+                        all_fields.push(quote! { #(#attrs)* #fvis #ident #colon_token #new_ty });
+                    } else if config.conflate_key_expr.is_some() {
+                        let new_ty = quote! { ::flux::spine::SpineConflatingQueue<#inner_ty> };
+                        all_fields.push(quote! { #(#attrs)* #fvis #ident #colon_token #new_ty });
+                    } else if config.mtu_expr.is_some() {
                         let dcache_ident = format_ident!("{}_dcache", ident.as_ref().unwrap());
                         let new_ty = quote! {
                             ::flux::spine::SpineQueue<::flux::spine::DCacheMsg<#inner_ty>>
@@ -516,6 +899,16 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
             }
         }
 
+        impl ::flux::spine::SpineConsumers for #consumers_ident {
+            fn leave_groups(&mut self) {
+                #(#leave_groups)*
+            }
+
+            fn renew_groups(&mut self) {
+                #(#renew_groups)*
+            }
+        }
+
         #[derive(Clone, Copy, Debug)]
         #vis struct #producers_ident { #producer_fields, timestamp: ::flux::timing::TrackingTimestamp }
         impl #producers_ident {
@@ -579,8 +972,10 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                     f(&mut scoped);
 
                     ::flux::core_affinity::set_for_current(*::flux::core_affinity::get_core_ids().unwrap().last().unwrap());
+                    ::flux::timing::Instant::cache_thread_socket();
 
                     #(#persisting)*     // ← injected only for #[persist] fields
+                    #(#recording)*      // ← injected only for #[record] fields
                 });
                 ::flux::tracing::info!("Finished…");
             }
@@ -593,6 +988,8 @@ pub fn from_spine(attr: TokenStream, item: TokenStream) -> TokenStream {
                 std::thread::scope(|s| {
                     let mut scoped = ::flux::spine::ScopedSpine::new(&mut self, s, on_panic, custom_signal_handler);
                     f(&mut scoped);
+
+                    #(#recording)*
                 })
             }
 